@@ -0,0 +1,85 @@
+/* Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+ * file at the top-level directory of this distribution.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+// THIS IS A GENERATED FILE. PLEASE DO NOT EDIT.
+// Instead, please regenerate using cbindgen from capi/.
+
+#ifndef shift_or_euc_h
+#define shift_or_euc_h
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include "encoding_rs.h"
+
+/* The opaque detector type. Define SHIFT_OR_EUC_DETECTOR before including
+ * this header to use a C++ wrapper type instead. */
+#ifndef SHIFT_OR_EUC_DETECTOR
+#define SHIFT_OR_EUC_DETECTOR ShiftOrEucDetector
+typedef struct ShiftOrEucDetector_ ShiftOrEucDetector;
+#endif
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Deallocates a detector obtained from `shift_or_euc_detector_new()`.
+ *
+ * # Safety
+ *
+ * UB ensues if the argument is not a pointer obtained from
+ * `shift_or_euc_detector_new()` or if it has already been deallocated.
+ * `NULL` is not allowed.
+ */
+void shift_or_euc_detector_free(SHIFT_OR_EUC_DETECTOR *detector);
+
+/**
+ * Feeds bytes to the detector. If `last` is `true`, the end of the stream
+ * is considered to occur immediately after the end of `buffer`.
+ * Otherwise, the stream is expected to continue. `buffer_len` may be zero.
+ *
+ * Returns `SHIFT_JIS_ENCODING`, `EUC_JP_ENCODING` or `ISO_2022_JP_ENCODING`
+ * of the `encoding_rs` C API once the detector has decided or `NULL` if the
+ * detector is undecided. If `NULL` is returned even when passing `true` as
+ * `last`, falling back to Shift_JIS is the best guess for Web purposes.
+ *
+ * Do not call again after the function has returned non-`NULL` or after
+ * the function has been called with `true` as `last`. Doing so aborts.
+ *
+ * # Safety
+ *
+ * UB ensues if `detector` is not a live detector obtained from
+ * `shift_or_euc_detector_new()` or if `buffer` and `buffer_len` don't
+ * designate a valid block of memory. `buffer` may be `NULL` if
+ * `buffer_len` is zero.
+ */
+const ENCODING_RS_ENCODING *shift_or_euc_detector_feed(SHIFT_OR_EUC_DETECTOR *detector,
+                                                       const uint8_t *buffer,
+                                                       size_t buffer_len,
+                                                       bool last);
+
+/**
+ * Instantiates a detector on the heap. If `allow_2022` is `true` the
+ * possible guesses are Shift_JIS, EUC-JP, ISO-2022-JP, and undecided. If
+ * `allow_2022` is `false`, the possible guesses are Shift_JIS, EUC-JP, and
+ * undecided.
+ *
+ * The detector must be deallocated with `shift_or_euc_detector_free()`.
+ */
+SHIFT_OR_EUC_DETECTOR *shift_or_euc_detector_new(bool allow_2022);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif // shift_or_euc_h
@@ -0,0 +1,105 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! C API for [`shift_or_euc`](https://docs.rs/shift_or_euc/).
+//!
+//! The functions take and return `Encoding` pointers of the
+//! [C API of `encoding_rs`](https://github.com/hsivonen/encoding_c), so the
+//! detector plugs into C and C++ code that already decodes with it. The
+//! declarations are in `include/shift_or_euc.h` (generated with `cbindgen`
+//! from this file, using `cbindgen.toml`); dependent build scripts find the
+//! header directory as `DEP_SHIFT_OR_EUC_C_INCLUDE_DIR`.
+//!
+//! The detector doesn't unwind across the FFI boundary: calling
+//! `shift_or_euc_detector_feed()` again after it has decided or after `last`
+//! aborts.
+
+use encoding_rs::Encoding;
+use shift_or_euc::Detector;
+
+/// Instantiates a detector on the heap. If `allow_2022` is `true` the
+/// possible guesses are Shift_JIS, EUC-JP, ISO-2022-JP, and undecided. If
+/// `allow_2022` is `false`, the possible guesses are Shift_JIS, EUC-JP, and
+/// undecided.
+///
+/// The detector must be deallocated with `shift_or_euc_detector_free()`.
+#[no_mangle]
+pub extern "C" fn shift_or_euc_detector_new(allow_2022: bool) -> *mut Detector {
+    Box::into_raw(Box::new(Detector::new(allow_2022)))
+}
+
+/// Deallocates a detector obtained from `shift_or_euc_detector_new()`.
+///
+/// # Safety
+///
+/// UB ensues if the argument is not a pointer obtained from
+/// `shift_or_euc_detector_new()` or if it has already been deallocated.
+/// `NULL` is not allowed.
+#[no_mangle]
+pub unsafe extern "C" fn shift_or_euc_detector_free(detector: *mut Detector) {
+    drop(Box::from_raw(detector));
+}
+
+/// Feeds bytes to the detector. If `last` is `true`, the end of the stream
+/// is considered to occur immediately after the end of `buffer`.
+/// Otherwise, the stream is expected to continue. `buffer_len` may be zero.
+///
+/// Returns `SHIFT_JIS_ENCODING`, `EUC_JP_ENCODING` or `ISO_2022_JP_ENCODING`
+/// of the `encoding_rs` C API once the detector has decided or `NULL` if the
+/// detector is undecided. If `NULL` is returned even when passing `true` as
+/// `last`, falling back to Shift_JIS is the best guess for Web purposes.
+///
+/// Do not call again after the function has returned non-`NULL` or after
+/// the function has been called with `true` as `last`. Doing so aborts.
+///
+/// # Safety
+///
+/// UB ensues if `detector` is not a live detector obtained from
+/// `shift_or_euc_detector_new()` or if `buffer` and `buffer_len` don't
+/// designate a valid block of memory. `buffer` may be `NULL` if
+/// `buffer_len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn shift_or_euc_detector_feed(
+    detector: *mut Detector,
+    buffer: *const u8,
+    buffer_len: usize,
+    last: bool,
+) -> *const Encoding {
+    let buffer = if buffer_len == 0 {
+        &[][..]
+    } else {
+        ::std::slice::from_raw_parts(buffer, buffer_len)
+    };
+    match (*detector).feed(buffer, last) {
+        Some(encoding) => encoding,
+        None => ::std::ptr::null(),
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+
+    #[test]
+    fn test_feed() {
+        unsafe {
+            let detector = shift_or_euc_detector_new(true);
+            assert!(shift_or_euc_detector_feed(detector, ::std::ptr::null(), 0, false).is_null());
+            // "あ" in EUC-JP.
+            let bytes = b"a\xA4\xA2";
+            let encoding = shift_or_euc_detector_feed(detector, bytes.as_ptr(), bytes.len(), false);
+            assert_eq!(encoding, EUC_JP as *const Encoding);
+            shift_or_euc_detector_free(detector);
+        }
+    }
+}
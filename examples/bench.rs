@@ -0,0 +1,50 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compares the throughput of `Detector` with the byte-at-a-time
+//! `testing::reference_verdict()` on synthetic inputs whose verdict comes
+//! late. Run with `cargo run --release --example bench --features
+//! test-support` (add `reference-impl` to measure the fallback path).
+
+use std::time::Instant;
+
+use shift_or_euc::testing::reference_verdict;
+use shift_or_euc::Detector;
+
+const ITERATIONS: u32 = 200;
+
+fn measure<F: Fn(&[u8]) -> Option<shift_or_euc::Verdict>>(input: &[u8], detect: F) -> f64 {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(detect(std::hint::black_box(input)));
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    elapsed * 1e9 / (f64::from(ITERATIONS) * input.len() as f64)
+}
+
+fn main() {
+    // Mostly ASCII markup with an ambiguous kanji per line, decided by the
+    // last line.
+    let mut markup = b"<p class=\"x\">\xE0\xE0</p>\n".repeat(4096);
+    markup.extend_from_slice(b"\xA4\xA2");
+    // Ambiguous kanji only.
+    let kanji = b"\xE0\xE0".repeat(32768);
+    for &(name, input) in [("markup", &markup[..]), ("kanji", &kanji[..])].iter() {
+        let optimized = measure(input, |input| Detector::new(true).feed_verdict(input, true));
+        let reference = measure(input, |input| reference_verdict(input, true));
+        assert_eq!(
+            Detector::new(true).feed_verdict(input, true),
+            reference_verdict(input, true)
+        );
+        println!(
+            "{}: Detector {:.2} ns/byte, reference {:.2} ns/byte",
+            name, optimized, reference
+        );
+    }
+}
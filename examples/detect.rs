@@ -7,50 +7,29 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs::File;
-use std::io::Read;
+//! Usage: `detect PATH...`
+//!
+//! Walks the paths with `shift_or_euc::scan::Scanner` and prints one
+//! `path: result` line per file. The `shift-or-euc` tool (built with the
+//! `cli` feature) does the same with JSON output, progress and resuming.
 
-use shift_or_euc::Detector;
+use std::path::PathBuf;
+
+use shift_or_euc::scan::Scanner;
 
 fn main() {
-    let mut args = std::env::args_os();
-    if args.next().is_none() {
-        eprintln!("Error: Program name missing from arguments.");
-        std::process::exit(-1);
+    let paths: Vec<PathBuf> = std::env::args_os().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        eprintln!("Error: At least one path argument needed.");
+        std::process::exit(-2);
     }
-    if let Some(path) = args.next() {
-        if args.next().is_some() {
-            eprintln!("Error: Too many arguments.");
-            std::process::exit(-3);
+    for report in Scanner::new(paths, true) {
+        // Without a journal, the scanner doesn't fail.
+        let report = report.unwrap();
+        match (report.error(), report.encoding()) {
+            (Some(error), _) => println!("{}: Error: {}", report.path().display(), error),
+            (None, Some(encoding)) => println!("{}: {}", report.path().display(), encoding),
+            (None, None) => println!("{}: Undecided", report.path().display()),
         }
-        if let Ok(mut file) = File::open(path) {
-            let mut buffer = [0u8; 4096];
-            let mut detector = Detector::new(true);
-            loop {
-                if let Ok(num_read) = file.read(&mut buffer[..]) {
-                    let opt_enc = if num_read == 0 {
-                        detector.feed(b"", true)
-                    } else {
-                        detector.feed(&buffer[..num_read], false)
-                    };
-                    if let Some(encoding) = opt_enc {
-                        println!("{}", encoding.name());
-                        return;
-                    } else if num_read == 0 {
-                        println!("Undecided");
-                        return;
-                    }
-                } else {
-                    eprintln!("Error: Error reading file.");
-                    std::process::exit(-5);
-                }
-            }
-        } else {
-            eprintln!("Error: Could not open file.");
-            std::process::exit(-4);
-        }
-    } else {
-        eprintln!("Error: One path argument needed.");
-        std::process::exit(-2);
     }
 }
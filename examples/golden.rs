@@ -0,0 +1,18 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Prints the golden test vectors generated from the current behavior of
+//! the detector. Run with `cargo run --example golden --features
+//! test-support > golden/vectors.json`.
+
+use shift_or_euc::golden::generate_golden_vectors;
+
+fn main() {
+    print!("{}", generate_golden_vectors().to_json());
+}
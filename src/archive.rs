@@ -0,0 +1,443 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+/// The size of a tar header and of the blocks of tar data.
+const TAR_BLOCK: usize = 512;
+
+/// The name of the entry that ends a cpio archive.
+const CPIO_TRAILER: &[u8] = b"TRAILER!!!";
+
+/// The name of an archive entry as stored and as decoded.
+///
+/// Cloning is cheap: the name is reference-counted, and with
+/// `detect_tar_names_interned()` or `detect_cpio_names_interned()`, entries
+/// with the same name share it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveName {
+    offset: u64,
+    raw: Arc<[u8]>,
+    name: Arc<str>,
+}
+
+impl ArchiveName {
+    /// The offset of the header of the entry in the archive. For a tar
+    /// entry with a GNU long name or a PAX extended header, the offset of
+    /// the first of those headers.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The name as stored.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// The name as decoded from `ArchiveNames::encoding()`, with malformed
+    /// sequences replaced with the REPLACEMENT CHARACTER.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The result of `detect_tar_names()` and `detect_cpio_names()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveNames {
+    encoding: &'static Encoding,
+    decided: bool,
+    complete: bool,
+    names: Vec<ArchiveName>,
+}
+
+impl ArchiveNames {
+    /// The encoding that the names were decoded from: the one that the
+    /// names decided jointly or the fallback, Shift_JIS, if they didn't.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Whether the names decided the encoding.
+    pub fn decided(&self) -> bool {
+        self.decided
+    }
+
+    /// Whether the archive was read up to its end marker. If not, it was
+    /// truncated or malformed and only the names before the damage are
+    /// listed.
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The names of the entries in archive order.
+    pub fn names(&self) -> &[ArchiveName] {
+        &self.names
+    }
+}
+
+/// Detects the encoding of the entry names of a tar archive jointly and
+/// decodes them. `allow_2022` has the same meaning as for `Detector::new()`.
+///
+/// Names are read from GNU long name entries (type `L`), from the `path`
+/// records of PAX extended headers (type `x`) and from the `name` and
+/// `prefix` fields of the headers, in that order of precedence. PAX paths
+/// are meant to be UTF-8, but archivers that don't know the encoding of the
+/// file system store the bytes as they are, so they are detected like the
+/// others. Each name ends at a character boundary, as with
+/// `RecordDetector`.
+pub fn detect_tar_names(archive: &[u8], allow_2022: bool) -> ArchiveNames {
+    let (names, complete) = tar_names(archive);
+    decode_names(names, complete, allow_2022, false)
+}
+
+/// The same as `detect_tar_names()` except that entries with the same raw
+/// name share the storage of the raw and the decoded name, which reduces
+/// peak memory for archives that repeat names many times (e.g. appended
+/// or incremental archives that store the same tree again) at the cost of
+/// a lookup per name.
+pub fn detect_tar_names_interned(archive: &[u8], allow_2022: bool) -> ArchiveNames {
+    let (names, complete) = tar_names(archive);
+    decode_names(names, complete, allow_2022, true)
+}
+
+/// Detects the encoding of the entry names of a cpio archive in the
+/// portable ASCII (`070707`) or new ASCII (`070701` and `070702`) format
+/// jointly and decodes them. `allow_2022` has the same meaning as for
+/// `Detector::new()`.
+pub fn detect_cpio_names(archive: &[u8], allow_2022: bool) -> ArchiveNames {
+    let (names, complete) = cpio_names(archive);
+    decode_names(names, complete, allow_2022, false)
+}
+
+/// The same as `detect_cpio_names()` except that entries with the same raw
+/// name share storage, as with `detect_tar_names_interned()`.
+pub fn detect_cpio_names_interned(archive: &[u8], allow_2022: bool) -> ArchiveNames {
+    let (names, complete) = cpio_names(archive);
+    decode_names(names, complete, allow_2022, true)
+}
+
+/// Runs joint detection on the raw names and decodes them, sharing the
+/// storage of duplicates if `intern` is `true`.
+fn decode_names(
+    names: Vec<(u64, Vec<u8>)>,
+    complete: bool,
+    allow_2022: bool,
+    intern: bool,
+) -> ArchiveNames {
+    let mut detector = Detector::new(allow_2022);
+    let mut decided = None;
+    for (_, raw) in names.iter() {
+        decided = detector.feed(raw, false);
+        if decided.is_some() {
+            break;
+        }
+        detector.record_boundary();
+    }
+    if decided.is_none() {
+        decided = detector.feed(b"", true);
+    }
+    let encoding = decided.unwrap_or_else(|| detector.fallback().encoding());
+    let mut interned: BTreeMap<Arc<[u8]>, Arc<str>> = BTreeMap::new();
+    ArchiveNames {
+        encoding,
+        decided: decided.is_some(),
+        complete,
+        names: names
+            .into_iter()
+            .map(|(offset, raw)| {
+                if let Some((raw, name)) = interned.get_key_value(&raw[..]) {
+                    return ArchiveName {
+                        offset,
+                        raw: Arc::clone(raw),
+                        name: Arc::clone(name),
+                    };
+                }
+                let name: Arc<str> = Arc::from(&*encoding.decode_without_bom_handling(&raw).0);
+                let raw: Arc<[u8]> = Arc::from(raw);
+                if intern {
+                    interned.insert(Arc::clone(&raw), Arc::clone(&name));
+                }
+                ArchiveName { offset, raw, name }
+            })
+            .collect(),
+    }
+}
+
+/// Returns `field` up to its first zero byte.
+fn until_nul(field: &[u8]) -> &[u8] {
+    match field.iter().position(|&b| b == 0) {
+        Some(len) => &field[..len],
+        None => field,
+    }
+}
+
+/// Parses a numeric tar header field: octal digits surrounded by spaces and
+/// zero bytes or, for large values, the base-256 encoding of GNU tar.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field.first()? & 0x80 != 0 {
+        let mut value = u64::from(field[0] & 0x7F);
+        for &byte in &field[1..] {
+            value = value.checked_mul(256)? | u64::from(byte);
+        }
+        return Some(value);
+    }
+    let digits = until_nul(field);
+    let digits = digits
+        .iter()
+        .position(|&b| b != b' ')
+        .map_or(&digits[..0], |start| &digits[start..]);
+    let mut value = 0u64;
+    for &byte in digits.iter().take_while(|&&b| b != b' ') {
+        if !(b'0'..=b'7').contains(&byte) {
+            return None;
+        }
+        value = value.checked_mul(8)? + u64::from(byte - b'0');
+    }
+    Some(value)
+}
+
+/// Returns the value of the `path` record of a PAX extended header.
+fn pax_path(data: &[u8]) -> Option<&[u8]> {
+    let mut rest = data;
+    let mut path = None;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let len = core::str::from_utf8(&rest[..space])
+            .ok()?
+            .parse::<usize>()
+            .ok()?;
+        if len <= space || len > rest.len() {
+            return None;
+        }
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n")?;
+        let equals = record.iter().position(|&b| b == b'=')?;
+        if &record[..equals] == b"path" {
+            path = Some(&record[equals + 1..]);
+        }
+        rest = &rest[len..];
+    }
+    path
+}
+
+/// The raw names of a tar archive with their offsets and whether the end
+/// of the archive was reached.
+fn tar_names(archive: &[u8]) -> (Vec<(u64, Vec<u8>)>, bool) {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    let mut pending: Option<(usize, Vec<u8>)> = None;
+    while pos + TAR_BLOCK <= archive.len() {
+        let header = &archive[pos..pos + TAR_BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            return (names, true);
+        }
+        let size = match tar_number(&header[124..136]) {
+            Some(size) => size,
+            None => break,
+        };
+        let data_start = pos + TAR_BLOCK;
+        let data_len = match usize::try_from(size) {
+            Ok(len) if len <= archive.len() - data_start => len,
+            _ => break,
+        };
+        let data = &archive[data_start..data_start + data_len];
+        let first = pending.as_ref().map_or(pos, |&(first, _)| first);
+        match header[156] {
+            b'L' => pending = Some((first, until_nul(data).to_vec())),
+            b'x' => {
+                if let Some(path) = pax_path(data) {
+                    pending = Some((first, path.to_vec()));
+                } else if pending.is_none() {
+                    pending = Some((first, Vec::new()));
+                }
+            }
+            // Long link names, global headers and other metadata entries
+            // that don't name an entry.
+            b'K' | b'g' | b'V' => {}
+            _ => {
+                let name = match pending.take() {
+                    Some((_, name)) if !name.is_empty() => name,
+                    _ => {
+                        let mut name = Vec::new();
+                        let prefix = until_nul(&header[345..500]);
+                        if &header[257..263] == b"ustar\0" && !prefix.is_empty() {
+                            name.extend_from_slice(prefix);
+                            name.push(b'/');
+                        }
+                        name.extend_from_slice(until_nul(&header[..100]));
+                        name
+                    }
+                };
+                names.push((first as u64, name));
+            }
+        }
+        pos = data_start + data_len.next_multiple_of(TAR_BLOCK);
+    }
+    (names, false)
+}
+
+/// Parses a fixed-width ASCII number of a cpio header in `radix`.
+fn cpio_number(field: &[u8], radix: u32) -> Option<usize> {
+    usize::from_str_radix(core::str::from_utf8(field).ok()?, radix).ok()
+}
+
+/// The raw names of a cpio archive with their offsets and whether the
+/// trailer was reached.
+fn cpio_names(archive: &[u8]) -> (Vec<(u64, Vec<u8>)>, bool) {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    loop {
+        let rest = &archive[pos..];
+        // The header length, the offsets and widths of the name size and
+        // file size, their radix and the alignment.
+        let (header_len, name_size, file_size, radix, align) = match rest.get(..6) {
+            Some(b"070707") => (76, (59, 6), (65, 11), 8, 1),
+            Some(b"070701") | Some(b"070702") => (110, (94, 8), (54, 8), 16, 4),
+            _ => break,
+        };
+        if rest.len() < header_len {
+            break;
+        }
+        let name_len = cpio_number(&rest[name_size.0..name_size.0 + name_size.1], radix);
+        let file_len = cpio_number(&rest[file_size.0..file_size.0 + file_size.1], radix);
+        let (name_len, file_len) = match (name_len, file_len) {
+            (Some(name_len), Some(file_len)) => (name_len, file_len),
+            _ => break,
+        };
+        let name = match rest.get(header_len..header_len + name_len) {
+            Some(name) => until_nul(name),
+            None => break,
+        };
+        if name == CPIO_TRAILER {
+            return (names, true);
+        }
+        names.push((pos as u64, name.to_vec()));
+        let name_end = (header_len + name_len).next_multiple_of(align);
+        let next = match name_end.checked_add(file_len) {
+            Some(end) => end.next_multiple_of(align),
+            None => break,
+        };
+        if next > rest.len() {
+            break;
+        }
+        pos += next;
+    }
+    (names, false)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    /// A tar header of `kind` for `name` with `size` bytes of data.
+    fn tar_header(name: &[u8], kind: u8, size: usize) -> Vec<u8> {
+        let mut header = vec![0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name);
+        let size = format!("{:011o}\0", size);
+        header[124..136].copy_from_slice(size.as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    /// Appends `data` padded to whole blocks.
+    fn push_data(archive: &mut Vec<u8>, data: &[u8]) {
+        archive.extend_from_slice(data);
+        let padded = archive.len().next_multiple_of(TAR_BLOCK);
+        archive.resize(padded, 0);
+    }
+
+    #[test]
+    fn test_tar_names() {
+        let mut archive = Vec::new();
+        // "あ" in EUC-JP, which decides, in a GNU long name.
+        archive.extend(tar_header(b"././@LongLink", b'L', 6));
+        push_data(&mut archive, b"\xA4\xA2.txt");
+        archive.extend(tar_header(b"truncated", b'0', 3));
+        push_data(&mut archive, b"abc");
+        // "日本" in EUC-JP in a PAX path.
+        let pax = b"14 path=\xC6\xFC\xCB\xDC/\n";
+        archive.extend(tar_header(b"PaxHeader", b'x', pax.len()));
+        push_data(&mut archive, pax);
+        archive.extend(tar_header(b"short", b'5', 0));
+        archive.extend(tar_header(b"plain", b'0', 0));
+        archive.extend(vec![0u8; 2 * TAR_BLOCK]);
+        let names = detect_tar_names(&archive, true);
+        assert_eq!(names.encoding(), EUC_JP);
+        assert!(names.decided());
+        assert!(names.complete());
+        let decoded: Vec<&str> = names.names().iter().map(|name| name.name()).collect();
+        assert_eq!(decoded, ["あ.txt", "日本/", "plain"]);
+        assert_eq!(names.names()[1].offset(), 4 * TAR_BLOCK as u64);
+        assert_eq!(names.names()[1].raw(), b"\xC6\xFC\xCB\xDC/");
+        // The same tree stored again, as by an appended archive.
+        let mut appended = archive[..archive.len() - 2 * TAR_BLOCK].to_vec();
+        appended.extend_from_slice(&archive);
+        let names = detect_tar_names_interned(&appended, true);
+        assert_eq!(names.names().len(), 6);
+        assert_eq!(
+            names.names()[0],
+            ArchiveName {
+                offset: 0,
+                ..names.names()[3].clone()
+            }
+        );
+        assert!(Arc::ptr_eq(&names.names()[1].name, &names.names()[4].name));
+        assert!(Arc::ptr_eq(&names.names()[1].raw, &names.names()[4].raw));
+        let names = detect_tar_names(&appended, true);
+        assert!(!Arc::ptr_eq(&names.names()[1].name, &names.names()[4].name));
+        let truncated = detect_tar_names(&archive[..TAR_BLOCK + 10], true);
+        assert!(!truncated.complete());
+        assert!(truncated.names().is_empty());
+    }
+
+    #[test]
+    fn test_cpio_names() {
+        let mut archive = Vec::new();
+        for (name, data) in [(&b"\x82\xA0\0"[..], &b"xyz"[..]), (b"TRAILER!!!\0", b"")].iter() {
+            archive.extend_from_slice(
+                format!(
+                    "070701{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}",
+                    0, 0o100644, 0, 0, 1, 0, data.len(), 0, 0, 0, 0, name.len(), 0
+                )
+                .as_bytes(),
+            );
+            archive.extend_from_slice(name);
+            archive.resize(archive.len().next_multiple_of(4), 0);
+            archive.extend_from_slice(data);
+            archive.resize(archive.len().next_multiple_of(4), 0);
+        }
+        let names = detect_cpio_names(&archive, true);
+        assert_eq!(names.encoding(), SHIFT_JIS);
+        assert!(names.complete());
+        assert_eq!(names.names().len(), 1);
+        assert_eq!(names.names()[0].name(), "あ");
+        let mut odc = Vec::new();
+        odc.extend_from_slice(b"070707");
+        odc.extend_from_slice(&[b'0'; 53]);
+        odc.extend_from_slice(b"00000400000000000");
+        odc.extend_from_slice(b"abc\0");
+        let names = detect_cpio_names(&odc, true);
+        assert!(!names.complete());
+        assert_eq!(names.names()[0].name(), "abc");
+        assert!(!names.decided());
+    }
+}
@@ -0,0 +1,315 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::SHIFT_JIS;
+
+use crate::race::Racer;
+use crate::raw::EucJpMachine;
+use crate::raw::EucJpValidator;
+use crate::raw::ShiftJisMachine;
+use crate::raw::ShiftJisValidator;
+use crate::raw::Step;
+use crate::state::StateReader;
+use crate::state::StateWriter;
+use crate::Detector;
+
+/// What the Shift_JIS and EUC-JP candidates of `Detector` are backed by.
+/// See `Detector::set_backend()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Backend {
+    /// `encoding_rs` decoders (`raw::ShiftJisMachine` and
+    /// `raw::EucJpMachine`). This is the default.
+    #[default]
+    Decoder,
+    /// The hand-written validators (`raw::ShiftJisValidator` and
+    /// `raw::EucJpValidator`), which carry their own tables of mapped byte
+    /// pairs and don't decode anything, so they work with an `encoding_rs`
+    /// whose decode tables have been trimmed.
+    Validator,
+}
+
+/// Byte sequences spread over the decode tables of Shift_JIS and what they
+/// decode to: the ideographic space, the first and last Level 1 kanji, the
+/// first Level 2 kanji and the NEC and IBM extensions.
+const SHIFT_JIS_PROBES: [(&[u8], char); 6] = [
+    (b"\x81\x40", '\u{3000}'),
+    (b"\x88\x9F", '\u{4E9C}'),
+    (b"\x98\x72", '\u{8155}'),
+    (b"\x98\x9F", '\u{5F0C}'),
+    (b"\x87\x40", '\u{2460}'),
+    (b"\xFA\x40", '\u{2170}'),
+];
+
+/// Byte sequences spread over the decode tables of EUC-JP and what they
+/// decode to: the ideographic space, the first and last Level 1 kanji, the
+/// first Level 2 kanji, half-width katakana and the first JIS X 0212 kanji.
+const EUC_JP_PROBES: [(&[u8], char); 6] = [
+    (b"\xA1\xA1", '\u{3000}'),
+    (b"\xB0\xA1", '\u{4E9C}'),
+    (b"\xCF\xD3", '\u{8155}'),
+    (b"\xD0\xA1", '\u{5F0C}'),
+    (b"\x8E\xB1", '\u{FF71}'),
+    (b"\x8F\xB0\xA1", '\u{4E02}'),
+];
+
+/// Whether `encoding` decodes each probe to the expected character.
+fn decodes(encoding: &'static Encoding, probes: &[(&[u8], char)]) -> bool {
+    probes.iter().all(|&(bytes, expected)| {
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut output = [0u8; 4];
+        let (_, read, written) =
+            decoder.decode_to_utf8_without_replacement(bytes, &mut output, true);
+        read == bytes.len() && output[..written] == *expected.encode_utf8(&mut [0u8; 4]).as_bytes()
+    })
+}
+
+/// What the linked `encoding_rs` can decode, for embedders that build it
+/// with reduced data. See `capabilities()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    shift_jis: bool,
+    euc_jp: bool,
+}
+
+impl Capabilities {
+    /// Whether `encoding_rs` decodes Shift_JIS with its full tables.
+    pub fn shift_jis(&self) -> bool {
+        self.shift_jis
+    }
+
+    /// Whether `encoding_rs` decodes EUC-JP with its full tables, including
+    /// JIS X 0212.
+    pub fn euc_jp(&self) -> bool {
+        self.euc_jp
+    }
+
+    /// `Backend::Decoder` if both encodings decode fully and
+    /// `Backend::Validator` otherwise.
+    pub fn backend(&self) -> Backend {
+        if self.shift_jis && self.euc_jp {
+            Backend::Decoder
+        } else {
+            Backend::Validator
+        }
+    }
+}
+
+/// Checks at run time whether the linked `encoding_rs` decodes Shift_JIS
+/// and EUC-JP in full by decoding a few characters from across their
+/// tables.
+///
+/// With trimmed tables, the decoder-backed candidates would reject valid
+/// input, so pass `Capabilities::backend()` to `Detector::set_backend()`
+/// (or `DetectorBuilder::backend()`) to get the same verdicts as with full
+/// tables. The check decodes a dozen characters, so do it once rather than
+/// per detector.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        shift_jis: decodes(SHIFT_JIS, &SHIFT_JIS_PROBES),
+        euc_jp: decodes(EUC_JP, &EUC_JP_PROBES),
+    }
+}
+
+/// Forwards a method call to whichever candidate a `$name` holds.
+macro_rules! forward {
+    ($name:ident, $self:ident, $candidate:ident => $call:expr) => {
+        match $self {
+            $name::Decoder($candidate) => $call,
+            $name::Validator($candidate) => $call,
+        }
+    };
+}
+
+/// Generates a candidate of `Detector` that is backed by either `$machine`
+/// or `$validator` according to a `Backend`.
+macro_rules! candidate {
+    ($name:ident, $machine:ident, $validator:ident) => {
+        pub(crate) enum $name {
+            Decoder($machine),
+            Validator($validator),
+        }
+
+        impl $name {
+            pub(crate) fn new() -> Self {
+                $name::Decoder($machine::new())
+            }
+
+            pub(crate) fn for_backend(backend: Backend, mid_stream: bool) -> Self {
+                match (backend, mid_stream) {
+                    (Backend::Decoder, false) => $name::Decoder($machine::new()),
+                    (Backend::Decoder, true) => $name::Decoder($machine::new_mid_stream()),
+                    (Backend::Validator, false) => $name::Validator($validator::new()),
+                    (Backend::Validator, true) => {
+                        $name::Validator($validator::new_mid_stream())
+                    }
+                }
+            }
+
+            #[inline(always)]
+            pub(crate) fn push(&mut self, byte: u8) -> Step {
+                forward!($name, self, candidate => candidate.push(byte))
+            }
+
+            pub(crate) fn finish(&mut self) -> Step {
+                forward!($name, self, candidate => candidate.finish())
+            }
+
+            pub(crate) fn rejected_by_half_width_katakana(&self) -> bool {
+                forward!($name, self, candidate => candidate.rejected_by_half_width_katakana())
+            }
+
+            pub(crate) fn pending(&self) -> usize {
+                forward!($name, self, candidate => candidate.pending())
+            }
+
+            pub(crate) fn resync(&mut self, skipped: &[u8]) {
+                forward!($name, self, candidate => candidate.resync(skipped))
+            }
+
+            pub(crate) fn discard_pending(&mut self) {
+                forward!($name, self, candidate => candidate.discard_pending())
+            }
+
+            pub(crate) fn set_defer_half_width_katakana(&mut self, defer: bool) {
+                forward!($name, self, candidate => candidate.set_defer_half_width_katakana(defer))
+            }
+
+            pub(crate) fn set_reject_half_width_katakana(&mut self, reject: bool) {
+                forward!($name, self, candidate => candidate.set_reject_half_width_katakana(reject))
+            }
+
+            pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+                forward!($name, self, candidate => candidate.write_state(writer))
+            }
+
+            pub(crate) fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+                forward!($name, self, candidate => candidate.read_state(reader))
+            }
+
+            /// The decoder to keep decoding with after the candidate has
+            /// been decided.
+            pub(crate) fn take_decoder(&mut self) -> Decoder {
+                match self {
+                    $name::Decoder(machine) => machine.take_decoder(),
+                    $name::Validator(validator) => validator.decoder(),
+                }
+            }
+
+            /// The non-ASCII character that the last byte completed, if any.
+            /// Always `None` for the validator, which doesn't decode.
+            pub(crate) fn last_char(&self) -> Option<char> {
+                match self {
+                    $name::Decoder(machine) => machine.last_char(),
+                    $name::Validator(_) => None,
+                }
+            }
+        }
+
+        impl Racer for $name {
+            #[inline(always)]
+            fn push(&mut self, byte: u8) -> Step {
+                $name::push(self, byte)
+            }
+
+            fn finish(&mut self) -> Step {
+                $name::finish(self)
+            }
+
+            fn pending(&self) -> usize {
+                $name::pending(self)
+            }
+
+            fn resync(&mut self, skipped: &[u8]) {
+                $name::resync(self, skipped)
+            }
+        }
+    };
+}
+
+candidate!(ShiftJisCandidate, ShiftJisMachine, ShiftJisValidator);
+candidate!(EucJpCandidate, EucJpMachine, EucJpValidator);
+
+impl EucJpCandidate {
+    pub(crate) fn set_jis_x_0212_decisive(&mut self, decisive: bool) {
+        forward!(EucJpCandidate, self, candidate => candidate.set_jis_x_0212_decisive(decisive))
+    }
+}
+
+impl Detector {
+    /// The backend of the Shift_JIS and EUC-JP candidates.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Sets what the Shift_JIS and EUC-JP candidates are backed by.
+    /// Defaults to `Backend::Decoder`. Call before the first call to
+    /// `feed()`.
+    ///
+    /// Both backends reach the same verdicts, so `Backend::Validator` is a
+    /// degraded mode for an `encoding_rs` whose decode tables have been
+    /// trimmed (see `capabilities()`) rather than a different detector. What
+    /// it degrades: `set_char_rule()` has no characters to run past the
+    /// rule, and the validation mode of `set_validation_mode()` keeps decoding
+    /// with the trimmed `encoding_rs` after the decision, so it may report
+    /// malformed sequences in valid input.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+        self.reset_candidates();
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Verdict;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = capabilities();
+        assert!(capabilities.shift_jis() && capabilities.euc_jp());
+        assert_eq!(capabilities.backend(), Backend::Decoder);
+        // Probes that a trimmed table would fail.
+        assert!(!decodes(EUC_JP, &[(b"\xB0\xA1", '\u{3000}')]));
+        assert!(!decodes(SHIFT_JIS, &[(b"\x88", '\u{4E9C}')]));
+    }
+
+    #[test]
+    fn test_validator_backend() {
+        let inputs: [&[u8]; 4] = [b"\x82\xA0", b"\xA4\xA2", b"\xB1", b"\x8F\xB0\xA1"];
+        for &input in inputs.iter() {
+            let mut decoder_backed = Detector::new(true);
+            let mut detector = Detector::new(true);
+            detector.set_backend(Backend::Validator);
+            assert_eq!(detector.backend(), Backend::Validator);
+            assert_eq!(
+                detector.feed_verdict(input, true),
+                decoder_backed.feed_verdict(input, true)
+            );
+        }
+        let mut detector = Detector::new(true);
+        detector.set_backend(Backend::Validator);
+        detector.set_validation_mode(true);
+        // The decoder picks up the lead byte that the validator holds.
+        assert_eq!(
+            detector.feed_verdict(b"\xE0\xA1\x88", false),
+            Some(Verdict::ShiftJis)
+        );
+        assert_eq!(
+            detector.feed_verdict(b"\x9F", true),
+            Some(Verdict::ShiftJis)
+        );
+        assert_eq!(detector.validation_statistics().unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Usage: `shift-or-euc [--no-iso-2022-jp] [--convert | --in-place] [FILE]`
+//! or `shift-or-euc [--no-iso-2022-jp] [--json] [--progress stderr]
+//! [--resume-from JOURNAL] PATH...`
+//!
+//! Reads `FILE` (or stdin if `FILE` is missing or `-`) and prints the name
+//! of the detected encoding or `Undecided`. With `--convert`, writes the
+//! contents converted to UTF-8 to stdout instead. With `--in-place`,
+//! replaces `FILE` with its contents converted to UTF-8, unless the
+//! conversion would be lossy or `FILE` already is UTF-8. Undecided input
+//! is converted from Shift_JIS.
+//!
+//! Given several paths, a directory or any of `--json`, `--progress` and
+//! `--resume-from`, walks the paths instead (see `shift_or_euc::scan`) and
+//! reports each file as soon as it completes: one `path: result` line, or
+//! with `--json` one JSON object per line (JSON Lines). `--progress stderr`
+//! counts the files on stderr. With `--resume-from`, the scan resumes from
+//! and appends to `JOURNAL`, and the files reported from the journal are
+//! marked `"cached":true` in JSON.
+//!
+//! Exits with 1 for bad arguments, 2 for I/O errors (after the walk, if
+//! any file couldn't be read) and 3 if `--in-place` refused to replace a
+//! file.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use shift_or_euc::scan::FileReport;
+use shift_or_euc::scan::Journal;
+use shift_or_euc::scan::Scanner;
+use shift_or_euc::Detector;
+
+#[derive(PartialEq)]
+enum Mode {
+    Detect,
+    Convert,
+    InPlace,
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: shift-or-euc [--no-iso-2022-jp] [--convert | --in-place] [FILE]");
+    eprintln!("       shift-or-euc [--no-iso-2022-jp] [--json] [--progress stderr]");
+    eprintln!("                    [--resume-from JOURNAL] PATH...");
+    std::process::exit(1);
+}
+
+fn fail(message: &str, path: Option<&Path>, code: i32) -> ! {
+    match path {
+        Some(path) => eprintln!("shift-or-euc: {}: {}", path.display(), message),
+        None => eprintln!("shift-or-euc: {}", message),
+    }
+    std::process::exit(code);
+}
+
+fn main() {
+    let mut allow_2022 = true;
+    let mut mode = Mode::Detect;
+    let mut json = false;
+    let mut progress = false;
+    let mut journal: Option<OsString> = None;
+    let mut paths: Vec<OsString> = Vec::new();
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--no-iso-2022-jp" {
+            allow_2022 = false;
+        } else if arg == "--convert" || arg == "--in-place" {
+            if mode != Mode::Detect {
+                usage();
+            }
+            mode = if arg == "--convert" {
+                Mode::Convert
+            } else {
+                Mode::InPlace
+            };
+        } else if arg == "--json" {
+            json = true;
+        } else if arg == "--progress" {
+            if !matches!(args.next(), Some(ref target) if target == "stderr") {
+                fail("--progress only supports stderr", None, 1);
+            }
+            progress = true;
+        } else if arg == "--resume-from" {
+            match args.next() {
+                Some(path) => journal = Some(path),
+                None => fail("--resume-from needs a journal", None, 1),
+            }
+        } else if matches!(arg.to_str(), Some(arg) if arg.starts_with("--")) {
+            usage();
+        } else {
+            paths.push(arg);
+        }
+    }
+    let walk = json
+        || progress
+        || journal.is_some()
+        || paths.len() > 1
+        || matches!(paths.first(), Some(path) if Path::new(path).is_dir());
+    if walk {
+        if mode != Mode::Detect || paths.is_empty() || paths.iter().any(|path| path == "-") {
+            usage();
+        }
+        let paths = paths.into_iter().map(PathBuf::from).collect();
+        scan(paths, allow_2022, json, progress, journal.as_deref());
+        return;
+    }
+    let path = paths.pop().filter(|path| path != "-").map(PathBuf::from);
+    if mode == Mode::InPlace && path.is_none() {
+        fail("--in-place needs a file", None, 1);
+    }
+    let mut bytes = Vec::new();
+    let read = match &path {
+        Some(path) => File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)),
+        None => std::io::stdin().read_to_end(&mut bytes),
+    };
+    if let Err(err) = read {
+        fail(&err.to_string(), path.as_deref(), 2);
+    }
+    let outcome = Detector::new(allow_2022).feed_with_outcome(&bytes, true);
+    if mode == Mode::Detect {
+        match outcome.verdict() {
+            Some(verdict) => println!("{}", verdict.name()),
+            None => println!("Undecided"),
+        }
+        return;
+    }
+    let (text, malformed) = outcome
+        .guess()
+        .encoding()
+        .decode_without_bom_handling(&bytes);
+    if mode == Mode::Convert {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        if let Err(err) = out.write_all(text.as_bytes()).and_then(|_| out.flush()) {
+            fail(&err.to_string(), None, 2);
+        }
+        return;
+    }
+    let path = path.unwrap();
+    if !bytes.is_ascii() && std::str::from_utf8(&bytes).is_ok() {
+        // Probably converted already.
+        fail("not replaced: already UTF-8", Some(&path), 3);
+    }
+    if malformed {
+        fail(
+            &format!("not replaced: malformed as {}", outcome.guess().name()),
+            Some(&path),
+            3,
+        );
+    }
+    if let Cow::Borrowed(_) = text {
+        // ASCII reads the same in UTF-8.
+        return;
+    }
+    if let Err(err) = replace(&path, text.as_bytes()) {
+        fail(&err.to_string(), Some(&path), 2);
+    }
+}
+
+/// Writes `contents` next to `path` and renames the result over `path`,
+/// keeping the permissions, so that an interruption leaves either the old
+/// or the new file.
+fn replace(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let permissions = std::fs::metadata(path)?.permissions();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".shift-or-euc.tmp");
+    let temporary = PathBuf::from(temporary);
+    let result = File::create(&temporary).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::set_permissions(&temporary, permissions)?;
+        std::fs::rename(&temporary, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Walks `paths`, reporting each file on stdout.
+fn scan(
+    paths: Vec<PathBuf>,
+    allow_2022: bool,
+    json: bool,
+    progress: bool,
+    journal: Option<&OsStr>,
+) {
+    let mut scanner = Scanner::new(paths, allow_2022);
+    if let Some(journal) = journal {
+        match Journal::open(Path::new(journal)) {
+            Ok(journal) => scanner.set_journal(Some(journal)),
+            Err(err) => fail(&err.to_string(), Some(Path::new(journal)), 2),
+        }
+    }
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+    for (count, report) in scanner.enumerate() {
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => fail(&err.to_string(), journal.map(Path::new), 2),
+        };
+        if progress {
+            eprintln!("[{}] {}", count + 1, report.path().display());
+        }
+        failed |= report.error().is_some();
+        let line = if json {
+            json_line(&report)
+        } else {
+            text_line(&report)
+        };
+        if let Err(err) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+            fail(&err.to_string(), None, 2);
+        }
+    }
+    if failed {
+        std::process::exit(2);
+    }
+}
+
+/// Formats `report` as `path: result`.
+fn text_line(report: &FileReport) -> String {
+    let path = report.path().to_string_lossy();
+    match (report.error(), report.encoding()) {
+        (Some(error), _) => format!("{}: Error: {}", path, error),
+        (None, Some(encoding)) => format!("{}: {}", path, encoding),
+        (None, None) => format!("{}: Undecided", path),
+    }
+}
+
+/// Formats `report` as a JSON object.
+fn json_line(report: &FileReport) -> String {
+    let mut line = String::from("{\"path\":");
+    push_json_string(&mut line, &report.path().to_string_lossy());
+    let push_optional = |line: &mut String, value: Option<&str>| match value {
+        Some(value) => push_json_string(line, value),
+        None => line.push_str("null"),
+    };
+    match report.error() {
+        None => {
+            line.push_str(",\"encoding\":");
+            push_optional(&mut line, report.encoding());
+            line.push_str(",\"reason\":");
+            push_optional(&mut line, report.reason());
+            line.push_str(&format!(",\"examined\":{}", report.examined_len()));
+        }
+        Some(error) => {
+            line.push_str(",\"error\":");
+            push_json_string(&mut line, error);
+        }
+    }
+    if report.cached() {
+        line.push_str(",\"cached\":true");
+    }
+    line.push('}');
+    line
+}
+
+/// Appends `s` to `json` as a JSON string literal.
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
@@ -0,0 +1,144 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+/// The longest BOM, the UTF-8 one.
+const MAX_BOM_LEN: usize = 3;
+
+/// Runs the detector on `buffer` as the complete stream in one call, except
+/// that a UTF-8, UTF-16LE or UTF-16BE byte order mark at the start decides
+/// `UTF_8`, `UTF_16LE` or `UTF_16BE` without running the detector.
+/// `allow_2022` has the same meaning as for `Detector::new()`.
+///
+/// The detector by itself misdetects BOM-bearing Unicode input as one of the
+/// legacy encodings. As in the Encoding Standard, the BOM takes precedence
+/// over everything else.
+pub fn detect_with_bom(buffer: &[u8], allow_2022: bool) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(buffer) {
+        return Some(encoding);
+    }
+    Detector::new(allow_2022).feed(buffer, true)
+}
+
+/// A detector that checks for a byte order mark before running a `Detector`,
+/// the streaming counterpart of `detect_with_bom()`.
+///
+/// Up to two bytes are held back while they may be the start of a BOM.
+pub struct BomDetector {
+    detector: Detector,
+    held: [u8; MAX_BOM_LEN - 1],
+    held_len: usize,
+    sniffing: bool,
+}
+
+impl BomDetector {
+    /// Instantiates the detector. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        BomDetector::with_detector(Detector::new(allow_2022))
+    }
+
+    /// Instantiates the detector with a configured detector that hasn't been
+    /// fed yet.
+    pub fn with_detector(detector: Detector) -> Self {
+        BomDetector {
+            detector,
+            held: [0; MAX_BOM_LEN - 1],
+            held_len: 0,
+            sniffing: true,
+        }
+    }
+
+    /// The detector, e.g. for its `outcome()`. It hasn't seen any input if
+    /// a BOM decided.
+    pub fn detector(&self) -> &Detector {
+        &self.detector
+    }
+
+    /// Feeds bytes to the detector. A BOM may be split across calls. The
+    /// return value and the rules about calling again are the same as for
+    /// `Detector::feed()`, except that the return value may also be
+    /// `UTF_8`, `UTF_16LE` or `UTF_16BE` for a BOM.
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        if self.sniffing {
+            let mut prefix = [0u8; MAX_BOM_LEN];
+            prefix[..self.held_len].copy_from_slice(&self.held[..self.held_len]);
+            let taken = buffer.len().min(MAX_BOM_LEN - self.held_len);
+            prefix[self.held_len..self.held_len + taken].copy_from_slice(&buffer[..taken]);
+            let prefix = &prefix[..self.held_len + taken];
+            if let Some((encoding, _)) = Encoding::for_bom(prefix) {
+                self.sniffing = false;
+                return Some(encoding);
+            }
+            if !last && is_bom_prefix(prefix) {
+                self.held[..prefix.len()].copy_from_slice(prefix);
+                self.held_len = prefix.len();
+                return None;
+            }
+            self.sniffing = false;
+            if self.held_len != 0 {
+                let held = self.held;
+                if let Some(encoding) = self.detector.feed(&held[..self.held_len], false) {
+                    return Some(encoding);
+                }
+            }
+        }
+        self.detector.feed(buffer, last)
+    }
+}
+
+/// Whether `prefix` may be the start of a BOM but isn't a whole one.
+pub(crate) fn is_bom_prefix(prefix: &[u8]) -> bool {
+    matches!(prefix, [] | [0xEF] | [0xEF, 0xBB] | [0xFE] | [0xFF])
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+    use encoding_rs::UTF_16BE;
+    use encoding_rs::UTF_16LE;
+    use encoding_rs::UTF_8;
+
+    #[test]
+    fn test_detect_with_bom() {
+        // "日本" in UTF-8, whose BOM the detector alone takes for EUC-JP.
+        let utf_8 = b"\xEF\xBB\xBF\xE6\x97\xA5\xE6\x9C\xAC";
+        assert_eq!(crate::detect(utf_8, true), Some(EUC_JP));
+        assert_eq!(detect_with_bom(utf_8, true), Some(UTF_8));
+        assert_eq!(detect_with_bom(b"\xFF\xFE\xE5\x65", true), Some(UTF_16LE));
+        assert_eq!(detect_with_bom(b"\xFE\xFF\x65\xE5", true), Some(UTF_16BE));
+        assert_eq!(detect_with_bom(b"\xA4\xA2", true), Some(EUC_JP));
+    }
+
+    #[test]
+    fn test_bom_detector() {
+        let mut detector = BomDetector::new(true);
+        assert_eq!(detector.feed(b"", false), None);
+        assert_eq!(detector.feed(b"\xEF", false), None);
+        assert_eq!(detector.feed(b"\xBB", false), None);
+        assert_eq!(detector.feed(b"\xBF\xE6", false), Some(UTF_8));
+        // 0xFE 0xA1 isn't a BOM, so the held 0xFE is fed to the detector,
+        // which it decides.
+        let mut detector = BomDetector::new(true);
+        assert_eq!(detector.feed(b"\xFE", false), None);
+        assert_eq!(detector.feed(b"\xA1\xA1", false), Some(EUC_JP));
+        assert_eq!(detector.detector().outcome().examined_len(), 1);
+        // The start of a BOM at the end of the stream is truncated EUC-JP.
+        let mut detector = BomDetector::new(true);
+        assert_eq!(detector.feed(b"\xEF", true), Some(SHIFT_JIS));
+    }
+}
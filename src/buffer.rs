@@ -0,0 +1,162 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The error returned when a `Buffer` cannot hold more bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("buffer capacity exceeded")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CapacityError {}
+
+/// Backing storage for the bytes that buffering types hold back while the
+/// detector is undecided.
+///
+/// Implemented for `Vec<u8>` (growable, global allocator) and for
+/// `SliceBuffer` (fixed, caller-provided memory). Implement it for other
+/// storage, e.g. a vector from an arena or bump allocator, to control where
+/// the held bytes live.
+///
+/// The buffering types take a `Buffer` through `with_buffer()`
+/// (`DetectingDecoder`, `ProgressiveDecoder`, `LineDecoder`,
+/// `DecodedChars` and `io::DetectingReader`),
+/// `pipeline::PipelineBuilder::sink_with_buffer()` and
+/// `io::sniff_reader_with_buffer()`, and default to `Vec<u8>`. What
+/// happens when the storage is full depends on the type. The replay buffer
+/// of `Detector::set_require_certain_iso_2022_jp()` in validation mode is
+/// always a `Vec<u8>`.
+pub trait Buffer {
+    /// The bytes currently held.
+    fn as_slice(&self) -> &[u8];
+
+    /// The bytes currently held, for overwriting in place.
+    fn as_mut_slice(&mut self) -> &mut [u8];
+
+    /// Appends `data` or returns an error without appending anything if the
+    /// storage cannot hold all of it.
+    fn try_extend(&mut self, data: &[u8]) -> Result<(), CapacityError>;
+
+    /// Shortens the bytes held to `len` or extends them to `len` with zeros,
+    /// or returns an error without changing anything if the storage cannot
+    /// hold `len` bytes.
+    fn try_resize(&mut self, len: usize) -> Result<(), CapacityError>;
+
+    /// Removes all bytes.
+    fn clear(&mut self);
+}
+
+impl Buffer for Vec<u8> {
+    fn as_slice(&self) -> &[u8] {
+        &self[..]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self[..]
+    }
+
+    fn try_extend(&mut self, data: &[u8]) -> Result<(), CapacityError> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn try_resize(&mut self, len: usize) -> Result<(), CapacityError> {
+        self.resize(len, 0);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+}
+
+/// A `Buffer` in caller-provided fixed-size memory.
+pub struct SliceBuffer<'a> {
+    storage: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceBuffer<'a> {
+    /// Uses `storage` as the backing memory. The capacity is the length of
+    /// `storage`.
+    pub fn new(storage: &'a mut [u8]) -> Self {
+        SliceBuffer { storage, len: 0 }
+    }
+
+    /// The number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+}
+
+impl Buffer for SliceBuffer<'_> {
+    fn as_slice(&self) -> &[u8] {
+        &self.storage[..self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.storage[..self.len]
+    }
+
+    fn try_extend(&mut self, data: &[u8]) -> Result<(), CapacityError> {
+        let end = self.len + data.len();
+        if end > self.storage.len() {
+            return Err(CapacityError);
+        }
+        self.storage[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+
+    fn try_resize(&mut self, len: usize) -> Result<(), CapacityError> {
+        if len > self.storage.len() {
+            return Err(CapacityError);
+        }
+        if len > self.len {
+            self.storage[self.len..len].fill(0);
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slice_buffer() {
+        let mut storage = [0u8; 4];
+        let mut buffer = SliceBuffer::new(&mut storage[..]);
+        assert_eq!(buffer.try_extend(b"abc"), Ok(()));
+        assert_eq!(buffer.try_extend(b"de"), Err(CapacityError));
+        assert_eq!(buffer.as_slice(), b"abc");
+        buffer.clear();
+        assert_eq!(buffer.try_extend(b"abcd"), Ok(()));
+        assert_eq!(buffer.as_slice(), b"abcd");
+        assert_eq!(buffer.try_resize(5), Err(CapacityError));
+        assert_eq!(buffer.try_resize(1), Ok(()));
+        assert_eq!(buffer.try_resize(3), Ok(()));
+        assert_eq!(buffer.as_slice(), b"a\0\0");
+    }
+}
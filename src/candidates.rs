@@ -0,0 +1,145 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::state::VERDICTS;
+use crate::Detector;
+use crate::Verdict;
+
+/// A set of verdicts, as returned by `Detector::remaining_candidates()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Candidates {
+    bits: u8,
+}
+
+impl Candidates {
+    /// The bit of `verdict`.
+    fn bit(verdict: Verdict) -> u8 {
+        let index = VERDICTS.iter().position(|&v| v == verdict).unwrap_or(0);
+        1 << index
+    }
+
+    /// Adds `verdict` to the set.
+    fn insert(&mut self, verdict: Verdict) {
+        self.bits |= Candidates::bit(verdict);
+    }
+
+    /// Whether `verdict` is in the set.
+    pub fn contains(&self, verdict: Verdict) -> bool {
+        self.bits & Candidates::bit(verdict) != 0
+    }
+
+    /// The number of verdicts in the set.
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Whether the set is exactly Shift_JIS and EUC-JP: the input so far
+    /// rules out everything else but doesn't tell the two apart.
+    pub fn is_legacy_pair(&self) -> bool {
+        self.bits == Candidates::bit(Verdict::ShiftJis) | Candidates::bit(Verdict::EucJp)
+    }
+
+    /// The verdicts in the set in the order Shift_JIS, EUC-JP, ISO-2022-JP,
+    /// UTF-8.
+    pub fn iter(&self) -> impl Iterator<Item = Verdict> {
+        let candidates = *self;
+        VERDICTS
+            .iter()
+            .copied()
+            .filter(move |&verdict| candidates.contains(verdict))
+    }
+}
+
+impl Detector {
+    /// The encodings that the input examined so far hasn't ruled out.
+    ///
+    /// Once the detector has decided, that is just the verdict. Before that,
+    /// Shift_JIS and EUC-JP are both in the set, ISO-2022-JP is while
+    /// `Detector::new()` allowed it and no non-ASCII byte or malformed escape
+    /// sequence has ruled it out, and UTF-8 is while `set_allow_utf8()` is on
+    /// and the non-ASCII input is well-formed UTF-8 (including while UTF-8
+    /// holds back a decision of the other rules, whose verdict is then in the
+    /// set too).
+    ///
+    /// Callers that only need to know whether the stream is ISO-2022-JP can
+    /// stop feeding once `Candidates::is_legacy_pair()` holds.
+    pub fn remaining_candidates(&self) -> Candidates {
+        let mut candidates = Candidates::default();
+        if let Some(verdict) = self.verdict {
+            candidates.insert(verdict);
+            return candidates;
+        }
+        match self.utf8.as_ref().and_then(|utf8| utf8.held_verdict()) {
+            Some(verdict) => candidates.insert(verdict),
+            None => {
+                candidates.insert(Verdict::ShiftJis);
+                candidates.insert(Verdict::EucJp);
+                if !self.iso_2022_jp_disqualified {
+                    candidates.insert(Verdict::Iso2022Jp);
+                }
+            }
+        }
+        if self.utf8.is_some() {
+            candidates.insert(Verdict::Utf8);
+        }
+        candidates
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DetectorBuilder;
+
+    #[test]
+    fn test_remaining_candidates() {
+        let mut detector = Detector::new(true);
+        let candidates = detector.remaining_candidates();
+        assert_eq!(candidates.len(), 3);
+        assert!(candidates.contains(Verdict::Iso2022Jp));
+        assert!(!candidates.contains(Verdict::Utf8));
+        assert!(!candidates.is_legacy_pair());
+        assert_eq!(detector.feed_verdict(b"<html>\xE0", false), None);
+        let candidates = detector.remaining_candidates();
+        assert!(candidates.is_legacy_pair());
+        assert_eq!(
+            candidates.iter().collect::<alloc::vec::Vec<_>>(),
+            [Verdict::ShiftJis, Verdict::EucJp]
+        );
+        assert_eq!(detector.feed_verdict(b"\xA1", true), None);
+        assert!(detector.remaining_candidates().is_legacy_pair());
+        let mut detector = Detector::new(false);
+        assert!(detector.remaining_candidates().is_legacy_pair());
+        // "あ" in EUC-JP.
+        detector.feed_verdict(b"\xA4\xA2", false);
+        let candidates = detector.remaining_candidates();
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates.contains(Verdict::EucJp));
+        // "日本" in UTF-8 decides Shift_JIS, which UTF-8 holds back.
+        let mut detector = DetectorBuilder::new().allow_utf8(true).build();
+        assert_eq!(detector.remaining_candidates().len(), 4);
+        assert_eq!(detector.feed_verdict("日本".as_bytes(), false), None);
+        assert_eq!(
+            detector
+                .remaining_candidates()
+                .iter()
+                .collect::<alloc::vec::Vec<_>>(),
+            [Verdict::ShiftJis, Verdict::Utf8]
+        );
+        assert!(Candidates::default().is_empty());
+    }
+}
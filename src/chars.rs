@@ -0,0 +1,309 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::io::Read;
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+
+use crate::malformed::decode_with_policy;
+use crate::Buffer;
+use crate::ChunkSizes;
+use crate::Detector;
+use crate::MalformedError;
+use crate::MalformedPolicy;
+
+/// An iterator over the characters of a reader in a Japanese legacy
+/// encoding, e.g. as character-level streaming input for a parser.
+///
+/// The bytes are held back until the detector decides (or the reader
+/// reaches its end, in which case the detector's fallback guess is used)
+/// and the characters are then decoded using the decided encoding. Reads
+/// that fail with `io::ErrorKind::Interrupted` are retried. Other read
+/// errors are yielded as they occur, and iteration may continue after
+/// them. The read size grows as set with `set_chunk_sizes()`.
+///
+/// Under `MalformedPolicy::Error`, a malformed sequence is yielded as an
+/// `io::ErrorKind::InvalidData` error that wraps a `MalformedError` after
+/// the characters before it, and iteration ends.
+///
+/// An iterator `with_buffer()` holds the bytes in caller-provided storage
+/// instead. The bytes that the storage cannot hold commit to the fallback
+/// guess, as at the end of the reader.
+pub struct DecodedChars<R: Read, B: Buffer = Vec<u8>> {
+    reader: R,
+    detector: Detector,
+    decoder: Option<Decoder>,
+    malformed_policy: MalformedPolicy,
+    chunk_sizes: ChunkSizes,
+    chunk: Vec<u8>,
+    held: B,
+    text: String,
+    next: usize,
+    position: u64,
+    error: Option<MalformedError>,
+    done: bool,
+}
+
+impl<R: Read> DecodedChars<R> {
+    /// Wraps `reader`. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(reader: R, allow_2022: bool) -> Self {
+        DecodedChars::with_buffer(reader, allow_2022, Vec::new())
+    }
+}
+
+impl<R: Read, B: Buffer> DecodedChars<R, B> {
+    /// The same as `new()` but holds the bytes back in `buffer`.
+    pub fn with_buffer(reader: R, allow_2022: bool, buffer: B) -> Self {
+        DecodedChars {
+            reader,
+            detector: Detector::new(allow_2022),
+            decoder: None,
+            malformed_policy: MalformedPolicy::Replace,
+            chunk_sizes: ChunkSizes::DEFAULT,
+            chunk: Vec::new(),
+            held: buffer,
+            text: String::new(),
+            next: 0,
+            position: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first call to `next()`.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.malformed_policy = policy;
+    }
+
+    /// Sets how much to read from the inner reader at a time. Defaults to
+    /// `ChunkSizes::DEFAULT`. Call before the first call to `next()`.
+    pub fn set_chunk_sizes(&mut self, sizes: ChunkSizes) {
+        self.chunk_sizes = sizes;
+    }
+
+    /// The encoding the characters are decoded from or `None` if the
+    /// detector hasn't decided yet.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.as_ref().map(|decoder| decoder.encoding())
+    }
+
+    /// Reads the next chunk and decodes whatever can be decoded.
+    fn fill(&mut self) -> io::Result<()> {
+        let size = if self.chunk.is_empty() {
+            self.chunk_sizes.first()
+        } else {
+            self.chunk_sizes
+                .next(self.chunk.len(), self.decoder.is_some())
+        };
+        self.chunk.resize(size, 0);
+        let mut chunk = std::mem::take(&mut self.chunk);
+        let result = self.fill_from(&mut chunk);
+        self.chunk = chunk;
+        result
+    }
+
+    /// Reads into `chunk` and decodes whatever can be decoded.
+    fn fill_from(&mut self, chunk: &mut [u8]) -> io::Result<()> {
+        let read = loop {
+            match self.reader.read(chunk) {
+                Ok(read) => break read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        let last = read == 0;
+        let chunk = &chunk[..read];
+        let policy = self.malformed_policy;
+        let decoder = match self.decoder.as_mut() {
+            Some(decoder) => decoder,
+            None => {
+                let encoding = match self.detector.feed(chunk, last) {
+                    Some(encoding) => encoding,
+                    None if last => self.detector.fallback().encoding(),
+                    None => match self.held.try_extend(chunk) {
+                        Ok(()) => return Ok(()),
+                        Err(_) => self.detector.fallback().encoding(),
+                    },
+                };
+                self.decoder
+                    .insert(encoding.new_decoder_without_bom_handling())
+            }
+        };
+        // Replaces the consumed text.
+        self.text.clear();
+        self.next = 0;
+        let held = self.held.as_slice();
+        let mut result = Ok(0);
+        if !held.is_empty() {
+            result = decode_part(
+                decoder,
+                held,
+                &mut self.position,
+                &mut self.text,
+                false,
+                policy,
+            );
+            self.held.clear();
+        }
+        if result.is_ok() {
+            result = decode_part(
+                decoder,
+                chunk,
+                &mut self.position,
+                &mut self.text,
+                last,
+                policy,
+            );
+        }
+        if let Err(err) = result {
+            self.error = Some(err);
+            self.done = true;
+        }
+        if last {
+            self.done = true;
+        }
+        Ok(())
+    }
+}
+
+/// Appends the decoding of `src`, which follows the `position` bytes decoded
+/// so far, to `text`. Takes the fields of a `DecodedChars` separately so
+/// that `src` can be borrowed from it.
+fn decode_part(
+    decoder: &mut Decoder,
+    src: &[u8],
+    position: &mut u64,
+    text: &mut String,
+    last: bool,
+    policy: MalformedPolicy,
+) -> Result<u64, MalformedError> {
+    let offset = *position;
+    *position += src.len() as u64;
+    decode_with_policy(decoder, src, offset, text, last, policy)
+}
+
+impl<R: Read, B: Buffer> Iterator for DecodedChars<R, B> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<io::Result<char>> {
+        loop {
+            if let Some(c) = self.text[self.next..].chars().next() {
+                self.next += c.len_utf8();
+                return Some(Ok(c));
+            }
+            if let Some(err) = self.error.take() {
+                return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err)));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.fill() {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    /// A reader that returns one byte per call.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_decoded_chars() {
+        let mut chars = DecodedChars::new(Trickle(b"a\xA4\xA2b"), true);
+        assert_eq!(chars.encoding(), None);
+        assert_eq!(chars.next().unwrap().unwrap(), 'a');
+        assert_eq!(chars.encoding(), Some(EUC_JP));
+        let rest: String = chars.map(|c| c.unwrap()).collect();
+        assert_eq!(rest, "あb");
+        // Undecided input uses the fallback.
+        let mut chars = DecodedChars::new(&b"\xE0\xE0"[..], true);
+        assert!(chars.next().unwrap().is_ok());
+        assert_eq!(chars.encoding(), Some(SHIFT_JIS));
+        assert!(chars.next().is_none());
+    }
+
+    #[test]
+    fn test_malformed_policy() {
+        let mut chars = DecodedChars::new(Trickle(b"\xA4\xA2a\xA4"), true);
+        chars.set_malformed_policy(MalformedPolicy::Error);
+        assert_eq!(chars.next().unwrap().unwrap(), 'あ');
+        assert_eq!(chars.next().unwrap().unwrap(), 'a');
+        let err = chars.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(chars.next().is_none());
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        // A kanji that is valid in both Shift_JIS and EUC-JP, then "あ" in
+        // EUC-JP.
+        let input = b"abc\xE0\xE0\xA4\xA2";
+        let mut storage = [0u8; 3];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let mut chars = DecodedChars::with_buffer(Trickle(input), true, buffer);
+        // The kanji doesn't fit, which commits to the fallback.
+        assert_eq!(chars.next().unwrap().unwrap(), 'a');
+        assert_eq!(chars.encoding(), Some(SHIFT_JIS));
+        let text: String = chars.map(|c| c.unwrap()).collect();
+        assert_eq!(text, &SHIFT_JIS.decode_without_bom_handling(input).0[1..]);
+        let mut storage = [0u8; 8];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let chars = DecodedChars::with_buffer(Trickle(input), true, buffer);
+        let text: String = chars.map(|c| c.unwrap()).collect();
+        assert_eq!(text, EUC_JP.decode_without_bom_handling(input).0);
+    }
+
+    /// A reader that records the sizes of the buffers it is given.
+    struct Recording<'a>(&'a [u8], Vec<usize>);
+
+    impl<'a> Read for Recording<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1.push(buf.len());
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_chunk_sizes() {
+        let mut input = vec![b'a'; 10];
+        input.extend_from_slice(b"\xA4\xA2");
+        input.resize(60, b'b');
+        let mut chars = DecodedChars::new(Recording(&input, Vec::new()), true);
+        chars.set_chunk_sizes(ChunkSizes {
+            initial: 4,
+            max: 16,
+        });
+        assert_eq!(chars.by_ref().count(), 59);
+        assert_eq!(chars.reader.1, [4, 8, 16, 16, 16, 16]);
+    }
+}
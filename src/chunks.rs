@@ -0,0 +1,87 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// How much reader adapters such as `DecodedChars` read at a time.
+///
+/// The first read is `initial` bytes, so that the detector gets to decide
+/// without waiting for a large read to complete. While the detector is
+/// undecided, each read is twice the size of the previous one, up to `max`.
+/// Once the detector has decided, each read is `max` bytes, since only
+/// throughput matters from then on. Over high-latency file systems such as
+/// NFS, a large `max` makes a big difference.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkSizes {
+    /// The size of the first read. Treated as 1 if zero.
+    pub initial: usize,
+    /// The size of reads once grown. Treated as `initial` if smaller.
+    pub max: usize,
+}
+
+impl ChunkSizes {
+    /// The sizes used unless set otherwise: 1 KiB growing to 64 KiB.
+    pub const DEFAULT: ChunkSizes = ChunkSizes {
+        initial: 1024,
+        max: 65536,
+    };
+
+    /// Reads of `size` bytes throughout.
+    pub const fn fixed(size: usize) -> ChunkSizes {
+        ChunkSizes {
+            initial: size,
+            max: size,
+        }
+    }
+
+    /// The size of the first read.
+    pub fn first(&self) -> usize {
+        self.initial.max(1)
+    }
+
+    /// The size of the read after one of `previous` bytes given whether
+    /// the detector has decided.
+    pub fn next(&self, previous: usize, decided: bool) -> usize {
+        let max = self.max.max(self.first());
+        if decided {
+            max
+        } else {
+            previous.saturating_mul(2).clamp(self.first(), max)
+        }
+    }
+}
+
+impl Default for ChunkSizes {
+    fn default() -> Self {
+        ChunkSizes::DEFAULT
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_growth() {
+        let sizes = ChunkSizes {
+            initial: 100,
+            max: 350,
+        };
+        assert_eq!(sizes.first(), 100);
+        assert_eq!(sizes.next(100, false), 200);
+        assert_eq!(sizes.next(200, false), 350);
+        assert_eq!(sizes.next(350, false), 350);
+        assert_eq!(sizes.next(100, true), 350);
+        let sizes = ChunkSizes { initial: 0, max: 0 };
+        assert_eq!(sizes.first(), 1);
+        assert_eq!(sizes.next(1, false), 1);
+        assert_eq!(ChunkSizes::fixed(4096).next(4096, true), 4096);
+    }
+}
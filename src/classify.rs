@@ -0,0 +1,232 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::check_transport;
+use crate::Certainty;
+use crate::DetectionOutcome;
+use crate::Detector;
+use crate::Mangling;
+use crate::Reason;
+use crate::Verdict;
+
+/// What a batch conversion tool should do with a file, as decided by
+/// `classify()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Convert the file from `Classification::verdict()`.
+    Convert,
+    /// The file probably is in `Classification::verdict()` but the evidence
+    /// is weak, so a person should check the conversion.
+    Review,
+    /// The file is ASCII, so it reads the same in all the encodings and
+    /// needs no conversion.
+    Skip,
+    /// The file doesn't look like text, looks crafted or was mangled in
+    /// transport (see `Classification::mangling()`); don't convert it.
+    Quarantine,
+}
+
+/// The thresholds of `classify_with()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClassifyThresholds {
+    /// From this percentage of C0 control bytes other than whitespace and
+    /// ESC, the file is binary and quarantined.
+    pub binary_percent: u64,
+    /// Whether a verdict with `Certainty::Likely` is converted instead of
+    /// reviewed.
+    pub convert_likely: bool,
+    /// Whether a verdict that rests on a character cut off at the end of the
+    /// file is converted instead of reviewed. Files truncated by a transfer
+    /// or a size limit end that way, so the cut proves little.
+    pub convert_truncated: bool,
+    /// Whether to run `check_transport()` first and quarantine files that
+    /// were mangled wholesale in transport.
+    pub check_transport: bool,
+}
+
+impl ClassifyThresholds {
+    /// The thresholds used by `classify()`.
+    pub const DEFAULT: ClassifyThresholds = ClassifyThresholds {
+        binary_percent: 5,
+        convert_likely: true,
+        convert_truncated: false,
+        check_transport: false,
+    };
+}
+
+impl Default for ClassifyThresholds {
+    fn default() -> Self {
+        ClassifyThresholds::DEFAULT
+    }
+}
+
+/// The result of `classify()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Classification {
+    action: Action,
+    control_bytes: u64,
+    mangling: Option<Mangling>,
+    outcome: DetectionOutcome,
+}
+
+impl Classification {
+    /// What to do with the file.
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// The encoding to convert from for `Action::Convert` and
+    /// `Action::Review` (the fallback if the detector remained undecided)
+    /// or `None` otherwise.
+    pub fn verdict(&self) -> Option<Verdict> {
+        match self.action {
+            Action::Convert | Action::Review => Some(self.outcome.guess()),
+            Action::Skip | Action::Quarantine => None,
+        }
+    }
+
+    /// The number of C0 control bytes counted by the binary guard.
+    pub fn control_bytes(&self) -> u64 {
+        self.control_bytes
+    }
+
+    /// How the file was mangled in transport, if
+    /// `ClassifyThresholds::check_transport` is set and the check found
+    /// something.
+    pub fn mangling(&self) -> Option<Mangling> {
+        self.mangling
+    }
+
+    /// What the detector concluded, e.g. for `DetectionOutcome::explain()`.
+    pub fn outcome(&self) -> DetectionOutcome {
+        self.outcome
+    }
+}
+
+/// Classifies the complete contents of a file into an `Action` with the
+/// default thresholds and a detector that has `set_scoring()` enabled, so
+/// that files valid in both Shift_JIS and EUC-JP get a verdict to review.
+/// `allow_2022` has the same meaning as for `Detector::new()`.
+pub fn classify(bytes: &[u8], allow_2022: bool) -> Classification {
+    let mut detector = Detector::new(allow_2022);
+    detector.set_scoring(true);
+    classify_with(bytes, detector, &ClassifyThresholds::DEFAULT)
+}
+
+/// The same as `classify()` but with a caller-configured detector that
+/// hasn't been fed yet and caller-chosen thresholds.
+///
+/// The first of these that applies decides:
+///
+/// 1. Binary data (see `ClassifyThresholds::binary_percent`), transport
+///    mangling (if `ClassifyThresholds::check_transport`), an escape flood
+///    or crafted ambiguity (see `Detector::suspicious()`) or an edge byte
+///    error is quarantined.
+/// 2. A certain verdict is converted, except that a verdict decided by a
+///    truncated character is reviewed unless
+///    `ClassifyThresholds::convert_truncated`.
+/// 3. A likely verdict is converted if `ClassifyThresholds::convert_likely`.
+/// 4. ASCII that remains undecided is skipped.
+/// 5. Everything else (tentative and scored verdicts and undecided non-ASCII
+///    input) is reviewed.
+pub fn classify_with(
+    bytes: &[u8],
+    mut detector: Detector,
+    thresholds: &ClassifyThresholds,
+) -> Classification {
+    let control_bytes = bytes
+        .iter()
+        .filter(|&&byte| match byte {
+            0x09 | 0x0A | 0x0B | 0x0C | 0x0D | 0x1B => false,
+            byte => byte < 0x20,
+        })
+        .count() as u64;
+    let mangling = if thresholds.check_transport {
+        check_transport(bytes)
+    } else {
+        None
+    };
+    let outcome = detector.feed_with_outcome(bytes, true);
+    let binary = control_bytes != 0
+        && control_bytes * 100 >= (bytes.len() as u64) * thresholds.binary_percent;
+    let truncated = matches!(
+        outcome.reason(),
+        Some(Reason::EucJpTruncated) | Some(Reason::ShiftJisTruncated)
+    );
+    let action = if binary
+        || mangling.is_some()
+        || outcome.suspicious().is_some()
+        || outcome.edge_byte_error().is_some()
+    {
+        Action::Quarantine
+    } else {
+        match outcome.certainty() {
+            Some(Certainty::Certain) if !truncated || thresholds.convert_truncated => {
+                Action::Convert
+            }
+            Some(Certainty::Likely) if thresholds.convert_likely => Action::Convert,
+            None if bytes.is_ascii() => Action::Skip,
+            _ => Action::Review,
+        }
+    };
+    Classification {
+        action,
+        control_bytes,
+        mangling,
+        outcome,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let classification = classify(b"\x82\xA0", true);
+        assert_eq!(classification.action(), Action::Convert);
+        assert_eq!(classification.verdict(), Some(Verdict::ShiftJis));
+        assert_eq!(classify(b"plain", true).action(), Action::Skip);
+        // Valid in both, so only the scores decide.
+        let classification = classify(b"\xF0\xA1", true);
+        assert_eq!(classification.action(), Action::Review);
+        assert_eq!(classification.verdict(), Some(Verdict::EucJp));
+        // A lead byte cut off at the end.
+        let classification = classify(b"\xE0", false);
+        assert_eq!(classification.action(), Action::Review);
+        let thresholds = ClassifyThresholds {
+            convert_truncated: true,
+            ..ClassifyThresholds::DEFAULT
+        };
+        let classification = classify_with(b"\xE0", Detector::new(false), &thresholds);
+        assert_eq!(classification.action(), Action::Convert);
+        let classification = classify(b"\x00\x00\x82\xA0\x10", true);
+        assert_eq!(classification.action(), Action::Quarantine);
+        assert_eq!(classification.control_bytes(), 3);
+        assert_eq!(classification.verdict(), None);
+        // Two lines of kana in UTF-16LE, whose zero bytes are below the
+        // binary threshold.
+        let utf_16le: Vec<u8> = "あいうえおかきくけこさしすせそ\n"
+            .repeat(2)
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes().to_vec())
+            .collect();
+        let thresholds = ClassifyThresholds {
+            check_transport: true,
+            ..ClassifyThresholds::DEFAULT
+        };
+        assert_ne!(classify(&utf_16le, true).action(), Action::Quarantine);
+        let classification = classify_with(&utf_16le, Detector::new(true), &thresholds);
+        assert_eq!(classification.action(), Action::Quarantine);
+        assert_eq!(classification.mangling(), Some(Mangling::Utf16Le));
+    }
+}
@@ -0,0 +1,425 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(feature = "encoding_rs")]
+use crate::Backend;
+use crate::Conformance;
+use crate::Detector;
+use crate::EdgeByteTreatment;
+#[cfg(feature = "scoring")]
+use crate::ScoringThresholds;
+use crate::Verdict;
+
+/// All the options of a `Detector` as one immutable value, e.g. one per
+/// tenant of a server, from which per-request detectors are instantiated
+/// with `Detector::with_config()`.
+///
+/// The detector has no tables to copy: a configuration is a few bytes that
+/// each detector copies, so a configuration can be shared by reference or
+/// in an `Arc` without the detectors holding on to it. Each field has the
+/// same meaning and default as the corresponding `Detector` method. The
+/// struct is `#[non_exhaustive]` so that options can be added; start from
+/// `DetectorConfig::new()` and set fields.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectorConfig {
+    /// See `Detector::new()`.
+    pub allow_2022: bool,
+    /// See `Detector::set_allow_utf8()`.
+    pub allow_utf8: bool,
+    /// See `Detector::new_mid_stream()`.
+    pub mid_stream: bool,
+    /// See `Detector::set_conformance()`.
+    pub conformance: Conformance,
+    /// See `Detector::set_require_certain_iso_2022_jp()`.
+    pub require_certain_iso_2022_jp: bool,
+    /// See `Detector::set_strict_iso_2022_jp()`.
+    pub strict_iso_2022_jp: bool,
+    /// See `Detector::set_skip_ansi_sequences()`.
+    pub skip_ansi_sequences: bool,
+    /// See `Detector::set_jis_x_0212_decisive()`.
+    pub jis_x_0212_decisive: bool,
+    /// See `Detector::set_text_art()`.
+    pub text_art: bool,
+    /// See `Detector::set_reject_half_width_katakana()`.
+    pub reject_half_width_katakana: bool,
+    /// See `Detector::set_del_treatment()`.
+    pub del_treatment: EdgeByteTreatment,
+    /// See `Detector::set_c1_treatment()`.
+    pub c1_treatment: EdgeByteTreatment,
+    /// See `Detector::set_hashing()`.
+    pub hashing: bool,
+    /// See `Detector::set_scoring()`.
+    #[cfg(feature = "scoring")]
+    pub scoring: bool,
+    /// See `Detector::set_scoring_thresholds()`.
+    #[cfg(feature = "scoring")]
+    pub scoring_thresholds: ScoringThresholds,
+    /// See `Detector::set_fallback()`.
+    pub fallback: Verdict,
+    /// See `Detector::set_blank_verdict()`.
+    pub blank_verdict: Option<Verdict>,
+    /// See `Detector::set_undecided_default()`.
+    pub undecided_default: Option<Verdict>,
+    /// See `Detector::set_max_examined_len()`.
+    pub max_examined_len: Option<u64>,
+    /// See `Detector::set_max_bytes()`.
+    pub max_bytes: Option<u64>,
+    /// See `Detector::set_validation_mode()`.
+    #[cfg(feature = "encoding_rs")]
+    pub validation_mode: bool,
+    /// See `Detector::set_backend()`.
+    #[cfg(feature = "encoding_rs")]
+    pub backend: Backend,
+}
+
+impl DetectorConfig {
+    /// The configuration of `Detector::new(allow_2022)`.
+    pub fn new(allow_2022: bool) -> Self {
+        DetectorConfig {
+            allow_2022,
+            allow_utf8: false,
+            mid_stream: false,
+            conformance: Conformance::Pragmatic,
+            require_certain_iso_2022_jp: false,
+            strict_iso_2022_jp: false,
+            skip_ansi_sequences: false,
+            jis_x_0212_decisive: false,
+            text_art: false,
+            reject_half_width_katakana: true,
+            del_treatment: EdgeByteTreatment::Evidence,
+            c1_treatment: EdgeByteTreatment::Evidence,
+            hashing: false,
+            #[cfg(feature = "scoring")]
+            scoring: false,
+            #[cfg(feature = "scoring")]
+            scoring_thresholds: ScoringThresholds::DEFAULT,
+            fallback: Verdict::ShiftJis,
+            blank_verdict: None,
+            undecided_default: None,
+            max_examined_len: None,
+            max_bytes: None,
+            #[cfg(feature = "encoding_rs")]
+            validation_mode: false,
+            #[cfg(feature = "encoding_rs")]
+            backend: Backend::Decoder,
+        }
+    }
+}
+
+/// Builds a `Detector` (or a `DetectorConfig` to build many from) one
+/// option at a time. `Detector::new()` and `Detector::new_mid_stream()` are
+/// shorthands for builders that only set `allow_2022()` and `mid_stream()`.
+#[derive(Debug, Clone)]
+pub struct DetectorBuilder {
+    config: DetectorConfig,
+}
+
+impl DetectorBuilder {
+    /// Instantiates a builder with the defaults of all the options and
+    /// ISO-2022-JP allowed.
+    pub fn new() -> Self {
+        DetectorBuilder {
+            config: DetectorConfig::new(true),
+        }
+    }
+
+    /// Instantiates a builder that starts from `config`.
+    pub fn from_config(config: DetectorConfig) -> Self {
+        DetectorBuilder { config }
+    }
+
+    /// Sets whether ISO-2022-JP is a possible guess. See `Detector::new()`.
+    pub fn allow_2022(mut self, allow_2022: bool) -> Self {
+        self.config.allow_2022 = allow_2022;
+        self
+    }
+
+    /// Sets whether UTF-8 is a possible guess. See
+    /// `Detector::set_allow_utf8()`.
+    pub fn allow_utf8(mut self, allow_utf8: bool) -> Self {
+        self.config.allow_utf8 = allow_utf8;
+        self
+    }
+
+    /// Sets whether the input starts at an arbitrary offset of a stream.
+    /// See `Detector::new_mid_stream()`.
+    pub fn mid_stream(mut self, mid_stream: bool) -> Self {
+        self.config.mid_stream = mid_stream;
+        self
+    }
+
+    /// See `Detector::set_conformance()`.
+    pub fn conformance(mut self, conformance: Conformance) -> Self {
+        self.config.conformance = conformance;
+        self
+    }
+
+    /// See `Detector::set_require_certain_iso_2022_jp()`.
+    pub fn require_certain_iso_2022_jp(mut self, require: bool) -> Self {
+        self.config.require_certain_iso_2022_jp = require;
+        self
+    }
+
+    /// See `Detector::set_strict_iso_2022_jp()`.
+    pub fn strict_iso_2022_jp(mut self, strict: bool) -> Self {
+        self.config.strict_iso_2022_jp = strict;
+        self
+    }
+
+    /// See `Detector::set_skip_ansi_sequences()`.
+    pub fn skip_ansi_sequences(mut self, skip: bool) -> Self {
+        self.config.skip_ansi_sequences = skip;
+        self
+    }
+
+    /// See `Detector::set_jis_x_0212_decisive()`.
+    pub fn jis_x_0212_decisive(mut self, decisive: bool) -> Self {
+        self.config.jis_x_0212_decisive = decisive;
+        self
+    }
+
+    /// See `Detector::set_text_art()`.
+    pub fn text_art(mut self, text_art: bool) -> Self {
+        self.config.text_art = text_art;
+        self
+    }
+
+    /// See `Detector::set_reject_half_width_katakana()`.
+    pub fn reject_half_width_katakana(mut self, reject: bool) -> Self {
+        self.config.reject_half_width_katakana = reject;
+        self
+    }
+
+    /// See `Detector::set_del_treatment()`.
+    pub fn del_treatment(mut self, treatment: EdgeByteTreatment) -> Self {
+        self.config.del_treatment = treatment;
+        self
+    }
+
+    /// See `Detector::set_c1_treatment()`.
+    pub fn c1_treatment(mut self, treatment: EdgeByteTreatment) -> Self {
+        self.config.c1_treatment = treatment;
+        self
+    }
+
+    /// See `Detector::set_hashing()`.
+    pub fn hashing(mut self, enabled: bool) -> Self {
+        self.config.hashing = enabled;
+        self
+    }
+
+    /// See `Detector::set_scoring()`.
+    #[cfg(feature = "scoring")]
+    pub fn scoring(mut self, enabled: bool) -> Self {
+        self.config.scoring = enabled;
+        self
+    }
+
+    /// See `Detector::set_scoring_thresholds()`.
+    #[cfg(feature = "scoring")]
+    pub fn scoring_thresholds(mut self, thresholds: ScoringThresholds) -> Self {
+        self.config.scoring_thresholds = thresholds;
+        self
+    }
+
+    /// Sets how a tie is broken. See `Detector::set_fallback()`.
+    pub fn fallback(mut self, fallback: Verdict) -> Self {
+        self.config.fallback = fallback;
+        self
+    }
+
+    /// Sets the verdict for blank input. See
+    /// `Detector::set_blank_verdict()`.
+    pub fn blank_verdict(mut self, verdict: Option<Verdict>) -> Self {
+        self.config.blank_verdict = verdict;
+        self
+    }
+
+    /// Sets the verdict that the end of the stream decides if nothing else
+    /// has. See `Detector::set_undecided_default()`.
+    pub fn undecided_default(mut self, verdict: Option<Verdict>) -> Self {
+        self.config.undecided_default = verdict;
+        self
+    }
+
+    /// Sets the byte budget. See `Detector::set_max_examined_len()`.
+    pub fn max_examined_len(mut self, max: Option<u64>) -> Self {
+        self.config.max_examined_len = max;
+        self
+    }
+
+    /// Sets the budget after which the detector commits to its best guess.
+    /// See `Detector::set_max_bytes()`.
+    pub fn max_bytes(mut self, max: Option<u64>) -> Self {
+        self.config.max_bytes = max;
+        self
+    }
+
+    /// See `Detector::set_validation_mode()`.
+    #[cfg(feature = "encoding_rs")]
+    pub fn validation_mode(mut self, enabled: bool) -> Self {
+        self.config.validation_mode = enabled;
+        self
+    }
+
+    /// See `Detector::set_backend()`.
+    #[cfg(feature = "encoding_rs")]
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.config.backend = backend;
+        self
+    }
+
+    /// Returns the configuration built so far, e.g. to share it among many
+    /// detectors.
+    pub fn into_config(self) -> DetectorConfig {
+        self.config
+    }
+
+    /// Instantiates the detector.
+    pub fn build(&self) -> Detector {
+        Detector::with_config(&self.config)
+    }
+}
+
+impl Default for DetectorBuilder {
+    fn default() -> Self {
+        DetectorBuilder::new()
+    }
+}
+
+impl Detector {
+    /// Instantiates a detector with the options of `config`.
+    pub fn with_config(config: &DetectorConfig) -> Self {
+        let mut detector = Detector::with_defaults(config.allow_2022, config.mid_stream);
+        detector.set_allow_utf8(config.allow_utf8);
+        detector.set_conformance(config.conformance);
+        detector.set_require_certain_iso_2022_jp(config.require_certain_iso_2022_jp);
+        detector.set_strict_iso_2022_jp(config.strict_iso_2022_jp);
+        detector.set_skip_ansi_sequences(config.skip_ansi_sequences);
+        detector.set_jis_x_0212_decisive(config.jis_x_0212_decisive);
+        detector.set_text_art(config.text_art);
+        detector.set_reject_half_width_katakana(config.reject_half_width_katakana);
+        detector.set_del_treatment(config.del_treatment);
+        detector.set_c1_treatment(config.c1_treatment);
+        detector.set_hashing(config.hashing);
+        #[cfg(feature = "scoring")]
+        {
+            detector.set_scoring(config.scoring);
+            detector.set_scoring_thresholds(config.scoring_thresholds);
+        }
+        detector.set_fallback(config.fallback);
+        detector.set_blank_verdict(config.blank_verdict);
+        detector.set_undecided_default(config.undecided_default);
+        detector.set_max_examined_len(config.max_examined_len);
+        detector.set_max_bytes(config.max_bytes);
+        #[cfg(feature = "encoding_rs")]
+        {
+            detector.set_validation_mode(config.validation_mode);
+            detector.set_backend(config.backend);
+        }
+        detector
+    }
+
+    /// The options of the detector, which `with_config()` would reproduce.
+    pub(crate) fn config(&self) -> DetectorConfig {
+        DetectorConfig {
+            allow_2022: self.allow_2022,
+            allow_utf8: self.allow_utf8,
+            mid_stream: self.mid_stream,
+            conformance: self.conformance,
+            require_certain_iso_2022_jp: self.require_certain_iso_2022_jp,
+            strict_iso_2022_jp: self.strict_iso_2022_jp,
+            skip_ansi_sequences: self.skip_ansi_sequences,
+            jis_x_0212_decisive: self.jis_x_0212_decisive,
+            text_art: self.text_art,
+            reject_half_width_katakana: self.reject_half_width_katakana,
+            del_treatment: self.del_treatment,
+            c1_treatment: self.c1_treatment,
+            hashing: self.examined_hash.is_some(),
+            #[cfg(feature = "scoring")]
+            scoring: self.scorer.is_some(),
+            #[cfg(feature = "scoring")]
+            scoring_thresholds: self.scoring_thresholds,
+            fallback: self.fallback,
+            blank_verdict: self.blank_verdict,
+            undecided_default: self.undecided_default,
+            max_examined_len: self.max_examined_len,
+            max_bytes: self.max_bytes,
+            #[cfg(feature = "encoding_rs")]
+            validation_mode: self.validation.is_some(),
+            #[cfg(feature = "encoding_rs")]
+            backend: self.backend,
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Verdict;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_with_config() {
+        let mut config = DetectorConfig::new(true);
+        config.text_art = true;
+        let config = Arc::new(config);
+        for _ in 0..2 {
+            let mut detector = Detector::with_config(&config);
+            assert_eq!(
+                detector.feed_verdict(b"\xB1\x82", false),
+                Some(Verdict::ShiftJis)
+            );
+        }
+        let mut detector = Detector::with_config(&DetectorConfig::new(true));
+        assert_eq!(
+            detector.feed_verdict(b"\xB1\x82", false),
+            Some(Verdict::EucJp)
+        );
+        #[cfg(feature = "diagnostics")]
+        assert_eq!(detector.policy(), Detector::new(true).policy());
+    }
+
+    #[test]
+    fn test_builder() {
+        let builder = DetectorBuilder::new().allow_2022(false);
+        #[cfg(feature = "diagnostics")]
+        assert_eq!(builder.build().policy(), Detector::new(false).policy());
+        // "あ" in EUC-JP is two half-width katakana characters in
+        // Shift_JIS.
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        let mut detector = builder.clone().reject_half_width_katakana(false).build();
+        assert_eq!(detector.feed_verdict(b"\xA4\xA2", true), None);
+        let mut detector = builder
+            .clone()
+            .reject_half_width_katakana(false)
+            .fallback(Verdict::EucJp)
+            .build();
+        assert_eq!(detector.feed_verdict(b"\xA4\xA2", true), None);
+        assert_eq!(detector.outcome().guess(), Verdict::EucJp);
+        #[cfg(feature = "diagnostics")]
+        assert_eq!(detector.policy().fallback(), Verdict::EucJp);
+        // The budget cuts off a lead byte, which then doesn't count as
+        // truncated.
+        let mut detector = builder.max_examined_len(Some(3)).build();
+        assert_eq!(detector.feed_verdict(b"ab", false), None);
+        assert!(!detector.budget_exhausted());
+        assert_eq!(detector.feed_verdict(b"\xE0\x82\xA0", true), None);
+        assert!(detector.budget_exhausted());
+        assert_eq!(detector.examined_len(), 3);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::Instant;
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+/// The number of bytes fed to the detector between checks of the clock.
+const CHUNK_SIZE: usize = 4096;
+
+/// The outcome of `detect_with_deadline()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeadlineResult {
+    encoding: Option<&'static Encoding>,
+    examined: usize,
+    complete: bool,
+}
+
+impl DeadlineResult {
+    /// The verdict if the detector decided. If the deadline cut detection
+    /// short, the best guess from the bytes examined: the detector's
+    /// `Detector::current_guess()` or, if that is `None`, its fallback.
+    /// `None` if the detector saw the entire buffer and remained undecided.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// The number of bytes fed to the detector. If a guess was made, this
+    /// is the length of the prefix up to the end of the chunk that contained
+    /// the deciding byte.
+    pub fn examined(&self) -> usize {
+        self.examined
+    }
+
+    /// `true` if the detector either decided or saw the entire buffer and
+    /// `false` if the deadline cut detection short.
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Runs the detector on the complete `buffer` but stops at `deadline`,
+/// returning the answer available at that point. `allow_2022` has the same
+/// meaning as for `Detector::new()`.
+///
+/// The clock is checked every 4 KiB, so the overshoot past the deadline is
+/// bounded by the time taken to examine that much input. At least one
+/// chunk is examined even if the deadline has already passed.
+///
+/// If the detector saw the entire buffer and remained undecided, falling
+/// back to Shift_JIS is the best guess for Web purposes.
+pub fn detect_with_deadline(buffer: &[u8], allow_2022: bool, deadline: Instant) -> DeadlineResult {
+    let mut detector = Detector::new(allow_2022);
+    let mut examined = 0;
+    for chunk in buffer.chunks(CHUNK_SIZE) {
+        if examined != 0 && Instant::now() >= deadline {
+            let guess = detector
+                .current_guess()
+                .unwrap_or_else(|| detector.outcome().guess().encoding());
+            return DeadlineResult {
+                encoding: Some(guess),
+                examined,
+                complete: false,
+            };
+        }
+        examined += chunk.len();
+        let last = examined == buffer.len();
+        let encoding = detector.feed(chunk, last);
+        if encoding.is_some() || last {
+            return DeadlineResult {
+                encoding,
+                examined,
+                complete: true,
+            };
+        }
+    }
+    DeadlineResult {
+        encoding: detector.feed(b"", true),
+        examined,
+        complete: true,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_deadline_passed() {
+        let mut buffer = vec![b'a'; CHUNK_SIZE * 3];
+        buffer.extend_from_slice(b"\xA4\xA2");
+        let result = detect_with_deadline(&buffer[..], true, Instant::now());
+        // The fallback, since ASCII doesn't lean either way.
+        assert_eq!(result.encoding(), Some(SHIFT_JIS));
+        assert_eq!(result.examined(), CHUNK_SIZE);
+        assert!(!result.complete());
+    }
+
+    #[test]
+    fn test_deadline_not_reached() {
+        let mut buffer = vec![b'a'; CHUNK_SIZE * 3];
+        buffer.extend_from_slice(b"\xA4\xA2");
+        let deadline = Instant::now() + std::time::Duration::from_secs(3600);
+        let result = detect_with_deadline(&buffer[..], true, deadline);
+        assert_eq!(result.encoding(), Some(EUC_JP));
+        assert_eq!(result.examined(), buffer.len());
+        assert!(result.complete());
+        let result = detect_with_deadline(b"", true, deadline);
+        assert_eq!(result.encoding(), None);
+        assert!(result.complete());
+    }
+}
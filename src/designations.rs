@@ -0,0 +1,183 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+const ASCII: u8 = 1;
+const JIS_ROMAN: u8 = 1 << 1;
+const KATAKANA: u8 = 1 << 2;
+const JIS_X_0208_1978: u8 = 1 << 3;
+const JIS_X_0208_1983: u8 = 1 << 4;
+
+/// The set of ISO-2022-JP designations that occurred in a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Designations {
+    bits: u8,
+}
+
+impl Designations {
+    fn has(&self, bit: u8) -> bool {
+        self.bits & bit != 0
+    }
+
+    /// Whether ESC ( B (ASCII) occurred.
+    pub fn ascii(&self) -> bool {
+        self.has(ASCII)
+    }
+
+    /// Whether ESC ( J (JIS X 0201 Roman) occurred.
+    pub fn jis_roman(&self) -> bool {
+        self.has(JIS_ROMAN)
+    }
+
+    /// Whether ESC ( I (JIS X 0201 katakana) occurred. This designation is
+    /// not part of RFC 1468 ISO-2022-JP but is supported by the WHATWG
+    /// decoder.
+    pub fn katakana(&self) -> bool {
+        self.has(KATAKANA)
+    }
+
+    /// Whether ESC $ @ (JIS C 6226-1978, a.k.a. JIS X 0208-1978) occurred.
+    pub fn jis_x_0208_1978(&self) -> bool {
+        self.has(JIS_X_0208_1978)
+    }
+
+    /// Whether ESC $ B (JIS X 0208-1983) occurred.
+    pub fn jis_x_0208_1983(&self) -> bool {
+        self.has(JIS_X_0208_1983)
+    }
+
+    /// Whether no designations occurred.
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ScanState {
+    Normal,
+    Escape,
+    EscapeSecond(u8),
+}
+
+/// Collects the ISO-2022-JP designation escape sequences that occur in a
+/// stream.
+///
+/// `Detector` stops at the first designation, so it cannot tell which
+/// character sets the stream actually uses. Once the detector has guessed
+/// ISO-2022-JP, running this scanner over the stream (from the start) tells
+/// e.g. whether the 1978 or the 1983 version of JIS X 0208 was designated,
+/// which matters for converters that treat the two differently. (The WHATWG
+/// decoder treats them the same.)
+///
+/// Escape sequences may be split across calls to `feed()`.
+pub struct DesignationScanner {
+    state: ScanState,
+    designations: Designations,
+}
+
+impl DesignationScanner {
+    /// Instantiates the scanner.
+    pub fn new() -> Self {
+        DesignationScanner {
+            state: ScanState::Normal,
+            designations: Designations::default(),
+        }
+    }
+
+    /// Scans more bytes of the stream.
+    pub fn feed(&mut self, buffer: &[u8]) {
+        let mut i = 0;
+        while i < buffer.len() {
+            if self.state == ScanState::Normal {
+                match memchr::memchr(0x1B, &buffer[i..]) {
+                    Some(escape) => {
+                        i += escape + 1;
+                        self.state = ScanState::Escape;
+                        continue;
+                    }
+                    None => {
+                        return;
+                    }
+                }
+            }
+            let byte = buffer[i];
+            self.state = match self.state {
+                ScanState::Escape if byte == 0x1B => ScanState::Escape,
+                ScanState::Escape => ScanState::EscapeSecond(byte),
+                ScanState::EscapeSecond(second) => {
+                    self.designations.bits |= match (second, byte) {
+                        (0x28, 0x42) => ASCII,
+                        (0x28, 0x4A) => JIS_ROMAN,
+                        (0x28, 0x49) => KATAKANA,
+                        (0x24, 0x40) => JIS_X_0208_1978,
+                        (0x24, 0x42) => JIS_X_0208_1983,
+                        _ => 0,
+                    };
+                    if byte == 0x1B {
+                        ScanState::Escape
+                    } else {
+                        ScanState::Normal
+                    }
+                }
+                ScanState::Normal => unreachable!(),
+            };
+            i += 1;
+        }
+    }
+
+    /// The designations seen so far.
+    pub fn designations(&self) -> Designations {
+        self.designations
+    }
+}
+
+impl Default for DesignationScanner {
+    fn default() -> Self {
+        DesignationScanner::new()
+    }
+}
+
+/// Returns the ISO-2022-JP designations that occur in `buffer`.
+pub fn scan_designations(buffer: &[u8]) -> Designations {
+    let mut scanner = DesignationScanner::new();
+    scanner.feed(buffer);
+    scanner.designations()
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_designations() {
+        let designations = scan_designations(b"a\x1B$@\x30\x21\x1B(Jb\x1B(B");
+        assert!(designations.jis_x_0208_1978());
+        assert!(designations.jis_roman());
+        assert!(designations.ascii());
+        assert!(!designations.jis_x_0208_1983());
+        assert!(!designations.katakana());
+        assert!(scan_designations(b"abc\x1B[0m").is_empty());
+    }
+
+    #[test]
+    fn test_split_escapes() {
+        let input = b"\x1B$B\x30\x21\x1B(I\x31";
+        for split in 0..=input.len() {
+            let mut scanner = DesignationScanner::new();
+            scanner.feed(&input[..split]);
+            scanner.feed(&input[split..]);
+            let designations = scanner.designations();
+            assert!(designations.jis_x_0208_1983());
+            assert!(designations.katakana());
+            assert!(!designations.ascii());
+        }
+    }
+}
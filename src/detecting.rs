@@ -0,0 +1,362 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+
+use crate::find_non_ascii_or_escape;
+use crate::malformed::decode_to_utf16_with_policy;
+use crate::malformed::decode_with_policy;
+use crate::Buffer;
+use crate::Detector;
+use crate::MalformedError;
+use crate::MalformedPolicy;
+
+/// A decoder that detects and decodes in a single pass over the input.
+///
+/// While the detector is undecided, the input is held in an internal
+/// buffer and nothing is emitted. Once the detector decides, the held
+/// prefix and everything after it are decoded using the decided encoding,
+/// so no text is ever revised. If the stream ends undecided, the held input
+/// is decoded using the detector's fallback guess (Shift_JIS by default).
+///
+/// The buffer grows with the undecided prefix, which is usually short but
+/// for pure ASCII is the whole stream. To bound it, instantiate the decoder
+/// `with_detector()` and a detector that has `set_max_examined_len()`: once
+/// the budget is exhausted, the decoder commits to the fallback. A decoder
+/// `with_buffer()` holds the input in caller-provided storage instead, and
+/// the input that the storage cannot hold commits to the fallback the same
+/// way.
+pub struct DetectingDecoder<B: Buffer = Vec<u8>> {
+    detector: Detector,
+    decoder: Option<Decoder>,
+    held: B,
+    malformed_policy: MalformedPolicy,
+    sniff_limit: Option<usize>,
+    eager_ascii_prefix: bool,
+    position: u64,
+    malformed: u64,
+}
+
+impl DetectingDecoder {
+    /// Instantiates the decoder. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        DetectingDecoder::with_detector(Detector::new(allow_2022))
+    }
+
+    /// Instantiates the decoder with a configured detector that hasn't been
+    /// fed yet.
+    pub fn with_detector(detector: Detector) -> Self {
+        DetectingDecoder::with_buffer(detector, Vec::new())
+    }
+}
+
+impl<B: Buffer> DetectingDecoder<B> {
+    /// Instantiates the decoder with a configured detector that hasn't been
+    /// fed yet, holding the input in `buffer` while the detector is
+    /// undecided.
+    pub fn with_buffer(detector: Detector, buffer: B) -> Self {
+        DetectingDecoder {
+            detector,
+            decoder: None,
+            held: buffer,
+            malformed_policy: MalformedPolicy::Replace,
+            sniff_limit: None,
+            eager_ascii_prefix: false,
+            position: 0,
+            malformed: 0,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first call to
+    /// `decode_to_string()` or `decode_to_utf16()`.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.malformed_policy = policy;
+    }
+
+    /// Sets the number of held bytes after which the decoder commits to the
+    /// detector's `current_guess()` (or, failing that, the fallback) if the
+    /// detector hasn't decided, or `None` for no limit. Defaults to `None`.
+    /// Unlike `Detector::set_max_examined_len()`, this bounds how long text
+    /// is held back without limiting the detector. Call before the first
+    /// call to `decode_to_string()` or `decode_to_utf16()`.
+    pub fn set_sniff_limit(&mut self, limit: Option<usize>) {
+        self.sniff_limit = limit;
+    }
+
+    /// Sets whether the ASCII at the start of the stream is decoded right
+    /// away instead of being held back with the rest. Defaults to `false`.
+    /// Bytes up to the first non-ASCII byte or 0x1B decode the same in all
+    /// the candidate encodings, so this only holds back from there on, e.g.
+    /// for a terminal pager that cares about the time to the first text.
+    /// Call before the first call to `decode_to_string()` or
+    /// `decode_to_utf16()`.
+    pub fn set_eager_ascii_prefix(&mut self, eager: bool) {
+        self.eager_ascii_prefix = eager;
+    }
+
+    /// The encoding that the stream is decoded from or `None` while the
+    /// detector is undecided.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.as_ref().map(Decoder::encoding)
+    }
+
+    /// The number of bytes held while the detector is undecided.
+    pub fn pending(&self) -> usize {
+        self.held.as_slice().len()
+    }
+
+    /// The number of malformed sequences decoded so far, i.e. the number of
+    /// replacements performed under `MalformedPolicy::Replace`.
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// The detector, e.g. for its `outcome()`.
+    pub fn detector(&self) -> &Detector {
+        &self.detector
+    }
+
+    /// Decodes `src`, appending the text to `dst` once the encoding has been
+    /// decided (including the text of the held input at that point) and
+    /// holding `src` back otherwise. `last` signals the end of the stream,
+    /// which decides if nothing else has.
+    ///
+    /// Under `MalformedPolicy::Error`, returns an error for the first
+    /// malformed sequence after the text before it has been appended. The
+    /// decoder should not be used after that.
+    pub fn decode_to_string(
+        &mut self,
+        src: &[u8],
+        dst: &mut String,
+        last: bool,
+    ) -> Result<(), MalformedError> {
+        let policy = self.malformed_policy;
+        self.decode_with(src, last, |decoder, src, offset, last| {
+            decode_with_policy(decoder, src, offset, dst, last, policy)
+        })
+    }
+
+    /// The same as `decode_to_string()` but appends UTF-16 to `dst`.
+    pub fn decode_to_utf16(
+        &mut self,
+        src: &[u8],
+        dst: &mut Vec<u16>,
+        last: bool,
+    ) -> Result<(), MalformedError> {
+        let policy = self.malformed_policy;
+        self.decode_with(src, last, |decoder, src, offset, last| {
+            decode_to_utf16_with_policy(decoder, src, offset, dst, last, policy)
+        })
+    }
+
+    /// Runs the detector on `src` unless decided and decodes with `decode`,
+    /// which returns the number of malformed sequences.
+    fn decode_with<F>(
+        &mut self,
+        src: &[u8],
+        last: bool,
+        mut decode: F,
+    ) -> Result<(), MalformedError>
+    where
+        F: FnMut(&mut Decoder, &[u8], u64, bool) -> Result<u64, MalformedError>,
+    {
+        let mut src = src;
+        if self.decoder.is_none() {
+            let verdict = self.detector.feed(src, last);
+            if verdict.is_none() && self.eager_ascii_prefix && self.held.as_slice().is_empty() {
+                // The ASCII decodes the same with any of the candidates.
+                let safe = find_non_ascii_or_escape(src);
+                let mut ascii = self
+                    .detector
+                    .fallback()
+                    .encoding()
+                    .new_decoder_without_bom_handling();
+                decode_chunk(
+                    &mut ascii,
+                    &src[..safe],
+                    false,
+                    &mut self.position,
+                    &mut self.malformed,
+                    &mut decode,
+                )?;
+                src = &src[safe..];
+            }
+            let encoding = match verdict {
+                Some(encoding) => encoding,
+                None if last || self.detector.budget_exhausted() => {
+                    self.detector.fallback().encoding()
+                }
+                None => {
+                    if self.held.try_extend(src).is_err() {
+                        self.detector.fallback().encoding()
+                    } else if self
+                        .sniff_limit
+                        .is_some_and(|limit| self.held.as_slice().len() >= limit)
+                    {
+                        src = &[];
+                        self.detector
+                            .current_guess()
+                            .unwrap_or_else(|| self.detector.fallback().encoding())
+                    } else {
+                        return Ok(());
+                    }
+                }
+            };
+            let decoder = self
+                .decoder
+                .insert(encoding.new_decoder_without_bom_handling());
+            let result = decode_chunk(
+                decoder,
+                self.held.as_slice(),
+                false,
+                &mut self.position,
+                &mut self.malformed,
+                &mut decode,
+            );
+            self.held.clear();
+            result?;
+        }
+        let decoder = match self.decoder.as_mut() {
+            Some(decoder) => decoder,
+            None => unreachable!("Decoding before a decision."),
+        };
+        decode_chunk(
+            decoder,
+            src,
+            last,
+            &mut self.position,
+            &mut self.malformed,
+            &mut decode,
+        )
+    }
+}
+
+/// Decodes the next `src` of the stream, which follows the `position` bytes
+/// decoded so far, with `decode` and counts its malformed sequences into
+/// `malformed`. Takes the fields of a `DetectingDecoder` separately so that
+/// `src` can be borrowed from it.
+fn decode_chunk<F>(
+    decoder: &mut Decoder,
+    src: &[u8],
+    last: bool,
+    position: &mut u64,
+    malformed: &mut u64,
+    decode: &mut F,
+) -> Result<(), MalformedError>
+where
+    F: FnMut(&mut Decoder, &[u8], u64, bool) -> Result<u64, MalformedError>,
+{
+    let offset = *position;
+    *position += src.len() as u64;
+    match decode(decoder, src, offset, last) {
+        Ok(count) => {
+            *malformed += count;
+            Ok(())
+        }
+        Err(err) => {
+            *malformed += 1;
+            Err(err)
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DetectorBuilder;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_held_prefix() {
+        let mut decoder = DetectingDecoder::new(true);
+        let mut text = String::new();
+        assert_eq!(decoder.decode_to_string(b"ab", &mut text, false), Ok(()));
+        assert_eq!(text, "");
+        assert_eq!(decoder.encoding(), None);
+        assert_eq!(decoder.pending(), 2);
+        // "あ" in EUC-JP.
+        assert_eq!(
+            decoder.decode_to_string(b"\xA4\xA2", &mut text, false),
+            Ok(())
+        );
+        assert_eq!(text, "abあ");
+        assert_eq!(decoder.encoding(), Some(EUC_JP));
+        assert_eq!(decoder.pending(), 0);
+        assert_eq!(decoder.decode_to_string(b"c", &mut text, true), Ok(()));
+        assert_eq!(text, "abあc");
+    }
+
+    #[test]
+    fn test_fallback() {
+        // A kanji that is valid in both Shift_JIS and EUC-JP.
+        let mut decoder = DetectingDecoder::new(true);
+        let mut utf16 = Vec::new();
+        assert_eq!(decoder.decode_to_utf16(b"\xE0", &mut utf16, false), Ok(()));
+        assert_eq!(decoder.decode_to_utf16(b"\xA1", &mut utf16, true), Ok(()));
+        assert_eq!(decoder.encoding(), Some(SHIFT_JIS));
+        let (expected, _) = SHIFT_JIS.decode_without_bom_handling(b"\xE0\xA1");
+        assert_eq!(utf16, expected.encode_utf16().collect::<Vec<u16>>());
+        // The budget commits to the fallback before the end of the stream.
+        let detector = DetectorBuilder::new().max_examined_len(Some(1)).build();
+        let mut decoder = DetectingDecoder::with_detector(detector);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"a\xA4\xA2", &mut text, false),
+            Ok(())
+        );
+        assert_eq!(decoder.encoding(), Some(SHIFT_JIS));
+        assert_eq!(text, "a､｢");
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        let mut storage = [0u8; 4];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let mut decoder = DetectingDecoder::with_buffer(Detector::new(true), buffer);
+        let mut text = String::new();
+        assert_eq!(decoder.decode_to_string(b"abc", &mut text, false), Ok(()));
+        assert_eq!(decoder.pending(), 3);
+        // A kanji that is valid in both Shift_JIS and EUC-JP doesn't fit,
+        // so the input commits to the fallback.
+        assert_eq!(
+            decoder.decode_to_string(b"d\xE0\xE0", &mut text, false),
+            Ok(())
+        );
+        assert_eq!(decoder.encoding(), Some(SHIFT_JIS));
+        assert_eq!(decoder.pending(), 0);
+        assert_eq!(
+            text,
+            SHIFT_JIS.decode_without_bom_handling(b"abcd\xE0\xE0").0
+        );
+    }
+
+    #[test]
+    fn test_error_offset() {
+        let mut decoder = DetectingDecoder::new(true);
+        decoder.set_malformed_policy(MalformedPolicy::Error);
+        let mut text = String::new();
+        assert_eq!(decoder.decode_to_string(b"ab", &mut text, false), Ok(()));
+        // Malformed in both Shift_JIS and EUC-JP.
+        let err = decoder
+            .decode_to_string(b"\xA0", &mut text, true)
+            .unwrap_err();
+        assert_eq!(err.offset(), 2);
+        assert_eq!(text, "ab");
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The handful of functions that most callers need, with the choices made
+//! for them: ISO-2022-JP and UTF-8 are allowed, a byte order mark takes
+//! precedence and input that the rules leave undecided gets the fallback
+//! guess, Shift_JIS, so the answer is always an encoding.
+//!
+//! This module is the semver-stable layer of the crate. Its functions keep
+//! their signatures until the next major version even when the rest of the
+//! API changes in minor versions; a minor version may still improve the
+//! verdicts they return, as the detector improves. Reach for `Detector`
+//! and the rest of the crate when the choices above don't fit.
+
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+use encoding_rs::Encoding;
+use encoding_rs::SHIFT_JIS;
+
+use crate::html::sniff_html;
+#[cfg(feature = "std")]
+use crate::io::DetectingReader;
+use crate::Detector;
+use crate::DetectorBuilder;
+use crate::Verdict;
+
+/// The detector that the other functions use, for input that arrives in
+/// pieces: ISO-2022-JP and UTF-8 allowed, with Shift_JIS as
+/// `Detector::set_undecided_default()`, so its last call to `feed()`
+/// always returns an encoding. It doesn't look for a byte order mark.
+pub fn detector() -> Detector {
+    DetectorBuilder::new()
+        .allow_2022(true)
+        .allow_utf8(true)
+        .undecided_default(Some(Verdict::ShiftJis))
+        .build()
+}
+
+/// The encoding of `bytes`, the complete input: the one that a byte order
+/// mark names or else what `detector()` decides.
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    let mut detector = detector();
+    detector.feed(bytes, true);
+    detector.outcome().guess().encoding()
+}
+
+/// Decodes `bytes`, the complete input, as `detect()` says, removing a
+/// byte order mark. Returns the text, the encoding and whether malformed
+/// sequences were replaced with U+FFFD, as `Encoding::decode()` does.
+pub fn decode(bytes: &[u8]) -> (Cow<'_, str>, &'static Encoding, bool) {
+    detect(bytes).decode(bytes)
+}
+
+/// The encoding of `bytes`, the complete HTML document, as browsers sniff
+/// it (see `html::sniff_html()`), with Shift_JIS for undecided input.
+pub fn detect_html(bytes: &[u8]) -> &'static Encoding {
+    sniff_html(bytes, true).unwrap_or(SHIFT_JIS)
+}
+
+/// Reads all of `reader` and decodes it as `detector()` decides, replacing
+/// malformed sequences with U+FFFD. A UTF-8 byte order mark is removed;
+/// use `decode()` for input that may be UTF-16.
+#[cfg(feature = "std")]
+pub fn read_to_string<R: Read>(reader: R) -> io::Result<String> {
+    let mut text = String::new();
+    DetectingReader::with_detector(reader, detector()).read_to_string(&mut text)?;
+    if text.starts_with('\u{FEFF}') {
+        text.remove(0);
+    }
+    Ok(text)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::ISO_2022_JP;
+    use encoding_rs::UTF_16LE;
+    use encoding_rs::UTF_8;
+
+    #[test]
+    fn test_easy() {
+        assert_eq!(detect(b"\xA4\xA2"), EUC_JP);
+        assert_eq!(detect(b"\x1B$B$\"\x1B(B"), ISO_2022_JP);
+        assert_eq!(detect("あ".as_bytes()), UTF_8);
+        assert_eq!(detect(b"\xFF\xFEa\x00"), UTF_16LE);
+        // Undecided input gets the fallback.
+        assert_eq!(detect(b"plain"), SHIFT_JIS);
+        assert_eq!(detect(b"\xF0\xA1"), SHIFT_JIS);
+        assert_eq!(decode(b"\x82\xA0"), (Cow::from("あ"), SHIFT_JIS, false));
+        assert_eq!(detect_html(b"<meta charset=euc-jp>"), EUC_JP);
+        assert_eq!(detect_html(b"<p>"), SHIFT_JIS);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_to_string() {
+        assert_eq!(
+            read_to_string(&b"\xEF\xBB\xBF\xE3\x81\x82"[..]).unwrap(),
+            "あ"
+        );
+        assert_eq!(read_to_string(&b"\xA4\xA2"[..]).unwrap(), "あ");
+    }
+}
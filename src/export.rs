@@ -0,0 +1,98 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rendering of a guess as the artifacts that Web tooling wants.
+//!
+//! The labels used are the WHATWG names of the encodings, which all browsers
+//! understand. For `iconv`, whose names mean slightly different things, the
+//! closest iconv names are used instead.
+
+use alloc::format;
+use alloc::string::String;
+
+use encoding_rs::Encoding;
+use encoding_rs::SHIFT_JIS;
+
+/// Returns a `<meta charset>` tag for `encoding`, e.g.
+/// `<meta charset="Shift_JIS">`.
+pub fn meta_charset_tag(encoding: &'static Encoding) -> String {
+    format!("<meta charset=\"{}\">", encoding.name())
+}
+
+/// Returns a `Content-Type` header value for `mime_type` (e.g.
+/// `text/html`) in `encoding`, e.g. `text/html; charset=Shift_JIS`.
+pub fn content_type(mime_type: &str, encoding: &'static Encoding) -> String {
+    format!("{}; charset={}", mime_type, encoding.name())
+}
+
+/// Returns the prefix of a data URL for `mime_type` in `encoding`, e.g.
+/// `data:text/plain;charset=Shift_JIS,`. The percent-encoded bytes follow.
+pub fn data_url_prefix(mime_type: &str, encoding: &'static Encoding) -> String {
+    format!("data:{};charset={},", mime_type, encoding.name())
+}
+
+/// Returns the iconv name for `encoding`.
+///
+/// The WHATWG Shift_JIS is Microsoft's variant, which iconv calls CP932.
+/// (iconv's SHIFT_JIS lacks the Microsoft extensions.)
+pub fn iconv_name(encoding: &'static Encoding) -> &'static str {
+    if encoding == SHIFT_JIS {
+        "CP932"
+    } else {
+        encoding.name()
+    }
+}
+
+/// Quotes `arg` for a POSIX shell.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Returns an iconv command line that converts the file at `path` from
+/// `encoding` to UTF-8 on standard output. `path` is quoted for a POSIX
+/// shell.
+pub fn iconv_command(encoding: &'static Encoding, path: &str) -> String {
+    format!(
+        "iconv -f {} -t UTF-8 {}",
+        iconv_name(encoding),
+        shell_quote(path)
+    )
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::ISO_2022_JP;
+
+    #[test]
+    fn test_snippets() {
+        assert_eq!(meta_charset_tag(EUC_JP), "<meta charset=\"EUC-JP\">");
+        assert_eq!(
+            content_type("text/html", SHIFT_JIS),
+            "text/html; charset=Shift_JIS"
+        );
+        assert_eq!(
+            data_url_prefix("text/plain", ISO_2022_JP),
+            "data:text/plain;charset=ISO-2022-JP,"
+        );
+    }
+
+    #[test]
+    fn test_iconv_command() {
+        assert_eq!(
+            iconv_command(SHIFT_JIS, "it's.txt"),
+            "iconv -f CP932 -t UTF-8 'it'\\''s.txt'"
+        );
+        assert_eq!(iconv_command(EUC_JP, "a"), "iconv -f EUC-JP -t UTF-8 'a'");
+    }
+}
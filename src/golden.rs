@@ -0,0 +1,523 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Golden test vectors shared with bindings and independent
+//! reimplementations. Requires the `test-support` feature.
+//!
+//! The vectors ship with the crate as `golden/vectors.json`. The file is a
+//! JSON object with a `version` number (`GOLDEN_VECTORS_VERSION`) and a
+//! `vectors` array. Each vector is an object with
+//!
+//! * `input`: the input bytes as upper-case hex digits,
+//! * `allow_2022`: whether ISO-2022-JP is allowed (see `Detector::new()`),
+//! * `verdict`: the encoding name of the verdict after feeding the whole
+//!   input with `last` set or `null` if undecided and
+//! * `offset`: `Detector::examined_len()` after that, i.e. the number of
+//!   bytes up to and including the deciding byte.
+//!
+//! The version is bumped whenever a vector is changed or removed. To
+//! regenerate the file after a deliberate change of behavior, run
+//! `cargo run --example golden --features test-support > golden/vectors.json`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::Write;
+
+use crate::Detector;
+use crate::Verdict;
+
+/// The version of the vectors that `generate_golden_vectors()` produces.
+pub const GOLDEN_VECTORS_VERSION: u32 = 1;
+
+/// The JSON of the vectors that ship with the crate.
+const SHIPPED: &str = include_str!("../golden/vectors.json");
+
+/// One golden test vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenVector {
+    /// The input bytes.
+    pub input: Vec<u8>,
+    /// Whether ISO-2022-JP is allowed.
+    pub allow_2022: bool,
+    /// The verdict after feeding the whole input with `last` set.
+    pub verdict: Option<Verdict>,
+    /// `Detector::examined_len()` after feeding the whole input.
+    pub offset: u64,
+}
+
+impl GoldenVector {
+    /// Runs a `Detector` on the input and returns a vector with the
+    /// outcome.
+    pub fn run(input: &[u8], allow_2022: bool) -> GoldenVector {
+        let mut detector = Detector::new(allow_2022);
+        let verdict = detector.feed_verdict(input, true);
+        GoldenVector {
+            input: input.to_vec(),
+            allow_2022,
+            verdict,
+            offset: detector.examined_len(),
+        }
+    }
+
+    /// Whether `Detector` reaches this vector's outcome.
+    pub fn check(&self) -> bool {
+        GoldenVector::run(&self.input, self.allow_2022) == *self
+    }
+}
+
+/// A versioned set of golden test vectors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenVectors {
+    /// The version of the set.
+    pub version: u32,
+    /// The vectors.
+    pub vectors: Vec<GoldenVector>,
+}
+
+impl GoldenVectors {
+    /// Serializes the vectors as JSON in the format described in the module
+    /// documentation, one vector per line.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = writeln!(json, "{{\n  \"version\": {},", self.version);
+        json.push_str("  \"vectors\": [\n");
+        for (i, vector) in self.vectors.iter().enumerate() {
+            json.push_str("    {\"input\": \"");
+            for byte in vector.input.iter() {
+                let _ = write!(json, "{:02X}", byte);
+            }
+            let verdict = match vector.verdict {
+                Some(verdict) => format!("\"{}\"", verdict.name()),
+                None => "null".to_string(),
+            };
+            let _ = write!(
+                json,
+                "\", \"allow_2022\": {}, \"verdict\": {}, \"offset\": {}}}",
+                vector.allow_2022, verdict, vector.offset
+            );
+            json.push_str(if i + 1 == self.vectors.len() {
+                "\n"
+            } else {
+                ",\n"
+            });
+        }
+        json.push_str("  ]\n}\n");
+        json
+    }
+}
+
+/// Malformed golden vector JSON.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GoldenError {
+    offset: usize,
+    message: &'static str,
+}
+
+impl GoldenError {
+    /// The byte offset in the JSON where the problem was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// The vectors that ship with the crate.
+pub fn golden_vectors() -> GoldenVectors {
+    parse_golden_vectors(SHIPPED).expect("The shipped vectors must parse.")
+}
+
+/// Generates the vectors from the current behavior of `Detector`: a fixed
+/// list of inputs that exercise each rule and pseudo-random mixes of
+/// ASCII, escapes and interesting bytes, each with and without ISO-2022-JP
+/// allowed.
+pub fn generate_golden_vectors() -> GoldenVectors {
+    let mut inputs: Vec<Vec<u8>> = [
+        &b""[..],
+        b"abc",
+        b"\x82\xA0",
+        b"\xA4\xA2",
+        b"\xE0\xE0",
+        b"ab\xE0",
+        b"ab\x8F",
+        b"\x8E\xB1",
+        b"\x8F\xB0\xA1",
+        b"\xB1",
+        b"\xFF",
+        b"\x80",
+        b"\x1B$B\x24\x22\x1B(B",
+        b"\x1B$@",
+        b"\x1B(J",
+        b"\x1B(I",
+        b"\x1B(X",
+        b"a\x1B",
+        b"\x1B\x1B$B",
+        b"\x82\xA0\x1B$B",
+        b"\x1B[31m\x82\xA0",
+    ]
+    .iter()
+    .map(|input| input.to_vec())
+    .collect();
+    let alphabet: [&[u8]; 10] = [
+        b"abc ",
+        b"\n",
+        b"\x1B",
+        b"$B",
+        b"\x82\xA0",
+        b"\xA4\xA2",
+        b"\xE0",
+        b"\x8E",
+        b"\x8F\xB0",
+        b"\xB1",
+    ];
+    let mut state = 1u32;
+    for _ in 0..100 {
+        let mut input = Vec::new();
+        for _ in 0..6 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            input.extend_from_slice(alphabet[(state >> 16) as usize % alphabet.len()]);
+        }
+        inputs.push(input);
+    }
+    let mut vectors = Vec::with_capacity(inputs.len() * 2);
+    for input in inputs.iter() {
+        for &allow_2022 in [true, false].iter() {
+            vectors.push(GoldenVector::run(input, allow_2022));
+        }
+    }
+    GoldenVectors {
+        version: GOLDEN_VECTORS_VERSION,
+        vectors,
+    }
+}
+
+/// Parses golden vector JSON in the format described in the module
+/// documentation. Keys other than the documented ones are ignored.
+pub fn parse_golden_vectors(json: &str) -> Result<GoldenVectors, GoldenError> {
+    let mut parser = Parser {
+        bytes: json.as_bytes(),
+        pos: 0,
+    };
+    let root = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    let version = root.key("version").and_then(Json::number);
+    let version = version
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or(GoldenError {
+            offset: root.offset,
+            message: "missing version",
+        })?;
+    let list = match root.key("vectors") {
+        Some(Json {
+            value: Value::Array(list),
+            ..
+        }) => list,
+        _ => {
+            return Err(GoldenError {
+                offset: root.offset,
+                message: "missing vectors",
+            })
+        }
+    };
+    let mut vectors = Vec::with_capacity(list.len());
+    for item in list.iter() {
+        let error = |message| GoldenError {
+            offset: item.offset,
+            message,
+        };
+        let input = match item.key("input") {
+            Some(Json {
+                value: Value::String(hex),
+                ..
+            }) => parse_hex(hex).ok_or_else(|| error("malformed input"))?,
+            _ => return Err(error("missing input")),
+        };
+        let allow_2022 = match item.key("allow_2022") {
+            Some(Json {
+                value: Value::Bool(allow_2022),
+                ..
+            }) => *allow_2022,
+            _ => return Err(error("missing allow_2022")),
+        };
+        let verdict = match item.key("verdict") {
+            Some(Json {
+                value: Value::Null, ..
+            }) => None,
+            Some(Json {
+                value: Value::String(name),
+                ..
+            }) => Some(verdict_for_name(name).ok_or_else(|| error("unknown verdict"))?),
+            _ => return Err(error("missing verdict")),
+        };
+        let offset = item
+            .key("offset")
+            .and_then(Json::number)
+            .ok_or_else(|| error("missing offset"))?;
+        vectors.push(GoldenVector {
+            input,
+            allow_2022,
+            verdict,
+            offset,
+        });
+    }
+    Ok(GoldenVectors { version, vectors })
+}
+
+fn verdict_for_name(name: &str) -> Option<Verdict> {
+    [Verdict::ShiftJis, Verdict::EucJp, Verdict::Iso2022Jp]
+        .iter()
+        .copied()
+        .find(|verdict| verdict.name() == name)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            &[high, low] => Some((hex_digit(high)? << 4) | hex_digit(low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    char::from(byte).to_digit(16).map(|digit| digit as u8)
+}
+
+/// A parsed JSON value and the offset where it starts.
+struct Json {
+    offset: usize,
+    value: Value,
+}
+
+enum Value {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn key(&self, key: &str) -> Option<&Json> {
+        match self.value {
+            Value::Object(ref members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<u64> {
+        match self.value {
+            Value::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+}
+
+/// A parser for the subset of JSON that the vectors use: no fractions,
+/// exponents or negative numbers.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> GoldenError {
+        GoldenError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), GoldenError> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) != Some(&byte) {
+            return Err(self.error("unexpected character"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, GoldenError> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let value = match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let name = self.string()?;
+                        self.expect(b':')?;
+                        members.push((name, self.value()?));
+                        self.skip_whitespace();
+                        match self.bytes.get(self.pos) {
+                            Some(b',') => self.pos += 1,
+                            Some(b'}') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(self.error("unexpected character")),
+                        }
+                    }
+                }
+                Value::Object(members)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        items.push(self.value()?);
+                        self.skip_whitespace();
+                        match self.bytes.get(self.pos) {
+                            Some(b',') => self.pos += 1,
+                            Some(b']') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(self.error("unexpected character")),
+                        }
+                    }
+                }
+                Value::Array(items)
+            }
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'0'..=b'9') => {
+                let mut number = 0u64;
+                while let Some(&digit @ b'0'..=b'9') = self.bytes.get(self.pos) {
+                    number = number
+                        .checked_mul(10)
+                        .and_then(|number| number.checked_add(u64::from(digit - b'0')))
+                        .ok_or_else(|| self.error("number out of range"))?;
+                    self.pos += 1;
+                }
+                Value::Number(number)
+            }
+            _ => {
+                let rest = &self.bytes[self.pos..];
+                let (value, len) = if rest.starts_with(b"true") {
+                    (Value::Bool(true), 4)
+                } else if rest.starts_with(b"false") {
+                    (Value::Bool(false), 5)
+                } else if rest.starts_with(b"null") {
+                    (Value::Null, 4)
+                } else {
+                    return Err(self.error("unexpected character"));
+                };
+                self.pos += len;
+                value
+            }
+        };
+        Ok(Json { offset, value })
+    }
+
+    /// Parses a string, which only needs to support the escapes `\"` and
+    /// `\\`.
+    fn string(&mut self) -> Result<String, GoldenError> {
+        self.expect(b'"')?;
+        let mut string = Vec::new();
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => match self.bytes.get(self.pos + 1) {
+                    Some(&escaped @ (b'"' | b'\\')) => {
+                        string.push(escaped);
+                        self.pos += 2;
+                    }
+                    _ => return Err(self.error("unsupported escape")),
+                },
+                Some(&byte) => {
+                    string.push(byte);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+        // The input came from a `&str` and only ASCII was removed.
+        Ok(String::from_utf8(string).expect("must be UTF-8"))
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shipped_vectors_are_current() {
+        let generated = generate_golden_vectors();
+        assert_eq!(SHIPPED, generated.to_json());
+        assert_eq!(golden_vectors(), generated);
+    }
+
+    #[test]
+    fn test_vectors_pass() {
+        let vectors = golden_vectors();
+        assert_eq!(vectors.version, GOLDEN_VECTORS_VERSION);
+        for vector in vectors.vectors.iter() {
+            assert!(vector.check(), "{:?}", vector);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let vectors = parse_golden_vectors(
+            r#"{"vectors": [{"offset": 1, "verdict": "Shift_JIS", "input": "82a0",
+                "allow_2022": false, "note": "\"kana\""}], "version": 7}"#,
+        )
+        .unwrap();
+        assert_eq!(vectors.version, 7);
+        assert_eq!(
+            vectors.vectors,
+            vec![GoldenVector {
+                input: vec![0x82, 0xA0],
+                allow_2022: false,
+                verdict: Some(Verdict::ShiftJis),
+                offset: 1,
+            }]
+        );
+        assert!(vectors.vectors[0].check());
+        let err = parse_golden_vectors(r#"{"version": 1, "vectors": [{}]}"#).unwrap_err();
+        assert_eq!(err.offset(), 27);
+        assert!(parse_golden_vectors(r#"{"version": 1} x"#).is_err());
+        assert!(parse_golden_vectors(r#"{"version": 1, "vectors": [}"#).is_err());
+    }
+}
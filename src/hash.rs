@@ -0,0 +1,47 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// The initial state of 64-bit FNV-1a.
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Continues a 64-bit FNV-1a hash with `bytes`.
+pub(crate) fn fnv1a_update(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Hashes `bytes` the same way `Detector::examined_hash()` hashes the
+/// bytes the detector examined (64-bit FNV-1a). This is not a
+/// cryptographic hash.
+pub fn hash_examined(bytes: &[u8]) -> u64 {
+    fnv1a_update(FNV_OFFSET_BASIS, bytes)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(hash_examined(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(hash_examined(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(
+            fnv1a_update(hash_examined(b"fo"), b"obar"),
+            hash_examined(b"foobar")
+        );
+    }
+}
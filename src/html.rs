@@ -0,0 +1,326 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding sniffing for HTML in the order browsers use: a byte order mark,
+//! then a `<meta charset>` (or `<meta http-equiv="Content-Type">`)
+//! declaration found by the prescan of the HTML Standard, then the byte
+//! detector.
+
+use encoding_rs::Encoding;
+use encoding_rs::UTF_16BE;
+use encoding_rs::UTF_16LE;
+use encoding_rs::UTF_8;
+use encoding_rs::WINDOWS_1252;
+use encoding_rs::X_USER_DEFINED;
+
+use crate::bom::is_bom_prefix;
+use crate::Detector;
+
+/// The number of bytes from the start of the stream that the prescan looks
+/// at, as in browsers.
+pub const PRESCAN_LEN: usize = 1024;
+
+/// Whether `byte` is whitespace for the purposes of the prescan.
+fn is_space(byte: u8) -> bool {
+    matches!(byte, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Whether `bytes` begins with `prefix`, ignoring ASCII case.
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Parses the attribute at `*pos` per the "get an attribute" algorithm of
+/// the HTML Standard, returning its name and value in their original case
+/// (the algorithm lowercases ASCII, so compare ignoring case) or `None` if
+/// there are no more attributes in the tag or the input ends first.
+fn get_attribute<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<(&'a [u8], &'a [u8])> {
+    while *pos < bytes.len() && (is_space(bytes[*pos]) || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *bytes.get(*pos)? == b'>' {
+        return None;
+    }
+    let name_start = *pos;
+    loop {
+        match *bytes.get(*pos)? {
+            b'=' if *pos > name_start => break,
+            b'/' | b'>' => return Some((&bytes[name_start..*pos], &[])),
+            byte if is_space(byte) => {
+                let name = &bytes[name_start..*pos];
+                while is_space(*bytes.get(*pos)?) {
+                    *pos += 1;
+                }
+                if bytes[*pos] != b'=' {
+                    return Some((name, &[]));
+                }
+                *pos += 1;
+                return get_value(bytes, pos, name);
+            }
+            _ => {}
+        }
+        *pos += 1;
+    }
+    let name = &bytes[name_start..*pos];
+    *pos += 1;
+    get_value(bytes, pos, name)
+}
+
+/// Parses the value after the `=` of the attribute `name`.
+fn get_value<'a>(bytes: &'a [u8], pos: &mut usize, name: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+    while is_space(*bytes.get(*pos)?) {
+        *pos += 1;
+    }
+    match bytes[*pos] {
+        quote @ b'"' | quote @ b'\'' => {
+            let value_start = *pos + 1;
+            let len = bytes[value_start..].iter().position(|&b| b == quote)?;
+            *pos = value_start + len + 1;
+            Some((name, &bytes[value_start..value_start + len]))
+        }
+        b'>' => Some((name, &[])),
+        _ => {
+            let value_start = *pos;
+            loop {
+                let byte = *bytes.get(*pos)?;
+                if is_space(byte) || byte == b'>' {
+                    return Some((name, &bytes[value_start..*pos]));
+                }
+                *pos += 1;
+            }
+        }
+    }
+}
+
+/// Extracts the encoding from the value of `<meta content>` per the
+/// "algorithm for extracting a character encoding from a meta element" of
+/// the HTML Standard.
+fn encoding_from_content(content: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    loop {
+        let found = content[pos..]
+            .windows(7)
+            .position(|window| window.eq_ignore_ascii_case(b"charset"))?;
+        pos += found + 7;
+        while pos < content.len() && is_space(content[pos]) {
+            pos += 1;
+        }
+        if content.get(pos) == Some(&b'=') {
+            break;
+        }
+    }
+    pos += 1;
+    while pos < content.len() && is_space(content[pos]) {
+        pos += 1;
+    }
+    let value = match *content.get(pos)? {
+        quote @ b'"' | quote @ b'\'' => {
+            let rest = &content[pos + 1..];
+            &rest[..rest.iter().position(|&b| b == quote)?]
+        }
+        _ => {
+            let rest = &content[pos..];
+            let len = rest
+                .iter()
+                .position(|&b| is_space(b) || b == b';')
+                .unwrap_or(rest.len());
+            &rest[..len]
+        }
+    };
+    Encoding::for_label(value)
+}
+
+/// Processes the attributes of a `<meta>` tag whose attributes start at
+/// `*pos`.
+fn meta_encoding(bytes: &[u8], pos: &mut usize) -> Option<&'static Encoding> {
+    let mut seen_http_equiv = false;
+    let mut seen_content = false;
+    let mut seen_charset = false;
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = get_attribute(bytes, pos) {
+        if name.eq_ignore_ascii_case(b"http-equiv") {
+            if !seen_http_equiv {
+                seen_http_equiv = true;
+                got_pragma = value.eq_ignore_ascii_case(b"content-type");
+            }
+        } else if name.eq_ignore_ascii_case(b"content") {
+            if !seen_content {
+                seen_content = true;
+                if charset.is_none() {
+                    if let Some(encoding) = encoding_from_content(value) {
+                        charset = Some(Some(encoding));
+                        need_pragma = Some(true);
+                    }
+                }
+            }
+        } else if name.eq_ignore_ascii_case(b"charset") && !seen_charset {
+            seen_charset = true;
+            if charset.is_none() {
+                charset = Some(Encoding::for_label(value));
+                need_pragma = Some(false);
+            }
+        }
+    }
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+    let charset = charset??;
+    Some(if charset == UTF_16BE || charset == UTF_16LE {
+        UTF_8
+    } else if charset == X_USER_DEFINED {
+        WINDOWS_1252
+    } else {
+        charset
+    })
+}
+
+/// Runs the prescan of the HTML Standard ("prescan a byte stream to
+/// determine its encoding") on the first `PRESCAN_LEN` bytes of `bytes`
+/// and returns the encoding declared by the first `<meta>` tag that
+/// declares one, skipping comments and the insides of other tags like a
+/// browser does.
+///
+/// As in the standard, a declaration of UTF-16 means UTF-8 and a
+/// declaration of x-user-defined means windows-1252. Unknown labels are
+/// ignored.
+pub fn prescan_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LEN)];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            // The dashes of "<!--" may also be the start of "-->".
+            let len = rest[2..].windows(3).position(|window| window == b"-->")?;
+            pos += 2 + len + 3;
+            continue;
+        }
+        if starts_with_ignore_case(rest, b"<meta")
+            && matches!(rest.get(5), Some(&byte) if is_space(byte) || byte == b'/')
+        {
+            pos += 6;
+            if let Some(encoding) = meta_encoding(bytes, &mut pos) {
+                return Some(encoding);
+            }
+            continue;
+        }
+        let tag_start = match rest {
+            [b'<', b'/', letter, ..] | [b'<', letter, ..] if letter.is_ascii_alphabetic() => {
+                Some(if rest[1] == b'/' { 2 } else { 1 })
+            }
+            _ => None,
+        };
+        if let Some(tag_start) = tag_start {
+            pos += tag_start;
+            while pos < bytes.len() && !is_space(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(bytes, &mut pos).is_some() {}
+            pos += 1;
+            continue;
+        }
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += rest.iter().position(|&b| b == b'>')? + 1;
+            continue;
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Sniffs the encoding of an HTML document from `bytes`, the start of the
+/// stream, in the order browsers use: a BOM, then `prescan_meta()`, then a
+/// `Detector` that allows ISO-2022-JP. `last` is whether `bytes` is the
+/// whole stream.
+///
+/// If `last` is `false`, the BOM and the prescan are only conclusive once
+/// `bytes` covers them, so the return value is `None` for a shorter prefix
+/// that may still become a BOM or the prescan window, which is
+/// `PRESCAN_LEN` bytes long, unless a declaration was found already. Call
+/// again with a longer prefix in that case. A `None` with `last` set means
+/// that the detector remained undecided; browsers fall back to Shift_JIS
+/// (see `Detector::fallback()`).
+pub fn sniff_html(bytes: &[u8], last: bool) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return Some(encoding);
+    }
+    if !last && is_bom_prefix(bytes) {
+        return None;
+    }
+    if let Some(encoding) = prescan_meta(bytes) {
+        return Some(encoding);
+    }
+    if !last && bytes.len() < PRESCAN_LEN {
+        return None;
+    }
+    Detector::new(true).feed(bytes, last)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_prescan_meta() {
+        assert_eq!(prescan_meta(b"<meta charset=euc-jp>"), Some(EUC_JP));
+        assert_eq!(
+            prescan_meta(b"<!doctype html><HTML><Meta Charset=\"Shift_JIS\">"),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(
+            prescan_meta(b"<meta http-equiv='Content-Type' content='text/html; charset=EUC-JP'>"),
+            Some(EUC_JP)
+        );
+        // A content attribute without the pragma doesn't count.
+        assert_eq!(
+            prescan_meta(b"<meta content='text/html; charset=EUC-JP'>"),
+            None
+        );
+        assert_eq!(prescan_meta(b"<meta charset=utf-16le>"), Some(UTF_8));
+        // Declarations in comments and attribute values are skipped.
+        assert_eq!(
+            prescan_meta(b"<!-- <meta charset=euc-jp> --><meta charset=sjis>"),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(
+            prescan_meta(b"<p title='<meta charset=euc-jp>'><meta charset=sjis>"),
+            Some(SHIFT_JIS)
+        );
+        assert_eq!(prescan_meta(b"<meta charset=bogus>"), None);
+        let mut late = vec![b' '; PRESCAN_LEN];
+        late.extend_from_slice(b"<meta charset=euc-jp>");
+        assert_eq!(prescan_meta(&late), None);
+    }
+
+    #[test]
+    fn test_sniff_html() {
+        assert_eq!(
+            sniff_html(b"\xEF\xBB\xBF<meta charset=euc-jp>", true),
+            Some(UTF_8)
+        );
+        assert_eq!(
+            sniff_html(b"<meta charset=euc-jp>\x82\xA0", true),
+            Some(EUC_JP)
+        );
+        assert_eq!(sniff_html(b"<p>\x82\xA0", true), Some(SHIFT_JIS));
+        // A meta may still follow within the prescan window.
+        assert_eq!(sniff_html(b"<p>\x82\xA0", false), None);
+        let mut long = b"<p>\x82\xA0".to_vec();
+        long.resize(PRESCAN_LEN, b' ');
+        assert_eq!(sniff_html(&long, false), Some(SHIFT_JIS));
+    }
+}
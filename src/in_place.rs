@@ -0,0 +1,86 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::raw::EucJpValidator;
+use crate::raw::Iso2022JpMachine;
+use crate::raw::ShiftJisValidator;
+use crate::raw::Step;
+use crate::Verdict;
+
+/// Decides `bytes`, taken as a complete stream, with the hand-written
+/// validators and no state beyond a few bytes on the stack, falling back
+/// to Shift_JIS if undecided. `allow_2022` has the same meaning as for
+/// `Detector::new()`.
+///
+/// The verdict is the same as that of a default-configured `Detector` (or
+/// its fallback). This is meant for short inputs, such as names and
+/// labels, that are detected at a high rate: it costs nothing to set up,
+/// but it pushes every byte to the candidates without skipping runs of
+/// ASCII, so for inputs longer than a few hundred bytes `Detector` is
+/// faster.
+pub fn detect_in_place(bytes: &[u8], allow_2022: bool) -> Verdict {
+    let mut iso_2022_jp = if allow_2022 {
+        Some(Iso2022JpMachine::new())
+    } else {
+        None
+    };
+    let mut shift_jis = ShiftJisValidator::new();
+    let mut euc_jp = EucJpValidator::new();
+    for &byte in bytes {
+        if let Some(machine) = iso_2022_jp.as_mut() {
+            // Until ISO-2022-JP is ruled out, the bytes are ASCII, which is
+            // neutral for the other candidates.
+            match machine.push(byte) {
+                Step::Confirmed => return Verdict::Iso2022Jp,
+                Step::Possible => continue,
+                Step::Rejected => iso_2022_jp = None,
+            }
+        }
+        match euc_jp.push(byte) {
+            Step::Rejected => return Verdict::ShiftJis,
+            Step::Confirmed => return Verdict::EucJp,
+            Step::Possible => {}
+        }
+        if shift_jis.push(byte) == Step::Rejected {
+            return Verdict::EucJp;
+        }
+    }
+    if euc_jp.finish() == Step::Rejected {
+        return Verdict::ShiftJis;
+    }
+    if shift_jis.finish() == Step::Rejected {
+        return Verdict::EucJp;
+    }
+    Verdict::ShiftJis
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Detector;
+
+    #[test]
+    fn test_matches_detector() {
+        for &allow_2022 in [false, true].iter() {
+            for first in 0..=0xFFu8 {
+                for second in 0..=0xFFu8 {
+                    for input in [[first, second, b' '], [b'\x1B', first, second]].iter() {
+                        let expected = Detector::new(allow_2022)
+                            .feed_verdict(input, true)
+                            .unwrap_or(Verdict::ShiftJis);
+                        assert_eq!(detect_in_place(input, allow_2022), expected, "{:X?}", input);
+                    }
+                }
+            }
+        }
+    }
+}
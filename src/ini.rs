@@ -0,0 +1,182 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Encoding;
+use encoding_rs::UTF_16LE;
+
+use crate::Detector;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IniState {
+    /// At the start of the stream.
+    Start,
+    /// After an initial 0xFF, which may start a UTF-16LE BOM.
+    Bom,
+    LineStart,
+    /// In a `[section]` or `[registry key]` line.
+    Section,
+    /// In a `;` or `#` comment line.
+    Comment,
+    Key,
+    /// In a quoted `.reg` value name.
+    QuotedKey,
+    /// After a backslash in a quoted `.reg` value name.
+    QuotedKeyEscape,
+    Value,
+}
+
+/// A detector for Windows INI files and registry export (`.reg`) files,
+/// which are mostly ASCII with occasional legacy-encoded values.
+///
+/// Only the values, i.e. what follows the first `=` on a line, together
+/// with the line breaks after them, are fed to the detector. Section and
+/// registry key lines, comments and key or value names are not evidence.
+///
+/// A `.reg` file written by REGEDIT5 and later is UTF-16LE with a byte order
+/// mark, in which case the detector decides `UTF_16LE` right away.
+pub struct IniDetector {
+    detector: Detector,
+    state: IniState,
+}
+
+impl IniDetector {
+    /// Instantiates the detector. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        IniDetector {
+            detector: Detector::new(allow_2022),
+            state: IniState::Start,
+        }
+    }
+
+    /// Feeds bytes to the detector. Lines may be split across calls. The
+    /// return value and the rules about calling again are the same as for
+    /// `Detector::feed()`, except that the return value may also be
+    /// `UTF_16LE`.
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        let mut value_start = None;
+        for (i, &byte) in buffer.iter().enumerate() {
+            match (self.state, value_start) {
+                (IniState::Value, None) => value_start = Some(i),
+                (IniState::Value, Some(_)) => {}
+                (_, Some(start)) => {
+                    value_start = None;
+                    if let Some(encoding) = self.detector.feed(&buffer[start..i], false) {
+                        return Some(encoding);
+                    }
+                }
+                (_, None) => {}
+            }
+            self.state = match self.state {
+                IniState::Start => {
+                    if byte == 0xFF {
+                        IniState::Bom
+                    } else {
+                        line_start(byte)
+                    }
+                }
+                IniState::Bom => {
+                    if byte == 0xFE {
+                        return Some(UTF_16LE);
+                    }
+                    key(byte)
+                }
+                IniState::LineStart => line_start(byte),
+                IniState::Section | IniState::Comment | IniState::Value => {
+                    if byte == b'\n' {
+                        IniState::LineStart
+                    } else {
+                        self.state
+                    }
+                }
+                IniState::Key => key(byte),
+                IniState::QuotedKey => match byte {
+                    b'"' => IniState::Key,
+                    b'\\' => IniState::QuotedKeyEscape,
+                    b'\n' => IniState::LineStart,
+                    _ => IniState::QuotedKey,
+                },
+                IniState::QuotedKeyEscape => match byte {
+                    b'\n' => IniState::LineStart,
+                    _ => IniState::QuotedKey,
+                },
+            };
+        }
+        if let Some(start) = value_start {
+            if let Some(encoding) = self.detector.feed(&buffer[start..], false) {
+                return Some(encoding);
+            }
+        }
+        if last {
+            return self.detector.feed(b"", true);
+        }
+        None
+    }
+}
+
+/// The state after `byte` at the start of a line.
+fn line_start(byte: u8) -> IniState {
+    match byte {
+        b' ' | b'\t' | b'\r' | b'\n' => IniState::LineStart,
+        b'[' => IniState::Section,
+        b';' | b'#' => IniState::Comment,
+        b'"' => IniState::QuotedKey,
+        _ => key(byte),
+    }
+}
+
+/// The state after `byte` in a key or value name.
+fn key(byte: u8) -> IniState {
+    match byte {
+        b'=' => IniState::Value,
+        b'\n' => IniState::LineStart,
+        _ => IniState::Key,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_values_only() {
+        // A registry key and a value name that look like "あ" in EUC-JP and
+        // a value that is "あ" in Shift_JIS.
+        let input =
+            b"REGEDIT4\r\n\r\n[HKEY_CURRENT_USER\\\xA4\xA2]\r\n\"\\\"\xA4\xA2=\"=\"\x82\xA0\"\r\n";
+        assert_eq!(Detector::new(true).feed(input, true), Some(EUC_JP));
+        for split in 0..=input.len() {
+            let mut detector = IniDetector::new(true);
+            let verdict = match detector.feed(&input[..split], false) {
+                Some(encoding) => Some(encoding),
+                None => detector.feed(&input[split..], true),
+            };
+            assert_eq!(verdict, Some(SHIFT_JIS));
+        }
+        let mut detector = IniDetector::new(true);
+        assert_eq!(
+            detector.feed(b"; \xA4\xA2\n[\xA4\xA2]\nkey=\x82\xA0\n", true),
+            Some(SHIFT_JIS)
+        );
+    }
+
+    #[test]
+    fn test_utf_16le() {
+        let mut detector = IniDetector::new(true);
+        assert_eq!(detector.feed(b"\xFF", false), None);
+        assert_eq!(detector.feed(b"\xFEW\x00", false), Some(UTF_16LE));
+        let mut detector = IniDetector::new(true);
+        assert_eq!(detector.feed(b"\xFFa=b\n", true), None);
+    }
+}
@@ -0,0 +1,664 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `std::io` adapters.
+
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+
+use encoding_rs::Encoding;
+
+use crate::Buffer;
+use crate::ChunkSizes;
+use crate::DetectingDecoder;
+use crate::Detector;
+use crate::MalformedError;
+use crate::MalformedPolicy;
+use crate::Verdict;
+
+/// How many bytes `sniff_reader()` reads at most before settling for the
+/// fallback guess.
+pub const DEFAULT_SNIFF_LEN: u64 = 64 * 1024;
+
+/// A reader that reads a stream in a Japanese legacy encoding from an inner
+/// reader and yields it as UTF-8, e.g. for `BufRead::lines()` on legacy
+/// log files.
+///
+/// The bytes are held back until the detector decides (or the inner reader
+/// reaches its end, in which case the detector's fallback guess is used),
+/// as with `DetectingDecoder`, so nothing is yielded before the encoding is
+/// known unless `set_eager_ascii_prefix()` is on. `set_sniff_limit()`
+/// bounds how much is held back. Reads of the inner reader that fail with
+/// `io::ErrorKind::Interrupted` are retried. Other errors of the inner
+/// reader are passed through, and reading may continue after them. The
+/// read size grows as set with `set_chunk_sizes()`.
+///
+/// Under `MalformedPolicy::Error`, a malformed sequence is reported as an
+/// `io::ErrorKind::InvalidData` error that wraps a `MalformedError` after
+/// the text before it, and the stream ends.
+pub struct DetectingReader<R: Read, B: Buffer = Vec<u8>> {
+    reader: R,
+    decoder: DetectingDecoder<B>,
+    chunk_sizes: ChunkSizes,
+    chunk: Vec<u8>,
+    text: String,
+    next: usize,
+    error: Option<MalformedError>,
+    done: bool,
+}
+
+impl<R: Read> DetectingReader<R> {
+    /// Wraps `reader`. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(reader: R, allow_2022: bool) -> Self {
+        DetectingReader::with_detector(reader, Detector::new(allow_2022))
+    }
+
+    /// Wraps `reader` with a configured detector that hasn't been fed yet.
+    pub fn with_detector(reader: R, detector: Detector) -> Self {
+        DetectingReader::with_buffer(reader, detector, Vec::new())
+    }
+}
+
+impl<R: Read, B: Buffer> DetectingReader<R, B> {
+    /// Wraps `reader` with a configured detector that hasn't been fed yet,
+    /// holding the bytes back in `buffer`. See
+    /// `DetectingDecoder::with_buffer()` for what happens when `buffer` is
+    /// full.
+    pub fn with_buffer(reader: R, detector: Detector, buffer: B) -> Self {
+        DetectingReader {
+            reader,
+            decoder: DetectingDecoder::with_buffer(detector, buffer),
+            chunk_sizes: ChunkSizes::DEFAULT,
+            chunk: Vec::new(),
+            text: String::new(),
+            next: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first read.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.decoder.set_malformed_policy(policy);
+    }
+
+    /// Sets how much to read from the inner reader at a time. Defaults to
+    /// `ChunkSizes::DEFAULT`. Call before the first read.
+    pub fn set_chunk_sizes(&mut self, sizes: ChunkSizes) {
+        self.chunk_sizes = sizes;
+    }
+
+    /// Sets the number of held bytes after which the reader commits to the
+    /// detector's current guess. See `DetectingDecoder::set_sniff_limit()`.
+    /// Call before the first read.
+    pub fn set_sniff_limit(&mut self, limit: Option<usize>) {
+        self.decoder.set_sniff_limit(limit);
+    }
+
+    /// Sets whether the ASCII at the start of the stream is yielded right
+    /// away. See `DetectingDecoder::set_eager_ascii_prefix()`. Call before
+    /// the first read.
+    pub fn set_eager_ascii_prefix(&mut self, eager: bool) {
+        self.decoder.set_eager_ascii_prefix(eager);
+    }
+
+    /// The encoding the stream is decoded from or `None` if the detector
+    /// hasn't decided yet.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.encoding()
+    }
+
+    /// Unwraps the inner reader. Bytes read from it but not yet yielded are
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next chunk and decodes whatever can be decoded, replacing
+    /// the consumed text.
+    fn fill(&mut self) -> io::Result<()> {
+        let size = if self.chunk.is_empty() {
+            self.chunk_sizes.first()
+        } else {
+            self.chunk_sizes
+                .next(self.chunk.len(), self.decoder.encoding().is_some())
+        };
+        self.chunk.resize(size, 0);
+        let read = loop {
+            match self.reader.read(&mut self.chunk) {
+                Ok(read) => break read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        let last = read == 0;
+        self.text.clear();
+        self.next = 0;
+        let result = self
+            .decoder
+            .decode_to_string(&self.chunk[..read], &mut self.text, last);
+        if let Err(err) = result {
+            self.error = Some(err);
+            self.done = true;
+        }
+        if last {
+            self.done = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read, B: Buffer> Read for DetectingReader<R, B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read, B: Buffer> BufRead for DetectingReader<R, B> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.next == self.text.len() {
+            if let Some(err) = self.error.take() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            if self.done {
+                break;
+            }
+            self.fill()?;
+        }
+        Ok(&self.text.as_bytes()[self.next..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.next = (self.next + amt).min(self.text.len());
+    }
+}
+
+/// A writer that takes a stream in a Japanese legacy encoding and writes it
+/// as UTF-8 to an inner writer, e.g. on the output side of a proxy.
+///
+/// The bytes are held back until the detector decides (or `finish()` is
+/// called, in which case the detector's fallback guess is used), as with
+/// `DetectingDecoder`. The held prefix is written to the inner writer as
+/// soon as the decision lands, and later writes are converted and passed
+/// through right away. `flush()` flushes the inner writer but does not
+/// force a decision. For filters before detection or sinks other than
+/// writers, use `pipeline::Pipeline` instead.
+///
+/// A write that fails hasn't consumed any of its bytes. If the inner writer
+/// fails once the bytes have been decoded, the write succeeds and the text
+/// is written first on the next call to `write()`, `flush()` or `finish()`,
+/// which fails if the inner writer still does.
+///
+/// Under `MalformedPolicy::Error`, the write that reaches a malformed
+/// sequence fails with an `io::ErrorKind::InvalidData` error that wraps a
+/// `MalformedError` after the text before it has been written. The writer
+/// should not be used after that.
+pub struct DetectingWriter<W: Write> {
+    inner: W,
+    decoder: DetectingDecoder,
+    text: String,
+    written: usize,
+}
+
+impl<W: Write> DetectingWriter<W> {
+    /// Wraps `inner`. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(inner: W, allow_2022: bool) -> Self {
+        DetectingWriter::with_detector(inner, Detector::new(allow_2022))
+    }
+
+    /// Wraps `inner` with a configured detector that hasn't been fed yet.
+    pub fn with_detector(inner: W, detector: Detector) -> Self {
+        DetectingWriter {
+            inner,
+            decoder: DetectingDecoder::with_detector(detector),
+            text: String::new(),
+            written: 0,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first write.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.decoder.set_malformed_policy(policy);
+    }
+
+    /// The encoding the stream is decoded from or `None` if the detector
+    /// hasn't decided yet.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.encoding()
+    }
+
+    /// The inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Signals the end of the stream, writes what was held back or pending,
+    /// flushes the inner writer and returns it.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.process(b"", true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Decodes `buf` and writes whatever text results. A failure of the
+    /// inner writer leaves the text pending when `buf` isn't the end.
+    fn process(&mut self, buf: &[u8], last: bool) -> io::Result<()> {
+        let result = self.decoder.decode_to_string(buf, &mut self.text, last);
+        match self.write_pending() {
+            Err(err) if last => return Err(err),
+            _ => {}
+        }
+        result.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes the text that hasn't been written yet.
+    fn write_pending(&mut self) -> io::Result<()> {
+        while self.written < self.text.len() {
+            match self.inner.write(&self.text.as_bytes()[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => self.written += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.text.clear();
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for DetectingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_pending()?;
+        self.process(buf, false)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+/// Reads from `reader` until the detector decides (or `reader` ends or
+/// `DEFAULT_SNIFF_LEN` bytes have been read, in which case the detector's
+/// fallback guess is used), and returns the verdict together with a reader
+/// that yields the original bytes from the start, e.g. for a parser that
+/// takes raw bytes and a separately known encoding. `allow_2022` has the
+/// same meaning as for `Detector::new()`.
+///
+/// Reads that fail with `io::ErrorKind::Interrupted` are retried. For other
+/// errors, the bytes read so far are lost along with `reader`.
+pub fn sniff_reader<R: Read>(
+    reader: R,
+    allow_2022: bool,
+) -> io::Result<(Verdict, ChainedReader<R>)> {
+    let mut detector = Detector::new(allow_2022);
+    detector.set_max_examined_len(Some(DEFAULT_SNIFF_LEN));
+    sniff_reader_with_detector(reader, detector)
+}
+
+/// The same as `sniff_reader()` but with a configured detector that hasn't
+/// been fed yet. Reading stops at the detector's `set_max_examined_len()`
+/// budget; without one, undecided input (such as ASCII) is read to its end
+/// and held in memory.
+pub fn sniff_reader_with_detector<R: Read>(
+    reader: R,
+    detector: Detector,
+) -> io::Result<(Verdict, ChainedReader<R>)> {
+    sniff_reader_with_chunk_sizes(reader, detector, ChunkSizes::DEFAULT)
+}
+
+/// The same as `sniff_reader_with_detector()` but reading as set by
+/// `chunk_sizes` instead of `ChunkSizes::DEFAULT`: the sniffing reads grow
+/// from `initial` bytes, and the returned reader refills its buffer with
+/// reads of `max` bytes.
+pub fn sniff_reader_with_chunk_sizes<R: Read>(
+    reader: R,
+    detector: Detector,
+    chunk_sizes: ChunkSizes,
+) -> io::Result<(Verdict, ChainedReader<R>)> {
+    sniff_reader_with_buffer(reader, detector, chunk_sizes, Vec::new())
+}
+
+/// The same as `sniff_reader_with_chunk_sizes()` but holding the sniffed
+/// prefix and the later reads in `buffer`. Sniffing stops as at the
+/// detector's budget once the next read wouldn't fit in `buffer`, and the
+/// returned reader fails with an error that wraps a `CapacityError` if
+/// `buffer` cannot hold a refill of `chunk_sizes.max` bytes.
+pub fn sniff_reader_with_buffer<R: Read, B: Buffer>(
+    mut reader: R,
+    mut detector: Detector,
+    chunk_sizes: ChunkSizes,
+    mut buffer: B,
+) -> io::Result<(Verdict, ChainedReader<R, B>)> {
+    buffer.clear();
+    let mut size = chunk_sizes.first();
+    loop {
+        let start = buffer.as_slice().len();
+        if buffer.try_resize(start + size).is_err() {
+            break;
+        }
+        let read = loop {
+            match reader.read(&mut buffer.as_mut_slice()[start..]) {
+                Ok(read) => break read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        buffer.try_resize(start + read).map_err(io::Error::other)?;
+        let last = read == 0;
+        if detector
+            .feed_verdict(&buffer.as_slice()[start..], last)
+            .is_some()
+            || last
+            || detector.budget_exhausted()
+        {
+            break;
+        }
+        size = chunk_sizes.next(size, false);
+    }
+    let verdict = detector.outcome().guess();
+    let buffer_len = chunk_sizes.next(size, true);
+    Ok((verdict, ChainedReader::new(buffer, reader, buffer_len)))
+}
+
+/// The reader returned by `sniff_reader()`: yields the sniffed prefix and
+/// then the rest of the inner reader, buffering the latter for `BufRead`.
+pub struct ChainedReader<R: Read, B: Buffer = Vec<u8>> {
+    reader: R,
+    buffer: B,
+    next: usize,
+    /// The size of the reads of the inner reader after the prefix.
+    buffer_len: usize,
+}
+
+impl<R: Read, B: Buffer> ChainedReader<R, B> {
+    fn new(prefix: B, reader: R, buffer_len: usize) -> Self {
+        ChainedReader {
+            reader,
+            buffer: prefix,
+            next: 0,
+            buffer_len,
+        }
+    }
+
+    /// The inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps the inner reader. Bytes of the prefix or buffered from the
+    /// inner reader that haven't been yielded yet are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read, B: Buffer> Read for ChainedReader<R, B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.next == self.buffer.as_slice().len() && buf.len() >= self.buffer_len {
+            return self.reader.read(buf);
+        }
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read, B: Buffer> BufRead for ChainedReader<R, B> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.next == self.buffer.as_slice().len() {
+            self.next = 0;
+            if let Err(err) = self.buffer.try_resize(self.buffer_len) {
+                self.buffer.clear();
+                return Err(io::Error::other(err));
+            }
+            match self.reader.read(self.buffer.as_mut_slice()) {
+                Ok(read) => self.buffer.try_resize(read).map_err(io::Error::other)?,
+                Err(err) => {
+                    self.buffer.clear();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(&self.buffer.as_slice()[self.next..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.next = (self.next + amt).min(self.buffer.as_slice().len());
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+
+    #[test]
+    fn test_lines() {
+        // "あ" and "い" in EUC-JP.
+        let input: &[u8] = b"log\n\xA4\xA2\n\xA4\xA4";
+        let mut reader = DetectingReader::new(input, true);
+        reader.set_chunk_sizes(ChunkSizes::fixed(2));
+        let lines: Vec<String> = reader.by_ref().lines().collect::<io::Result<_>>().unwrap();
+        assert_eq!(lines, ["log", "あ", "い"]);
+        assert_eq!(reader.encoding(), Some(EUC_JP));
+    }
+
+    #[test]
+    fn test_sniff_limit() {
+        // Kanji that are valid in both Shift_JIS and EUC-JP, then "あ" in
+        // EUC-JP.
+        let input: &[u8] = b"\xE0\xE0\xE0\xE0\xA4\xA2";
+        let mut reader = DetectingReader::new(input, true);
+        reader.set_chunk_sizes(ChunkSizes::fixed(2));
+        reader.set_sniff_limit(Some(4));
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(reader.encoding(), Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(
+            text,
+            encoding_rs::SHIFT_JIS.decode_without_bom_handling(input).0
+        );
+    }
+
+    #[test]
+    fn test_eager_ascii_prefix() {
+        // "あ" in EUC-JP after ASCII.
+        let input: &[u8] = b"ab\ncd\xA4\xA2";
+        let mut reader = DetectingReader::new(input, true);
+        reader.set_chunk_sizes(ChunkSizes::fixed(3));
+        reader.set_eager_ascii_prefix(true);
+        assert_eq!(reader.fill_buf().unwrap(), b"ab\n");
+        assert_eq!(reader.encoding(), None);
+        reader.consume(3);
+        assert_eq!(reader.fill_buf().unwrap(), b"cd");
+        reader.consume(2);
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "あ");
+        assert_eq!(reader.encoding(), Some(EUC_JP));
+        // Without it, the ASCII waits for the verdict.
+        let mut reader = DetectingReader::new(input, true);
+        reader.set_chunk_sizes(ChunkSizes::fixed(3));
+        assert_eq!(reader.fill_buf().unwrap(), b"ab\ncd");
+        assert_eq!(reader.encoding(), Some(EUC_JP));
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = DetectingWriter::new(Vec::new(), true);
+        writer.write_all(b"a").unwrap();
+        assert!(writer.get_ref().is_empty());
+        // "あ" in EUC-JP.
+        writer.write_all(b"\xA4\xA2b").unwrap();
+        assert_eq!(writer.encoding(), Some(EUC_JP));
+        assert_eq!(&writer.get_ref()[..], "aあb".as_bytes());
+        writer.write_all(b"\xA4").unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(&out[..], "aあb\u{FFFD}".as_bytes());
+        // Undecided until the end.
+        let mut writer = DetectingWriter::new(Vec::new(), true);
+        writer.write_all(b"plain").unwrap();
+        assert_eq!(&writer.finish().unwrap()[..], b"plain");
+        // Text that the inner writer fails to take is kept and written
+        // first on the next call, which fails without consuming anything
+        // while the inner writer does.
+        let mut writer = DetectingWriter::new(Failing(Vec::new(), 2), true);
+        assert_eq!(writer.write(b"\xA4\xA2").unwrap(), 2);
+        assert!(writer.get_ref().0.is_empty());
+        assert_eq!(writer.write(b"b").unwrap_err().kind(), io::ErrorKind::Other);
+        writer.write_all(b"b").unwrap();
+        assert_eq!(&writer.finish().unwrap().0[..], "あb".as_bytes());
+    }
+
+    /// A writer whose first writes fail.
+    struct Failing(Vec<u8>, usize);
+
+    impl Write for Failing {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.1 > 0 {
+                self.1 -= 1;
+                return Err(io::ErrorKind::Other.into());
+            }
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_malformed_error() {
+        let input: &[u8] = b"\x82\xA0\xFF";
+        let mut reader = DetectingReader::new(input, true);
+        reader.set_malformed_policy(MalformedPolicy::Error);
+        let mut text = String::new();
+        let err = reader.read_to_string(&mut text).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(text, "あ");
+        let mut rest = Vec::new();
+        assert_eq!(reader.read_to_end(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sniff_reader() {
+        // "あ" in EUC-JP after enough ASCII for several reads.
+        let mut input = vec![b'a'; 3000];
+        input.extend_from_slice(b"\xA4\xA2z");
+        let (verdict, mut reader) = sniff_reader(&input[..], true).unwrap();
+        assert_eq!(verdict, Verdict::EucJp);
+        let mut replayed = Vec::new();
+        reader.read_to_end(&mut replayed).unwrap();
+        assert_eq!(replayed, input);
+        // The budget settles for the fallback.
+        let mut detector = Detector::new(true);
+        detector.set_max_examined_len(Some(2));
+        let (verdict, reader) =
+            sniff_reader_with_detector(&b"abc\n\xA4\xA2"[..], detector).unwrap();
+        assert_eq!(verdict, Verdict::ShiftJis);
+        let lines: Vec<Vec<u8>> = reader.split(b'\n').collect::<io::Result<_>>().unwrap();
+        assert_eq!(lines, [&b"abc"[..], &b"\xA4\xA2"[..]]);
+    }
+
+    /// A reader that records the sizes of the buffers it is given.
+    struct Recording<'a>(&'a [u8], Vec<usize>);
+
+    impl<'a> Read for Recording<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1.push(buf.len());
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_sniff_chunk_sizes() {
+        // "あ" in EUC-JP in the second read.
+        let mut input = vec![b'a'; 10];
+        input.extend_from_slice(b"\xA4\xA2");
+        input.resize(40, b'b');
+        let sizes = ChunkSizes {
+            initial: 4,
+            max: 16,
+        };
+        let (verdict, mut reader) = sniff_reader_with_chunk_sizes(
+            Recording(&input, Vec::new()),
+            Detector::new(true),
+            sizes,
+        )
+        .unwrap();
+        assert_eq!(verdict, Verdict::EucJp);
+        let mut replayed = Vec::new();
+        reader.read_until(0, &mut replayed).unwrap();
+        assert_eq!(replayed, input);
+        assert_eq!(reader.get_ref().1, [4, 8, 16, 16, 16]);
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        // "あ" in EUC-JP.
+        let mut storage = [0u8; 8];
+        let mut reader = DetectingReader::with_buffer(
+            &b"abc\xA4\xA2"[..],
+            Detector::new(true),
+            crate::SliceBuffer::new(&mut storage[..]),
+        );
+        reader.set_chunk_sizes(ChunkSizes::fixed(2));
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "abcあ");
+        assert_eq!(reader.encoding(), Some(EUC_JP));
+
+        // The sniffing stops at the capacity, and refills that don't fit
+        // fail.
+        let sizes = ChunkSizes { initial: 2, max: 4 };
+        let mut storage = [0u8; 7];
+        let input: &[u8] = b"abcdefgh\xA4\xA2";
+        let (verdict, mut reader) = sniff_reader_with_buffer(
+            input,
+            Detector::new(true),
+            sizes,
+            crate::SliceBuffer::new(&mut storage[..]),
+        )
+        .unwrap();
+        assert_eq!(verdict, Verdict::ShiftJis);
+        assert_eq!(reader.fill_buf().unwrap(), b"abcdef");
+        reader.consume(6);
+        assert_eq!(reader.fill_buf().unwrap(), b"gh\xA4\xA2");
+        let mut storage = [0u8; 3];
+        let (_, mut reader) = sniff_reader_with_buffer(
+            input,
+            Detector::new(true),
+            sizes,
+            crate::SliceBuffer::new(&mut storage[..]),
+        )
+        .unwrap();
+        assert_eq!(reader.fill_buf().unwrap(), b"ab");
+        reader.consume(2);
+        let err = reader.fill_buf().unwrap_err();
+        assert!(err.get_ref().unwrap().is::<crate::CapacityError>());
+    }
+}
@@ -7,6 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![doc(html_root_url = "https://docs.rs/shift_or_euc/0.1.0")]
 
 //! A Japanese legacy encoding detector for detecting between Shift_JIS,
@@ -24,6 +25,14 @@
 //! size footprint that this crate adds on top of
 //! [`encoding_rs`](https://docs.rs/crate/encoding_rs) is tiny.
 //!
+//! # Getting Started
+//!
+//! `easy::detect()` and `easy::decode()` cover the common case of a whole
+//! file or HTTP body of unknown encoding, and `easy` is the layer of the API
+//! that stays stable across minor versions. The rest of the crate, whose
+//! common names `prelude` gathers, is for callers with constraints of their
+//! own, such as streaming input or a fixed set of candidate encodings.
+//!
 //! # Licensing
 //!
 //! See the file named [COPYRIGHT](https://github.com/hsivonen/shift_or_euc/blob/master/COPYRIGHT).
@@ -33,15 +42,15 @@
 //! The detector is based on two observations:
 //!
 //! 1. The ISO-2022-JP escape sequences don't normally occur in Shift_JIS or
-//! EUC-JP, so encountering such an escape sequence (before non-ASCII has been
-//! encountered) can be taken as indication of ISO-2022-JP.
+//!    EUC-JP, so encountering such an escape sequence (before non-ASCII has
+//!    been encountered) can be taken as indication of ISO-2022-JP.
 //! 2. When normal (full-with) kana or common kanji encoded as Shift_JIS is
-//! decoded as EUC-JP, or vice versa, the result is either an error or
-//! half-width katakana, and it's very uncommon for Japanese HTML to have
-//! half-width katakana character before a normal kana or common kanji
-//! character. Therefore, if decoding as Shift_JIS results in error or
-//! have-width katakana, the detector decides that the content is EUC-JP, and
-//! vice versa.
+//!    decoded as EUC-JP, or vice versa, the result is either an error or
+//!    half-width katakana, and it's very uncommon for Japanese HTML to have
+//!    half-width katakana character before a normal kana or common kanji
+//!    character. Therefore, if decoding as Shift_JIS results in error or
+//!    have-width katakana, the detector decides that the content is EUC-JP,
+//!    and vice versa.
 //!
 //! # Failure Modes
 //!
@@ -62,19 +71,400 @@
 //! Japanese Wikipedia has articles with such titles.) If the detector is
 //! undecided, falling back to Shift_JIS is typically the Web oriented better
 //! guess.
+//!
+//! # Worst-Case Work
+//!
+//! Each byte takes constant work: the candidates are state machines that
+//! never look back. The only state that can grow with the input is the
+//! replay buffer of `Detector::set_require_certain_iso_2022_jp()` in
+//! validation mode, which ends at a JIS X 0208 character.
+//!
+//! Input crafted to keep ISO-2022-JP possible for long, such as a flood of
+//! skipped ANSI sequences or of redundant designations, is cut off after
+//! `MAX_ESCAPE_SEQUENCES` escape sequences or an ANSI sequence longer than
+//! `MAX_ANSI_SEQUENCE_LEN` bytes, which rules out ISO-2022-JP and makes
+//! `Detector::suspicious()` return `Suspicious::EscapeFlood`.
+//!
+//! Input crafted to stay ambiguous between Shift_JIS and EUC-JP costs no
+//! more than any other input, but since Japanese text decides within its
+//! first few characters, `Detector::suspicious()` returns
+//! `Suspicious::Ambiguity` after `MAX_AMBIGUOUS_BYTES` non-ASCII bytes that
+//! don't tell the encodings apart.
+//!
+//! # Memory Footprint
+//!
+//! A `Detector` keeps all its state inline: on 64-bit targets, it is about
+//! 740 bytes with the default features and about 330 bytes with
+//! `default-features = false`. Of the difference, the two `encoding_rs`
+//! decoders take about 180 bytes and the scores of the `scoring` feature
+//! the rest. The detector has no tables of its own to load and allocates
+//! on the heap only for the replay buffer of
+//! `Detector::set_require_certain_iso_2022_jp()` in validation mode, so
+//! otherwise, detection doesn't allocate at all.
+//!
+//! # Cargo Features
+//!
+//! The default `std` feature links the standard library and enables the
+//! parts of the crate that need it: the `io`, `pipeline` and `scan` modules,
+//! `DecodedChars`, `detect_with_deadline()` and the `std::error::Error`
+//! impls. Without it, the crate is `#![no_std]` but still needs the `alloc`
+//! crate, so `Detector` can run on embedded targets that have an allocator.
+//! The `test-support` and `service` features imply `std`.
+//!
+//! The default `encoding_rs` feature makes the API talk in terms of
+//! `&'static encoding_rs::Encoding` and enables the parts of the crate that
+//! decode text. Without it, the crate does not depend on `encoding_rs` at
+//! all: `Detector::feed_verdict()` returns a crate-local `Verdict`, and
+//! Shift_JIS and EUC-JP are validated by hand-written state machines that
+//! consult bitmaps of the mapped index pointers (about 2.5 KB) instead of
+//! decoding. With it, the same state machines are available at run time as
+//! `Backend::Validator` for builds of `encoding_rs` with trimmed decode
+//! tables; `capabilities()` tells whether the linked build decodes in full.
+//!
+//! The optional `cli` feature builds the `shift-or-euc` command-line tool,
+//! which prints the detected encoding of a file or stdin and can convert it
+//! to UTF-8 (`--convert` to stdout, `--in-place` over the file).
+//!
+//! The optional `service` feature adds the `service` module, a detection
+//! endpoint that can be mounted in any HTTP server framework.
+//!
+//! The optional `reference-impl` feature turns off the fast path that skips
+//! runs of ASCII between characters, pushing every byte to the Shift_JIS and
+//! EUC-JP candidates instead. The verdicts are the same either way (see
+//! `testing::reference_verdict()`); the feature is a fallback switch.
+//!
+//! The optional `debug-trace` feature makes the detector record the steps
+//! of its candidates on every byte it examines, as runs of bytes, for
+//! `Detector::trace()`. The trace is for analyzing misdetections offline:
+//! it costs a branch per byte and grows on the heap with the input.
+//!
+//! The default `scoring` feature adds the character class scoring of
+//! `Detector::set_scoring()` and what builds on it: `classify()`,
+//! `calibrate_scoring()`, `class_breakdown()` and the `normalizer` module.
+//!
+//! The default `diagnostics` feature adds `DetectionOutcome::explain()`
+//! and `Detector::policy()`, which format text and so pull in the
+//! formatting machinery of `core`. The `service` feature implies it.
+//!
+//! The optional `simdutf8` feature makes the UTF-8 candidate of
+//! `Detector::set_allow_utf8()` validate with the `simdutf8` crate, so that
+//! input that is UTF-8 all along costs about as much to examine as ASCII.
+//! The verdicts are the same either way.
+//!
+//! # Minimal Configuration
+//!
+//! For a microcontroller that only has to tell Shift_JIS from EUC-JP (or
+//! ISO-2022-JP), depend on the crate with `default-features = false`. That
+//! leaves the hand-written validators, whose only data are the bitmaps of
+//! the mapped index pointers (about 2.5 KB), and none of the scoring,
+//! diagnostics, `encoding_rs` tables or replay buffering. The code that
+//! `Detector::new()` and `Detector::feed_verdict()` reach is then about
+//! 7 KB when compiled for x86-64 with `opt-level = "s"`, and whatever else
+//! the crate offers is only linked if called. Keep
+//! `debug-trace` off, since it records on the heap, and prefer
+//! `Detector::new()` and `feed_verdict()` over the helpers that build on
+//! them.
 
-use encoding_rs::Decoder;
-use encoding_rs::DecoderResult;
+extern crate alloc;
+
+#[cfg(feature = "debug-trace")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "encoding_rs")]
 use encoding_rs::Encoding;
+#[cfg(feature = "encoding_rs")]
 use encoding_rs::EUC_JP;
+#[cfg(feature = "encoding_rs")]
 use encoding_rs::ISO_2022_JP;
+#[cfg(feature = "encoding_rs")]
 use encoding_rs::SHIFT_JIS;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::UTF_8;
+
+#[cfg(feature = "encoding_rs")]
+mod archive;
+#[cfg(feature = "encoding_rs")]
+mod backend;
+#[cfg(feature = "encoding_rs")]
+mod bom;
+mod buffer;
+mod candidates;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+mod chars;
+mod chunks;
+#[cfg(feature = "scoring")]
+mod classify;
+mod config;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+mod deadline;
+mod designations;
+#[cfg(feature = "encoding_rs")]
+mod detecting;
+#[cfg(feature = "encoding_rs")]
+pub mod easy;
+#[cfg(feature = "encoding_rs")]
+pub mod export;
+#[cfg(all(feature = "encoding_rs", any(test, feature = "test-support")))]
+pub mod golden;
+mod hash;
+#[cfg(feature = "encoding_rs")]
+pub mod html;
+mod in_place;
+#[cfg(feature = "encoding_rs")]
+mod ini;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub mod io;
+#[cfg(feature = "encoding_rs")]
+mod lines;
+#[cfg(feature = "encoding_rs")]
+mod malformed;
+mod needed;
+#[cfg(all(feature = "encoding_rs", feature = "scoring"))]
+pub mod normalizer;
+mod outcome;
+#[cfg(feature = "encoding_rs")]
+mod pair;
+mod partial;
+#[cfg(feature = "encoding_rs")]
+mod pdf;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub mod pipeline;
+#[cfg(feature = "diagnostics")]
+mod policy;
+mod prefilter;
+pub mod prelude;
+#[cfg(feature = "encoding_rs")]
+mod progressive;
+mod race;
+pub mod raw;
+#[cfg(feature = "encoding_rs")]
+mod records;
+#[cfg(feature = "encoding_rs")]
+mod repair;
+#[cfg(feature = "encoding_rs")]
+mod representability;
+#[cfg(feature = "encoding_rs")]
+mod rules;
+mod sampling;
+#[cfg(feature = "std")]
+pub mod scan;
+#[cfg(feature = "scoring")]
+mod scoring;
+#[cfg(feature = "encoding_rs")]
+mod segments;
+#[cfg(feature = "service")]
+pub mod service;
+mod session;
+#[cfg(feature = "encoding_rs")]
+mod source;
+mod state;
+mod tables;
+#[cfg(all(feature = "encoding_rs", any(test, feature = "test-support")))]
+pub mod testing;
+#[cfg(feature = "debug-trace")]
+mod trace;
+mod transport;
+mod two_way;
+mod utf8;
+#[cfg(feature = "encoding_rs")]
+mod validation;
+
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::detect_cpio_names;
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::detect_cpio_names_interned;
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::detect_tar_names;
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::detect_tar_names_interned;
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::ArchiveName;
+#[cfg(feature = "encoding_rs")]
+pub use crate::archive::ArchiveNames;
+#[cfg(feature = "encoding_rs")]
+pub use crate::backend::capabilities;
+#[cfg(feature = "encoding_rs")]
+pub use crate::backend::Backend;
+#[cfg(feature = "encoding_rs")]
+pub use crate::backend::Capabilities;
+#[cfg(feature = "encoding_rs")]
+pub use crate::bom::detect_with_bom;
+#[cfg(feature = "encoding_rs")]
+pub use crate::bom::BomDetector;
+pub use crate::buffer::Buffer;
+pub use crate::buffer::CapacityError;
+pub use crate::buffer::SliceBuffer;
+pub use crate::candidates::Candidates;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub use crate::chars::DecodedChars;
+pub use crate::chunks::ChunkSizes;
+#[cfg(feature = "scoring")]
+pub use crate::classify::classify;
+#[cfg(feature = "scoring")]
+pub use crate::classify::classify_with;
+#[cfg(feature = "scoring")]
+pub use crate::classify::Action;
+#[cfg(feature = "scoring")]
+pub use crate::classify::Classification;
+#[cfg(feature = "scoring")]
+pub use crate::classify::ClassifyThresholds;
+pub use crate::config::DetectorBuilder;
+pub use crate::config::DetectorConfig;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub use crate::deadline::detect_with_deadline;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub use crate::deadline::DeadlineResult;
+pub use crate::designations::scan_designations;
+pub use crate::designations::DesignationScanner;
+pub use crate::designations::Designations;
+#[cfg(feature = "encoding_rs")]
+pub use crate::detecting::DetectingDecoder;
+pub use crate::hash::hash_examined;
+pub use crate::in_place::detect_in_place;
+#[cfg(feature = "encoding_rs")]
+pub use crate::ini::IniDetector;
+#[cfg(feature = "encoding_rs")]
+pub use crate::lines::LineDecoder;
+#[cfg(feature = "encoding_rs")]
+pub use crate::malformed::MalformedError;
+#[cfg(feature = "encoding_rs")]
+pub use crate::malformed::MalformedPolicy;
+pub use crate::needed::bytes_needed;
+pub use crate::needed::BytesNeeded;
+pub use crate::outcome::DetectionOutcome;
+#[cfg(feature = "diagnostics")]
+pub use crate::outcome::Verbosity;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pair::PairDetector;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pair::PairOutcome;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pair::PairReason;
+pub use crate::partial::detect_partial;
+pub use crate::partial::detect_partial_with;
+pub use crate::partial::PartialDetection;
+pub use crate::partial::Prospect;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pdf::detect_pdf_literals;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pdf::unescape_pdf_literal;
+#[cfg(feature = "encoding_rs")]
+pub use crate::pdf::PdfStringDetector;
+#[cfg(feature = "diagnostics")]
+pub use crate::policy::Policy;
+#[cfg(feature = "diagnostics")]
+pub use crate::policy::Rule;
+pub use crate::prefilter::detect_prefiltered;
+pub use crate::prefilter::prefilter;
+pub use crate::prefilter::prefilter_with_thresholds;
+pub use crate::prefilter::PrefilterThresholds;
+pub use crate::prefilter::Prefiltered;
+pub use crate::prefilter::PREFILTER_SAMPLE_LEN;
+#[cfg(feature = "encoding_rs")]
+pub use crate::progressive::ProgressiveDecoder;
+#[cfg(feature = "encoding_rs")]
+pub use crate::progressive::Revision;
+pub use crate::raw::MAX_ANSI_SEQUENCE_LEN;
+pub use crate::raw::MAX_ESCAPE_SEQUENCES;
+pub use crate::raw::MAX_PENDING;
+#[cfg(feature = "encoding_rs")]
+pub use crate::records::RecordDetector;
+#[cfg(feature = "encoding_rs")]
+pub use crate::records::RecordStructure;
+#[cfg(feature = "encoding_rs")]
+pub use crate::repair::suggest_repairs;
+#[cfg(feature = "encoding_rs")]
+pub use crate::repair::suggest_repairs_with_weights;
+#[cfg(feature = "encoding_rs")]
+pub use crate::repair::Repair;
+#[cfg(feature = "encoding_rs")]
+pub use crate::repair::RepairWeights;
+#[cfg(feature = "encoding_rs")]
+pub use crate::repair::WrongDecoding;
+#[cfg(feature = "encoding_rs")]
+pub use crate::representability::Representability;
+#[cfg(feature = "encoding_rs")]
+pub use crate::rules::CharRule;
+#[cfg(feature = "encoding_rs")]
+pub use crate::rules::RuleAction;
+pub use crate::sampling::detect_sampled;
+pub use crate::sampling::Sampled;
+pub use crate::sampling::SamplingConfig;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::calibrate_scoring;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::class_breakdown;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::Calibration;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::CharacterClasses;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::ClassBreakdown;
+#[cfg(feature = "scoring")]
+pub use crate::scoring::ScoringThresholds;
+#[cfg(feature = "encoding_rs")]
+pub use crate::segments::SegmentDetector;
+#[cfg(feature = "encoding_rs")]
+pub use crate::segments::SegmentVerdict;
+pub use crate::session::SessionDetector;
+pub use crate::session::Switch;
+#[cfg(feature = "encoding_rs")]
+pub use crate::source::SourceDetector;
+pub use crate::state::DetectorState;
+#[cfg(feature = "debug-trace")]
+pub use crate::trace::TraceRun;
+pub use crate::transport::check_transport;
+pub use crate::transport::Mangling;
+pub use crate::two_way::TwoWayDetector;
+
+#[cfg(feature = "encoding_rs")]
+pub use crate::validation::validate;
+#[cfg(feature = "encoding_rs")]
+pub use crate::validation::MalformedSequences;
+#[cfg(feature = "encoding_rs")]
+pub use crate::validation::ValidationReport;
+#[cfg(feature = "encoding_rs")]
+pub use crate::validation::MAX_RECORDED_OFFSETS;
+
+use crate::hash::fnv1a_update;
+use crate::hash::FNV_OFFSET_BASIS;
+use crate::race::Race;
+use crate::raw::Iso2022JpCertainty;
+use crate::raw::Iso2022JpMachine;
+use crate::raw::Step;
+#[cfg(feature = "scoring")]
+use crate::scoring::Scorer;
+use crate::utf8::Utf8Candidate;
+#[cfg(feature = "encoding_rs")]
+use crate::validation::Continuation;
+
+#[cfg(feature = "encoding_rs")]
+use crate::backend::EucJpCandidate;
+#[cfg(feature = "encoding_rs")]
+use crate::backend::ShiftJisCandidate;
+#[cfg(not(feature = "encoding_rs"))]
+use crate::raw::EucJpValidator as EucJpCandidate;
+#[cfg(not(feature = "encoding_rs"))]
+use crate::raw::ShiftJisValidator as ShiftJisCandidate;
+
+/// Returns the index of the first non-ASCII byte or the length of the
+/// buffer if not found.
+fn find_non_ascii(buffer: &[u8]) -> usize {
+    #[cfg(feature = "encoding_rs")]
+    let ascii_up_to = Encoding::ascii_valid_up_to(buffer);
+    #[cfg(not(feature = "encoding_rs"))]
+    let ascii_up_to = buffer
+        .iter()
+        .position(|&b| b >= 0x80)
+        .unwrap_or(buffer.len());
+    ascii_up_to
+}
 
 /// Returns the index of the first non-ASCII byte or the first
 /// 0x1B, whichever comes first, or the length of the buffer
 /// if neither is found.
 fn find_non_ascii_or_escape(buffer: &[u8]) -> usize {
-    let ascii_up_to = Encoding::ascii_valid_up_to(buffer);
+    let ascii_up_to = find_non_ascii(buffer);
     if let Some(escape) = memchr::memchr(0x1B, &buffer[..ascii_up_to]) {
         escape
     } else {
@@ -82,49 +472,449 @@ fn find_non_ascii_or_escape(buffer: &[u8]) -> usize {
     }
 }
 
-/// Feed decoder with one byte (if `last` is `false`) or EOF (if `last` is
-/// `true`). `byte` is ignored if `last` is `true`.
-/// Returns `true` if there was no rejection or `false` upon rejecting the
-/// encoding hypothesis represented by this decoder.
-#[inline(always)]
-fn feed_decoder(decoder: &mut Decoder, byte: u8, last: bool) -> bool {
-    let mut output = [0u16; 1];
-    let input = [byte];
-    let (result, _read, written) = decoder.decode_to_utf16_without_replacement(
-        if last { b"" } else { &input },
-        &mut output,
-        last,
-    );
-    match result {
-        DecoderResult::InputEmpty => {
-            if written == 1 {
-                match output[0] {
-                    0xFF61...0xFF9F => {
-                        return false;
-                    }
-                    _ => {}
-                }
-            }
+/// A guess made by the detector.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    /// Shift_JIS.
+    ShiftJis,
+    /// EUC-JP.
+    EucJp,
+    /// ISO-2022-JP.
+    Iso2022Jp,
+    /// UTF-8 (see `Detector::set_allow_utf8()`).
+    Utf8,
+}
+
+impl Verdict {
+    /// The name of the encoding as in the Encoding Standard.
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::ShiftJis => "Shift_JIS",
+            Verdict::EucJp => "EUC-JP",
+            Verdict::Iso2022Jp => "ISO-2022-JP",
+            Verdict::Utf8 => "UTF-8",
+        }
+    }
+
+    /// The label to pass to the browser's `TextDecoder` constructor, e.g.
+    /// `shift_jis`.
+    ///
+    /// This allows a Web UI to run only the detector, built without the
+    /// `encoding_rs` feature, and leave the decoding of the input to the
+    /// browser's native `TextDecoder`, avoiding the need to ship the
+    /// decoding tables to the client.
+    pub fn text_decoder_label(self) -> &'static str {
+        match self {
+            Verdict::ShiftJis => "shift_jis",
+            Verdict::EucJp => "euc-jp",
+            Verdict::Iso2022Jp => "iso-2022-jp",
+            Verdict::Utf8 => "utf-8",
         }
-        DecoderResult::Malformed(_, _) => {
-            return false;
+    }
+
+    /// The corresponding `encoding_rs` encoding.
+    #[cfg(feature = "encoding_rs")]
+    pub fn encoding(self) -> &'static Encoding {
+        match self {
+            Verdict::ShiftJis => SHIFT_JIS,
+            Verdict::EucJp => EUC_JP,
+            Verdict::Iso2022Jp => ISO_2022_JP,
+            Verdict::Utf8 => UTF_8,
         }
-        DecoderResult::OutputFull => {
-            unreachable!();
+    }
+}
+
+/// How strong the evidence for a verdict is.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Certainty {
+    /// The verdict rests on an ISO-2022-JP escape sequence alone, which
+    /// stray 0x1B bytes in binary-ish data can mimic, or, for a
+    /// `SegmentVerdict`, on evidence after a gap in the stream.
+    Tentative,
+    /// The input so far is only valid in the decided encoding or, for
+    /// ISO-2022-JP, contains a complete JIS X 0208 character.
+    Certain,
+    /// The input is valid in both Shift_JIS and EUC-JP and the verdict
+    /// rests on the character class scores of `Detector::set_scoring()`.
+    Scored,
+    /// The same as `Scored` but with a score margin that reaches the
+    /// `likely_margin` of `Detector::set_scoring_thresholds()`.
+    Likely,
+    /// Nothing in the input decided and the verdict is the default set with
+    /// `Detector::set_undecided_default()`.
+    Guessed,
+}
+
+/// What a `Detector` does with a DEL byte or a standalone C1-range byte.
+/// See `Detector::set_del_treatment()` and `Detector::set_c1_treatment()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum EdgeByteTreatment {
+    /// The byte counts as the WHATWG decoders see it. This is the default.
+    #[default]
+    Evidence,
+    /// The byte is skipped, as if it wasn't in the input.
+    Ignore,
+    /// The byte stops detection: the detector stays undecided and
+    /// `Detector::edge_byte_error()` reports the offset of the byte.
+    Error,
+}
+
+/// Why a `Detector` decided what it decided. See
+/// `DetectionOutcome::reason()`.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Reason {
+    /// An ISO-2022-JP designation escape sequence decided ISO-2022-JP.
+    Iso2022JpEscape,
+    /// A JIS X 0208 character after an ISO-2022-JP designation escape
+    /// sequence decided ISO-2022-JP (see
+    /// `Detector::set_require_certain_iso_2022_jp()`).
+    Iso2022JpCharacter,
+    /// The stream ended after an ISO-2022-JP designation escape sequence
+    /// before a JIS X 0208 character.
+    Iso2022JpEndOfStream,
+    /// A malformed sequence when decoding as EUC-JP decided Shift_JIS.
+    EucJpMalformed,
+    /// Half-width katakana when decoding as EUC-JP decided Shift_JIS.
+    EucJpHalfWidthKatakana,
+    /// A character that the caller's rule disqualifies when decoding as
+    /// EUC-JP decided Shift_JIS (see `Detector::set_char_rule()`).
+    EucJpRule,
+    /// An incomplete EUC-JP character at the end of the stream decided
+    /// Shift_JIS.
+    EucJpTruncated,
+    /// A JIS X 0212 character decided EUC-JP (see
+    /// `Detector::set_jis_x_0212_decisive()`).
+    JisX0212,
+    /// A malformed sequence when decoding as Shift_JIS decided EUC-JP.
+    ShiftJisMalformed,
+    /// Half-width katakana when decoding as Shift_JIS decided EUC-JP.
+    ShiftJisHalfWidthKatakana,
+    /// A character that the caller's rule disqualifies when decoding as
+    /// Shift_JIS decided EUC-JP (see `Detector::set_char_rule()`).
+    ShiftJisRule,
+    /// An incomplete Shift_JIS character at the end of the stream decided
+    /// EUC-JP.
+    ShiftJisTruncated,
+    /// The character class scores decided (see `Detector::set_scoring()`).
+    Scored,
+    /// Well-formed UTF-8 decided UTF-8 (see `Detector::set_allow_utf8()`).
+    Utf8,
+    /// The stream was empty or only ASCII whitespace, which decided the
+    /// verdict set with `Detector::set_blank_verdict()`.
+    Blank,
+    /// The stream was well-formed ISO-2022-JP to its end after an
+    /// ISO-2022-JP designation escape sequence (see
+    /// `Detector::set_strict_iso_2022_jp()`).
+    Iso2022JpStructure,
+    /// Nothing decided by the end of the stream, so the default set with
+    /// `Detector::set_undecided_default()` was returned.
+    UndecidedDefault,
+    /// The budget of `Detector::set_max_bytes()` ran out without a verdict,
+    /// so the detector committed to its best guess.
+    ByteBudget,
+}
+
+impl Reason {
+    /// The id of the `Policy` rule that fired.
+    pub fn rule_id(self) -> &'static str {
+        match self {
+            Reason::Iso2022JpEscape => "iso-2022-jp-escape",
+            Reason::Iso2022JpCharacter | Reason::Iso2022JpEndOfStream => "iso-2022-jp-certain",
+            Reason::EucJpMalformed | Reason::EucJpHalfWidthKatakana | Reason::EucJpRule => {
+                "euc-jp-error"
+            }
+            Reason::EucJpTruncated => "euc-jp-truncated",
+            Reason::JisX0212 => "euc-jp-jis-x-0212",
+            Reason::ShiftJisMalformed
+            | Reason::ShiftJisHalfWidthKatakana
+            | Reason::ShiftJisRule => "shift-jis-error",
+            Reason::ShiftJisTruncated => "shift-jis-truncated",
+            Reason::Scored => "frequency-scoring",
+            Reason::Utf8 => "utf-8",
+            Reason::Blank => "blank",
+            Reason::Iso2022JpStructure => "iso-2022-jp-strict",
+            Reason::UndecidedDefault => "undecided-default",
+            Reason::ByteBudget => "byte-budget",
         }
     }
-    true
+}
+
+/// The number of non-ASCII bytes that decode in both Shift_JIS and EUC-JP
+/// without telling them apart, after which `Detector::suspicious()` returns
+/// `Suspicious::Ambiguity`.
+pub const MAX_AMBIGUOUS_BYTES: u64 = 256;
+
+/// A sign that the input may have been crafted to make detection
+/// expensive or inconclusive.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Suspicious {
+    /// ISO-2022-JP was ruled out, because the input had more than
+    /// `MAX_ESCAPE_SEQUENCES` escape sequences to consider or a skipped ANSI
+    /// sequence longer than `MAX_ANSI_SEQUENCE_LEN` bytes.
+    EscapeFlood,
+    /// More than `MAX_AMBIGUOUS_BYTES` non-ASCII bytes decoded in both
+    /// Shift_JIS and EUC-JP while half-width katakana would have decided.
+    /// The byte pairs that both accept then are mostly EUC-JP Level 2 kanji
+    /// with Shift_JIS lead bytes 0xE0 or higher, so Japanese text has kana
+    /// or Level 1 kanji that decide long before: input that keeps away from
+    /// them this long has most likely been put together to stay ambiguous.
+    /// Detection goes on as usual.
+    Ambiguity,
+}
+
+/// How closely the evidence a `Detector` collects has to follow the
+/// decoders of the WHATWG Encoding Standard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Conformance {
+    /// Evidence is exactly what the WHATWG decoders produce for the bytes
+    /// as fed, starting from the decoders' initial state. Options that
+    /// tolerate deviations, such as mid-stream resynchronization, have no
+    /// effect.
+    StrictWhatwg,
+    /// Tolerance options take effect. This is the default.
+    Pragmatic,
 }
 
 /// A detector for detecting the character encoding of input on the
 /// precondition that the encoding is a Japanese legacy encoding.
 pub struct Detector {
-    shift_jis_decoder: Decoder,
-    euc_jp_decoder: Decoder,
-    second_byte_in_escape: u8,
+    shift_jis: ShiftJisCandidate,
+    euc_jp: EucJpCandidate,
+    iso_2022_jp: Iso2022JpMachine,
     iso_2022_jp_disqualified: bool,
-    escape_seen: bool,
+    iso_2022_jp_certainty: Option<Iso2022JpCertainty>,
+    require_certain_iso_2022_jp: bool,
+    strict_iso_2022_jp: bool,
+    skip_ansi_sequences: bool,
+    verdict: Option<Verdict>,
+    certainty: Option<Certainty>,
+    reason: Option<Reason>,
+    decided_at: Option<(u64, Option<u8>)>,
+    iso_2022_jp_ruled_out: Option<(u64, u8)>,
+    /// The stream offset of the start of the buffer that `detect()` runs on.
+    position: u64,
+    suspicious: Option<Suspicious>,
+    /// The number of non-ASCII bytes that both Shift_JIS and EUC-JP have
+    /// accepted while half-width katakana are decisive.
+    ambiguous_bytes: u64,
+    del_treatment: EdgeByteTreatment,
+    c1_treatment: EdgeByteTreatment,
+    held_c1: Option<u8>,
+    edge_byte_error: Option<u64>,
     finished: bool,
+    allow_2022: bool,
+    jis_x_0212_decisive: bool,
+    text_art: bool,
+    reject_half_width_katakana: bool,
+    mid_stream: bool,
+    conformance: Conformance,
+    fallback: Verdict,
+    blank_verdict: Option<Verdict>,
+    undecided_default: Option<Verdict>,
+    /// Whether all the bytes examined so far are ASCII whitespace.
+    blank: bool,
+    examined_len: u64,
+    max_examined_len: Option<u64>,
+    max_bytes: Option<u64>,
+    budget_exhausted: bool,
+    examined_hash: Option<u64>,
+    #[cfg(feature = "scoring")]
+    scorer: Option<Scorer>,
+    #[cfg(feature = "scoring")]
+    scoring_thresholds: ScoringThresholds,
+    allow_utf8: bool,
+    utf8: Option<Utf8Candidate>,
+    #[cfg(feature = "encoding_rs")]
+    backend: Backend,
+    #[cfg(feature = "encoding_rs")]
+    char_rule: Option<CharRule>,
+    #[cfg(feature = "encoding_rs")]
+    validation: Option<Validation>,
+    #[cfg(feature = "debug-trace")]
+    trace: Vec<TraceRun>,
+}
+
+/// A verdict reached by `Detector::detect()`.
+#[derive(Debug, Copy, Clone)]
+struct Decision {
+    verdict: Verdict,
+    certainty: Certainty,
+    reason: Reason,
+    /// The deciding byte or `None` if the end of the stream decided.
+    byte: Option<u8>,
+    /// The number of bytes of the buffer up to and including the deciding
+    /// byte.
+    examined: usize,
+    /// The number of bytes of the buffer that the winning candidate has
+    /// decoded or `None` if it has already seen the end of the stream.
+    #[cfg_attr(not(feature = "encoding_rs"), allow(dead_code))]
+    consumed: Option<usize>,
+}
+
+/// The EUC-JP candidate racing the Shift_JIS candidate of a `Detector` over
+/// `buffer`, with the rules of the detector deciding what their steps mean.
+struct JapaneseRace<'a> {
+    detector: &'a mut Detector,
+    buffer: &'a [u8],
+    /// Whether to count the non-ASCII bytes that both accept.
+    count_ambiguous: bool,
+}
+
+impl Race for JapaneseRace<'_> {
+    type First = EucJpCandidate;
+    type Second = ShiftJisCandidate;
+    type Decision = Decision;
+
+    fn candidates(&mut self) -> (&mut EucJpCandidate, &mut ShiftJisCandidate) {
+        (&mut self.detector.euc_jp, &mut self.detector.shift_jis)
+    }
+
+    fn first(&mut self, euc_jp: Step, index: Option<usize>) -> Option<Decision> {
+        let detector = &mut *self.detector;
+        let index = match index {
+            Some(index) => index,
+            None => {
+                if euc_jp != Step::Rejected {
+                    return None;
+                }
+                #[cfg(feature = "debug-trace")]
+                detector.trace_finish(self.buffer.len(), None, Some(euc_jp), None);
+                return Some(Decision {
+                    verdict: Verdict::ShiftJis,
+                    certainty: Certainty::Certain,
+                    reason: if detector.euc_jp.rejected_by_half_width_katakana() {
+                        Reason::EucJpHalfWidthKatakana
+                    } else {
+                        Reason::EucJpTruncated
+                    },
+                    byte: None,
+                    examined: self.buffer.len(),
+                    consumed: Some(self.buffer.len()),
+                });
+            }
+        };
+        let byte = self.buffer[index];
+        #[cfg(feature = "debug-trace")]
+        {
+            if euc_jp != Step::Possible {
+                detector.trace_byte(index, None, Some(euc_jp), None);
+            }
+        }
+        match euc_jp {
+            Step::Rejected => {
+                return Some(Decision {
+                    verdict: Verdict::ShiftJis,
+                    certainty: Certainty::Certain,
+                    reason: if detector.euc_jp.rejected_by_half_width_katakana() {
+                        Reason::EucJpHalfWidthKatakana
+                    } else {
+                        Reason::EucJpMalformed
+                    },
+                    byte: Some(byte),
+                    examined: index + 1,
+                    consumed: Some(index),
+                });
+            }
+            Step::Confirmed => {
+                return Some(Decision {
+                    verdict: Verdict::EucJp,
+                    certainty: Certainty::Certain,
+                    reason: Reason::JisX0212,
+                    byte: Some(byte),
+                    examined: index + 1,
+                    consumed: Some(index + 1),
+                });
+            }
+            Step::Possible => {}
+        }
+        #[cfg(feature = "encoding_rs")]
+        {
+            if let Some(decision) = detector.apply_char_rule(Verdict::EucJp, byte, index) {
+                #[cfg(feature = "debug-trace")]
+                detector.trace_byte(index, None, Some(euc_jp), None);
+                return Some(decision);
+            }
+        }
+        None
+    }
+
+    #[cfg_attr(not(feature = "debug-trace"), allow(unused_variables))]
+    fn second(&mut self, euc_jp: Step, shift_jis: Step, index: Option<usize>) -> Option<Decision> {
+        let detector = &mut *self.detector;
+        let index = match index {
+            Some(index) => index,
+            None => {
+                #[cfg(feature = "debug-trace")]
+                detector.trace_finish(self.buffer.len(), None, Some(euc_jp), Some(shift_jis));
+                if shift_jis != Step::Rejected {
+                    return None;
+                }
+                return Some(Decision {
+                    verdict: Verdict::EucJp,
+                    certainty: Certainty::Certain,
+                    reason: if detector.shift_jis.rejected_by_half_width_katakana() {
+                        Reason::ShiftJisHalfWidthKatakana
+                    } else {
+                        Reason::ShiftJisTruncated
+                    },
+                    byte: None,
+                    examined: self.buffer.len(),
+                    consumed: None,
+                });
+            }
+        };
+        let byte = self.buffer[index];
+        #[cfg(feature = "debug-trace")]
+        detector.trace_byte(index, None, Some(euc_jp), Some(shift_jis));
+        if shift_jis == Step::Rejected {
+            return Some(Decision {
+                verdict: Verdict::EucJp,
+                certainty: Certainty::Certain,
+                reason: if detector.shift_jis.rejected_by_half_width_katakana() {
+                    Reason::ShiftJisHalfWidthKatakana
+                } else {
+                    Reason::ShiftJisMalformed
+                },
+                byte: Some(byte),
+                examined: index + 1,
+                consumed: Some(index + 1),
+            });
+        }
+        #[cfg(feature = "encoding_rs")]
+        {
+            if let Some(decision) = detector.apply_char_rule(Verdict::ShiftJis, byte, index) {
+                return Some(decision);
+            }
+        }
+        if self.count_ambiguous && byte >= 0x80 {
+            detector.ambiguous_bytes += 1;
+        }
+        None
+    }
+}
+
+impl Decision {
+    /// The decision for a buffer that the buffer of this decision starts
+    /// `offset` bytes into.
+    fn offset_by(self, offset: usize) -> Decision {
+        Decision {
+            examined: self.examined + offset,
+            consumed: self.consumed.map(|consumed| consumed + offset),
+            ..self
+        }
+    }
+}
+
+/// The state of the validation mode.
+#[cfg(feature = "encoding_rs")]
+struct Validation {
+    /// The stream offset of the start of the next buffer.
+    position: usize,
+    decided: Option<(Verdict, Continuation)>,
 }
 
 impl Detector {
@@ -133,16 +923,686 @@ impl Detector {
     /// `allow_2022` is `false`, the possible guesses are Shift_JIS, EUC-JP,
     /// and undecided.
     pub fn new(allow_2022: bool) -> Self {
+        DetectorBuilder::new().allow_2022(allow_2022).build()
+    }
+
+    /// A detector with the defaults of all the options, which
+    /// `with_config()` starts from.
+    pub(crate) fn with_defaults(allow_2022: bool, mid_stream: bool) -> Self {
         Detector {
-            shift_jis_decoder: SHIFT_JIS.new_decoder_without_bom_handling(),
-            euc_jp_decoder: EUC_JP.new_decoder_without_bom_handling(),
-            second_byte_in_escape: 0,
+            shift_jis: ShiftJisCandidate::new(),
+            euc_jp: EucJpCandidate::new(),
+            iso_2022_jp: Iso2022JpMachine::new(),
             iso_2022_jp_disqualified: !allow_2022,
-            escape_seen: false,
+            iso_2022_jp_certainty: None,
+            require_certain_iso_2022_jp: false,
+            strict_iso_2022_jp: false,
+            skip_ansi_sequences: false,
+            verdict: None,
+            certainty: None,
+            reason: None,
+            decided_at: None,
+            iso_2022_jp_ruled_out: None,
+            position: 0,
+            suspicious: None,
+            ambiguous_bytes: 0,
+            del_treatment: EdgeByteTreatment::Evidence,
+            c1_treatment: EdgeByteTreatment::Evidence,
+            held_c1: None,
+            edge_byte_error: None,
             finished: false,
+            allow_2022,
+            jis_x_0212_decisive: false,
+            text_art: false,
+            reject_half_width_katakana: true,
+            mid_stream,
+            conformance: Conformance::Pragmatic,
+            fallback: Verdict::ShiftJis,
+            blank_verdict: None,
+            undecided_default: None,
+            blank: true,
+            examined_len: 0,
+            max_examined_len: None,
+            max_bytes: None,
+            budget_exhausted: false,
+            examined_hash: None,
+            #[cfg(feature = "scoring")]
+            scorer: None,
+            #[cfg(feature = "scoring")]
+            scoring_thresholds: ScoringThresholds::DEFAULT,
+            allow_utf8: false,
+            utf8: None,
+            #[cfg(feature = "encoding_rs")]
+            backend: Backend::Decoder,
+            #[cfg(feature = "encoding_rs")]
+            char_rule: None,
+            #[cfg(feature = "encoding_rs")]
+            validation: None,
+            #[cfg(feature = "debug-trace")]
+            trace: Vec::new(),
+        }
+    }
+
+    /// Instantiates a detector for input that starts at an arbitrary offset
+    /// of a stream (e.g. after seeking into the middle of a file) instead of
+    /// at the start of the stream. `allow_2022` has the same meaning as for
+    /// `new()`.
+    ///
+    /// Since the first bytes fed may be the tail of a character whose start
+    /// was not seen, the detector does not collect evidence for Shift_JIS or
+    /// EUC-JP until it has seen a byte after which the next byte has to be
+    /// at a character boundary under that encoding. For EUC-JP, any ASCII
+    /// byte resynchronizes. For Shift_JIS, only ASCII bytes that cannot be
+    /// trail bytes (0x00 to 0x3F and 0x7F) resynchronize. Such bytes (e.g.
+    /// spaces, digits and line breaks) are normally plentiful in text.
+    pub fn new_mid_stream(allow_2022: bool) -> Self {
+        DetectorBuilder::new()
+            .allow_2022(allow_2022)
+            .mid_stream(true)
+            .build()
+    }
+
+    /// Instantiates a detector that only distinguishes between Shift_JIS and
+    /// EUC-JP. Unlike `Detector::new(false)`, the returned `TwoWayDetector`
+    /// has no ISO-2022-JP bookkeeping at all, so its per-byte loop has no
+    /// escape-related branches.
+    pub fn shift_jis_vs_euc_jp() -> TwoWayDetector {
+        TwoWayDetector::new()
+    }
+
+    /// Instantiates the detector with the defaults appropriate for an
+    /// application whose UI locale is `locale` (a BCP 47 language tag such
+    /// as `ja-JP` or `ja-JP-mac`, or a POSIX locale such as `ja_JP.eucJP`).
+    ///
+    /// As in browsers, ISO-2022-JP is allowed only for the Japanese locales,
+    /// and the fallback (see `fallback()`) is always Shift_JIS, since that
+    /// is the better guess for undecided content even in the Japanese UI of
+    /// systems whose native encoding was EUC-JP.
+    pub fn for_locale(locale: &str) -> Self {
+        let language = locale.split(['-', '_']).next().unwrap_or("");
+        Detector::new(language.eq_ignore_ascii_case("ja"))
+    }
+
+    /// The guess to use if the detector remains undecided at the end of the
+    /// stream. This is Shift_JIS for all presets.
+    pub fn fallback(&self) -> Verdict {
+        self.fallback
+    }
+
+    /// Sets the guess to use if the detector remains undecided, i.e. how a
+    /// tie between the candidates is broken. Defaults to
+    /// `Verdict::ShiftJis`. The detector itself still returns `None` when
+    /// undecided unless `set_undecided_default()` says otherwise; the
+    /// fallback is reported by `fallback()`, `policy()` and
+    /// `DetectionOutcome::guess()`.
+    pub fn set_fallback(&mut self, fallback: Verdict) {
+        self.fallback = fallback;
+    }
+
+    /// The verdict for a stream that is empty or only ASCII whitespace.
+    pub fn blank_verdict(&self) -> Option<Verdict> {
+        self.blank_verdict
+    }
+
+    /// Sets the verdict for a stream that is empty or only ASCII whitespace
+    /// (space, tab, line feed, form feed and carriage return). Defaults to
+    /// `None`, i.e. such a stream leaves the detector undecided like any
+    /// other ASCII. With `Some(_)`, the end of such a stream decides the
+    /// given verdict with `Reason::Blank`, so that callers can tell blank
+    /// input apart from ASCII text without checking the bytes themselves.
+    /// `DetectionOutcome::blank()` reports blank input either way.
+    pub fn set_blank_verdict(&mut self, verdict: Option<Verdict>) {
+        self.blank_verdict = verdict;
+    }
+
+    /// The verdict that the end of the stream decides if nothing else has.
+    pub fn undecided_default(&self) -> Option<Verdict> {
+        self.undecided_default
+    }
+
+    /// Sets the verdict that the end of the stream decides if nothing else
+    /// has, so that the last call to `feed()` always returns an encoding.
+    /// Defaults to `None`, which keeps returning `None` for callers that
+    /// want to know about ambiguous input. With `Some(_)`, the verdict has
+    /// `Certainty::Guessed` and `Reason::UndecidedDefault`, so the
+    /// ambiguity can still be told from the outcome.
+    ///
+    /// Stopping at an edge byte (see `EdgeByteTreatment::Error`) still
+    /// leaves the detector undecided.
+    pub fn set_undecided_default(&mut self, verdict: Option<Verdict>) {
+        self.undecided_default = verdict;
+    }
+
+    /// Returns the detector to the state it had before the first call to
+    /// `feed()`, so that one instance can be reused across documents
+    /// without constructing a new one each time. `allow_2022` has the same
+    /// meaning as for `new()`. Everything set with the `set_*()` methods is
+    /// kept.
+    pub fn reset(&mut self, allow_2022: bool) {
+        self.reset_candidates();
+        self.iso_2022_jp = Iso2022JpMachine::new();
+        self.iso_2022_jp
+            .set_skip_ansi_sequences(self.skip_ansi_sequences);
+        self.iso_2022_jp_disqualified = !allow_2022;
+        self.iso_2022_jp_certainty = None;
+        self.verdict = None;
+        self.certainty = None;
+        self.reason = None;
+        self.decided_at = None;
+        self.iso_2022_jp_ruled_out = None;
+        self.position = 0;
+        self.suspicious = None;
+        self.ambiguous_bytes = 0;
+        self.held_c1 = None;
+        self.edge_byte_error = None;
+        self.finished = false;
+        self.allow_2022 = allow_2022;
+        self.blank = true;
+        self.examined_len = 0;
+        self.budget_exhausted = false;
+        if self.examined_hash.is_some() {
+            self.examined_hash = Some(FNV_OFFSET_BASIS);
+        }
+        #[cfg(feature = "scoring")]
+        if self.scorer.is_some() {
+            self.scorer = Some(Scorer::new());
+        }
+        self.set_allow_utf8(self.allow_utf8);
+        #[cfg(feature = "encoding_rs")]
+        {
+            if let Some(validation) = self.validation.as_mut() {
+                validation.position = 0;
+                validation.decided = None;
+            }
+        }
+        #[cfg(feature = "debug-trace")]
+        self.trace.clear();
+    }
+
+    /// Sets whether a complete JIS X 0212 character in EUC-JP (0x8F
+    /// followed by two bytes that map to a JIS X 0212 character) decides
+    /// EUC-JP. Defaults to `false`. Call before the first call to `feed()`.
+    ///
+    /// Even when not decisive, such a sequence usually decides EUC-JP,
+    /// because its last byte decodes as half-width katakana under Shift_JIS
+    /// unless it is 0xE0 or higher. Making JIS X 0212 decisive also covers
+    /// the remaining cases at the risk of misdetecting Shift_JIS text that
+    /// has a kanji whose lead byte is 0x8F followed by a kanji whose lead
+    /// byte is 0xE0 or higher before any other evidence.
+    ///
+    /// As with all multi-byte sequences, the bytes of a JIS X 0212 sequence
+    /// may be split across calls to `feed()` at any point, including right
+    /// after 0x8F. An incomplete sequence at the end of the stream rules out
+    /// EUC-JP.
+    pub fn set_jis_x_0212_decisive(&mut self, decisive: bool) {
+        self.euc_jp.set_jis_x_0212_decisive(decisive);
+        self.jis_x_0212_decisive = decisive;
+    }
+
+    /// Sets whether the detector uses the tolerance profile for text art,
+    /// such as 2ch-style Shift_JIS art. Defaults to `false`. Call before
+    /// the first call to `feed()`. Has no effect under
+    /// `Conformance::StrictWhatwg`.
+    ///
+    /// Text art often has half-width katakana (e.g. in faces like `(ﾟ∀ﾟ)`)
+    /// before any other evidence, which normally decides EUC-JP right away.
+    /// With this profile, half-width katakana only counts as evidence at the
+    /// end of the stream, so a malformed sequence anywhere in the stream
+    /// decides first. Line-drawing characters and symbols are valid in both
+    /// encodings and remain neutral, except that the Shift_JIS ones
+    /// (lead bytes 0x81 to 0x84) are malformed in EUC-JP and decide
+    /// Shift_JIS.
+    ///
+    /// The cost is latency: EUC-JP text is typically only decided at the
+    /// end of the stream, because EUC-JP kana and kanji decode as
+    /// half-width katakana under Shift_JIS.
+    pub fn set_text_art(&mut self, text_art: bool) {
+        self.text_art = text_art;
+        self.configure_candidates();
+    }
+
+    /// Sets whether half-width katakana counts as evidence against the
+    /// encoding under which it decodes. Defaults to `true`. Call before the
+    /// first call to `feed()`.
+    ///
+    /// Half-width katakana is rare in real text, so normally it rules out
+    /// the encoding like a malformed sequence does, which is what decides
+    /// most EUC-JP input (whose kana and kanji decode as half-width katakana
+    /// under Shift_JIS). Turning this off suits input known to use
+    /// half-width katakana (e.g. from old point-of-sale systems) at the cost
+    /// of leaving much more input undecided; consider combining it with
+    /// `set_scoring()`. `set_text_art()` has no effect when this is off.
+    pub fn set_reject_half_width_katakana(&mut self, reject: bool) {
+        self.reject_half_width_katakana = reject;
+        self.configure_candidates();
+    }
+
+    /// Sets the maximum number of bytes that the detector examines, or
+    /// `None` for no limit. Defaults to `None`. Call before the first call to
+    /// `feed()`.
+    ///
+    /// Once the budget has been used up without a verdict, the rest of the
+    /// stream isn't examined: `feed()` keeps returning `None` (except that
+    /// scoring still decides at the end of the stream from the bytes
+    /// examined) and `budget_exhausted()` returns `true`. An incomplete
+    /// character at the end of the budget does not count as truncated, since
+    /// the stream goes on.
+    pub fn set_max_examined_len(&mut self, max: Option<u64>) {
+        self.max_examined_len = max;
+    }
+
+    /// Whether the detector has stopped examining the input because of
+    /// `set_max_examined_len()` or `set_max_bytes()`.
+    pub fn budget_exhausted(&self) -> bool {
+        self.budget_exhausted
+    }
+
+    /// Sets the number of bytes after which the detector commits to its
+    /// best guess if it hasn't decided yet, or `None` for no limit. Defaults
+    /// to `None`. Call before the first call to `feed()`.
+    ///
+    /// Unlike `set_max_examined_len()`, which leaves the detector undecided
+    /// until the end of the stream, the call to `feed()` that reaches the
+    /// budget returns a verdict: UTF-8 if `set_allow_utf8()` applies to the
+    /// bytes examined, otherwise the leaning of the scores of
+    /// `set_scoring()` and, failing that, `fallback()`. The verdict has
+    /// `Reason::ByteBudget` (see `forced()`) and, unless the scores decided,
+    /// `Certainty::Guessed`. Stopping at an edge byte (see
+    /// `EdgeByteTreatment::Error`) still leaves the detector undecided.
+    pub fn set_max_bytes(&mut self, max: Option<u64>) {
+        self.max_bytes = max;
+    }
+
+    /// Whether the verdict is a guess forced by `set_max_bytes()` rather
+    /// than a decision of the input.
+    pub fn forced(&self) -> bool {
+        self.reason == Some(Reason::ByteBudget)
+    }
+
+    /// Sets what the detector does with DEL (0x7F), which terminal logs have
+    /// where a key press was recorded. Defaults to
+    /// `EdgeByteTreatment::Evidence`: DEL is neutral between characters but
+    /// malformed within one. Call before the first call to `feed()`. Has no
+    /// effect under `Conformance::StrictWhatwg`.
+    pub fn set_del_treatment(&mut self, treatment: EdgeByteTreatment) {
+        self.del_treatment = treatment;
+    }
+
+    /// Sets what the detector does with standalone C1-range bytes (0x80 to
+    /// 0x9F), which terminal logs have as C1 controls. A C1-range byte is
+    /// standalone if it occurs between characters and is followed by a byte
+    /// that cannot be a trail byte (below 0x40 or DEL) or by the end of the
+    /// stream, so that neither Shift_JIS nor EUC-JP can combine it with the
+    /// next byte into a character. Defaults to `EdgeByteTreatment::Evidence`:
+    /// such a byte is malformed in EUC-JP (unless it is 0x8E or 0x8F) and
+    /// in Shift_JIS (unless it is 0x80), so it usually decides Shift_JIS.
+    /// Call before the first call to `feed()`. Has no effect under
+    /// `Conformance::StrictWhatwg`.
+    ///
+    /// Telling whether a byte is standalone takes the next byte, so a
+    /// C1-range byte at the end of a buffer is held until the next call.
+    pub fn set_c1_treatment(&mut self, treatment: EdgeByteTreatment) {
+        self.c1_treatment = treatment;
+    }
+
+    /// The stream offset of the byte that stopped detection under
+    /// `EdgeByteTreatment::Error` or `None` if detection hasn't been stopped.
+    pub fn edge_byte_error(&self) -> Option<u64> {
+        self.edge_byte_error
+    }
+
+    /// The DEL and C1 treatments in effect.
+    fn edge_treatments(&self) -> (EdgeByteTreatment, EdgeByteTreatment) {
+        if self.conformance == Conformance::Pragmatic {
+            (self.del_treatment, self.c1_treatment)
+        } else {
+            (EdgeByteTreatment::Evidence, EdgeByteTreatment::Evidence)
+        }
+    }
+
+    /// The number of bytes at the end of the input fed so far that form an
+    /// incomplete character or escape sequence in some candidate encoding,
+    /// i.e. whose meaning depends on bytes not fed yet. Never more than
+    /// `MAX_PENDING`. (With `set_require_certain_iso_2022_jp()`, the
+    /// ISO-2022-JP verdict additionally waits for a complete character after
+    /// the escape sequence, but that doesn't make any bytes pending in this
+    /// sense.)
+    pub fn pending(&self) -> usize {
+        let iso_2022_jp = if self.iso_2022_jp_disqualified {
+            0
+        } else {
+            self.iso_2022_jp.pending()
+        };
+        let pending = self
+            .shift_jis
+            .pending()
+            .max(self.euc_jp.pending())
+            .max(iso_2022_jp)
+            .max(usize::from(self.held_c1.is_some()));
+        debug_assert!(pending <= MAX_PENDING);
+        pending
+    }
+
+    /// Sets whether the detector computes a hash of the bytes it examines
+    /// (see `examined_hash()`). Defaults to `false`. Call before the first
+    /// call to `feed()`.
+    pub fn set_hashing(&mut self, enabled: bool) {
+        self.examined_hash = if enabled {
+            Some(FNV_OFFSET_BASIS)
+        } else {
+            None
+        };
+    }
+
+    /// The number of bytes the verdict (or the lack of one so far) is based
+    /// on: the bytes up to and including the deciding byte after a verdict
+    /// and all the bytes fed so far otherwise. Bytes fed in validation mode
+    /// after the verdict don't count.
+    pub fn examined_len(&self) -> u64 {
+        self.examined_len
+    }
+
+    /// The offset, counted from the first byte fed across all calls to
+    /// `feed()`, of the byte that decided or, if the end of the stream (or
+    /// a budget) decided, the number of bytes examined. `None` if the
+    /// detector is undecided. The same as `DetectionOutcome::decided_at()`.
+    ///
+    /// The bytes consumed before the verdict, e.g. to account for decoding
+    /// the prefix again with the decided encoding, are `examined_len()`,
+    /// which is one more than this offset when a byte decided.
+    pub fn decided_at(&self) -> Option<u64> {
+        self.decided_at.map(|(offset, _)| offset)
+    }
+
+    /// With hashing enabled, the `hash_examined()` hash of the first
+    /// `examined_len()` bytes of the stream.
+    ///
+    /// A caching layer can compare this with `hash_examined()` of the same
+    /// prefix of a possibly modified file: if they match, a cached verdict
+    /// still applies. (If the verdict was reached at the end of the stream,
+    /// the length of the file has to match, too.)
+    pub fn examined_hash(&self) -> Option<u64> {
+        self.examined_hash
+    }
+
+    /// Sets whether the detector scores the input as Shift_JIS and as EUC-JP
+    /// in order to decide input that is valid in both instead of remaining
+    /// undecided at the end of the stream. Defaults to `false`. Call before
+    /// the first call to `feed()`.
+    ///
+    /// Each character counts by its class: kana, punctuation and the common
+    /// (Level 1) kanji count most, the rest of JIS X 0208 a little, and
+    /// half-width katakana and user-defined characters count against the
+    /// encoding. At the end of the stream, the encoding with the higher score
+    /// is decided with `Certainty::Scored` (or `Certainty::Likely`, see
+    /// `set_scoring_thresholds()`). On a tie, e.g. for ASCII, the detector
+    /// remains undecided.
+    #[cfg(feature = "scoring")]
+    pub fn set_scoring(&mut self, enabled: bool) {
+        self.scorer = if enabled { Some(Scorer::new()) } else { None };
+    }
+
+    /// Sets the score margins that scored verdicts need. Defaults to
+    /// `ScoringThresholds::DEFAULT`. Call before the first call to `feed()`.
+    ///
+    /// Thresholds fitted with `calibrate_scoring()` on a corpus of the
+    /// inputs at hand make `Certainty::Likely` mean a known accuracy, so
+    /// that it can be worded accordingly in user interfaces.
+    #[cfg(feature = "scoring")]
+    pub fn set_scoring_thresholds(&mut self, thresholds: ScoringThresholds) {
+        self.scoring_thresholds = thresholds;
+    }
+
+    /// With scoring enabled, the Shift_JIS and EUC-JP scores (in that order)
+    /// of the input fed while the detector was undecided.
+    #[cfg(feature = "scoring")]
+    pub fn scores(&self) -> Option<(i64, i64)> {
+        self.scorer.as_ref().map(Scorer::scores)
+    }
+
+    /// With scoring enabled, the classes of the characters (see
+    /// `class_breakdown()`) fed while the detector was undecided, e.g. for
+    /// telling a person what undecided input looks like.
+    #[cfg(feature = "scoring")]
+    pub fn class_breakdown(&self) -> Option<ClassBreakdown> {
+        self.scorer.as_ref().map(Scorer::class_breakdown)
+    }
+
+    /// Sets whether ISO-2022-JP requires a complete JIS X 0208 character
+    /// after the escape sequences before it is decided. Defaults to `false`.
+    /// Call before the first call to `feed()`.
+    ///
+    /// By default, the first ISO-2022-JP designation escape sequence (e.g.
+    /// `ESC ( B`) decides ISO-2022-JP with `Certainty::Tentative`. When
+    /// required, the detector instead keeps following the input as
+    /// ISO-2022-JP: a JIS X 0208 character decides it with
+    /// `Certainty::Certain`, while malformed ISO-2022-JP or a non-ASCII byte
+    /// rules it out and detection continues between Shift_JIS and EUC-JP.
+    /// This guards against stray 0x1B bytes in binary-ish data. If the
+    /// stream ends first, ISO-2022-JP is decided with
+    /// `Certainty::Tentative`.
+    pub fn set_require_certain_iso_2022_jp(&mut self, require: bool) {
+        self.require_certain_iso_2022_jp = require;
+    }
+
+    /// Sets whether ISO-2022-JP is only decided once the whole stream has
+    /// turned out to be well-formed ISO-2022-JP. Defaults to `false`. Call
+    /// before the first call to `feed()`. Overrides
+    /// `set_require_certain_iso_2022_jp()`.
+    ///
+    /// When strict, the detector follows the input after the first
+    /// designation escape sequence with the state machine of the WHATWG
+    /// ISO-2022-JP decoder to the end of the stream. A later escape sequence
+    /// that isn't a designation, a designation right after another one, a
+    /// Shift Out or Shift In byte (0x0E or 0x0F), a byte out of range for
+    /// the current mode or a non-ASCII byte rules out ISO-2022-JP, and
+    /// detection continues between Shift_JIS and EUC-JP. If the end of the
+    /// stream arrives first, ISO-2022-JP is decided with
+    /// `Reason::Iso2022JpStructure`: with `Certainty::Certain` if there was
+    /// a JIS X 0208 character and with `Certainty::Tentative` otherwise.
+    ///
+    /// Since an ISO-2022-JP verdict waits for the end of the stream, use
+    /// this for complete documents rather than for prefixes. Unlike
+    /// `set_require_certain_iso_2022_jp()`, this doesn't keep a replay
+    /// buffer, so in validation mode (see `set_validation_mode()`), the
+    /// statistics after such a verdict are those of a well-formed stream.
+    pub fn set_strict_iso_2022_jp(&mut self, strict: bool) {
+        self.strict_iso_2022_jp = strict;
+    }
+
+    /// Sets whether ANSI CSI sequences (`ESC [`, e.g. color codes) and OSC
+    /// sequences (`ESC ]`, e.g. window titles) are skipped as noise when
+    /// looking for ISO-2022-JP escape sequences. Defaults to `false`. Call
+    /// before the first call to `feed()`.
+    ///
+    /// Without this, the first such sequence in a terminal capture rules
+    /// out ISO-2022-JP, because it is not an ISO-2022-JP designation.
+    pub fn set_skip_ansi_sequences(&mut self, skip: bool) {
+        self.skip_ansi_sequences = skip;
+        self.iso_2022_jp.set_skip_ansi_sequences(skip);
+    }
+
+    /// The certainty of the verdict returned by the last call to `feed()`
+    /// or `None` if that call returned `None`. Only ISO-2022-JP verdicts
+    /// can be tentative.
+    pub fn certainty(&self) -> Option<Certainty> {
+        self.certainty
+    }
+
+    /// Whether the detector has cut off work on input that looks crafted to
+    /// make detection expensive or has seen input that looks crafted to
+    /// stay ambiguous. Such input still gets a verdict, but deployments that
+    /// screen traffic may want to log or reject it.
+    pub fn suspicious(&self) -> Option<Suspicious> {
+        self.suspicious
+    }
+
+    /// Everything the detector has concluded from the input fed so far in one
+    /// value that can be passed around and that gains diagnostics over time
+    /// without breaking changes.
+    pub fn outcome(&self) -> DetectionOutcome {
+        DetectionOutcome {
+            verdict: self.verdict,
+            certainty: self.certainty,
+            fallback: self.fallback,
+            blank: self.blank,
+            finished: self.finished,
+            examined_len: self.examined_len,
+            pending: self.pending(),
+            suspicious: self.suspicious,
+            edge_byte_error: self.edge_byte_error,
+            reason: self.reason,
+            decided_at: self.decided_at,
+            iso_2022_jp_ruled_out: self.iso_2022_jp_ruled_out,
+            #[cfg(feature = "scoring")]
+            scores: self.scores(),
+            #[cfg(feature = "scoring")]
+            class_breakdown: self.class_breakdown(),
         }
     }
 
+    /// Sets how closely the detector follows the WHATWG decoders. Defaults
+    /// to `Conformance::Pragmatic`. Call before the first call to `feed()`.
+    ///
+    /// With `Conformance::StrictWhatwg`, a detector instantiated with
+    /// `new_mid_stream()` collects evidence from the first byte like one
+    /// instantiated with `new()`.
+    pub fn set_conformance(&mut self, conformance: Conformance) {
+        self.conformance = conformance;
+        self.reset_candidates();
+    }
+
+    /// Replaces the Shift_JIS and EUC-JP candidates with fresh ones.
+    fn reset_candidates(&mut self) {
+        #[cfg(feature = "encoding_rs")]
+        {
+            let mid_stream = self.mid_stream_resync();
+            self.shift_jis = ShiftJisCandidate::for_backend(self.backend, mid_stream);
+            self.euc_jp = EucJpCandidate::for_backend(self.backend, mid_stream);
+        }
+        #[cfg(not(feature = "encoding_rs"))]
+        if self.mid_stream_resync() {
+            self.shift_jis = ShiftJisCandidate::new_mid_stream();
+            self.euc_jp = EucJpCandidate::new_mid_stream();
+        } else {
+            self.shift_jis = ShiftJisCandidate::new();
+            self.euc_jp = EucJpCandidate::new();
+        }
+        self.configure_candidates();
+    }
+
+    /// Applies the options to the Shift_JIS and EUC-JP candidates.
+    fn configure_candidates(&mut self) {
+        let defer = self.text_art && self.conformance == Conformance::Pragmatic;
+        self.euc_jp
+            .set_jis_x_0212_decisive(self.jis_x_0212_decisive);
+        self.euc_jp.set_defer_half_width_katakana(defer);
+        self.shift_jis.set_defer_half_width_katakana(defer);
+        self.euc_jp
+            .set_reject_half_width_katakana(self.reject_half_width_katakana);
+        self.shift_jis
+            .set_reject_half_width_katakana(self.reject_half_width_katakana);
+    }
+
+    /// The conformance mode.
+    pub fn conformance(&self) -> Conformance {
+        self.conformance
+    }
+
+    /// Whether scoring has been enabled with `set_scoring()`.
+    #[cfg(feature = "diagnostics")]
+    fn scoring(&self) -> bool {
+        #[cfg(feature = "scoring")]
+        {
+            self.scorer.is_some()
+        }
+        #[cfg(not(feature = "scoring"))]
+        {
+            false
+        }
+    }
+
+    /// Whether a rule has been set with `set_char_rule()`.
+    #[cfg(feature = "diagnostics")]
+    fn char_rule_set(&self) -> bool {
+        #[cfg(feature = "encoding_rs")]
+        {
+            self.char_rule.is_some()
+        }
+        #[cfg(not(feature = "encoding_rs"))]
+        {
+            false
+        }
+    }
+
+    /// Whether mid-stream resynchronization is in effect.
+    fn mid_stream_resync(&self) -> bool {
+        self.mid_stream && self.conformance == Conformance::Pragmatic
+    }
+
+    /// Dumps the decision logic that the detector has been configured with.
+    #[cfg(feature = "diagnostics")]
+    pub fn policy(&self) -> Policy {
+        Policy::new(
+            self.allow_2022,
+            self.require_certain_iso_2022_jp,
+            self.strict_iso_2022_jp,
+            self.skip_ansi_sequences,
+            self.jis_x_0212_decisive,
+            self.reject_half_width_katakana,
+            self.text_art
+                && self.reject_half_width_katakana
+                && self.conformance == Conformance::Pragmatic,
+            self.edge_treatments() != (EdgeByteTreatment::Evidence, EdgeByteTreatment::Evidence),
+            self.mid_stream_resync(),
+            self.scoring(),
+            self.char_rule_set(),
+            self.allow_utf8,
+            self.max_bytes.is_some(),
+            self.blank_verdict,
+            self.undecided_default,
+            self.fallback,
+        )
+    }
+
+    /// Sets whether the detector keeps decoding with the decided encoding
+    /// after a verdict in order to collect statistics about malformed
+    /// sequences. Defaults to `false`. Call before the first call to
+    /// `feed()`.
+    ///
+    /// In validation mode, `feed()` may be called again after it has
+    /// returned `Some(_)`, until it has been called with `true` as `last`.
+    /// It keeps returning the same encoding and the stream is decoded as
+    /// that encoding, so a single pass yields both the verdict and
+    /// `validation_statistics()` for it.
+    #[cfg(feature = "encoding_rs")]
+    pub fn set_validation_mode(&mut self, enabled: bool) {
+        self.validation = if enabled {
+            Some(Validation {
+                position: 0,
+                decided: None,
+            })
+        } else {
+            None
+        };
+    }
+
+    /// In validation mode, once there is a verdict, the malformed sequences
+    /// (each of which would decode as a replacement character) that
+    /// decoding the stream as the decided encoding has produced so far.
+    /// Offsets are relative to the start of the stream. The input before
+    /// the verdict never contains malformed sequences, since those would
+    /// have ruled out the decided encoding.
+    ///
+    /// Returns `None` outside validation mode and before a verdict.
+    #[cfg(feature = "encoding_rs")]
+    pub fn validation_statistics(&self) -> Option<&MalformedSequences> {
+        self.validation
+            .as_ref()
+            .and_then(|validation| validation.decided.as_ref())
+            .map(|(_, continuation)| continuation.malformed())
+    }
+
     /// Feeds bytes to the detector. If `last` is `true` the end of the stream
     /// is considered to occur immediately after the end of `buffer`.
     /// Otherwise, the stream is expected to continue. `buffer` may be empty.
@@ -155,78 +1615,531 @@ impl Detector {
     /// Shift_JIS. Returns `Some(encoding_rs::EUC_JP)` if the detector
     /// guessed EUC-JP. Returns `Some(encoding_rs::ISO_2022_JP)` if the
     /// detector guessed ISO-2022-JP (only possible if `true` was passed as
-    /// `allow_2022` when instantiating the detector). Returns `None` if the
-    /// detector is undecided. If `None` is returned even when passing `true`
-    /// as `last`, falling back to Shift_JIS is the best guess for Web
-    /// purposes.
+    /// `allow_2022` when instantiating the detector). Returns
+    /// `Some(encoding_rs::UTF_8)` if the detector guessed UTF-8 (only
+    /// possible with `set_allow_utf8()`). Returns `None` if the detector is
+    /// undecided. If `None` is returned even when passing `true` as `last`,
+    /// falling back to Shift_JIS is the best guess for Web purposes.
+    ///
+    /// Some options return a verdict that the input itself doesn't decide:
+    /// the call that reaches the budget of `set_max_bytes()` returns its
+    /// guess, and with `true` as `last`, a blank stream returns the verdict
+    /// of `set_blank_verdict()` and an undecided one that of
+    /// `set_undecided_default()`. `outcome()` tells these apart by their
+    /// `Reason`.
+    ///
+    /// Do not call again after the method has returned `Some(_)` (unless in
+    /// validation mode) or after the method has been called with `true` as
+    /// `last`.
+    ///
+    /// # Panics
+    ///
+    /// If called after the method has returned `Some(_)` (unless in
+    /// validation mode) or after the method has been called with `true` as
+    /// `last`.
+    #[cfg(feature = "encoding_rs")]
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        self.feed_verdict(buffer, last).map(Verdict::encoding)
+    }
+
+    /// The encoding that the bytes fed so far lean towards, without
+    /// changing the state of the detector, e.g. to start decoding before the
+    /// end of the stream and decode again only if the verdict of `feed()`
+    /// turns out different.
+    ///
+    /// After a verdict, this is the verdict. Before that, it is, in order:
+    /// UTF-8 if `set_allow_utf8()` is on and the non-ASCII bytes so far are
+    /// well-formed UTF-8, ISO-2022-JP if a complete designation escape
+    /// sequence has been seen (with `set_require_certain_iso_2022_jp()` or
+    /// `set_strict_iso_2022_jp()`, which wait for more) and ISO-2022-JP hasn't
+    /// been ruled out, or the higher-scoring of Shift_JIS and EUC-JP with
+    /// `set_scoring()` (however small the margin). `None` if none of these
+    /// apply, e.g. for ASCII, in which case `DetectionOutcome::guess()` has
+    /// the fallback.
+    #[cfg(feature = "encoding_rs")]
+    pub fn current_guess(&self) -> Option<&'static Encoding> {
+        if let Some(verdict) = self.verdict {
+            return Some(verdict.encoding());
+        }
+        if self.utf8.as_ref().is_some_and(Utf8Candidate::non_ascii) {
+            return Some(UTF_8);
+        }
+        if !self.iso_2022_jp_disqualified && self.iso_2022_jp.confirming_sequence().is_some() {
+            return Some(ISO_2022_JP);
+        }
+        #[cfg(feature = "scoring")]
+        if let Some(scorer) = self.scorer.as_ref() {
+            let (shift_jis, euc_jp) = scorer.scores();
+            if shift_jis != euc_jp {
+                return Some(if shift_jis > euc_jp {
+                    SHIFT_JIS
+                } else {
+                    EUC_JP
+                });
+            }
+        }
+        None
+    }
+
+    /// The same as `feed_verdict()` but returns the whole `outcome()`: the
+    /// verdict together with its `Reason` and the stream offset of the byte
+    /// that decided, e.g. for logging.
+    ///
+    /// # Panics
     ///
-    /// Do not call again after the method has returned `Some(_)` or after
-    /// the method has been called with `true` as `last`.
+    /// If called after the outcome has had a verdict or after the method
+    /// has been called with `true` as `last`.
+    pub fn feed_with_outcome(&mut self, buffer: &[u8], last: bool) -> DetectionOutcome {
+        self.feed_verdict(buffer, last);
+        self.outcome()
+    }
+
+    /// The same as `feed()` but returns a crate-local `Verdict` instead of
+    /// an `encoding_rs` encoding. This is the only way to feed the detector
+    /// when the `encoding_rs` feature is disabled.
     ///
     /// # Panics
     ///
     /// If called after the method has returned `Some(_)` or after the method
     /// has been called with `true` as `last`.
-    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+    pub fn feed_verdict(&mut self, buffer: &[u8], last: bool) -> Option<Verdict> {
         assert!(
             !self.finished,
             "Tried to used a detector that has finished."
         );
-        self.finished = true; // Will change back to false unless we return early
-        let mut i = 0;
-        if !self.iso_2022_jp_disqualified {
-            if !self.escape_seen {
-                i = find_non_ascii_or_escape(buffer);
-            }
-            while i < buffer.len() {
-                let byte = buffer[i];
-                if byte > 0x7F {
-                    self.iso_2022_jp_disqualified = true;
-                    break;
+        #[cfg(feature = "encoding_rs")]
+        {
+            if let Some(validation) = self.validation.as_mut() {
+                if let Some((verdict, continuation)) = validation.decided.as_mut() {
+                    continuation.feed(buffer, validation.position, last);
+                    validation.position += buffer.len();
+                    self.finished = last;
+                    return Some(*verdict);
                 }
-                if !self.escape_seen && byte == 0x1B {
-                    self.escape_seen = true;
-                    i += 1;
-                    continue;
+            }
+        }
+        let budget = match (self.max_examined_len, self.max_bytes) {
+            (Some(max_examined_len), Some(max_bytes)) => Some(max_examined_len.min(max_bytes)),
+            (max_examined_len, max_bytes) => max_examined_len.or(max_bytes),
+        };
+        let examinable = match budget {
+            Some(_) if self.budget_exhausted => &buffer[..0],
+            Some(max) => {
+                let remaining = max - self.examined_len.min(max);
+                if remaining < buffer.len() as u64 {
+                    self.budget_exhausted = true;
+                    &buffer[..remaining as usize]
+                } else {
+                    buffer
                 }
-                if self.escape_seen && self.second_byte_in_escape == 0 {
-                    self.second_byte_in_escape = byte;
-                    i += 1;
-                    continue;
+            }
+            None => buffer,
+        };
+        let forced = self
+            .max_bytes
+            .is_some_and(|max| self.examined_len + examinable.len() as u64 >= max);
+        // The examined bytes are not the end of the stream.
+        self.budget_exhausted |= forced && !last;
+        self.blank = self.blank && examinable.iter().all(u8::is_ascii_whitespace);
+        if let Some(verdict) = self.feed_held_utf8(examinable, last) {
+            return verdict;
+        }
+        #[cfg_attr(not(feature = "scoring"), allow(unused_mut))]
+        let mut decision = if self.budget_exhausted {
+            // The examined bytes are not the end of the stream.
+            self.detect_edges(examinable, false)
+        } else {
+            self.detect_edges(examinable, last)
+        };
+        #[cfg(feature = "scoring")]
+        if let Some(scorer) = self.scorer.as_mut() {
+            if decision.is_none() && self.edge_byte_error.is_none() {
+                scorer.feed(examinable);
+                if last {
+                    decision =
+                        scorer
+                            .verdict(&self.scoring_thresholds)
+                            .map(|(verdict, certainty)| Decision {
+                                verdict,
+                                certainty,
+                                reason: Reason::Scored,
+                                byte: None,
+                                examined: examinable.len(),
+                                consumed: None,
+                            });
                 }
-                match (self.second_byte_in_escape, byte) {
-                    (0x28, 0x42) | (0x28, 0x4A) | (0x28, 0x49) | (0x24, 0x40) | (0x24, 0x42) => {
-                        return Some(ISO_2022_JP);
+            }
+        }
+        let decision = self.decide_utf8(examinable, last, decision);
+        let decision = match (decision, self.blank_verdict) {
+            (None, Some(verdict)) if self.blank && last && !self.budget_exhausted => {
+                Some(Decision {
+                    verdict,
+                    certainty: Certainty::Certain,
+                    reason: Reason::Blank,
+                    byte: None,
+                    examined: examinable.len(),
+                    consumed: None,
+                })
+            }
+            _ => decision,
+        };
+        let decision = match decision {
+            None if forced && self.edge_byte_error.is_none() => {
+                Some(self.forced_decision(examinable.len()))
+            }
+            _ => decision,
+        };
+        let decision = match (decision, self.undecided_default) {
+            (None, Some(verdict)) if last && self.edge_byte_error.is_none() => Some(Decision {
+                verdict,
+                certainty: Certainty::Guessed,
+                reason: Reason::UndecidedDefault,
+                byte: None,
+                examined: examinable.len(),
+                consumed: None,
+            }),
+            _ => decision,
+        };
+        self.finished = last || decision.is_some();
+        self.verdict = decision.map(|decision| decision.verdict);
+        self.certainty = decision.map(|decision| decision.certainty);
+        self.reason = decision.map(|decision| decision.reason);
+        let examined = decision.map_or(examinable.len(), |decision| decision.examined);
+        self.examined_len += examined as u64;
+        self.decided_at = decision.map(|decision| match decision.byte {
+            Some(byte) => (self.examined_len - 1, Some(byte)),
+            None => (self.examined_len, None),
+        });
+        if let Some(hash) = self.examined_hash.as_mut() {
+            *hash = fnv1a_update(*hash, &buffer[..examined]);
+        }
+        #[cfg(feature = "encoding_rs")]
+        {
+            if let Some(validation) = self.validation.as_mut() {
+                if let Some(Decision {
+                    verdict, consumed, ..
+                }) = decision
+                {
+                    let decoder = match (verdict, consumed) {
+                        (_, None) => None,
+                        (Verdict::ShiftJis, _) => Some(self.shift_jis.take_decoder()),
+                        (Verdict::EucJp, _) => Some(self.euc_jp.take_decoder()),
+                        (Verdict::Iso2022Jp, _) => {
+                            Some(ISO_2022_JP.new_decoder_without_bom_handling())
+                        }
+                        (Verdict::Utf8, _) => Some(UTF_8.new_decoder_without_bom_handling()),
+                    };
+                    let mut continuation = Continuation::new(decoder);
+                    if let Some(consumed) = consumed {
+                        let offset = validation.position + consumed;
+                        if let Some(certainty) = self.iso_2022_jp_certainty.as_ref() {
+                            let bytes = certainty.bytes();
+                            continuation.feed(bytes, offset - bytes.len(), false);
+                        } else if let Some(sequence) = self.iso_2022_jp.confirming_sequence() {
+                            continuation.feed(&sequence, offset - sequence.len(), false);
+                        }
+                        continuation.feed(&buffer[consumed..], offset, last);
                     }
-                    _ => {}
+                    validation.decided = Some((verdict, continuation));
+                    self.finished = last;
                 }
-                if self.escape_seen {
-                    self.iso_2022_jp_disqualified = true;
-                    break;
+                validation.position += buffer.len();
+            }
+        }
+        decision.map(|decision| decision.verdict)
+    }
+
+    /// The best guess once the budget of `set_max_bytes()` has run out for
+    /// a buffer with `examined` bytes examined: the leaning of the scores or
+    /// the fallback.
+    fn forced_decision(&self, examined: usize) -> Decision {
+        #[cfg_attr(not(feature = "scoring"), allow(unused_mut))]
+        let mut guess = (self.fallback, Certainty::Guessed);
+        #[cfg(feature = "scoring")]
+        if let Some(scored) = self
+            .scorer
+            .as_ref()
+            .and_then(|scorer| scorer.verdict(&self.scoring_thresholds))
+        {
+            guess = scored;
+        }
+        let (verdict, certainty) = guess;
+        Decision {
+            verdict,
+            certainty,
+            reason: Reason::ByteBudget,
+            byte: None,
+            examined,
+            consumed: None,
+        }
+    }
+
+    /// Runs `detect()` on the pieces of `buffer` between the bytes that the
+    /// DEL and C1 treatments skip or stop at.
+    fn detect_edges(&mut self, buffer: &[u8], last: bool) -> Option<Decision> {
+        if self.edge_byte_error.is_some() {
+            return None;
+        }
+        let (del, c1) = self.edge_treatments();
+        self.position = self.examined_len;
+        if del == EdgeByteTreatment::Evidence && c1 == EdgeByteTreatment::Evidence {
+            return self.detect(buffer, last);
+        }
+        let is_c1_standalone = |next: Option<&u8>| match next {
+            Some(&next) => next < 0x40 || next == 0x7F,
+            None => true,
+        };
+        if let Some(held) = self.held_c1.take() {
+            if buffer.is_empty() && !last {
+                self.held_c1 = Some(held);
+                return None;
+            }
+            if !is_c1_standalone(buffer.first()) {
+                self.position = self.examined_len - 1;
+                if let Some(decision) = self.detect(&[held], false) {
+                    // Let the winner see the held byte, too, so that the
+                    // decision holds for the current buffer.
+                    if decision.verdict == Verdict::ShiftJis {
+                        self.shift_jis.push(held);
+                    }
+                    return Some(Decision {
+                        examined: 0,
+                        consumed: decision.consumed.map(|_| 0),
+                        ..decision
+                    });
                 }
-                i += 1;
+            } else if c1 == EdgeByteTreatment::Error {
+                self.edge_byte_error = Some(self.examined_len - 1);
+                return None;
             }
         }
-        for &byte in &buffer[i..] {
-            if !feed_decoder(&mut self.euc_jp_decoder, byte, false) {
-                return Some(SHIFT_JIS);
+        let mut start = 0;
+        loop {
+            let edge = buffer[start..].iter().position(|&byte| match byte {
+                0x7F => del != EdgeByteTreatment::Evidence,
+                0x80..=0x9F => c1 != EdgeByteTreatment::Evidence,
+                _ => false,
+            });
+            let end = match edge {
+                Some(edge) => start + edge,
+                None => {
+                    self.position = self.examined_len + start as u64;
+                    return self
+                        .detect(&buffer[start..], last)
+                        .map(|decision| decision.offset_by(start));
+                }
+            };
+            self.position = self.examined_len + start as u64;
+            if let Some(decision) = self.detect(&buffer[start..end], false) {
+                return Some(decision.offset_by(start));
             }
-            if !feed_decoder(&mut self.shift_jis_decoder, byte, false) {
-                return Some(EUC_JP);
+            let byte = buffer[end];
+            let treatment = if byte == 0x7F {
+                del
+            } else if self.shift_jis.pending() != 0 || self.euc_jp.pending() != 0 {
+                EdgeByteTreatment::Evidence
+            } else if end + 1 == buffer.len() && !last {
+                self.held_c1 = Some(byte);
+                return None;
+            } else if is_c1_standalone(buffer.get(end + 1)) {
+                c1
+            } else {
+                EdgeByteTreatment::Evidence
+            };
+            match treatment {
+                EdgeByteTreatment::Evidence => {
+                    if let Some(decision) = self.detect(&buffer[end..end + 1], false) {
+                        return Some(decision.offset_by(end));
+                    }
+                }
+                EdgeByteTreatment::Ignore => {}
+                EdgeByteTreatment::Error => {
+                    self.edge_byte_error = Some(self.examined_len + end as u64);
+                    return None;
+                }
             }
+            start = end + 1;
         }
-        if last {
-            if !feed_decoder(&mut self.euc_jp_decoder, 0, true) {
-                return Some(SHIFT_JIS);
+    }
+
+    /// Runs the candidates on `buffer`.
+    fn detect(&mut self, buffer: &[u8], last: bool) -> Option<Decision> {
+        let mut i = 0;
+        if !self.iso_2022_jp_disqualified {
+            if !self.iso_2022_jp.escape_seen() {
+                i = find_non_ascii_or_escape(buffer);
             }
-            if !feed_decoder(&mut self.shift_jis_decoder, 0, true) {
-                return Some(EUC_JP);
+            while i < buffer.len() {
+                let step = match self.iso_2022_jp_certainty.as_mut() {
+                    Some(certainty) => certainty.push(buffer[i]),
+                    None => match self.iso_2022_jp.push(buffer[i]) {
+                        Step::Confirmed if self.strict_iso_2022_jp => {
+                            self.iso_2022_jp_certainty =
+                                self.iso_2022_jp.confirming_sequence().map(|sequence| {
+                                    Iso2022JpCertainty::new_strict(
+                                        sequence,
+                                        self.skip_ansi_sequences,
+                                    )
+                                });
+                            Step::Possible
+                        }
+                        Step::Confirmed if self.require_certain_iso_2022_jp => {
+                            // Only validation mode replays the bytes.
+                            #[cfg(feature = "encoding_rs")]
+                            let record = self.validation.is_some();
+                            #[cfg(not(feature = "encoding_rs"))]
+                            let record = false;
+                            self.iso_2022_jp_certainty =
+                                self.iso_2022_jp.confirming_sequence().map(|sequence| {
+                                    Iso2022JpCertainty::new(
+                                        sequence,
+                                        self.skip_ansi_sequences,
+                                        record,
+                                    )
+                                });
+                            Step::Possible
+                        }
+                        step => step,
+                    },
+                };
+                #[cfg(feature = "debug-trace")]
+                self.trace_byte(i, Some(step), None, None);
+                match step {
+                    Step::Confirmed => {
+                        self.resync_candidates(&buffer[..i + 1]);
+                        let certain = self.iso_2022_jp_certainty.is_some();
+                        return Some(Decision {
+                            verdict: Verdict::Iso2022Jp,
+                            certainty: if certain {
+                                Certainty::Certain
+                            } else {
+                                Certainty::Tentative
+                            },
+                            reason: if certain {
+                                Reason::Iso2022JpCharacter
+                            } else {
+                                Reason::Iso2022JpEscape
+                            },
+                            byte: Some(buffer[i]),
+                            examined: i + 1,
+                            consumed: Some(i + 1),
+                        });
+                    }
+                    Step::Rejected => {
+                        let flooded = match self.iso_2022_jp_certainty.as_ref() {
+                            Some(certainty) => certainty.flooded(),
+                            None => self.iso_2022_jp.flooded(),
+                        };
+                        if flooded {
+                            self.suspicious = Some(Suspicious::EscapeFlood);
+                        }
+                        self.iso_2022_jp_ruled_out = Some((self.position + i as u64, buffer[i]));
+                        self.iso_2022_jp_disqualified = true;
+                        break;
+                    }
+                    Step::Possible => {}
+                }
+                i += 1;
+            }
+            // Shift_JIS and EUC-JP haven't seen the bytes that only
+            // ISO-2022-JP has examined, which are all ASCII.
+            self.resync_candidates(&buffer[..i]);
+            if last && !self.iso_2022_jp_disqualified {
+                if let Some(certainty) = self.iso_2022_jp_certainty.as_mut() {
+                    let step = certainty.finish();
+                    let jis_x_0208_seen = certainty.jis_x_0208_seen();
+                    #[cfg(feature = "debug-trace")]
+                    self.trace_finish(buffer.len(), Some(step), None, None);
+                    if step == Step::Rejected {
+                        self.iso_2022_jp_disqualified = true;
+                    } else if self.strict_iso_2022_jp {
+                        return Some(Decision {
+                            verdict: Verdict::Iso2022Jp,
+                            certainty: if jis_x_0208_seen {
+                                Certainty::Certain
+                            } else {
+                                Certainty::Tentative
+                            },
+                            reason: Reason::Iso2022JpStructure,
+                            byte: None,
+                            examined: buffer.len(),
+                            consumed: Some(buffer.len()),
+                        });
+                    } else {
+                        return Some(Decision {
+                            verdict: Verdict::Iso2022Jp,
+                            certainty: Certainty::Tentative,
+                            reason: Reason::Iso2022JpEndOfStream,
+                            byte: None,
+                            examined: buffer.len(),
+                            consumed: Some(buffer.len()),
+                        });
+                    }
+                }
             }
-            return None;
         }
-        self.finished = false;
+        // Without decisive half-width katakana, Japanese text is often
+        // ambiguous throughout, so the count would mean nothing.
+        let count_ambiguous = self.suspicious.is_none()
+            && self.reject_half_width_katakana
+            && !(self.text_art && self.conformance == Conformance::Pragmatic);
+        let mut race = JapaneseRace {
+            detector: self,
+            buffer,
+            count_ambiguous,
+        };
+        if let Some(decision) = race::race(&mut race, buffer, i) {
+            return Some(decision);
+        }
+        if count_ambiguous && self.ambiguous_bytes > MAX_AMBIGUOUS_BYTES {
+            self.suspicious = Some(Suspicious::Ambiguity);
+        }
+        if last {
+            return race::finish(&mut JapaneseRace {
+                detector: self,
+                buffer,
+                count_ambiguous,
+            });
+        }
         None
     }
+
+    /// Lets the Shift_JIS and EUC-JP candidates resynchronize on ASCII bytes
+    /// that they skip.
+    fn resync_candidates(&mut self, skipped: &[u8]) {
+        self.euc_jp.resync(skipped);
+        self.shift_jis.resync(skipped);
+    }
+
+    /// Treats the current position as a character boundary, discarding any
+    /// incomplete Shift_JIS or EUC-JP character without counting it as
+    /// evidence.
+    #[cfg(feature = "encoding_rs")]
+    pub(crate) fn record_boundary(&mut self) {
+        self.shift_jis.discard_pending();
+        self.euc_jp.discard_pending();
+    }
+
+    /// Whether ISO-2022-JP is still a possible guess.
+    pub(crate) fn iso_2022_jp_possible(&self) -> bool {
+        !self.iso_2022_jp_disqualified
+    }
+}
+
+/// Runs the detector on `buffer` as the complete stream in one call.
+/// `allow_2022` has the same meaning as for `Detector::new()`.
+///
+/// Returns `None` if the input doesn't tell the encodings apart, e.g.
+/// because it is ASCII or because its non-ASCII characters decode without
+/// errors or half-width katakana in both Shift_JIS and EUC-JP (as some
+/// uncommon kanji do). Since the whole stream has been seen, there is
+/// nothing more to wait for: `None` means that the input decodes the same
+/// way or equally plausibly either way, and falling back to Shift_JIS (see
+/// `Detector::fallback()`) is the best guess for Web purposes.
+#[cfg(feature = "encoding_rs")]
+pub fn detect(buffer: &[u8], allow_2022: bool) -> Option<&'static Encoding> {
+    Detector::new(allow_2022).feed(buffer, true)
 }
 
 // Any copyright to the test code below this comment is dedicated to the
@@ -235,6 +2148,109 @@ impl Detector {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_feed_verdict() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"abc", false), None);
+        assert_eq!(
+            detector.feed_verdict(b"\x82\xA0\xA4\xA2", true),
+            Some(Verdict::ShiftJis)
+        );
+        let mut detector = Detector::new(false);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B$B\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        assert_eq!(Verdict::EucJp.name(), "EUC-JP");
+    }
+
+    #[test]
+    fn test_footprint() {
+        // Keeps the figures of the crate docs honest on 64-bit targets.
+        if cfg!(target_pointer_width = "64") {
+            let mut limit = 344;
+            if cfg!(feature = "encoding_rs") {
+                limit += 192;
+            }
+            if cfg!(feature = "scoring") {
+                limit += 224;
+            }
+            assert!(core::mem::size_of::<Detector>() <= limit);
+        }
+    }
+
+    #[test]
+    fn test_for_locale() {
+        for locale in ["ja", "ja-JP", "ja-JP-mac", "JA_jp.eucJP"].iter() {
+            let mut detector = Detector::for_locale(locale);
+            assert_eq!(
+                detector.feed_verdict(b"\x1B$B", false),
+                Some(Verdict::Iso2022Jp)
+            );
+            assert_eq!(detector.fallback(), Verdict::ShiftJis);
+        }
+        let mut detector = Detector::for_locale("jam");
+        assert_eq!(detector.feed_verdict(b"\x1B$B", false), None);
+    }
+
+    #[test]
+    fn test_examined_hash() {
+        // The byte after "あ" in EUC-JP is not examined.
+        let mut detector = Detector::new(true);
+        detector.set_hashing(true);
+        assert_eq!(detector.feed_verdict(b"ab", false), None);
+        assert_eq!(detector.examined_len(), 2);
+        assert_eq!(detector.examined_hash(), Some(hash_examined(b"ab")));
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2c", false),
+            Some(Verdict::EucJp)
+        );
+        assert_eq!(detector.examined_len(), 3);
+        assert_eq!(detector.examined_hash(), Some(hash_examined(b"ab\xA4")));
+        assert_eq!(Detector::new(true).examined_hash(), None);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_validation_mode() {
+        // "あ" in EUC-JP decides, then a malformed sequence split across
+        // calls and a byte that is malformed in EUC-JP.
+        let mut detector = Detector::new(true);
+        detector.set_validation_mode(true);
+        assert_eq!(detector.validation_statistics(), None);
+        assert_eq!(detector.feed(b"a\xA4\xA2\xA4", false), Some(EUC_JP));
+        assert_eq!(detector.validation_statistics().unwrap().count(), 0);
+        assert_eq!(detector.feed(b"\x41b\x80", true), Some(EUC_JP));
+        let statistics = detector.validation_statistics().unwrap();
+        assert_eq!(statistics.count(), 2);
+        assert_eq!(statistics.first_offsets(), &[3, 6]);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_validation_mode_continues_mid_buffer() {
+        // EUC-JP is ruled out by the lead byte of "あ" in Shift_JIS, whose
+        // trail byte and a truncated lead byte follow in the same buffer.
+        let mut detector = Detector::new(true);
+        detector.set_validation_mode(true);
+        assert_eq!(detector.feed(b"a\x82\xA0\x82", true), Some(SHIFT_JIS));
+        assert_eq!(
+            detector.validation_statistics().unwrap().first_offsets(),
+            &[3]
+        );
+        // ISO-2022-JP decoding starts with the confirming escape sequence.
+        let mut detector = Detector::new(true);
+        detector.set_validation_mode(true);
+        assert_eq!(detector.feed(b"\x1B$", false), None);
+        assert_eq!(detector.feed(b"B\x24\x22\xFF", true), Some(ISO_2022_JP));
+        assert_eq!(
+            detector.validation_statistics().unwrap().first_offsets(),
+            &[5]
+        );
+    }
+
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_iso_2022_jp() {
         let mut detector = Detector::new(true);
@@ -244,30 +2260,35 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_error_precedence() {
         let mut detector = Detector::new(true);
         assert_eq!(detector.feed(b"abc\xFF", true), Some(SHIFT_JIS));
     }
 
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_invalid_euc_jp() {
         let mut detector = Detector::new(true);
         assert_eq!(detector.feed(b"abc\x81\x40", true), Some(SHIFT_JIS));
     }
 
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_invalid_shift_jis() {
         let mut detector = Detector::new(true);
         assert_eq!(detector.feed(b"abc\xEB\xA8", true), Some(EUC_JP));
     }
 
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_invalid_shift_jis_before_invalid_euc_jp() {
         let mut detector = Detector::new(true);
         assert_eq!(detector.feed(b"abc\xEB\xA8\x81\x40", true), Some(EUC_JP));
     }
 
+    #[cfg(feature = "encoding_rs")]
     #[test]
     fn test_undecided() {
         let mut detector = Detector::new(true);
@@ -275,4 +2296,561 @@ mod tests {
         assert_eq!(detector.feed(b"abc", false), None);
     }
 
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_jis_x_0212_split() {
+        // "a" and a JIS X 0212 kanji whose last byte is a lead byte in
+        // Shift_JIS.
+        let input = b"a\x8F\xB0\xE0";
+        for decisive in [false, true].iter().cloned() {
+            for split in 0..=input.len() {
+                let mut detector = Detector::new(true);
+                detector.set_jis_x_0212_decisive(decisive);
+                let mut result = detector.feed(&input[..split], false);
+                if result.is_none() {
+                    result = detector.feed(&input[split..], false);
+                }
+                assert_eq!(result, if decisive { Some(EUC_JP) } else { None });
+            }
+        }
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(b"\x8F", false), None);
+        assert_eq!(detector.feed(b"\xB0", true), Some(SHIFT_JIS));
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_mid_stream_split_sequence() {
+        // Trail byte of a Shift_JIS character, a kanji, a space and "あ" in
+        // Shift_JIS.
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(b"\xB0\xE0\x40 \x82\xA0", false), Some(EUC_JP));
+        let mut detector = Detector::new_mid_stream(true);
+        assert_eq!(
+            detector.feed(b"\xB0\xE0\x40 \x82\xA0", false),
+            Some(SHIFT_JIS)
+        );
+    }
+
+    #[test]
+    fn test_require_certain_iso_2022_jp() {
+        // "あ" in ISO-2022-JP split across calls.
+        let mut detector = Detector::new(true);
+        detector.set_require_certain_iso_2022_jp(true);
+        assert_eq!(detector.feed_verdict(b"a\x1B$B\x24", false), None);
+        assert_eq!(detector.certainty(), None);
+        assert_eq!(
+            detector.feed_verdict(b"\x22", false),
+            Some(Verdict::Iso2022Jp)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Certain));
+        // A stray escape followed by "あ" in EUC-JP.
+        let mut detector = Detector::new(true);
+        detector.set_require_certain_iso_2022_jp(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B(B\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Certain));
+        // Only an escape before the end of the stream.
+        let mut detector = Detector::new(true);
+        detector.set_require_certain_iso_2022_jp(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B(Babc", true),
+            Some(Verdict::Iso2022Jp)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Tentative));
+        let mut detector = Detector::new(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B(B", false),
+            Some(Verdict::Iso2022Jp)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Tentative));
+    }
+
+    #[test]
+    fn test_strict_iso_2022_jp() {
+        // "あ" in ISO-2022-JP only decides at the end of the stream.
+        let mut detector = Detector::new(true);
+        detector.set_strict_iso_2022_jp(true);
+        assert_eq!(detector.feed_verdict(b"\x1B$B\x24\x22", false), None);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B(B", true),
+            Some(Verdict::Iso2022Jp)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Certain));
+        assert_eq!(
+            detector.outcome().reason(),
+            Some(Reason::Iso2022JpStructure)
+        );
+        // Then "あ" in Shift_JIS.
+        let mut detector = Detector::new(true);
+        detector.set_strict_iso_2022_jp(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B$B\x24\x22\x1B(B\x82\xA0", true),
+            Some(Verdict::ShiftJis)
+        );
+        // Shift Out after the escape sequence.
+        let input = b"\x1B(Ba\x0Eb";
+        let mut detector = Detector::new(true);
+        detector.set_strict_iso_2022_jp(true);
+        assert_eq!(detector.feed_verdict(input, true), None);
+        assert_eq!(detector.outcome().iso_2022_jp_ruled_out(), Some(4));
+        assert_eq!(
+            Detector::new(true).feed_verdict(input, true),
+            Some(Verdict::Iso2022Jp)
+        );
+    }
+
+    #[test]
+    fn test_skip_ansi_sequences() {
+        let log = b"\x1B[1;32mok\x1B[0m \x1B]0;log\x07\x1B$B\x24\x22";
+        assert_eq!(Detector::new(true).feed_verdict(log, true), None);
+        let mut detector = Detector::new(true);
+        detector.set_skip_ansi_sequences(true);
+        assert_eq!(detector.feed_verdict(log, true), Some(Verdict::Iso2022Jp));
+        // "あ" in Shift_JIS after a color code.
+        let mut detector = Detector::new(true);
+        detector.set_skip_ansi_sequences(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B[31m\x82\xA0", true),
+            Some(Verdict::ShiftJis)
+        );
+    }
+
+    #[test]
+    fn test_escape_flood() {
+        let mut flood = b"\x1B[m".repeat(MAX_ESCAPE_SEQUENCES + 1);
+        flood.extend_from_slice(b"\x1B$B\x24\x22");
+        let mut detector = Detector::new(true);
+        detector.set_skip_ansi_sequences(true);
+        assert_eq!(detector.feed_verdict(&flood, true), None);
+        assert_eq!(detector.suspicious(), Some(Suspicious::EscapeFlood));
+        // Undecided for other reasons.
+        let mut detector = Detector::new(true);
+        detector.set_skip_ansi_sequences(true);
+        assert_eq!(detector.feed_verdict(b"\x1B[m\xE0\xE0", true), None);
+        assert_eq!(detector.suspicious(), None);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_current_guess() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(b"<html>", false), None);
+        assert_eq!(detector.current_guess(), None);
+        // An incomplete escape sequence doesn't lean.
+        assert_eq!(detector.feed(b"\x1B$", false), None);
+        assert_eq!(detector.current_guess(), None);
+        // "あ" in EUC-JP.
+        assert_eq!(detector.feed(b"\xA4\xA2", false), Some(EUC_JP));
+        assert_eq!(detector.current_guess(), Some(EUC_JP));
+        let mut detector = DetectorBuilder::new().skip_ansi_sequences(true).build();
+        assert_eq!(detector.feed(b"\x1B", false), None);
+        assert_eq!(detector.current_guess(), None);
+        assert_eq!(detector.feed(b"[", false), None);
+        assert_eq!(detector.current_guess(), None);
+        // A designation waiting for a JIS X 0208 character, "あ".
+        let mut detector = DetectorBuilder::new()
+            .require_certain_iso_2022_jp(true)
+            .build();
+        assert_eq!(detector.feed(b"\x1B$B", false), None);
+        assert_eq!(detector.current_guess(), Some(ISO_2022_JP));
+        assert_eq!(detector.feed(b"\x24\x22", false), Some(ISO_2022_JP));
+        // "日本" in UTF-8.
+        let mut detector = DetectorBuilder::new().allow_utf8(true).build();
+        assert_eq!(detector.feed("日本".as_bytes(), false), None);
+        // Peeking doesn't change anything.
+        assert_eq!(detector.current_guess(), Some(UTF_8));
+        assert_eq!(detector.current_guess(), Some(UTF_8));
+        assert_eq!(detector.feed(b"", true), Some(UTF_8));
+    }
+
+    #[cfg(all(feature = "encoding_rs", feature = "scoring"))]
+    #[test]
+    fn test_current_guess_scoring() {
+        // A Level 2 kanji in EUC-JP that would be a user-defined character
+        // in Shift_JIS leans EUC-JP even below the margin to decide.
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        detector.set_scoring_thresholds(ScoringThresholds {
+            min_margin: 5,
+            likely_margin: 5,
+        });
+        assert_eq!(detector.feed(b"\xF0\xA1", false), None);
+        assert_eq!(detector.current_guess(), Some(EUC_JP));
+        assert_eq!(detector.feed(b"", true), None);
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(b"\xF0\xA1", false), None);
+        assert_eq!(detector.current_guess(), None);
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        // A kanji that both encodings accept, over and over.
+        let ambiguous = b"\xE0\xE0".repeat(MAX_AMBIGUOUS_BYTES as usize / 2);
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(&ambiguous, false), None);
+        assert_eq!(detector.suspicious(), None);
+        assert_eq!(detector.feed_verdict(b"\xE0\xE0", false), None);
+        assert_eq!(detector.suspicious(), Some(Suspicious::Ambiguity));
+        // "あ" in EUC-JP still decides.
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        assert_eq!(detector.suspicious(), Some(Suspicious::Ambiguity));
+        // Half-width katakana that don't decide make ambiguity common.
+        let mut detector = Detector::new(true);
+        detector.set_reject_half_width_katakana(false);
+        assert_eq!(detector.feed_verdict(&ambiguous.repeat(2), true), None);
+        assert_eq!(detector.suspicious(), None);
+    }
+
+    #[test]
+    fn test_edge_bytes() {
+        // DEL within a kanji that is valid in both encodings, followed by
+        // "あ" in EUC-JP.
+        let input = b"\xE0\x7F\xA1\xA4\xA2";
+        assert_eq!(
+            Detector::new(true).feed_verdict(input, true),
+            Some(Verdict::ShiftJis)
+        );
+        let mut detector = Detector::new(true);
+        detector.set_del_treatment(EdgeByteTreatment::Ignore);
+        assert_eq!(detector.feed_verdict(input, true), Some(Verdict::EucJp));
+        let mut detector = Detector::new(true);
+        detector.set_del_treatment(EdgeByteTreatment::Error);
+        assert_eq!(detector.feed_verdict(b"ab\x7F\xA4\xA2", true), None);
+        assert_eq!(detector.edge_byte_error(), Some(2));
+        let mut detector = Detector::new(true);
+        detector.set_del_treatment(EdgeByteTreatment::Error);
+        detector.set_conformance(Conformance::StrictWhatwg);
+        assert_eq!(detector.feed_verdict(b"\x7F", true), None);
+        assert_eq!(detector.edge_byte_error(), None);
+
+        // A C1 CSI before "あ" in EUC-JP, split after the CSI, and "あ" in
+        // Shift_JIS, whose lead byte is in the C1 range but not standalone.
+        assert_eq!(
+            Detector::new(true).feed_verdict(b"a\x9B", false),
+            Some(Verdict::ShiftJis)
+        );
+        for &(treatment, verdict) in [
+            (EdgeByteTreatment::Ignore, Some(Verdict::EucJp)),
+            (EdgeByteTreatment::Error, None),
+        ]
+        .iter()
+        {
+            let mut detector = Detector::new(true);
+            detector.set_c1_treatment(treatment);
+            assert_eq!(detector.feed_verdict(b"a\x9B", false), None);
+            assert_eq!(detector.pending(), 1);
+            assert_eq!(detector.feed_verdict(b"0m\xA4\xA2", true), verdict);
+            let mut detector = Detector::new(true);
+            detector.set_c1_treatment(treatment);
+            assert_eq!(detector.feed_verdict(b"a\x82", false), None);
+            assert_eq!(
+                detector.feed_verdict(b"\xA0", true),
+                Some(Verdict::ShiftJis)
+            );
+        }
+        let mut detector = Detector::new(true);
+        detector.set_c1_treatment(EdgeByteTreatment::Error);
+        assert_eq!(detector.feed_verdict(b"a\x9B", false), None);
+        assert_eq!(detector.feed_verdict(b"0m", true), None);
+        assert_eq!(detector.edge_byte_error(), Some(1));
+    }
+
+    #[test]
+    fn test_pending() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"a\x1B$", false), None);
+        assert_eq!(detector.pending(), 2);
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"\xE0\xE0\x8F\xB0", false), None);
+        assert_eq!(detector.pending(), MAX_PENDING);
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"\xE0\xE0", false), None);
+        assert_eq!(detector.pending(), 0);
+    }
+
+    #[test]
+    fn test_text_art() {
+        // Half-width "ｷﾀ" and "━" in Shift_JIS.
+        let art = b"\xB7\xC0\x84\xAA";
+        assert_eq!(
+            Detector::new(false).feed_verdict(art, true),
+            Some(Verdict::EucJp)
+        );
+        let mut detector = Detector::new(false);
+        detector.set_text_art(true);
+        assert_eq!(detector.feed_verdict(art, false), Some(Verdict::ShiftJis));
+        // "あ" in EUC-JP is only decided at the end of the stream.
+        let mut detector = Detector::new(false);
+        detector.set_text_art(true);
+        assert_eq!(detector.feed_verdict(b"\xA4\xA2", false), None);
+        assert_eq!(detector.feed_verdict(b"", true), Some(Verdict::EucJp));
+        let mut detector = Detector::new(false);
+        detector.set_text_art(true);
+        detector.set_conformance(Conformance::StrictWhatwg);
+        assert_eq!(detector.feed_verdict(art, false), Some(Verdict::EucJp));
+    }
+
+    #[test]
+    fn test_outcome() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"a\xE0", false), None);
+        let outcome = detector.outcome();
+        assert_eq!(outcome.verdict(), None);
+        assert_eq!(outcome.guess(), Verdict::ShiftJis);
+        assert_eq!(outcome.pending(), 1);
+        assert!(!outcome.finished());
+        assert_eq!(
+            detector.feed_verdict(b"\xA1 \xA4\xA2", false),
+            Some(Verdict::EucJp)
+        );
+        let outcome = detector.outcome();
+        assert_eq!(outcome.verdict(), Some(Verdict::EucJp));
+        assert_eq!(outcome.certainty(), Some(Certainty::Certain));
+        assert_eq!(outcome.examined_len(), 5);
+        assert!(outcome.finished());
+    }
+
+    #[test]
+    fn test_blank_verdict() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b" \r\n", true), None);
+        assert!(detector.outcome().blank());
+        detector.reset(true);
+        detector.set_blank_verdict(Some(Verdict::Utf8));
+        assert_eq!(detector.feed_verdict(b" \t", false), None);
+        assert_eq!(detector.feed_verdict(b"\n", true), Some(Verdict::Utf8));
+        let outcome = detector.outcome();
+        assert_eq!(outcome.reason(), Some(Reason::Blank));
+        assert_eq!(outcome.decided_at(), Some(3));
+        detector.reset(true);
+        assert_eq!(detector.feed_verdict(b"", true), Some(Verdict::Utf8));
+        detector.reset(true);
+        assert_eq!(detector.feed_verdict(b" a ", true), None);
+        assert!(!detector.outcome().blank());
+    }
+
+    #[test]
+    fn test_undecided_default() {
+        let mut detector = Detector::new(true);
+        detector.set_undecided_default(Some(Verdict::ShiftJis));
+        assert_eq!(detector.feed_verdict(b"\xF0", false), None);
+        assert_eq!(
+            detector.feed_verdict(b"\xA1", true),
+            Some(Verdict::ShiftJis)
+        );
+        assert_eq!(detector.certainty(), Some(Certainty::Guessed));
+        assert_eq!(detector.outcome().reason(), Some(Reason::UndecidedDefault));
+        // Evidence still decides.
+        detector.reset(true);
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        // The blank verdict takes precedence on blank input.
+        detector.reset(true);
+        detector.set_blank_verdict(Some(Verdict::Utf8));
+        assert_eq!(detector.feed_verdict(b" ", true), Some(Verdict::Utf8));
+        let detector = DetectorBuilder::new()
+            .undecided_default(Some(Verdict::EucJp))
+            .build();
+        assert_eq!(detector.undecided_default(), Some(Verdict::EucJp));
+    }
+
+    #[test]
+    fn test_decided_at() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"abc", false), None);
+        assert_eq!(detector.decided_at(), None);
+        assert_eq!(
+            detector.feed_verdict(b"d\xA4\xA2ef", false),
+            Some(Verdict::EucJp)
+        );
+        // 0xA4 is half-width katakana in Shift_JIS.
+        assert_eq!(detector.decided_at(), Some(4));
+        assert_eq!(detector.examined_len(), 5);
+        assert_eq!(detector.outcome().decided_at(), detector.decided_at());
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let mut detector = Detector::new(true);
+        detector.set_max_bytes(Some(4));
+        assert_eq!(detector.feed_verdict(b"ab", false), None);
+        assert!(!detector.forced());
+        // The budget runs out within the buffer.
+        assert_eq!(
+            detector.feed_verdict(b"\xF0\xA1\xA4\xA2", false),
+            Some(Verdict::ShiftJis)
+        );
+        assert!(detector.forced());
+        assert!(detector.budget_exhausted());
+        let outcome = detector.outcome();
+        assert!(outcome.forced());
+        assert_eq!(outcome.certainty(), Some(Certainty::Guessed));
+        assert_eq!(outcome.examined_len(), 4);
+        // A decision within the budget isn't forced.
+        let mut detector = DetectorBuilder::new().max_bytes(Some(4)).build();
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2", false),
+            Some(Verdict::EucJp)
+        );
+        assert!(!detector.forced());
+        let mut detector = DetectorBuilder::new()
+            .allow_utf8(true)
+            .max_bytes(Some(3))
+            .build();
+        assert_eq!(
+            detector.feed_verdict("日本".as_bytes(), false),
+            Some(Verdict::Utf8)
+        );
+    }
+
+    #[cfg(feature = "scoring")]
+    #[test]
+    fn test_scoring() {
+        // A Level 2 kanji in EUC-JP that would be a user-defined character
+        // in Shift_JIS.
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"\xF0\xA1", true), None);
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        assert_eq!(detector.feed_verdict(b"\xF0", false), None);
+        assert_eq!(detector.feed_verdict(b"\xA1", true), Some(Verdict::EucJp));
+        assert_eq!(detector.certainty(), Some(Certainty::Scored));
+        assert_eq!(detector.scores(), Some((-3, 1)));
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        detector.set_scoring_thresholds(ScoringThresholds {
+            min_margin: 5,
+            likely_margin: 5,
+        });
+        assert_eq!(detector.feed_verdict(b"\xF0\xA1", true), None);
+        #[cfg(feature = "diagnostics")]
+        assert!(detector
+            .policy()
+            .rules()
+            .iter()
+            .any(|rule| rule.id() == "frequency-scoring" && rule.enabled()));
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        assert_eq!(detector.feed_verdict(b"abc", true), None);
+    }
+
+    #[test]
+    fn test_feed_with_outcome() {
+        let mut detector = Detector::new(true);
+        let outcome = detector.feed_with_outcome(b"ab", false);
+        assert_eq!(outcome.reason(), None);
+        let outcome = detector.feed_with_outcome(b"c\x1B$B", false);
+        assert_eq!(outcome.verdict(), Some(Verdict::Iso2022Jp));
+        assert_eq!(outcome.reason(), Some(Reason::Iso2022JpEscape));
+        assert_eq!(outcome.decided_at(), Some(5));
+        let mut detector = Detector::new(true);
+        let outcome = detector.feed_with_outcome(b"\xA4\xA2", true);
+        assert_eq!(outcome.reason(), Some(Reason::ShiftJisHalfWidthKatakana));
+        assert_eq!(outcome.decided_at(), Some(0));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut detector = Detector::new(true);
+        detector.set_skip_ansi_sequences(true);
+        detector.set_text_art(true);
+        assert_eq!(
+            detector.feed_verdict(b"\x1B[0m\x1B$B", false),
+            Some(Verdict::Iso2022Jp)
+        );
+        detector.reset(true);
+        assert_eq!(detector.outcome().verdict(), None);
+        assert_eq!(detector.examined_len(), 0);
+        // The options are kept.
+        assert_eq!(
+            detector.feed_verdict(b"\x1B[0m\x1B$B", false),
+            Some(Verdict::Iso2022Jp)
+        );
+        detector.reset(false);
+        assert_eq!(detector.feed_verdict(b"\x1B$B\xA4\xA2", false), None);
+        assert_eq!(detector.feed_verdict(b"", true), Some(Verdict::EucJp));
+        let mut detector = Detector::new(false);
+        assert_eq!(detector.feed_verdict(b"\xE0", false), None);
+        detector.reset(false);
+        assert_eq!(
+            detector.feed_verdict(b"\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+    }
+
+    #[test]
+    fn test_strict_whatwg_ignores_mid_stream() {
+        let mut detector = Detector::new_mid_stream(true);
+        detector.set_conformance(Conformance::StrictWhatwg);
+        assert_eq!(
+            detector.feed_verdict(b"\xB0\xE0\x40 \x82\xA0", false),
+            Some(Verdict::EucJp)
+        );
+        #[cfg(feature = "diagnostics")]
+        assert!(!detector.policy().rules()[0].enabled());
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"\xA4\xA2", false), Some(EUC_JP));
+        assert_eq!(detect(b"\x1B$B", true), Some(ISO_2022_JP));
+        assert_eq!(detect(b"\x1B$B", false), None);
+        assert_eq!(detect(b"", true), None);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_mid_stream_resync_in_ascii_prefix() {
+        let mut detector = Detector::new_mid_stream(true);
+        assert_eq!(detector.feed(b"ab \xA4\xA2", true), Some(EUC_JP));
+    }
+
+    #[test]
+    fn test_text_decoder_label() {
+        assert_eq!(Verdict::ShiftJis.text_decoder_label(), "shift_jis");
+        assert_eq!(Verdict::EucJp.text_decoder_label(), "euc-jp");
+        assert_eq!(Verdict::Iso2022Jp.text_decoder_label(), "iso-2022-jp");
+        assert_eq!(Verdict::Utf8.text_decoder_label(), "utf-8");
+    }
+
+    #[test]
+    fn test_mid_stream_resync_after_escape() {
+        // An ASCII line that only ISO-2022-JP examines, whose line feed
+        // resynchronizes the candidates before 0x85, which isn't an EUC-JP
+        // lead byte.
+        let input = b"\x1B(Babc\n\x85 \xA4\xA2";
+        let builder = DetectorBuilder::new().allow_2022(true).mid_stream(true);
+        let mut detector = builder.clone().allow_2022(false).build();
+        assert_eq!(detector.feed_verdict(input, true), Some(Verdict::ShiftJis));
+        assert_eq!(detector.decided_at(), Some(7));
+        let mut detector = builder.clone().require_certain_iso_2022_jp(true).build();
+        assert_eq!(detector.feed_verdict(input, true), Some(Verdict::ShiftJis));
+        assert_eq!(detector.decided_at(), Some(7));
+        let mut detector = builder.clone().strict_iso_2022_jp(true).build();
+        assert_eq!(detector.feed_verdict(input, true), Some(Verdict::ShiftJis));
+        assert_eq!(detector.decided_at(), Some(7));
+        // Skipped ANSI sequences, after which the machine has no escape
+        // sequence in progress.
+        let builder = builder.skip_ansi_sequences(true);
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(b"\x1B[mB\x85abc", true),
+            Some(Verdict::ShiftJis)
+        );
+        assert_eq!(detector.decided_at(), Some(4));
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(b"\x1B[1mabc\n\x82\xA0 x", true),
+            Some(Verdict::ShiftJis)
+        );
+    }
 }
@@ -0,0 +1,310 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+
+use crate::malformed::decode_with_policy;
+use crate::Buffer;
+use crate::Detector;
+use crate::MalformedError;
+use crate::MalformedPolicy;
+
+/// A decoder for interactive line-oriented streams, such as chat, IRC or
+/// telnet sessions, that emits each line as soon as its line feed arrives.
+///
+/// Each complete line is fed to the detector before it is decoded, so the
+/// line that decides the encoding is already decoded using the decided
+/// encoding. Lines that complete while the detector is still undecided are
+/// decoded using the detector's fallback guess (Shift_JIS) and are not
+/// revised later; `is_decided()` tells whether the guess was final. A line
+/// feed is a character boundary in all three encodings, so switching
+/// decoders between lines never splits a character.
+///
+/// A decoder `with_buffer()` holds the incomplete line in caller-provided
+/// storage. A line that the storage cannot hold is decoded in parts as it
+/// arrives, and if a part after the first decides, the decided encoding
+/// takes effect from the next line.
+pub struct LineDecoder<B: Buffer = Vec<u8>> {
+    state: LineState,
+    partial: B,
+}
+
+/// The state of a `LineDecoder` other than the incomplete line, so that
+/// the line can be borrowed while decoding it.
+struct LineState {
+    detector: Detector,
+    decoder: Decoder,
+    decided: bool,
+    malformed_policy: MalformedPolicy,
+    position: u64,
+    malformed: u64,
+    /// Whether a part of a line that is still incomplete has been decoded.
+    mid_line: bool,
+    /// The decided encoding if it has to wait for the next line.
+    switch: Option<&'static Encoding>,
+}
+
+impl LineDecoder {
+    /// Instantiates the decoder. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        LineDecoder::with_buffer(allow_2022, Vec::new())
+    }
+}
+
+impl<B: Buffer> LineDecoder<B> {
+    /// The same as `new()` but holds the incomplete line in `buffer`.
+    pub fn with_buffer(allow_2022: bool, buffer: B) -> Self {
+        let detector = Detector::new(allow_2022);
+        let decoder = detector
+            .fallback()
+            .encoding()
+            .new_decoder_without_bom_handling();
+        LineDecoder {
+            state: LineState {
+                detector,
+                decoder,
+                decided: false,
+                malformed_policy: MalformedPolicy::Replace,
+                position: 0,
+                malformed: 0,
+                mid_line: false,
+                switch: None,
+            },
+            partial: buffer,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first call to
+    /// `decode_to_string()`.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.state.malformed_policy = policy;
+    }
+
+    /// The encoding the next line will be decoded from.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.state
+            .switch
+            .unwrap_or_else(|| self.state.decoder.encoding())
+    }
+
+    /// Whether the detector has decided, i.e. whether `encoding()` is final.
+    pub fn is_decided(&self) -> bool {
+        self.state.decided
+    }
+
+    /// The number of malformed sequences decoded so far, i.e. the number of
+    /// replacements performed under `MalformedPolicy::Replace`.
+    pub fn malformed(&self) -> u64 {
+        self.state.malformed
+    }
+
+    /// The number of bytes of an incomplete line being held.
+    pub fn pending(&self) -> usize {
+        self.partial.as_slice().len()
+    }
+
+    /// Decodes the lines that `src` completes, appending the text (including
+    /// the line feeds) to `dst` and holding back the bytes after the last
+    /// line feed. `last` signals the end of the stream, which completes the
+    /// last line even without a line feed. Returns the number of lines
+    /// appended.
+    ///
+    /// Under `MalformedPolicy::Error`, returns an error for the first
+    /// malformed sequence after the text before it has been appended. The
+    /// decoder should not be used after that.
+    pub fn decode_to_string(
+        &mut self,
+        src: &[u8],
+        dst: &mut String,
+        last: bool,
+    ) -> Result<usize, MalformedError> {
+        let mut lines = 0;
+        let mut rest = src;
+        while let Some(newline) = memchr::memchr(b'\n', rest) {
+            let (line, tail) = rest.split_at(newline + 1);
+            rest = tail;
+            lines += 1;
+            if self.pending() == 0 {
+                self.state.decode(line, dst, true, false)?;
+            } else if self.partial.try_extend(line).is_ok() {
+                self.decode_partial(dst, true, false)?;
+            } else {
+                self.decode_partial(dst, false, false)?;
+                self.state.decode(line, dst, true, false)?;
+            }
+        }
+        if self.partial.try_extend(rest).is_err() {
+            self.decode_partial(dst, false, false)?;
+            if self.partial.try_extend(rest).is_err() {
+                self.state.decode(rest, dst, false, false)?;
+            }
+        }
+        if last {
+            if self.pending() != 0 || self.state.mid_line {
+                lines += 1;
+            }
+            self.decode_partial(dst, true, true)?;
+        }
+        Ok(lines)
+    }
+
+    /// Decodes and removes the incomplete line held.
+    fn decode_partial(
+        &mut self,
+        dst: &mut String,
+        end: bool,
+        last: bool,
+    ) -> Result<(), MalformedError> {
+        let result = self.state.decode(self.partial.as_slice(), dst, end, last);
+        self.partial.clear();
+        result
+    }
+}
+
+impl LineState {
+    /// Feeds `part`, which continues the current line and completes it if
+    /// `end` is `true`, to the detector and decodes it.
+    fn decode(
+        &mut self,
+        part: &[u8],
+        dst: &mut String,
+        end: bool,
+        last: bool,
+    ) -> Result<(), MalformedError> {
+        if !self.decided {
+            if let Some(encoding) = self.detector.feed(part, last) {
+                self.decided = true;
+                if encoding != self.decoder.encoding() {
+                    if self.mid_line {
+                        // The part may start within a character.
+                        self.switch = Some(encoding);
+                    } else {
+                        self.decoder = encoding.new_decoder_without_bom_handling();
+                    }
+                }
+            }
+        }
+        let offset = self.position;
+        self.position += part.len() as u64;
+        let result = decode_with_policy(
+            &mut self.decoder,
+            part,
+            offset,
+            dst,
+            last,
+            self.malformed_policy,
+        );
+        self.mid_line = !end;
+        if end {
+            if let Some(encoding) = self.switch.take() {
+                self.decoder = encoding.new_decoder_without_bom_handling();
+            }
+        }
+        match result {
+            Ok(malformed) => {
+                self.malformed += malformed;
+                Ok(())
+            }
+            Err(err) => {
+                self.malformed += 1;
+                Err(err)
+            }
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_lines() {
+        let mut decoder = LineDecoder::new(true);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"hi\n\xA4", &mut text, false),
+            Ok(1)
+        );
+        assert_eq!(text, "hi\n");
+        assert_eq!(decoder.encoding(), SHIFT_JIS);
+        assert_eq!(decoder.pending(), 1);
+        // "あ" in EUC-JP completes on the next line feed.
+        assert_eq!(
+            decoder.decode_to_string(b"\xA2\nb", &mut text, false),
+            Ok(1)
+        );
+        assert_eq!(text, "hi\nあ\n");
+        assert_eq!(decoder.encoding(), EUC_JP);
+        assert!(decoder.is_decided());
+        assert_eq!(decoder.decode_to_string(b"", &mut text, true), Ok(1));
+        assert_eq!(text, "hi\nあ\nb");
+        assert_eq!(decoder.malformed(), 0);
+    }
+
+    #[test]
+    fn test_undecided_lines_use_fallback() {
+        // A kanji that is valid in both Shift_JIS and EUC-JP.
+        let mut decoder = LineDecoder::new(true);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"\xE0\xE0\n", &mut text, false),
+            Ok(1)
+        );
+        assert!(!decoder.is_decided());
+        assert_eq!(text, SHIFT_JIS.decode_without_bom_handling(b"\xE0\xE0\n").0);
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        let mut storage = [0u8; 4];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let mut decoder = LineDecoder::with_buffer(true, buffer);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"ab\n\xA4", &mut text, false),
+            Ok(1)
+        );
+        assert_eq!(decoder.pending(), 1);
+        // "あ" in EUC-JP completes the line.
+        assert_eq!(decoder.decode_to_string(b"\xA2\n", &mut text, false), Ok(1));
+        assert_eq!(text, "ab\nあ\n");
+        // A line that doesn't fit is decoded in parts. "い" in EUC-JP
+        // decides in a part after the first, so the rest of its line is
+        // still decoded as the fallback Shift_JIS.
+        let mut storage = [0u8; 4];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let mut decoder = LineDecoder::with_buffer(true, buffer);
+        let mut text = String::new();
+        assert_eq!(decoder.decode_to_string(b"abc", &mut text, false), Ok(0));
+        assert_eq!(decoder.decode_to_string(b"de\xA4", &mut text, false), Ok(0));
+        assert_eq!(text, "abc");
+        assert_eq!(
+            decoder.decode_to_string(b"\xA4\nf", &mut text, false),
+            Ok(1)
+        );
+        assert_eq!(decoder.encoding(), EUC_JP);
+        assert!(decoder.is_decided());
+        assert_eq!(
+            decoder.decode_to_string(b"\xA4\xA2", &mut text, true),
+            Ok(1)
+        );
+        assert_eq!(text, "abcde､､\nfあ");
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use encoding_rs::Decoder;
+use encoding_rs::DecoderResult;
+
+/// What the decoding adapters do with malformed byte sequences.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MalformedPolicy {
+    /// Emit U+FFFD REPLACEMENT CHARACTER (the WHATWG behavior).
+    #[default]
+    Replace,
+    /// Emit nothing for the malformed sequence.
+    Skip,
+    /// Stop decoding with a `MalformedError`.
+    Error,
+}
+
+/// A malformed byte sequence under `MalformedPolicy::Error`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MalformedError {
+    offset: u64,
+}
+
+impl MalformedError {
+    /// The stream offset of the first byte of the malformed sequence.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl fmt::Display for MalformedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "malformed byte sequence at offset {}", self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MalformedError {}
+
+/// Decodes `src`, which starts at stream offset `offset`, appending the text
+/// to `dst` according to `policy`. Returns the number of malformed
+/// sequences or an error for the first one under `MalformedPolicy::Error`,
+/// in which case the text before it has been appended.
+pub(crate) fn decode_with_policy(
+    decoder: &mut Decoder,
+    src: &[u8],
+    offset: u64,
+    dst: &mut String,
+    last: bool,
+    policy: MalformedPolicy,
+) -> Result<u64, MalformedError> {
+    let mut malformed = 0;
+    let mut total_read = 0;
+    loop {
+        dst.reserve(
+            decoder
+                .max_utf8_buffer_length_without_replacement(src.len() - total_read)
+                .unwrap_or(src.len() * 3 + 16),
+        );
+        let (result, read) =
+            decoder.decode_to_string_without_replacement(&src[total_read..], dst, last);
+        total_read += read;
+        match result {
+            DecoderResult::InputEmpty => {
+                return Ok(malformed);
+            }
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(bad, after) => {
+                malformed += 1;
+                match policy {
+                    MalformedPolicy::Replace => dst.push('\u{FFFD}'),
+                    MalformedPolicy::Skip => {}
+                    MalformedPolicy::Error => {
+                        return Err(malformed_error(offset, total_read, bad, after));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The same as `decode_with_policy()` but appends UTF-16 to `dst`.
+pub(crate) fn decode_to_utf16_with_policy(
+    decoder: &mut Decoder,
+    src: &[u8],
+    offset: u64,
+    dst: &mut Vec<u16>,
+    last: bool,
+    policy: MalformedPolicy,
+) -> Result<u64, MalformedError> {
+    let mut malformed = 0;
+    let mut total_read = 0;
+    loop {
+        let len = dst.len();
+        let needed = decoder
+            .max_utf16_buffer_length(src.len() - total_read)
+            .unwrap_or(src.len() + 16);
+        dst.resize(len + needed, 0);
+        let (result, read, written) =
+            decoder.decode_to_utf16_without_replacement(&src[total_read..], &mut dst[len..], last);
+        dst.truncate(len + written);
+        total_read += read;
+        match result {
+            DecoderResult::InputEmpty => {
+                return Ok(malformed);
+            }
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(bad, after) => {
+                malformed += 1;
+                match policy {
+                    MalformedPolicy::Replace => dst.push(0xFFFD),
+                    MalformedPolicy::Skip => {}
+                    MalformedPolicy::Error => {
+                        return Err(malformed_error(offset, total_read, bad, after));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The error for a malformed sequence reported by a decoder that has read
+/// `total_read` bytes of a buffer that starts at stream offset `offset`.
+fn malformed_error(offset: u64, total_read: usize, bad: u8, after: u8) -> MalformedError {
+    // The sequence may have started in an earlier buffer.
+    let end = offset + total_read as u64;
+    MalformedError {
+        offset: end - u64::from(after) - u64::from(bad),
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_decode_with_policy() {
+        let src = b"a\x82\xA0\xFFb";
+        let decode = |policy| {
+            let mut text = String::new();
+            let result = decode_with_policy(
+                &mut SHIFT_JIS.new_decoder_without_bom_handling(),
+                src,
+                10,
+                &mut text,
+                true,
+                policy,
+            );
+            (result, text)
+        };
+        assert_eq!(
+            decode(MalformedPolicy::Replace),
+            (Ok(1), "aあ\u{FFFD}b".to_string())
+        );
+        assert_eq!(decode(MalformedPolicy::Skip), (Ok(1), "aあb".to_string()));
+        let (result, text) = decode(MalformedPolicy::Error);
+        assert_eq!(result.unwrap_err().offset(), 13);
+        assert_eq!(text, "aあ");
+    }
+
+    #[test]
+    fn test_decode_to_utf16_with_policy() {
+        let mut utf16 = Vec::new();
+        let result = decode_to_utf16_with_policy(
+            &mut SHIFT_JIS.new_decoder_without_bom_handling(),
+            b"a\x82\xA0\xFFb",
+            10,
+            &mut utf16,
+            true,
+            MalformedPolicy::Replace,
+        );
+        assert_eq!(result, Ok(1));
+        assert_eq!(utf16, [0x61, 0x3042, 0xFFFD, 0x62]);
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Detector;
+use crate::Verdict;
+
+/// How much of a buffer the detector needed for its verdict. Returned by
+/// `bytes_needed()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BytesNeeded {
+    verdict: Verdict,
+    streaming: Option<usize>,
+    truncated: usize,
+}
+
+impl BytesNeeded {
+    /// The verdict for the whole buffer.
+    pub fn verdict(&self) -> Verdict {
+        self.verdict
+    }
+
+    /// The number of bytes a streaming detector sees before it decides, or
+    /// `None` if the verdict depends on the end of the buffer (e.g. an
+    /// incomplete character at the end).
+    pub fn streaming(&self) -> Option<usize> {
+        self.streaming
+    }
+
+    /// The length of the shortest prefix that gives the same verdict when
+    /// the detector is told that the prefix is the whole input, i.e. the
+    /// smallest sniff window that would have worked for this buffer. Can be
+    /// shorter than `streaming()`, since the end of the input can decide
+    /// (by truncating a character).
+    pub fn truncated(&self) -> usize {
+        self.truncated
+    }
+}
+
+/// Analyzes how many bytes of `buffer`, taken as a complete stream, the
+/// detector needs for its verdict, to help choose sniff-window sizes from
+/// real traffic. `allow_2022` has the same meaning as for `Detector::new()`.
+/// Returns `None` if the detector is undecided for the whole buffer.
+///
+/// Takes time quadratic in `streaming()` (or in the length of the buffer if
+/// that is `None`), which is normally small.
+pub fn bytes_needed(buffer: &[u8], allow_2022: bool) -> Option<BytesNeeded> {
+    let mut detector = Detector::new(allow_2022);
+    let streaming = detector.feed_verdict(buffer, false);
+    let verdict = match streaming {
+        Some(verdict) => verdict,
+        None => detector.feed_verdict(b"", true)?,
+    };
+    let streaming = streaming.map(|_| detector.examined_len() as usize);
+    let bound = streaming.unwrap_or(buffer.len());
+    let truncated = (0..=bound)
+        .find(|&len| Detector::new(allow_2022).feed_verdict(&buffer[..len], true) == Some(verdict))
+        .unwrap_or(bound);
+    Some(BytesNeeded {
+        verdict,
+        streaming,
+        truncated,
+    })
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_needed() {
+        // "あ" in Shift_JIS, whose lead byte rules out EUC-JP.
+        let needed = bytes_needed(b"ab\x82\xA0cd", true).unwrap();
+        assert_eq!(needed.verdict(), Verdict::ShiftJis);
+        assert_eq!(needed.streaming(), Some(3));
+        assert_eq!(needed.truncated(), 3);
+        // "あ" in EUC-JP, whose first byte is half-width katakana in
+        // Shift_JIS.
+        let needed = bytes_needed(b"ab\xA4\xA2", true).unwrap();
+        assert_eq!(needed.verdict(), Verdict::EucJp);
+        assert_eq!(needed.streaming(), Some(3));
+        assert_eq!(needed.truncated(), 3);
+        // Kanji valid in both followed by a truncated character, which is
+        // checked for EUC-JP first.
+        let needed = bytes_needed(b"\xE0\xE0\xE0\xE0\xE0", true).unwrap();
+        assert_eq!(needed.verdict(), Verdict::ShiftJis);
+        assert_eq!(needed.streaming(), None);
+        assert_eq!(needed.truncated(), 1);
+        assert_eq!(bytes_needed(b"abc", true), None);
+    }
+}
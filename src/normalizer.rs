@@ -0,0 +1,177 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An API in the shape of Python's charset-normalizer, for porting tooling
+//! that uses it: `from_bytes()` returns the encodings that the input
+//! decodes in without errors as `Matches`, best first.
+//!
+//! Only the encodings that this crate knows about are considered:
+//! ISO-2022-JP, Shift_JIS, EUC-JP and UTF-8.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::ISO_2022_JP;
+use encoding_rs::SHIFT_JIS;
+use encoding_rs::UTF_8;
+
+use crate::scoring::Scorer;
+use crate::DetectorBuilder;
+
+/// The number of characters in `CharsetMatch::preview()`.
+pub const PREVIEW_CHARS: usize = 64;
+
+/// The candidates in the order that breaks ties between equal scores.
+const CANDIDATES: [&Encoding; 4] = [UTF_8, SHIFT_JIS, EUC_JP, ISO_2022_JP];
+
+/// An encoding that the input decodes in without errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharsetMatch {
+    encoding: &'static Encoding,
+    score: i64,
+    detected: bool,
+    preview: String,
+}
+
+impl CharsetMatch {
+    /// The encoding.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// How Japanese the decoded text looks: the character-class score of
+    /// `Detector::set_scoring()` of the text's characters, whatever the
+    /// encoding. Characters that aren't in JIS X 0208 or half-width
+    /// katakana don't count.
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+
+    /// Whether a `Detector` (with `DetectorBuilder::allow_utf8()` and
+    /// `DetectorBuilder::scoring()` enabled) decided this encoding.
+    pub fn detected(&self) -> bool {
+        self.detected
+    }
+
+    /// The first `PREVIEW_CHARS` characters of the decoded text.
+    pub fn preview(&self) -> &str {
+        &self.preview
+    }
+}
+
+/// The result of `from_bytes()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matches {
+    matches: Vec<CharsetMatch>,
+}
+
+impl Matches {
+    /// The best match or `None` if the input decodes without errors in none
+    /// of the encodings.
+    pub fn best(&self) -> Option<&CharsetMatch> {
+        self.matches.first()
+    }
+
+    /// The matches, best first.
+    pub fn iter(&self) -> core::slice::Iter<'_, CharsetMatch> {
+        self.matches.iter()
+    }
+
+    /// The number of matches.
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Whether there are no matches.
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Matches {
+    type Item = &'a CharsetMatch;
+    type IntoIter = core::slice::Iter<'a, CharsetMatch>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Decodes the complete input `bytes` in each encoding and returns those
+/// that decode without errors, best first.
+///
+/// The encoding that a detector decides comes first. The others follow by
+/// descending `CharsetMatch::score()` and then in the order UTF-8,
+/// Shift_JIS, EUC-JP, ISO-2022-JP. So ASCII, which decodes the same in all
+/// of them, lists UTF-8 first.
+pub fn from_bytes(bytes: &[u8]) -> Matches {
+    let detected = DetectorBuilder::new()
+        .allow_utf8(true)
+        .scoring(true)
+        .build()
+        .feed(bytes, true);
+    let mut matches: Vec<CharsetMatch> = CANDIDATES
+        .iter()
+        .filter_map(|&encoding| {
+            let text = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+            Some(CharsetMatch {
+                encoding,
+                score: text_score(&text),
+                detected: detected == Some(encoding),
+                preview: text.chars().take(PREVIEW_CHARS).collect(),
+            })
+        })
+        .collect();
+    // A stable sort keeps the tie-breaking order.
+    matches.sort_by(|a, b| b.detected.cmp(&a.detected).then(b.score.cmp(&a.score)));
+    Matches { matches }
+}
+
+/// Scores `text` by its characters as encoded in Shift_JIS, which has all
+/// of JIS X 0208 and half-width katakana. Unmappable characters become
+/// ASCII numeric character references, which don't count.
+fn text_score(text: &str) -> i64 {
+    let (bytes, _, _) = SHIFT_JIS.encode(text);
+    let mut scorer = Scorer::new();
+    scorer.feed(&bytes);
+    scorer.scores().0
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        // "日本語のテキスト" in EUC-JP.
+        let euc_jp = b"\xC6\xFC\xCB\xDC\xB8\xEC\xA4\xCE\xA5\xC6\xA5\xAD\xA5\xB9\xA5\xC8";
+        let matches = from_bytes(euc_jp);
+        let best = matches.best().unwrap();
+        assert_eq!(best.encoding(), EUC_JP);
+        assert!(best.detected());
+        assert_eq!(best.preview(), "日本語のテキスト");
+        assert!(matches.iter().all(|m| m.encoding() != UTF_8));
+        // ASCII decodes the same everywhere.
+        let matches = from_bytes(b"plain");
+        assert_eq!(matches.len(), 4);
+        assert_eq!(matches.best().unwrap().encoding(), UTF_8);
+        assert!(matches.iter().all(|m| !m.detected() && m.score() == 0));
+        let order: Vec<_> = (&matches).into_iter().map(|m| m.encoding()).collect();
+        assert_eq!(order, CANDIDATES.to_vec());
+        let matches = from_bytes("日本語".as_bytes());
+        assert_eq!(matches.best().unwrap().encoding(), UTF_8);
+        assert!(matches.best().unwrap().detected());
+        assert!(from_bytes(b"\xFF\xFF").is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(feature = "diagnostics")]
+use alloc::string::String;
+#[cfg(feature = "diagnostics")]
+use core::fmt::Write;
+
+use crate::Certainty;
+#[cfg(all(feature = "diagnostics", feature = "scoring"))]
+use crate::CharacterClasses;
+#[cfg(feature = "scoring")]
+use crate::ClassBreakdown;
+use crate::Reason;
+use crate::Suspicious;
+use crate::Verdict;
+
+/// How much `DetectionOutcome::explain()` says.
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Verbosity {
+    /// What decided and the verdict, for log lines.
+    Brief,
+    /// Also what ruled ISO-2022-JP out, the certainty, the number of bytes
+    /// examined and any warnings, for support tickets.
+    Detailed,
+}
+
+/// What a `Detector` has concluded from the input fed so far, as returned
+/// by `Detector::outcome()`.
+///
+/// The outcome is meant to grow as diagnostics are added. Its fields are
+/// private, new diagnostics get new getters and the struct is
+/// `#[non_exhaustive]`, so additions don't break downstream code. Each
+/// getter documents the `VERSION` that added it.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DetectionOutcome {
+    pub(crate) verdict: Option<Verdict>,
+    pub(crate) certainty: Option<Certainty>,
+    pub(crate) fallback: Verdict,
+    pub(crate) blank: bool,
+    pub(crate) finished: bool,
+    pub(crate) examined_len: u64,
+    pub(crate) pending: usize,
+    pub(crate) suspicious: Option<Suspicious>,
+    pub(crate) edge_byte_error: Option<u64>,
+    pub(crate) reason: Option<Reason>,
+    pub(crate) decided_at: Option<(u64, Option<u8>)>,
+    pub(crate) iso_2022_jp_ruled_out: Option<(u64, u8)>,
+    #[cfg(feature = "scoring")]
+    pub(crate) scores: Option<(i64, i64)>,
+    #[cfg(feature = "scoring")]
+    pub(crate) class_breakdown: Option<ClassBreakdown>,
+}
+
+impl DetectionOutcome {
+    /// The version of the set of diagnostics, incremented whenever a getter
+    /// is added.
+    pub const VERSION: u32 = 5;
+
+    /// The verdict or `None` if the detector is undecided. Since version 1.
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.verdict
+    }
+
+    /// The certainty of the verdict or `None` if the detector is undecided.
+    /// Since version 1.
+    pub fn certainty(&self) -> Option<Certainty> {
+        self.certainty
+    }
+
+    /// The verdict or, if the detector is undecided, its fallback guess.
+    /// Since version 1.
+    pub fn guess(&self) -> Verdict {
+        self.verdict.unwrap_or(self.fallback)
+    }
+
+    /// `true` if the detector must not be fed more (see `Detector::feed()`).
+    /// Since version 1.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The same as `Detector::examined_len()`. Since version 1.
+    pub fn examined_len(&self) -> u64 {
+        self.examined_len
+    }
+
+    /// The same as `Detector::pending()`. Since version 1.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// The same as `Detector::suspicious()`. Since version 1.
+    pub fn suspicious(&self) -> Option<Suspicious> {
+        self.suspicious
+    }
+
+    /// The same as `Detector::edge_byte_error()`. Since version 1.
+    pub fn edge_byte_error(&self) -> Option<u64> {
+        self.edge_byte_error
+    }
+
+    /// Why the detector decided or `None` if it is undecided. Since version
+    /// 2.
+    pub fn reason(&self) -> Option<Reason> {
+        self.reason
+    }
+
+    /// The stream offset of the byte that decided or, if the end of the
+    /// stream decided, the length of the stream. `None` if the detector is
+    /// undecided. Since version 2.
+    pub fn decided_at(&self) -> Option<u64> {
+        self.decided_at.map(|(offset, _)| offset)
+    }
+
+    /// The stream offset of the byte that ruled out ISO-2022-JP or `None` if
+    /// no byte did (e.g. because ISO-2022-JP wasn't allowed). Since version
+    /// 2.
+    pub fn iso_2022_jp_ruled_out(&self) -> Option<u64> {
+        self.iso_2022_jp_ruled_out.map(|(offset, _)| offset)
+    }
+
+    /// The same as `Detector::scores()`. Since version 2.
+    #[cfg(feature = "scoring")]
+    pub fn scores(&self) -> Option<(i64, i64)> {
+        self.scores
+    }
+
+    /// The same as `Detector::class_breakdown()`. Since version 3.
+    #[cfg(feature = "scoring")]
+    pub fn class_breakdown(&self) -> Option<ClassBreakdown> {
+        self.class_breakdown
+    }
+
+    /// `true` if the bytes examined so far are empty or only ASCII
+    /// whitespace, whether or not `Detector::set_blank_verdict()` made that
+    /// decide. Since version 4.
+    pub fn blank(&self) -> bool {
+        self.blank
+    }
+
+    /// The same as `Detector::forced()`. Since version 5.
+    pub fn forced(&self) -> bool {
+        self.reason == Some(Reason::ByteBudget)
+    }
+
+    /// A human-readable account of the outcome for log lines and support
+    /// tickets, e.g. "EUC-JP malformed at byte 212 (0x40) → Shift_JIS". The
+    /// wording is not stable; match on the getters instead of parsing it.
+    /// Since version 2.
+    #[cfg(feature = "diagnostics")]
+    pub fn explain(&self, verbosity: Verbosity) -> String {
+        let detailed = verbosity == Verbosity::Detailed;
+        #[cfg(feature = "scoring")]
+        let scores = self.scores;
+        #[cfg(not(feature = "scoring"))]
+        let scores: Option<(i64, i64)> = None;
+        let mut explanation = String::new();
+        if detailed {
+            if let Some((offset, byte)) = self.iso_2022_jp_ruled_out {
+                let _ = write!(
+                    explanation,
+                    "ISO-2022-JP ruled out at byte {} (0x{:02X}); ",
+                    offset, byte
+                );
+            }
+        }
+        match (self.verdict, self.reason, self.decided_at) {
+            (Some(verdict), Some(reason), Some((offset, byte))) => {
+                let what = match reason {
+                    Reason::Iso2022JpEscape => "ISO-2022-JP escape sequence",
+                    Reason::Iso2022JpCharacter => "ISO-2022-JP JIS X 0208 character",
+                    Reason::Iso2022JpEndOfStream => "ISO-2022-JP escape sequence only",
+                    Reason::EucJpMalformed => "EUC-JP malformed",
+                    Reason::EucJpHalfWidthKatakana => "EUC-JP half-width katakana",
+                    Reason::EucJpRule => "EUC-JP disqualified by rule",
+                    Reason::EucJpTruncated => "EUC-JP truncated",
+                    Reason::JisX0212 => "EUC-JP JIS X 0212 character",
+                    Reason::ShiftJisMalformed => "Shift_JIS malformed",
+                    Reason::ShiftJisHalfWidthKatakana => "Shift_JIS half-width katakana",
+                    Reason::ShiftJisRule => "Shift_JIS disqualified by rule",
+                    Reason::ShiftJisTruncated => "Shift_JIS truncated",
+                    Reason::Scored => "scored",
+                    Reason::Utf8 => "UTF-8",
+                    Reason::Blank => "blank input",
+                    Reason::Iso2022JpStructure => "well-formed ISO-2022-JP",
+                    Reason::UndecidedDefault => "undecided, default",
+                    Reason::ByteBudget => "byte budget spent, best guess",
+                };
+                explanation.push_str(what);
+                match (reason, byte, scores) {
+                    (Reason::Scored, _, Some((shift_jis, euc_jp))) => {
+                        let _ = write!(
+                            explanation,
+                            " Shift_JIS {} vs. EUC-JP {}",
+                            shift_jis, euc_jp
+                        );
+                    }
+                    (_, Some(byte), _) => {
+                        let _ = write!(explanation, " at byte {} (0x{:02X})", offset, byte);
+                    }
+                    (_, None, _) => {
+                        let _ = write!(explanation, " at end of stream (byte {})", offset);
+                    }
+                }
+                let _ = write!(explanation, " → {}", verdict.name());
+            }
+            _ => {
+                let _ = write!(
+                    explanation,
+                    "undecided after {} bytes → {} (fallback)",
+                    self.examined_len,
+                    self.fallback.name()
+                );
+            }
+        }
+        if detailed {
+            if let Some(certainty) = self.certainty {
+                let certainty = match certainty {
+                    Certainty::Tentative => "tentative",
+                    Certainty::Certain => "certain",
+                    Certainty::Scored => "scored",
+                    Certainty::Likely => "likely",
+                    Certainty::Guessed => "guessed",
+                };
+                let _ = write!(
+                    explanation,
+                    " ({}, {} bytes examined)",
+                    certainty, self.examined_len
+                );
+            }
+            if self.pending != 0 {
+                let _ = write!(explanation, "; {} byte(s) pending", self.pending);
+            }
+            match self.suspicious {
+                Some(Suspicious::EscapeFlood) => explanation.push_str("; suspicious: escape flood"),
+                Some(Suspicious::Ambiguity) => explanation.push_str("; suspicious: ambiguity"),
+                None => {}
+            }
+            if let Some(offset) = self.edge_byte_error {
+                let _ = write!(explanation, "; stopped at edge byte {}", offset);
+            }
+            #[cfg(feature = "scoring")]
+            if let (None, Some(breakdown)) = (self.verdict, self.class_breakdown) {
+                explanation.push_str("; as Shift_JIS: ");
+                push_classes(&mut explanation, breakdown.shift_jis());
+                explanation.push_str("; as EUC-JP: ");
+                push_classes(&mut explanation, breakdown.euc_jp());
+            }
+        }
+        explanation
+    }
+}
+
+/// Appends the non-zero counts of `classes` to `explanation`.
+#[cfg(all(feature = "diagnostics", feature = "scoring"))]
+fn push_classes(explanation: &mut String, classes: &CharacterClasses) {
+    let counts = [
+        (classes.kana(), "kana"),
+        (classes.punctuation(), "punctuation"),
+        (classes.symbols(), "symbols"),
+        (classes.level_1_kanji(), "Level 1 kanji"),
+        (classes.level_2_kanji(), "Level 2 kanji"),
+        (classes.half_width_katakana(), "half-width katakana"),
+        (classes.user_defined(), "user-defined"),
+        (classes.other(), "other"),
+    ];
+    let mut any = false;
+    for &(count, class) in counts.iter().filter(|&&(count, _)| count != 0) {
+        if any {
+            explanation.push_str(", ");
+        }
+        let _ = write!(explanation, "{} {}", count, class);
+        any = true;
+    }
+    if !any {
+        explanation.push_str("ASCII only");
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(all(test, feature = "diagnostics"))]
+mod tests {
+    use super::*;
+    use crate::Detector;
+
+    #[test]
+    fn test_explain() {
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed_verdict(b"ab\xE0", false), None);
+        let outcome = detector.outcome();
+        assert_eq!(outcome.iso_2022_jp_ruled_out(), Some(2));
+        assert_eq!(
+            outcome.explain(Verbosity::Brief),
+            "undecided after 3 bytes → Shift_JIS (fallback)"
+        );
+        assert_eq!(
+            outcome.explain(Verbosity::Detailed),
+            "ISO-2022-JP ruled out at byte 2 (0xE0); undecided after 3 bytes → Shift_JIS \
+             (fallback); 1 byte(s) pending"
+        );
+        assert_eq!(
+            detector.feed_verdict(b"\xA1 \x82", false),
+            Some(Verdict::ShiftJis)
+        );
+        let outcome = detector.outcome();
+        assert_eq!(outcome.reason(), Some(Reason::EucJpMalformed));
+        assert_eq!(outcome.decided_at(), Some(5));
+        assert_eq!(
+            outcome.explain(Verbosity::Brief),
+            "EUC-JP malformed at byte 5 (0x82) → Shift_JIS"
+        );
+        assert_eq!(
+            outcome.explain(Verbosity::Detailed),
+            "ISO-2022-JP ruled out at byte 2 (0xE0); EUC-JP malformed at byte 5 (0x82) → \
+             Shift_JIS (certain, 6 bytes examined)"
+        );
+        let mut detector = Detector::new(false);
+        assert_eq!(
+            detector.feed_verdict(b"\xE0", true),
+            Some(Verdict::ShiftJis)
+        );
+        assert_eq!(
+            detector.outcome().explain(Verbosity::Brief),
+            "EUC-JP truncated at end of stream (byte 1) → Shift_JIS"
+        );
+    }
+
+    #[cfg(feature = "scoring")]
+    #[test]
+    fn test_explain_class_breakdown() {
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        // Level 2 kanji in both.
+        assert_eq!(detector.feed_verdict(b"a\xE0\xA1", true), None);
+        assert_eq!(
+            detector.outcome().explain(Verbosity::Detailed),
+            "ISO-2022-JP ruled out at byte 1 (0xE0); undecided after 3 bytes → Shift_JIS \
+             (fallback); as Shift_JIS: 1 Level 2 kanji; as EUC-JP: 1 Level 2 kanji"
+        );
+    }
+}
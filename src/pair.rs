@@ -0,0 +1,342 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Encoding;
+use encoding_rs::BIG5;
+use encoding_rs::EUC_KR;
+use encoding_rs::GBK;
+
+use crate::race;
+use crate::race::Race;
+use crate::raw::DecoderMachine;
+use crate::raw::Step;
+
+/// Why a `PairDetector` decided. See `PairOutcome::reason()`.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PairReason {
+    /// A malformed sequence ruled out the other encoding.
+    Malformed,
+    /// An incomplete character at the end of the stream ruled out the other
+    /// encoding.
+    Truncated,
+}
+
+/// What a `PairDetector` has made of the input so far. See
+/// `PairDetector::outcome()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PairOutcome {
+    encoding: Option<&'static Encoding>,
+    reason: Option<PairReason>,
+    examined_len: u64,
+    finished: bool,
+    budget_exhausted: bool,
+}
+
+impl PairOutcome {
+    /// The encoding that wasn't ruled out or `None` if undecided.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// Why the detector decided or `None` if undecided.
+    pub fn reason(&self) -> Option<PairReason> {
+        self.reason
+    }
+
+    /// The number of bytes examined: up to and including the deciding byte
+    /// once decided.
+    pub fn examined_len(&self) -> u64 {
+        self.examined_len
+    }
+
+    /// Whether the detector has decided or seen the end of the stream, so
+    /// that it can't be fed any more.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether the detector stopped examining the input because of
+    /// `PairDetector::set_max_examined_len()`.
+    pub fn budget_exhausted(&self) -> bool {
+        self.budget_exhausted
+    }
+}
+
+/// A detector that tells apart any two ASCII-compatible legacy CJK
+/// encodings the way `Detector` tells apart Shift_JIS and EUC-JP: both are
+/// decoded side by side and the first one to hit a malformed sequence is
+/// ruled out, which decides the other. The race is the same code as that of
+/// `Detector`, ASCII skipping included.
+///
+/// Unlike `Detector`, this has no rules beyond decoding errors (no escape
+/// sequences, half-width katakana or scoring), so how often it decides
+/// depends on how much the byte ranges of the pair differ. If both hit an
+/// error on the same byte, the first encoding is ruled out.
+///
+/// `encoding_rs` decodes EUC-KR as its superset UHC (windows-949) and GBK
+/// as gb18030, so those are what the variants below race. It doesn't
+/// support Johab.
+pub struct PairDetector {
+    first: DecoderMachine,
+    second: DecoderMachine,
+    finished: bool,
+    max_examined_len: Option<u64>,
+    budget_exhausted: bool,
+    examined_len: u64,
+    encoding: Option<&'static Encoding>,
+    reason: Option<PairReason>,
+}
+
+impl PairDetector {
+    /// Instantiates a detector for `first` and `second`.
+    ///
+    /// # Panics
+    ///
+    /// If the encodings are the same or if either one isn't ASCII-compatible
+    /// (e.g. ISO-2022-JP or UTF-16).
+    pub fn new(first: &'static Encoding, second: &'static Encoding) -> Self {
+        assert_ne!(first, second, "The encodings must differ.");
+        assert!(
+            first.is_ascii_compatible() && second.is_ascii_compatible(),
+            "The encodings must be ASCII-compatible."
+        );
+        PairDetector {
+            first: DecoderMachine::for_encoding(first),
+            second: DecoderMachine::for_encoding(second),
+            finished: false,
+            max_examined_len: None,
+            budget_exhausted: false,
+            examined_len: 0,
+            encoding: None,
+            reason: None,
+        }
+    }
+
+    /// Instantiates a detector for Korean UHC (`EUC_KR`) versus Traditional
+    /// Chinese Big5. UHC is decided by its extended Hangul, whose trail
+    /// bytes 0x81 to 0xA0 Big5 doesn't have, and Big5 by trail bytes that
+    /// UHC doesn't have, such as 0x40.
+    pub fn euc_kr_or_big5() -> Self {
+        PairDetector::new(EUC_KR, BIG5)
+    }
+
+    /// Instantiates a detector for Simplified Chinese GBK versus Traditional
+    /// Chinese Big5. GBK is decided by the trail bytes 0x80 to 0xA0, which
+    /// Big5 doesn't have. Since GBK accepts almost every byte pair that Big5
+    /// does, Big5 text usually leaves the detector undecided.
+    pub fn gbk_or_big5() -> Self {
+        PairDetector::new(GBK, BIG5)
+    }
+
+    /// The two encodings in the order given to `new()`.
+    pub fn encodings(&self) -> (&'static Encoding, &'static Encoding) {
+        (self.first.encoding(), self.second.encoding())
+    }
+
+    /// Sets the maximum number of bytes that the detector examines, or
+    /// `None` for no limit. Defaults to `None`. Call before the first call to
+    /// `feed()`.
+    ///
+    /// Once the budget has been used up without a decision, the rest of the
+    /// stream isn't examined: `feed()` keeps returning `None` and
+    /// `budget_exhausted()` returns `true`. An incomplete character at the
+    /// end of the budget does not count as truncated, since the stream goes
+    /// on. See `Detector::set_max_examined_len()`.
+    pub fn set_max_examined_len(&mut self, max: Option<u64>) {
+        self.max_examined_len = max;
+    }
+
+    /// Whether the detector has stopped examining the input because of
+    /// `set_max_examined_len()`.
+    pub fn budget_exhausted(&self) -> bool {
+        self.budget_exhausted
+    }
+
+    /// The number of bytes examined so far. See
+    /// `PairOutcome::examined_len()`.
+    pub fn examined_len(&self) -> u64 {
+        self.examined_len
+    }
+
+    /// What the detector has made of the input so far.
+    pub fn outcome(&self) -> PairOutcome {
+        PairOutcome {
+            encoding: self.encoding,
+            reason: self.reason,
+            examined_len: self.examined_len,
+            finished: self.finished,
+            budget_exhausted: self.budget_exhausted,
+        }
+    }
+
+    /// Feeds bytes to the detector. If `last` is `true`, the end of the
+    /// stream is treated as occurring after the bytes.
+    ///
+    /// Returns the encoding that isn't ruled out once the other one is or
+    /// `None` if neither has been ruled out. Then, if `last` is `false`,
+    /// the caller is expected to call the method again with more bytes.
+    ///
+    /// # Panics
+    ///
+    /// If called after the method has returned `Some(_)` or after the method
+    /// has been called with `true` as `last`.
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        assert!(
+            !self.finished,
+            "Tried to used a detector that has finished."
+        );
+        self.finished = last;
+        let examinable = match self.max_examined_len {
+            Some(_) if self.budget_exhausted => &buffer[..0],
+            Some(max) => {
+                let remaining = max - self.examined_len.min(max);
+                if remaining < buffer.len() as u64 {
+                    self.budget_exhausted = true;
+                    &buffer[..remaining as usize]
+                } else {
+                    buffer
+                }
+            }
+            None => buffer,
+        };
+        let decision = match race::race(self, examinable, 0) {
+            None if last && !self.budget_exhausted => race::finish(self),
+            decision => decision,
+        };
+        let examined = match decision {
+            Some((_, _, Some(index))) => index + 1,
+            _ => examinable.len(),
+        };
+        self.examined_len += examined as u64;
+        let (encoding, reason, _) = decision?;
+        self.finished = true;
+        self.encoding = Some(encoding);
+        self.reason = Some(reason);
+        Some(encoding)
+    }
+
+    /// The same as `feed()` but returns the whole `outcome()`.
+    ///
+    /// # Panics
+    ///
+    /// If called after the outcome has had an encoding or after the method
+    /// has been called with `true` as `last`.
+    pub fn feed_with_outcome(&mut self, buffer: &[u8], last: bool) -> PairOutcome {
+        self.feed(buffer, last);
+        self.outcome()
+    }
+}
+
+/// A step that rules out one of the pair on the byte at the index or at the
+/// end of the stream if `None`.
+fn ruled_out(
+    step: Step,
+    index: Option<usize>,
+    other: &DecoderMachine,
+) -> Option<(&'static Encoding, PairReason, Option<usize>)> {
+    if step != Step::Rejected {
+        return None;
+    }
+    let reason = match index {
+        Some(_) => PairReason::Malformed,
+        None => PairReason::Truncated,
+    };
+    Some((other.encoding(), reason, index))
+}
+
+impl Race for PairDetector {
+    type First = DecoderMachine;
+    type Second = DecoderMachine;
+    /// The encoding left, why and the index of the deciding byte.
+    type Decision = (&'static Encoding, PairReason, Option<usize>);
+
+    fn candidates(&mut self) -> (&mut DecoderMachine, &mut DecoderMachine) {
+        (&mut self.first, &mut self.second)
+    }
+
+    fn first(&mut self, step: Step, index: Option<usize>) -> Option<Self::Decision> {
+        ruled_out(step, index, &self.second)
+    }
+
+    fn second(&mut self, _: Step, step: Step, index: Option<usize>) -> Option<Self::Decision> {
+        ruled_out(step, index, &self.first)
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_pair_detector() {
+        assert_eq!(
+            PairDetector::euc_kr_or_big5().feed(b"ab\x81\x81", false),
+            Some(EUC_KR)
+        );
+        assert_eq!(
+            PairDetector::euc_kr_or_big5().feed(b"ab\xB0\x40", false),
+            Some(BIG5)
+        );
+        assert_eq!(
+            PairDetector::gbk_or_big5().feed(b"\x81\x80", false),
+            Some(GBK)
+        );
+        let mut detector = PairDetector::gbk_or_big5();
+        assert_eq!(detector.encodings(), (GBK, BIG5));
+        assert_eq!(detector.feed(b"\xB0", false), None);
+        assert_eq!(detector.feed(b"\x40", true), None);
+        // The Japanese pair, with the rules of `Detector` left out.
+        let mut detector = PairDetector::new(EUC_JP, SHIFT_JIS);
+        assert_eq!(detector.feed(b"a", false), None);
+        assert_eq!(detector.feed(b"\x82\xA0", false), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn test_pair_outcome() {
+        let mut detector = PairDetector::euc_kr_or_big5();
+        let outcome = detector.feed_with_outcome(b"abc", false);
+        assert_eq!(outcome.encoding(), None);
+        assert_eq!(outcome.examined_len(), 3);
+        assert!(!outcome.finished());
+        let outcome = detector.feed_with_outcome(b"d\xB0\x40ef", false);
+        assert_eq!(outcome.encoding(), Some(BIG5));
+        assert_eq!(outcome.reason(), Some(PairReason::Malformed));
+        assert_eq!(outcome.examined_len(), 6);
+        assert!(outcome.finished());
+        // An incomplete Big5 character at the end rules Big5 out, too.
+        let mut detector = PairDetector::new(BIG5, EUC_KR);
+        let outcome = detector.feed_with_outcome(b"a\xB0", true);
+        assert_eq!(outcome.encoding(), Some(EUC_KR));
+        assert_eq!(outcome.reason(), Some(PairReason::Truncated));
+        assert_eq!(outcome.examined_len(), 2);
+    }
+
+    #[test]
+    fn test_pair_budget() {
+        let mut detector = PairDetector::euc_kr_or_big5();
+        detector.set_max_examined_len(Some(3));
+        assert_eq!(detector.feed(b"ab", false), None);
+        assert!(!detector.budget_exhausted());
+        // The deciding trail byte is past the budget, and the lead byte at
+        // its end doesn't count as truncated.
+        assert_eq!(detector.feed(b"\xB0\x40", false), None);
+        assert!(detector.budget_exhausted());
+        let outcome = detector.feed_with_outcome(b"\xB0\x40", true);
+        assert_eq!(outcome.encoding(), None);
+        assert_eq!(outcome.examined_len(), 3);
+        assert!(outcome.budget_exhausted());
+        assert!(outcome.finished());
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::DetectionOutcome;
+use crate::Detector;
+use crate::Verdict;
+
+/// Whether the rest of a document could change what its prefix says, as
+/// reported by `detect_partial()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Prospect {
+    /// The detector has decided, so the rest of the document cannot change
+    /// the verdict. There is no need to fetch more.
+    Settled,
+    /// The detector is undecided after non-ASCII that both Shift_JIS and
+    /// EUC-JP decode (uncommon kanji or a character cut off at the end of
+    /// the prefix), so more text is likely to decide. Until then,
+    /// `PartialDetection::guess()` is a guess.
+    Ambiguous,
+    /// The prefix is ASCII, so it tells nothing about the rest, which may
+    /// still be in any of the encodings (ISO-2022-JP included, if allowed).
+    Unknown,
+}
+
+/// The result of `detect_partial()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PartialDetection {
+    prospect: Prospect,
+    iso_2022_jp_possible: bool,
+    outcome: DetectionOutcome,
+}
+
+impl PartialDetection {
+    /// Whether fetching more of the document could change the verdict.
+    pub fn prospect(&self) -> Prospect {
+        self.prospect
+    }
+
+    /// The verdict or `None` if the prefix doesn't decide.
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.outcome.verdict()
+    }
+
+    /// The verdict or, if the prefix doesn't decide, the detector's
+    /// fallback guess.
+    pub fn guess(&self) -> Verdict {
+        self.outcome.guess()
+    }
+
+    /// Whether the rest of the document could still turn out to be
+    /// ISO-2022-JP, i.e. ISO-2022-JP is allowed and the prefix hasn't ruled
+    /// it out.
+    pub fn iso_2022_jp_possible(&self) -> bool {
+        self.iso_2022_jp_possible
+    }
+
+    /// What the detector concluded from the prefix.
+    pub fn outcome(&self) -> DetectionOutcome {
+        self.outcome
+    }
+}
+
+/// Runs the detector on `prefix`, the first bytes of a longer document
+/// (e.g. the body of a range request), and reports the verdict and whether
+/// fetching more could change it, e.g. for a crawler to decide whether a
+/// full fetch is worth it. Unlike with `detect()`, the end of `prefix`
+/// isn't the end of the stream, so a character cut off by the range
+/// doesn't decide. `allow_2022` has the same meaning as for
+/// `Detector::new()`.
+pub fn detect_partial(prefix: &[u8], allow_2022: bool) -> PartialDetection {
+    detect_partial_with(prefix, Detector::new(allow_2022))
+}
+
+/// The same as `detect_partial()` but with a caller-configured detector
+/// that hasn't been fed yet.
+pub fn detect_partial_with(prefix: &[u8], mut detector: Detector) -> PartialDetection {
+    let outcome = detector.feed_with_outcome(prefix, false);
+    let prospect = if outcome.verdict().is_some() {
+        Prospect::Settled
+    } else if prefix.is_ascii() {
+        Prospect::Unknown
+    } else {
+        Prospect::Ambiguous
+    };
+    PartialDetection {
+        prospect,
+        iso_2022_jp_possible: outcome.verdict().is_none() && detector.iso_2022_jp_possible(),
+        outcome,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_partial() {
+        // "あ" in EUC-JP.
+        let partial = detect_partial(b"<html>\xA4\xA2", true);
+        assert_eq!(partial.prospect(), Prospect::Settled);
+        assert_eq!(partial.verdict(), Some(Verdict::EucJp));
+        assert!(!partial.iso_2022_jp_possible());
+        let partial = detect_partial(b"<html>", true);
+        assert_eq!(partial.prospect(), Prospect::Unknown);
+        assert_eq!(partial.guess(), Verdict::ShiftJis);
+        assert!(partial.iso_2022_jp_possible());
+        assert!(!detect_partial(b"<html>", false).iso_2022_jp_possible());
+        // Cut off by the range rather than truncated.
+        let partial = detect_partial(b"<html>\xE0", true);
+        assert_eq!(partial.prospect(), Prospect::Ambiguous);
+        assert_eq!(partial.verdict(), None);
+        assert!(!partial.iso_2022_jp_possible());
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+/// Returns the bytes of a PDF literal string given its content between the
+/// outer parentheses, i.e. with the backslash escapes of the PDF
+/// specification resolved and line breaks normalized to LF.
+///
+/// Resolving the escapes matters for detection, since PDF writers escape
+/// Shift_JIS trail bytes 0x5C, 0x28 and 0x29 as `\\`, `\(` and `\)`, and
+/// often write all non-ASCII bytes as octal escapes such as `\202\240`.
+pub fn unescape_pdf_literal(literal: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(literal.len());
+    let mut iter = literal.iter().copied().peekable();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'\\' => match iter.next() {
+                Some(b'n') => bytes.push(b'\n'),
+                Some(b'r') => bytes.push(b'\r'),
+                Some(b't') => bytes.push(b'\t'),
+                Some(b'b') => bytes.push(0x08),
+                Some(b'f') => bytes.push(0x0C),
+                Some(digit @ b'0'..=b'7') => {
+                    let mut value = digit - b'0';
+                    for _ in 0..2 {
+                        match iter.peek() {
+                            Some(&digit @ b'0'..=b'7') => {
+                                iter.next();
+                                // High-order overflow is ignored.
+                                value = value.wrapping_mul(8).wrapping_add(digit - b'0');
+                            }
+                            _ => break,
+                        }
+                    }
+                    bytes.push(value);
+                }
+                // An escaped line break continues the string on the next
+                // line.
+                Some(b'\r') => {
+                    iter.next_if_eq(&b'\n');
+                }
+                Some(b'\n') => {}
+                // Other escaped bytes, including `\(`, `\)` and `\\`, stand
+                // for themselves.
+                Some(other) => bytes.push(other),
+                None => {}
+            },
+            b'\r' => {
+                iter.next_if_eq(&b'\n');
+                bytes.push(b'\n');
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    bytes
+}
+
+/// A detector for the literal strings extracted from the text of a PDF
+/// document that considers all the strings of the document jointly.
+///
+/// PDF text is usually split into many short strings (down to a single
+/// character where the text is kerned), so one string rarely has enough
+/// evidence. Each string is unescaped with `unescape_pdf_literal()` and its
+/// end is treated as a character boundary, like a record of
+/// `RecordDetector`, so a character is never assembled from the end of one
+/// string and the start of the next. Strings that start with the UTF-16BE
+/// byte order mark (as document metadata may) are not evidence.
+pub struct PdfStringDetector {
+    detector: Detector,
+}
+
+impl PdfStringDetector {
+    /// Instantiates the detector. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        PdfStringDetector {
+            detector: Detector::new(allow_2022),
+        }
+    }
+
+    /// Feeds the content of a literal string between its outer parentheses.
+    /// Returns the same as `Detector::feed()` when the stream is expected to
+    /// continue. Do not call again after the method has returned `Some(_)`.
+    pub fn feed_literal(&mut self, literal: &[u8]) -> Option<&'static Encoding> {
+        let bytes = unescape_pdf_literal(literal);
+        if bytes.starts_with(b"\xFE\xFF") {
+            return None;
+        }
+        let encoding = self.detector.feed(&bytes, false);
+        self.detector.record_boundary();
+        encoding
+    }
+
+    /// Signals that the document has no more strings. Returns the same as
+    /// `Detector::feed()` when called with `true` as `last`.
+    pub fn finish(&mut self) -> Option<&'static Encoding> {
+        self.detector.feed(b"", true)
+    }
+}
+
+/// Runs a `PdfStringDetector` on all the literal strings of a document.
+/// `allow_2022` has the same meaning as for `Detector::new()`.
+///
+/// If the result is undecided, falling back to Shift_JIS is the best guess.
+pub fn detect_pdf_literals<'a, I>(literals: I, allow_2022: bool) -> Option<&'static Encoding>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut detector = PdfStringDetector::new(allow_2022);
+    for literal in literals {
+        if let Some(encoding) = detector.feed_literal(literal) {
+            return Some(encoding);
+        }
+    }
+    detector.finish()
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(
+            unescape_pdf_literal(b"\\202\\240\\(a\\)\\\\\\q\\\r\nb\r\nc\\0611\\7"),
+            b"\x82\xA0(a)\\qb\nc11\x07"
+        );
+        assert_eq!(unescape_pdf_literal(b"\\501\\n\\"), b"A\n");
+    }
+
+    #[test]
+    fn test_joint_detection() {
+        // A trail byte 0x5C, which is escaped.
+        let literals: [&[u8]; 1] = [b"\\340\\\\"];
+        assert_eq!(
+            detect_pdf_literals(literals.iter().copied(), true),
+            Some(SHIFT_JIS)
+        );
+        // A lead byte at the end of a string doesn't pair with the next
+        // string, and a UTF-16BE string is not evidence.
+        let literals: [&[u8]; 3] = [b"\\340", b"@", b"\xFE\xFF\x30\x42"];
+        assert_eq!(detect_pdf_literals(literals.iter().copied(), true), None);
+        // Strings can be fed one at a time.
+        let literals: [&[u8]; 2] = [b"\\340\\241", b"\\202\\240"];
+        let mut detector = PdfStringDetector::new(true);
+        assert_eq!(detector.feed_literal(literals[0]), None);
+        assert_eq!(detector.feed_literal(literals[1]), Some(SHIFT_JIS));
+        let literals: [&[u8]; 1] = [b"\\244\\242"];
+        assert_eq!(
+            detect_pdf_literals(literals.iter().copied(), true),
+            Some(EUC_JP)
+        );
+    }
+}
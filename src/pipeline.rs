@@ -0,0 +1,1039 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Chaining of pre-filters, the detector and a sink.
+//!
+//! A `Pipeline` is a `std::io::Write` that passes the bytes written to it
+//! through a chain of `Filter`s, holds the filtered bytes back until the
+//! detector has decided and then decodes them, handing the text to a `Sink`.
+//! A `PipelineReader` is the `std::io::Read` counterpart that pulls the
+//! bytes from an inner reader and reads out the text as UTF-8.
+//! New filters and sinks plug in by implementing the traits, so adapters
+//! compose without a wrapper type per combination.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+use encoding_rs::SHIFT_JIS;
+
+use crate::malformed::decode_with_policy;
+use crate::Buffer;
+use crate::ChunkSizes;
+use crate::Detector;
+use crate::MalformedPolicy;
+
+/// A streaming byte-to-byte transformation applied before detection.
+pub trait Filter {
+    /// Transforms `input`, appending the result to `output`. Bytes that
+    /// cannot be transformed yet (e.g. an incomplete escape) are held until
+    /// the next call.
+    fn filter(&mut self, input: &[u8], output: &mut Vec<u8>);
+
+    /// Signals the end of the stream, appending whatever is still held.
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        let _ = output;
+    }
+}
+
+/// The receiving end of a `Pipeline`.
+pub trait Sink {
+    /// Called once with the encoding the text is decoded from before any
+    /// text is passed to `write_str()`.
+    fn verdict(&mut self, encoding: &'static Encoding) -> io::Result<()> {
+        let _ = encoding;
+        Ok(())
+    }
+
+    /// Receives decoded text.
+    fn write_str(&mut self, text: &str) -> io::Result<()>;
+
+    /// Flushes the sink.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A sink that writes the decoded text as UTF-8 to an inner writer.
+pub struct Utf8Sink<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Utf8Sink<W> {
+    /// Wraps `inner`.
+    pub fn new(inner: W) -> Self {
+        Utf8Sink { inner }
+    }
+
+    /// Returns the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Sink for Utf8Sink<W> {
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_all(text.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A sink that only collects statistics about the decoded text.
+#[derive(Debug, Default)]
+pub struct StatisticsSink {
+    encoding: Option<&'static Encoding>,
+    chars: u64,
+    non_ascii: u64,
+    replacements: u64,
+}
+
+impl StatisticsSink {
+    /// Instantiates the sink.
+    pub fn new() -> Self {
+        StatisticsSink::default()
+    }
+
+    /// The encoding the text was decoded from.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// The number of characters received.
+    pub fn chars(&self) -> u64 {
+        self.chars
+    }
+
+    /// The number of non-ASCII characters received.
+    pub fn non_ascii(&self) -> u64 {
+        self.non_ascii
+    }
+
+    /// The number of U+FFFD REPLACEMENT CHARACTERs received.
+    pub fn replacements(&self) -> u64 {
+        self.replacements
+    }
+}
+
+impl Sink for StatisticsSink {
+    fn verdict(&mut self, encoding: &'static Encoding) -> io::Result<()> {
+        self.encoding = Some(encoding);
+        Ok(())
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        for c in text.chars() {
+            self.chars += 1;
+            if c >= '\u{80}' {
+                self.non_ascii += 1;
+                if c == '\u{FFFD}' {
+                    self.replacements += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A sink that passes the decoded text to an inner sink and captures the
+/// first non-empty line, e.g. as a title preview for search results, so
+/// that showing one doesn't take decoding the input a second time.
+///
+/// Leading whitespace is skipped, trailing whitespace is removed and the
+/// preview is cut off after `max_chars` characters. Before the end of the
+/// stream, the line may still be incomplete.
+pub struct TitleSink<S: Sink> {
+    inner: S,
+    encoding: Option<&'static Encoding>,
+    title: String,
+    max_chars: usize,
+    chars: usize,
+    complete: bool,
+}
+
+impl<S: Sink> TitleSink<S> {
+    /// Wraps `inner`, capturing at most `max_chars` characters.
+    pub fn new(inner: S, max_chars: usize) -> Self {
+        TitleSink {
+            inner,
+            encoding: None,
+            title: String::new(),
+            max_chars,
+            chars: 0,
+            complete: max_chars == 0,
+        }
+    }
+
+    /// The encoding the text was decoded from.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// The first non-empty line or `None` if there hasn't been one.
+    pub fn title(&self) -> Option<&str> {
+        let title = self.title.trim_end();
+        if title.is_empty() {
+            None
+        } else {
+            Some(title)
+        }
+    }
+
+    /// Returns the inner sink.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the inner sink, discarding the preview.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn capture(&mut self, text: &str) {
+        for c in text.chars() {
+            if self.complete {
+                return;
+            }
+            match c {
+                '\n' | '\r' => self.complete = !self.title.is_empty(),
+                _ if self.title.is_empty() && c.is_whitespace() => {}
+                _ => {
+                    self.title.push(c);
+                    self.chars += 1;
+                    self.complete = self.chars == self.max_chars;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Sink> Sink for TitleSink<S> {
+    fn verdict(&mut self, encoding: &'static Encoding) -> io::Result<()> {
+        self.encoding = Some(encoding);
+        self.inner.verdict(encoding)
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.capture(text);
+        self.inner.write_str(text)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A filter that removes markup (anything from `<` to the next `>`), so that
+/// tag and attribute names don't count as evidence. Downstream stages only
+/// see the text content.
+#[derive(Debug, Default)]
+pub struct MarkupFilter {
+    in_tag: bool,
+}
+
+impl MarkupFilter {
+    /// Instantiates the filter.
+    pub fn new() -> Self {
+        MarkupFilter::default()
+    }
+}
+
+impl Filter for MarkupFilter {
+    fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let mut rest = input;
+        while !rest.is_empty() {
+            let delimiter = if self.in_tag { b'>' } else { b'<' };
+            match memchr::memchr(delimiter, rest) {
+                Some(pos) => {
+                    if !self.in_tag {
+                        output.extend_from_slice(&rest[..pos]);
+                    }
+                    self.in_tag = !self.in_tag;
+                    rest = &rest[pos + 1..];
+                }
+                None => {
+                    if !self.in_tag {
+                        output.extend_from_slice(rest);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// A filter that undoes the quoted-printable content transfer encoding.
+/// Malformed `=` escapes are passed through unchanged.
+#[derive(Debug, Default)]
+pub struct QuotedPrintableFilter {
+    held: Vec<u8>,
+}
+
+impl QuotedPrintableFilter {
+    /// Instantiates the filter.
+    pub fn new() -> Self {
+        QuotedPrintableFilter::default()
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        _ => None,
+    }
+}
+
+impl Filter for QuotedPrintableFilter {
+    fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let mut joined;
+        let mut rest = if self.held.is_empty() {
+            input
+        } else {
+            joined = std::mem::take(&mut self.held);
+            joined.extend_from_slice(input);
+            &joined[..]
+        };
+        while let Some(pos) = memchr::memchr(b'=', rest) {
+            output.extend_from_slice(&rest[..pos]);
+            let escape = &rest[pos..];
+            if escape.len() < 3 {
+                if escape.get(1) == Some(&b'\n') {
+                    rest = &escape[2..];
+                    continue;
+                }
+                self.held.extend_from_slice(escape);
+                return;
+            }
+            match (escape[1], escape[2]) {
+                (b'\r', b'\n') => {
+                    rest = &escape[3..];
+                }
+                (b'\n', _) => {
+                    rest = &escape[2..];
+                }
+                (high, low) => {
+                    if let (Some(high), Some(low)) = (hex_value(high), hex_value(low)) {
+                        output.push((high << 4) | low);
+                        rest = &escape[3..];
+                    } else {
+                        output.push(b'=');
+                        rest = &escape[1..];
+                    }
+                }
+            }
+        }
+        output.extend_from_slice(rest);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        output.append(&mut self.held);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RtfState {
+    Text,
+    Backslash,
+    Hex,
+    HexSecond(u8),
+    ControlWord,
+    Parameter,
+}
+
+/// A filter that reconstructs the raw bytes of the text of an RTF document.
+///
+/// RTF represents non-ASCII bytes of the document's legacy encoding as
+/// `\'xx` hex escapes. The filter turns these escapes into the bytes they
+/// stand for, unescapes `\\`, `\{` and `\}`, and drops control words,
+/// other control symbols, group braces and line breaks. Malformed hex
+/// escapes are passed through unchanged.
+#[derive(Debug)]
+pub struct RtfFilter {
+    state: RtfState,
+}
+
+impl RtfFilter {
+    /// Instantiates the filter.
+    pub fn new() -> Self {
+        RtfFilter {
+            state: RtfState::Text,
+        }
+    }
+}
+
+impl Default for RtfFilter {
+    fn default() -> Self {
+        RtfFilter::new()
+    }
+}
+
+impl Filter for RtfFilter {
+    fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let mut i = 0;
+        while i < input.len() {
+            let byte = input[i];
+            // Unless the byte needs to be reprocessed in the `Text` state.
+            let mut consumed = true;
+            self.state = match self.state {
+                RtfState::Text => match byte {
+                    b'\\' => RtfState::Backslash,
+                    b'{' | b'}' | b'\r' | b'\n' => RtfState::Text,
+                    _ => {
+                        output.push(byte);
+                        RtfState::Text
+                    }
+                },
+                RtfState::Backslash => match byte {
+                    b'\'' => RtfState::Hex,
+                    b'a'..=b'z' | b'A'..=b'Z' => RtfState::ControlWord,
+                    b'\\' | b'{' | b'}' => {
+                        output.push(byte);
+                        RtfState::Text
+                    }
+                    _ => RtfState::Text,
+                },
+                RtfState::Hex => {
+                    if hex_value(byte).is_some() {
+                        RtfState::HexSecond(byte)
+                    } else {
+                        output.extend_from_slice(b"\\'");
+                        consumed = false;
+                        RtfState::Text
+                    }
+                }
+                RtfState::HexSecond(high) => {
+                    match (hex_value(high), hex_value(byte)) {
+                        (Some(high), Some(low)) => output.push((high << 4) | low),
+                        _ => {
+                            output.extend_from_slice(&[b'\\', b'\'', high]);
+                            consumed = false;
+                        }
+                    }
+                    RtfState::Text
+                }
+                RtfState::ControlWord => match byte {
+                    b'a'..=b'z' | b'A'..=b'Z' => RtfState::ControlWord,
+                    b'0'..=b'9' | b'-' => RtfState::Parameter,
+                    b' ' => RtfState::Text,
+                    _ => {
+                        consumed = false;
+                        RtfState::Text
+                    }
+                },
+                RtfState::Parameter => match byte {
+                    b'0'..=b'9' => RtfState::Parameter,
+                    b' ' => RtfState::Text,
+                    _ => {
+                        consumed = false;
+                        RtfState::Text
+                    }
+                },
+            };
+            if consumed {
+                i += 1;
+            }
+        }
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        match self.state {
+            RtfState::Hex => output.extend_from_slice(b"\\'"),
+            RtfState::HexSecond(high) => output.extend_from_slice(&[b'\\', b'\'', high]),
+            _ => {}
+        }
+        self.state = RtfState::Text;
+    }
+}
+
+/// Decodes `data`, which follows the `decoded_len` bytes decoded so far,
+/// into `sink` and counts its malformed sequences into `malformed`. Takes
+/// the fields of a `Pipeline` separately so that `data` can be borrowed
+/// from it.
+fn decode_counted<S: Sink>(
+    decoder: &mut Decoder,
+    data: &[u8],
+    decoded_len: &mut u64,
+    malformed: &mut u64,
+    policy: MalformedPolicy,
+    sink: &mut S,
+    last: bool,
+) -> io::Result<()> {
+    let offset = *decoded_len;
+    *decoded_len += data.len() as u64;
+    match decode_to_sink(decoder, data, offset, last, policy, sink) {
+        Ok(count) => {
+            *malformed += count;
+            Ok(())
+        }
+        Err(err) => {
+            *malformed += 1;
+            Err(err)
+        }
+    }
+}
+
+/// Decodes `src`, which starts at stream offset `offset`, with `decoder`,
+/// passing the text to `sink`. Returns the number of malformed sequences.
+fn decode_to_sink<S: Sink>(
+    decoder: &mut Decoder,
+    src: &[u8],
+    offset: u64,
+    last: bool,
+    policy: MalformedPolicy,
+    sink: &mut S,
+) -> io::Result<u64> {
+    let mut text = String::new();
+    let result = decode_with_policy(decoder, src, offset, &mut text, last, policy);
+    if !text.is_empty() {
+        sink.write_str(&text)?;
+    }
+    result.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Builds a `Pipeline`.
+pub struct PipelineBuilder {
+    filters: Vec<Box<dyn Filter>>,
+    allow_2022: bool,
+    malformed_policy: MalformedPolicy,
+}
+
+impl PipelineBuilder {
+    /// Instantiates a builder with no filters and ISO-2022-JP allowed.
+    pub fn new() -> Self {
+        PipelineBuilder {
+            filters: Vec::new(),
+            allow_2022: true,
+            malformed_policy: MalformedPolicy::Replace,
+        }
+    }
+
+    /// Sets whether ISO-2022-JP is a possible guess. See `Detector::new()`.
+    pub fn allow_2022(mut self, allow_2022: bool) -> Self {
+        self.allow_2022 = allow_2022;
+        self
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Under `MalformedPolicy::Error`, the write
+    /// (or `finish()`) that reaches a malformed sequence fails with an
+    /// `io::ErrorKind::InvalidData` error that wraps a `MalformedError`
+    /// after the text before it has been passed to the sink. The pipeline
+    /// should not be used after that.
+    pub fn malformed_policy(mut self, policy: MalformedPolicy) -> Self {
+        self.malformed_policy = policy;
+        self
+    }
+
+    /// Appends a filter. Filters run in the order they were added.
+    pub fn filter<F: Filter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Completes the pipeline with `sink`.
+    pub fn sink<S: Sink>(self, sink: S) -> Pipeline<S> {
+        self.sink_with_buffer(sink, Vec::new())
+    }
+
+    /// Completes the pipeline as a reader that pulls bytes from `inner` and
+    /// reads out the decoded text as UTF-8, also passing it to `sink` (e.g.
+    /// a `StatisticsSink`).
+    pub fn reader<R: Read, S: Sink>(self, inner: R, sink: S) -> PipelineReader<R, S> {
+        PipelineReader {
+            inner,
+            pipeline: Some(self.sink(ReadSink {
+                inner: sink,
+                encoding: None,
+                text: Vec::new(),
+            })),
+            finished: None,
+            next: 0,
+            chunk_sizes: ChunkSizes::DEFAULT,
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Completes the pipeline with `sink`, holding bytes back in `buffer`
+    /// while the detector is undecided. If `buffer` cannot hold the bytes of
+    /// a write, the write fails with an error that wraps a `CapacityError`.
+    /// The detector and the buffer are then unaffected, but stateful filters
+    /// have already seen the bytes.
+    pub fn sink_with_buffer<S: Sink, B: Buffer>(self, sink: S, buffer: B) -> Pipeline<S, B> {
+        Pipeline {
+            filters: self.filters,
+            detector: Detector::new(self.allow_2022),
+            decoder: None,
+            malformed_policy: self.malformed_policy,
+            decoded_len: 0,
+            malformed: 0,
+            held: buffer,
+            verdict_pending: false,
+            sink,
+        }
+    }
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        PipelineBuilder::new()
+    }
+}
+
+/// A chain of filters, the detector and a sink. See the module
+/// documentation.
+///
+/// Bytes are held back until the detector decides. If the detector is still
+/// undecided when `finish()` is called, the bytes are decoded as Shift_JIS.
+/// Malformed sequences are handled according to the `MalformedPolicy` set
+/// on the builder.
+///
+/// If `Sink::verdict()` fails, the write that decided fails with its error
+/// after the bytes have been taken. They stay held, and the next write (or
+/// `finish()`) passes the verdict to the sink again before decoding them.
+pub struct Pipeline<S: Sink, B: Buffer = Vec<u8>> {
+    filters: Vec<Box<dyn Filter>>,
+    detector: Detector,
+    decoder: Option<Decoder>,
+    malformed_policy: MalformedPolicy,
+    decoded_len: u64,
+    malformed: u64,
+    held: B,
+    /// Whether the sink has yet to take the verdict of the detector.
+    verdict_pending: bool,
+    sink: S,
+}
+
+impl<S: Sink, B: Buffer> Pipeline<S, B> {
+    /// Returns the sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// The encoding the input is being decoded from or `None` if the detector
+    /// hasn't decided yet.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.decoder.as_ref().map(|decoder| decoder.encoding())
+    }
+
+    /// The number of malformed sequences decoded so far, i.e. the number of
+    /// replacements performed under `MalformedPolicy::Replace`.
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Decodes `data`, which follows the bytes decoded so far.
+    fn decode(&mut self, data: &[u8], last: bool) -> io::Result<()> {
+        decode_counted(
+            self.decoder.as_mut().expect("must have decided"),
+            data,
+            &mut self.decoded_len,
+            &mut self.malformed,
+            self.malformed_policy,
+            &mut self.sink,
+            last,
+        )
+    }
+
+    /// Runs `input` through the filters.
+    fn run_filters(&mut self, input: &[u8], last: bool) -> Vec<u8> {
+        let mut data = input.to_vec();
+        for filter in self.filters.iter_mut() {
+            let mut output = Vec::with_capacity(data.len());
+            filter.filter(&data, &mut output);
+            if last {
+                filter.finish(&mut output);
+            }
+            data = output;
+        }
+        data
+    }
+
+    fn process(&mut self, data: &[u8], last: bool) -> io::Result<()> {
+        if self.decoder.is_none() {
+            self.held.try_extend(data).map_err(io::Error::other)?;
+            let encoding = match self.detector.feed(data, last) {
+                Some(encoding) => encoding,
+                None if last => SHIFT_JIS,
+                None => {
+                    return Ok(());
+                }
+            };
+            self.decoder = Some(encoding.new_decoder_without_bom_handling());
+            self.verdict_pending = true;
+        } else if self.verdict_pending {
+            self.held.try_extend(data).map_err(io::Error::other)?;
+        } else {
+            return self.decode(data, last);
+        }
+        // The held bytes stay until the sink has taken the verdict, so that
+        // a sink that fails gets the verdict again on the next call instead
+        // of the finished detector being fed.
+        let encoding = self.encoding().expect("must have decided");
+        self.sink.verdict(encoding)?;
+        self.verdict_pending = false;
+        // Decode straight from the held bytes so that they stay in the
+        // storage of the `Buffer`.
+        let result = decode_counted(
+            self.decoder.as_mut().expect("must have decided"),
+            self.held.as_slice(),
+            &mut self.decoded_len,
+            &mut self.malformed,
+            self.malformed_policy,
+            &mut self.sink,
+            last,
+        );
+        self.held.clear();
+        result
+    }
+
+    /// Signals the end of the stream and returns the sink.
+    pub fn finish(mut self) -> io::Result<S> {
+        let data = self.run_filters(b"", true);
+        self.process(&data, true)?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl<S: Sink, B: Buffer> Write for Pipeline<S, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let data = self.run_filters(buf, false);
+        self.process(&data, false)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// The sink of the pipeline inside a `PipelineReader`: queues the text for
+/// `read()` and passes it on to the caller's sink.
+struct ReadSink<S: Sink> {
+    inner: S,
+    encoding: Option<&'static Encoding>,
+    text: Vec<u8>,
+}
+
+impl<S: Sink> Sink for ReadSink<S> {
+    fn verdict(&mut self, encoding: &'static Encoding) -> io::Result<()> {
+        self.encoding = Some(encoding);
+        self.inner.verdict(encoding)
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.text.extend_from_slice(text.as_bytes());
+        self.inner.write_str(text)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Read` that pulls bytes from an inner reader through the filters and
+/// the detector of a `Pipeline` and reads out the decoded text as UTF-8,
+/// which its sink receives, too. Built by `PipelineBuilder::reader()`.
+///
+/// The end of the inner reader is the end of the stream. Reads of the inner
+/// reader that fail with `io::ErrorKind::Interrupted` are retried, and
+/// their size grows as set with `set_chunk_sizes()`. Other errors are as
+/// for `Pipeline::write()`, and the reader should not be used after one.
+pub struct PipelineReader<R: Read, S: Sink> {
+    inner: R,
+    pipeline: Option<Pipeline<ReadSink<S>>>,
+    /// The sink once the end of the stream has been reached.
+    finished: Option<ReadSink<S>>,
+    /// The offset of the text not yet read out.
+    next: usize,
+    chunk_sizes: ChunkSizes,
+    chunk: Vec<u8>,
+}
+
+impl<R: Read, S: Sink> PipelineReader<R, S> {
+    fn read_sink(&mut self) -> &mut ReadSink<S> {
+        match self.pipeline.as_mut() {
+            Some(pipeline) => &mut pipeline.sink,
+            None => self.finished.as_mut().expect("must have a sink"),
+        }
+    }
+
+    fn read_sink_ref(&self) -> &ReadSink<S> {
+        match self.pipeline.as_ref() {
+            Some(pipeline) => &pipeline.sink,
+            None => self.finished.as_ref().expect("must have a sink"),
+        }
+    }
+
+    /// Sets how much to read from the inner reader at a time. Defaults to
+    /// `ChunkSizes::DEFAULT`. Call before the first read.
+    pub fn set_chunk_sizes(&mut self, sizes: ChunkSizes) {
+        self.chunk_sizes = sizes;
+    }
+
+    /// Returns the sink.
+    pub fn sink(&self) -> &S {
+        &self.read_sink_ref().inner
+    }
+
+    /// The encoding the input is being decoded from or `None` if the detector
+    /// hasn't decided yet.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.read_sink_ref().encoding
+    }
+
+    /// Returns the sink, discarding the text not yet read out.
+    pub fn into_sink(self) -> S {
+        match self.pipeline {
+            Some(pipeline) => pipeline.sink.inner,
+            None => self.finished.expect("must have a sink").inner,
+        }
+    }
+}
+
+impl<R: Read, S: Sink> Read for PipelineReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let next = self.next;
+            let sink = self.read_sink();
+            if next < sink.text.len() {
+                let len = (sink.text.len() - next).min(buf.len());
+                buf[..len].copy_from_slice(&sink.text[next..next + len]);
+                if next + len == sink.text.len() {
+                    sink.text.clear();
+                    self.next = 0;
+                } else {
+                    self.next = next + len;
+                }
+                return Ok(len);
+            }
+            let pipeline = match self.pipeline.as_mut() {
+                Some(pipeline) => pipeline,
+                None => return Ok(0),
+            };
+            let size = if self.chunk.is_empty() {
+                self.chunk_sizes.first()
+            } else {
+                self.chunk_sizes
+                    .next(self.chunk.len(), pipeline.encoding().is_some())
+            };
+            self.chunk.resize(size, 0);
+            let read = loop {
+                match self.inner.read(&mut self.chunk) {
+                    Ok(read) => break read,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            };
+            if read == 0 {
+                let pipeline = self.pipeline.take().expect("must be running");
+                self.finished = Some(pipeline.finish()?);
+            } else {
+                pipeline.write_all(&self.chunk[..read])?;
+            }
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+
+    #[test]
+    fn test_utf8_sink() {
+        let mut pipeline = PipelineBuilder::new().sink(Utf8Sink::new(Vec::new()));
+        pipeline.write_all(b"a\xA4").unwrap();
+        pipeline.write_all(b"\xA2b").unwrap();
+        assert_eq!(pipeline.encoding(), Some(EUC_JP));
+        let out = pipeline.finish().unwrap().into_inner();
+        assert_eq!(&out[..], "aあb".as_bytes());
+    }
+
+    #[test]
+    fn test_reader() {
+        // Markup whose attribute would look like EUC-JP, then "あ" in
+        // Shift_JIS.
+        let input: &[u8] = b"<p title=\xA4\xA2>\x82\xA0</p>";
+        let mut reader = PipelineBuilder::new()
+            .filter(MarkupFilter::new())
+            .reader(input, StatisticsSink::new());
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "あ");
+        assert_eq!(reader.encoding(), Some(SHIFT_JIS));
+        let statistics = reader.into_sink();
+        assert_eq!(statistics.encoding(), Some(SHIFT_JIS));
+        assert_eq!(statistics.chars(), 1);
+    }
+
+    /// A reader that records the sizes of the buffers it is given.
+    struct Recording<'a>(&'a [u8], Vec<usize>);
+
+    impl<'a> Read for Recording<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1.push(buf.len());
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_reader_chunk_sizes() {
+        let mut input = vec![b'a'; 10];
+        input.extend_from_slice(b"\xA4\xA2");
+        input.resize(60, b'b');
+        let mut reader =
+            PipelineBuilder::new().reader(Recording(&input, Vec::new()), StatisticsSink::new());
+        reader.set_chunk_sizes(ChunkSizes {
+            initial: 4,
+            max: 16,
+        });
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text.chars().count(), 59);
+        assert_eq!(reader.inner.1, [4, 8, 16, 16, 16, 16]);
+    }
+
+    #[test]
+    fn test_title_sink() {
+        // Blank lines before "あいう" in EUC-JP.
+        let mut pipeline = PipelineBuilder::new().sink(TitleSink::new(StatisticsSink::new(), 2));
+        pipeline.write_all(b"\r\n  \n \xA4\xA2").unwrap();
+        pipeline.write_all(b"\xA4\xA4\xA4\xA6\n").unwrap();
+        let sink = pipeline.finish().unwrap();
+        assert_eq!(sink.encoding(), Some(EUC_JP));
+        assert_eq!(sink.title(), Some("あい"));
+        assert_eq!(sink.inner().chars(), 10);
+
+        let mut pipeline = PipelineBuilder::new().sink(TitleSink::new(StatisticsSink::new(), 80));
+        pipeline.write_all(b"Title \r\nbody").unwrap();
+        assert_eq!(pipeline.finish().unwrap().title(), Some("Title"));
+        let pipeline = PipelineBuilder::new().sink(TitleSink::new(StatisticsSink::new(), 80));
+        assert_eq!(pipeline.finish().unwrap().title(), None);
+    }
+
+    #[test]
+    fn test_filters() {
+        // Quoted-printable Shift_JIS "あ" in markup whose attribute would
+        // otherwise look like EUC-JP.
+        let mut pipeline = PipelineBuilder::new()
+            .filter(QuotedPrintableFilter::new())
+            .filter(MarkupFilter::new())
+            .sink(StatisticsSink::new());
+        pipeline.write_all(b"<p title=3D=A4=A2>=8").unwrap();
+        pipeline.write_all(b"2=A0=\r\n</p>").unwrap();
+        let statistics = pipeline.finish().unwrap();
+        assert_eq!(statistics.encoding(), Some(SHIFT_JIS));
+        assert_eq!(statistics.chars(), 1);
+        assert_eq!(statistics.non_ascii(), 1);
+        assert_eq!(statistics.replacements(), 0);
+    }
+
+    #[test]
+    fn test_rtf_filter() {
+        // "あ" in Shift_JIS as a Unicode escape with a hex-escaped fallback
+        // split across writes, a control word whose name would otherwise be
+        // text and escaped special characters.
+        let mut pipeline = PipelineBuilder::new()
+            .filter(RtfFilter::new())
+            .sink(Utf8Sink::new(Vec::new()));
+        pipeline
+            .write_all(b"{\\rtf1\\ansicpg932 a\\u12354\\'8")
+            .unwrap();
+        pipeline.write_all(b"2\\'a0\\par\n\\{b\\'zz}").unwrap();
+        assert_eq!(pipeline.encoding(), Some(SHIFT_JIS));
+        let out = pipeline.finish().unwrap().into_inner();
+        assert_eq!(&out[..], "aあ{b\\'zz".as_bytes());
+    }
+
+    #[test]
+    fn test_malformed_policy() {
+        // "あ" in EUC-JP followed by a truncated character.
+        let pipeline = |policy| {
+            let mut pipeline = PipelineBuilder::new()
+                .malformed_policy(policy)
+                .sink(Utf8Sink::new(Vec::new()));
+            pipeline.write_all(b"\xA4\xA2a\xA4").unwrap();
+            pipeline
+        };
+        let pipeline_replace = pipeline(MalformedPolicy::Replace);
+        assert_eq!(pipeline_replace.malformed(), 0);
+        let out = pipeline_replace.finish().unwrap().into_inner();
+        assert_eq!(&out[..], "あa\u{FFFD}".as_bytes());
+        let out = pipeline(MalformedPolicy::Skip)
+            .finish()
+            .unwrap()
+            .into_inner();
+        assert_eq!(&out[..], "あa".as_bytes());
+        let mut pipeline_error = pipeline(MalformedPolicy::Error);
+        pipeline_error.write_all(b"\xFFb").unwrap_err();
+        assert_eq!(pipeline_error.malformed(), 1);
+        assert_eq!(&pipeline_error.sink().inner[..], "あa".as_bytes());
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        let mut storage = [0u8; 6];
+        let mut pipeline = PipelineBuilder::new().sink_with_buffer(
+            StatisticsSink::new(),
+            crate::SliceBuffer::new(&mut storage[..]),
+        );
+        pipeline.write_all(b"abc").unwrap();
+        assert!(pipeline.write_all(b"defg").is_err());
+        pipeline.write_all(b"\xA4\xA2").unwrap();
+        let statistics = pipeline.finish().unwrap();
+        assert_eq!(statistics.encoding(), Some(EUC_JP));
+        assert_eq!(statistics.chars(), 4);
+    }
+
+    #[test]
+    fn test_verdict_error() {
+        // A sink that fails to take the first verdict.
+        struct Refusing(StatisticsSink, bool);
+
+        impl Sink for Refusing {
+            fn verdict(&mut self, encoding: &'static Encoding) -> io::Result<()> {
+                if std::mem::take(&mut self.1) {
+                    return Err(io::ErrorKind::Other.into());
+                }
+                self.0.verdict(encoding)
+            }
+
+            fn write_str(&mut self, text: &str) -> io::Result<()> {
+                self.0.write_str(text)
+            }
+        }
+
+        let mut pipeline = PipelineBuilder::new().sink(Refusing(StatisticsSink::new(), true));
+        // "あ" in EUC-JP.
+        assert!(pipeline.write_all(b"a\xA4\xA2").is_err());
+        assert_eq!(pipeline.encoding(), Some(EUC_JP));
+        pipeline.write_all(b"b").unwrap();
+        let statistics = pipeline.finish().unwrap().0;
+        assert_eq!(statistics.encoding(), Some(EUC_JP));
+        assert_eq!(statistics.chars(), 3);
+    }
+
+    #[test]
+    fn test_undecided_falls_back() {
+        let pipeline = PipelineBuilder::new().sink(StatisticsSink::new());
+        let statistics = pipeline.finish().unwrap();
+        assert_eq!(statistics.encoding(), Some(SHIFT_JIS));
+        assert_eq!(statistics.chars(), 0);
+    }
+}
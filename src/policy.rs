@@ -0,0 +1,299 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::Verdict;
+
+/// One rule of the decision logic of a `Detector`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    id: &'static str,
+    description: &'static str,
+    decides: Option<Verdict>,
+    enabled: bool,
+}
+
+impl Rule {
+    /// A stable identifier for the rule.
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    /// A human-readable description of the rule.
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    /// The encoding the rule decides when it fires or `None` if the rule
+    /// only affects how evidence is collected.
+    pub fn decides(&self) -> Option<Verdict> {
+        self.decides
+    }
+
+    /// Whether the rule is active in the configuration that was dumped.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// A dump of the decision logic that a `Detector` has been configured
+/// with, e.g. for rendering in diagnostic UIs.
+///
+/// The first rule (in order) that fires decides. The detector has no
+/// thresholds or priors beyond the character class scores of the optional
+/// `frequency-scoring` rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    fallback: Verdict,
+}
+
+impl Policy {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        allow_2022: bool,
+        require_certain_iso_2022_jp: bool,
+        strict_iso_2022_jp: bool,
+        skip_ansi_sequences: bool,
+        jis_x_0212_decisive: bool,
+        half_width_katakana: bool,
+        text_art: bool,
+        edge_bytes: bool,
+        mid_stream: bool,
+        scoring: bool,
+        char_rule: bool,
+        utf8: bool,
+        byte_budget: bool,
+        blank: Option<Verdict>,
+        undecided_default: Option<Verdict>,
+        fallback: Verdict,
+    ) -> Self {
+        let rule = |id, description, decides, enabled| Rule {
+            id,
+            description,
+            decides,
+            enabled,
+        };
+        Policy {
+            rules: vec![
+                rule(
+                    "mid-stream-resync",
+                    "Ignore Shift_JIS and EUC-JP evidence until a byte that has to be followed by a character boundary.",
+                    None,
+                    mid_stream,
+                ),
+                rule(
+                    "iso-2022-jp-escape",
+                    "An ISO-2022-JP designation escape sequence before any non-ASCII byte decides ISO-2022-JP.",
+                    Some(Verdict::Iso2022Jp),
+                    allow_2022 && !require_certain_iso_2022_jp && !strict_iso_2022_jp,
+                ),
+                rule(
+                    "utf-8",
+                    "If the examined non-ASCII bytes are well-formed UTF-8 at the end of the stream, UTF-8 decides. Until UTF-8 is ruled out, the decisions of the rules below wait.",
+                    Some(Verdict::Utf8),
+                    utf8,
+                ),
+                rule(
+                    "iso-2022-jp-certain",
+                    "After an ISO-2022-JP designation escape sequence, a JIS X 0208 character decides ISO-2022-JP, while malformed ISO-2022-JP or a non-ASCII byte rules it out.",
+                    Some(Verdict::Iso2022Jp),
+                    allow_2022 && require_certain_iso_2022_jp && !strict_iso_2022_jp,
+                ),
+                rule(
+                    "iso-2022-jp-strict",
+                    "After an ISO-2022-JP designation escape sequence, input that is well-formed ISO-2022-JP to the end of the stream decides ISO-2022-JP, while any malformed ISO-2022-JP or a non-ASCII byte rules it out.",
+                    Some(Verdict::Iso2022Jp),
+                    allow_2022 && strict_iso_2022_jp,
+                ),
+                rule(
+                    "ansi-sequences",
+                    "Skip ANSI CSI and OSC sequences when looking for ISO-2022-JP escape sequences.",
+                    None,
+                    allow_2022 && skip_ansi_sequences,
+                ),
+                rule(
+                    "escape-flood",
+                    "Rule out ISO-2022-JP after too many escape sequences or an overlong ANSI sequence.",
+                    None,
+                    allow_2022
+                        && (require_certain_iso_2022_jp
+                            || strict_iso_2022_jp
+                            || skip_ansi_sequences),
+                ),
+                rule(
+                    "half-width-katakana",
+                    "Half-width katakana counts like a malformed sequence in the encoding under which it decodes.",
+                    None,
+                    half_width_katakana,
+                ),
+                rule(
+                    "text-art",
+                    "Half-width katakana only counts at the end of the stream, so that malformed sequences decide first.",
+                    None,
+                    text_art,
+                ),
+                rule(
+                    "edge-bytes",
+                    "Skip DEL or standalone C1-range bytes, or stop undecided at them, as configured.",
+                    None,
+                    edge_bytes,
+                ),
+                rule(
+                    "char-rule",
+                    "Characters that the caller-provided rule disqualifies count like malformed sequences, and its penalties count against the scores.",
+                    None,
+                    char_rule,
+                ),
+                rule(
+                    "euc-jp-error",
+                    "A malformed sequence or half-width katakana when decoding as EUC-JP decides Shift_JIS.",
+                    Some(Verdict::ShiftJis),
+                    true,
+                ),
+                rule(
+                    "euc-jp-jis-x-0212",
+                    "A complete JIS X 0212 character when decoding as EUC-JP decides EUC-JP.",
+                    Some(Verdict::EucJp),
+                    jis_x_0212_decisive,
+                ),
+                rule(
+                    "shift-jis-error",
+                    "A malformed sequence or half-width katakana when decoding as Shift_JIS decides EUC-JP.",
+                    Some(Verdict::EucJp),
+                    true,
+                ),
+                rule(
+                    "euc-jp-truncated",
+                    "An incomplete EUC-JP character at the end of the stream decides Shift_JIS.",
+                    Some(Verdict::ShiftJis),
+                    true,
+                ),
+                rule(
+                    "shift-jis-truncated",
+                    "An incomplete Shift_JIS character at the end of the stream decides EUC-JP.",
+                    Some(Verdict::EucJp),
+                    true,
+                ),
+                rule(
+                    "blank",
+                    "At the end of a stream that is empty or only ASCII whitespace, the configured verdict decides.",
+                    blank,
+                    blank.is_some(),
+                ),
+                rule(
+                    "frequency-scoring",
+                    "At the end of the stream, the encoding in which the characters score as more plausible by class decides.",
+                    None,
+                    scoring,
+                ),
+                rule(
+                    "byte-budget",
+                    "Once the configured number of bytes has been fed without a verdict, UTF-8, the leaning of the scores or the fallback decides.",
+                    None,
+                    byte_budget,
+                ),
+                rule(
+                    "undecided-default",
+                    "At the end of the stream, if no other rule has decided, the configured default decides.",
+                    undecided_default,
+                    undecided_default.is_some(),
+                ),
+            ],
+            fallback,
+        }
+    }
+
+    /// The rules in the order in which they are evaluated.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules[..]
+    }
+
+    /// The guess to use if no rule fires. (The detector itself returns
+    /// `None`.)
+    pub fn fallback(&self) -> Verdict {
+        self.fallback
+    }
+
+    /// Serializes the policy as JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"rules\":[");
+        for (i, rule) in self.rules.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            json.push_str("{\"id\":");
+            push_json_string(&mut json, rule.id);
+            json.push_str(",\"description\":");
+            push_json_string(&mut json, rule.description);
+            json.push_str(",\"decides\":");
+            match rule.decides {
+                Some(verdict) => push_json_string(&mut json, verdict.name()),
+                None => json.push_str("null"),
+            }
+            json.push_str(if rule.enabled {
+                ",\"enabled\":true}"
+            } else {
+                ",\"enabled\":false}"
+            });
+        }
+        json.push_str("],\"fallback\":");
+        push_json_string(&mut json, self.fallback().name());
+        json.push('}');
+        json
+    }
+}
+
+/// Appends `s` to `json` as a JSON string literal.
+pub(crate) fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Detector;
+
+    #[test]
+    fn test_policy() {
+        let policy = Detector::new(false).policy();
+        let rule = &policy.rules()[1];
+        assert_eq!(rule.id(), "iso-2022-jp-escape");
+        assert_eq!(rule.decides(), Some(Verdict::Iso2022Jp));
+        assert!(!rule.enabled());
+        let json = policy.to_json();
+        assert!(json.starts_with("{\"rules\":[{\"id\":\"mid-stream-resync\","));
+        assert!(json.ends_with("\"enabled\":false}],\"fallback\":\"Shift_JIS\"}"));
+    }
+
+    #[test]
+    fn test_push_json_string() {
+        let mut json = String::new();
+        push_json_string(&mut json, "a\"\\\n");
+        assert_eq!(json, "\"a\\\"\\\\\\u000a\"");
+    }
+}
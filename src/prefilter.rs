@@ -0,0 +1,166 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Detector;
+use crate::Verdict;
+
+/// The number of bytes from the start of the buffer that
+/// `detect_prefiltered()` samples.
+pub const PREFILTER_SAMPLE_LEN: usize = 4096;
+
+/// The thresholds of `prefilter()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PrefilterThresholds {
+    /// Below this many non-ASCII bytes, the sample is too small for the
+    /// densities to mean anything.
+    pub min_non_ascii: u64,
+    /// The minimum percentage of non-ASCII bytes in the range 0xA1 to 0xFE
+    /// for deciding EUC-JP.
+    pub euc_jp_percent: u64,
+    /// The minimum percentage of non-ASCII bytes that are Shift_JIS lead
+    /// bytes in the range 0x81 to 0x9F but never occur in EUC-JP for
+    /// deciding Shift_JIS.
+    pub shift_jis_percent: u64,
+}
+
+impl PrefilterThresholds {
+    /// The thresholds used by `prefilter()` and `detect_prefiltered()`.
+    pub const DEFAULT: PrefilterThresholds = PrefilterThresholds {
+        min_non_ascii: 64,
+        euc_jp_percent: 95,
+        shift_jis_percent: 20,
+    };
+}
+
+impl Default for PrefilterThresholds {
+    fn default() -> Self {
+        PrefilterThresholds::DEFAULT
+    }
+}
+
+/// The outcome of `prefilter()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Prefiltered {
+    /// The sample is ASCII without 0x1B bytes.
+    Ascii,
+    /// The byte distribution is typical enough of an encoding to decide it
+    /// without decoding.
+    Decided(Verdict),
+    /// The per-byte decoders have to decide.
+    Inconclusive,
+}
+
+/// Builds a histogram of `sample` and decides the obvious cases without
+/// decoding.
+///
+/// EUC-JP is decided if the sample has no bytes in the range 0x80 to 0xA0
+/// other than 0x8E and 0x8F (which never occur in EUC-JP as anything other
+/// than a prefix) and nearly all non-ASCII bytes are in the range 0xA1 to
+/// 0xFE. Shift_JIS is decided if a substantial share of the non-ASCII bytes
+/// are lead bytes in the range 0x81 to 0x9F that never occur in EUC-JP.
+/// A sample with a 0x1B byte or with few non-ASCII bytes is inconclusive.
+///
+/// Unlike `Detector`, this is a statistical guess: the decided encoding is
+/// not guaranteed to decode the sample without errors.
+pub fn prefilter(sample: &[u8]) -> Prefiltered {
+    prefilter_with_thresholds(sample, &PrefilterThresholds::DEFAULT)
+}
+
+/// The same as `prefilter()` but with caller-chosen thresholds, e.g. for
+/// tuning them against a corpus.
+pub fn prefilter_with_thresholds(sample: &[u8], thresholds: &PrefilterThresholds) -> Prefiltered {
+    let mut histogram = [0u64; 256];
+    for &byte in sample {
+        histogram[usize::from(byte)] += 1;
+    }
+    if histogram[0x1B] != 0 {
+        return Prefiltered::Inconclusive;
+    }
+    let non_ascii: u64 = histogram[0x80..].iter().sum();
+    if non_ascii == 0 {
+        return Prefiltered::Ascii;
+    }
+    if non_ascii < thresholds.min_non_ascii {
+        return Prefiltered::Inconclusive;
+    }
+    let not_euc_jp: u64 =
+        histogram[0x80..=0xA0].iter().sum::<u64>() - histogram[0x8E] - histogram[0x8F]
+            + histogram[0xFF];
+    let euc_jp_range: u64 = histogram[0xA1..=0xFE].iter().sum();
+    if not_euc_jp == 0 && euc_jp_range * 100 >= non_ascii * thresholds.euc_jp_percent {
+        return Prefiltered::Decided(Verdict::EucJp);
+    }
+    let shift_jis_leads: u64 =
+        histogram[0x81..=0x9F].iter().sum::<u64>() - histogram[0x8E] - histogram[0x8F];
+    if shift_jis_leads * 100 >= non_ascii * thresholds.shift_jis_percent {
+        return Prefiltered::Decided(Verdict::ShiftJis);
+    }
+    Prefiltered::Inconclusive
+}
+
+/// Detects the encoding of a complete buffer, first running `prefilter()`
+/// on its first `PREFILTER_SAMPLE_LEN` bytes and running a `Detector` only
+/// if the prefilter is inconclusive (or if the sample is ASCII but the
+/// buffer is longer than the sample).
+///
+/// `allow_2022` has the same meaning as for `Detector::new()`. Returns
+/// `None` if undecided.
+pub fn detect_prefiltered(buffer: &[u8], allow_2022: bool) -> Option<Verdict> {
+    let sample = &buffer[..buffer.len().min(PREFILTER_SAMPLE_LEN)];
+    match prefilter(sample) {
+        Prefiltered::Decided(verdict) => return Some(verdict),
+        Prefiltered::Ascii if sample.len() == buffer.len() => return None,
+        _ => {}
+    }
+    Detector::new(allow_2022).feed_verdict(buffer, true)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefilter() {
+        assert_eq!(prefilter(b"abc"), Prefiltered::Ascii);
+        assert_eq!(prefilter(b"\x1B$B"), Prefiltered::Inconclusive);
+        // "あい" in EUC-JP and Shift_JIS, repeated.
+        assert_eq!(
+            prefilter(&b"\xA4\xA2\xA4\xA4 ".repeat(20)),
+            Prefiltered::Decided(Verdict::EucJp)
+        );
+        assert_eq!(
+            prefilter(&b"\x82\xA0\x82\xA2 ".repeat(20)),
+            Prefiltered::Decided(Verdict::ShiftJis)
+        );
+        assert_eq!(prefilter(b"\xA4\xA2"), Prefiltered::Inconclusive);
+        let eager = PrefilterThresholds {
+            min_non_ascii: 2,
+            ..PrefilterThresholds::DEFAULT
+        };
+        assert_eq!(
+            prefilter_with_thresholds(b"\xA4\xA2", &eager),
+            Prefiltered::Decided(Verdict::EucJp)
+        );
+    }
+
+    #[test]
+    fn test_detect_prefiltered() {
+        assert_eq!(detect_prefiltered(b"abc", true), None);
+        assert_eq!(
+            detect_prefiltered(b"abc\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        let mut long = vec![b'a'; PREFILTER_SAMPLE_LEN];
+        long.extend_from_slice(b"\x82\xA0");
+        assert_eq!(detect_prefiltered(&long, true), Some(Verdict::ShiftJis));
+    }
+}
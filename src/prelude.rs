@@ -0,0 +1,25 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The names that most code using the crate needs, for
+//! `use shift_or_euc::prelude::*;`.
+
+#[cfg(feature = "encoding_rs")]
+pub use crate::easy;
+#[cfg(all(feature = "std", feature = "encoding_rs"))]
+pub use crate::io::DetectingReader;
+pub use crate::Certainty;
+#[cfg(feature = "encoding_rs")]
+pub use crate::DetectingDecoder;
+pub use crate::DetectionOutcome;
+pub use crate::Detector;
+pub use crate::DetectorBuilder;
+pub use crate::DetectorConfig;
+pub use crate::Reason;
+pub use crate::Verdict;
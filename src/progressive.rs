@@ -0,0 +1,366 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use encoding_rs::Decoder;
+use encoding_rs::Encoding;
+
+use crate::find_non_ascii_or_escape;
+use crate::malformed::decode_with_policy;
+use crate::Buffer;
+use crate::Detector;
+use crate::MalformedError;
+use crate::MalformedPolicy;
+
+/// A notification that text emitted by earlier calls to
+/// `ProgressiveDecoder::decode_to_string()` was decoded using the wrong
+/// encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Revision {
+    encoding: &'static Encoding,
+    discard_chars: usize,
+    discard_bytes: usize,
+}
+
+impl Revision {
+    /// The encoding that the detector decided, which replaces the
+    /// tentative encoding.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// The number of characters at the end of the text emitted by earlier
+    /// calls that have to be discarded.
+    pub fn discard_chars(&self) -> usize {
+        self.discard_chars
+    }
+
+    /// The same as `discard_chars()` but in UTF-8 bytes.
+    pub fn discard_bytes(&self) -> usize {
+        self.discard_bytes
+    }
+}
+
+/// Appends the decoding of `src`, which starts at stream offset `offset`, to
+/// `dst` and returns the number of characters appended and the number of
+/// malformed sequences.
+fn decode_counting(
+    decoder: &mut Decoder,
+    src: &[u8],
+    offset: u64,
+    dst: &mut String,
+    last: bool,
+    policy: MalformedPolicy,
+) -> Result<(usize, u64), MalformedError> {
+    let start = dst.len();
+    let malformed = decode_with_policy(decoder, src, offset, dst, last, policy)?;
+    Ok((dst[start..].chars().count(), malformed))
+}
+
+/// A decoder that starts emitting text right away using a tentative
+/// encoding while the detector is still undecided.
+///
+/// If the detector later decides an encoding other than the tentative one,
+/// the call that sees the deciding bytes returns a `Revision`. The recovery
+/// contract is:
+///
+/// * Text decoded from the bytes before the first non-ASCII byte or the
+///   first 0x1B byte is the same in all candidate encodings and is never
+///   revised.
+/// * The last `discard_chars()` characters (`discard_bytes()` UTF-8 bytes)
+///   emitted by the earlier calls were decoded from later bytes and are
+///   wrong. The caller has to remove them.
+/// * The text appended by the call that returned the revision is the
+///   correct decoding of all the bytes from the first non-ASCII or 0x1B byte
+///   onwards, so it replaces the discarded text.
+///
+/// After a verdict (or after `last`), no further revisions happen. To
+/// support the revision, the decoder holds on to the bytes from the first
+/// non-ASCII or 0x1B byte onwards until the detector decides.
+///
+/// Malformed sequences in discarded text don't count towards
+/// `malformed()`. A decoder `with_buffer()` holds the bytes in
+/// caller-provided storage instead. If the storage cannot hold the bytes
+/// of a call, the tentative encoding becomes final.
+pub struct ProgressiveDecoder<B: Buffer = Vec<u8>> {
+    detector: Detector,
+    tentative: &'static Encoding,
+    decoder: Decoder,
+    malformed_policy: MalformedPolicy,
+    replay: B,
+    diverged: bool,
+    decided: bool,
+    position: u64,
+    malformed: u64,
+    emitted_chars: usize,
+    emitted_bytes: usize,
+    emitted_malformed: u64,
+}
+
+impl ProgressiveDecoder {
+    /// Instantiates the decoder. `tentative` is the encoding to use while the
+    /// detector is undecided and the fallback if the detector remains
+    /// undecided at the end of the stream. `allow_2022` has the same meaning
+    /// as for `Detector::new()`.
+    pub fn new(allow_2022: bool, tentative: &'static Encoding) -> Self {
+        ProgressiveDecoder::with_buffer(allow_2022, tentative, Vec::new())
+    }
+}
+
+impl<B: Buffer> ProgressiveDecoder<B> {
+    /// The same as `new()` but holds the bytes to replay in `buffer`.
+    pub fn with_buffer(allow_2022: bool, tentative: &'static Encoding, buffer: B) -> Self {
+        ProgressiveDecoder {
+            detector: Detector::new(allow_2022),
+            tentative,
+            decoder: tentative.new_decoder_without_bom_handling(),
+            malformed_policy: MalformedPolicy::Replace,
+            replay: buffer,
+            diverged: false,
+            decided: false,
+            position: 0,
+            malformed: 0,
+            emitted_chars: 0,
+            emitted_bytes: 0,
+            emitted_malformed: 0,
+        }
+    }
+
+    /// Sets what to do with malformed byte sequences. Defaults to
+    /// `MalformedPolicy::Replace`. Call before the first call to
+    /// `decode_to_string()`.
+    pub fn set_malformed_policy(&mut self, policy: MalformedPolicy) {
+        self.malformed_policy = policy;
+    }
+
+    /// The number of malformed sequences in the text emitted so far, i.e.
+    /// the number of replacements performed under
+    /// `MalformedPolicy::Replace`.
+    pub fn malformed(&self) -> u64 {
+        self.malformed + self.emitted_malformed
+    }
+
+    /// The encoding currently being used for decoding.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.decoder.encoding()
+    }
+
+    /// Whether the encoding is final, i.e. no further revisions can happen.
+    pub fn is_decided(&self) -> bool {
+        self.decided
+    }
+
+    /// Decodes `src`, appending the text to `dst`. `last` signals the end of
+    /// the stream. Returns a `Revision` if text emitted by earlier calls has
+    /// to be discarded. See the type-level documentation for the contract.
+    ///
+    /// Under `MalformedPolicy::Error`, returns an error for a malformed
+    /// sequence in text that is not going to be discarded after the text
+    /// before it has been appended. The decoder should not be used after
+    /// that.
+    pub fn decode_to_string(
+        &mut self,
+        src: &[u8],
+        dst: &mut String,
+        last: bool,
+    ) -> Result<Option<Revision>, MalformedError> {
+        let policy = self.malformed_policy;
+        let offset = self.position;
+        self.position += src.len() as u64;
+        if self.decided {
+            let (_, malformed) =
+                decode_counting(&mut self.decoder, src, offset, dst, last, policy)?;
+            self.malformed += malformed;
+            return Ok(None);
+        }
+        let safe = if self.diverged {
+            0
+        } else {
+            find_non_ascii_or_escape(src)
+        };
+        if safe < src.len() {
+            self.diverged = true;
+        }
+        let (prefix, rest) = src.split_at(safe);
+        if self.replay.try_extend(rest).is_err() {
+            // Without the bytes, there is nothing to revise with.
+            self.decided = true;
+            self.replay.clear();
+            let (_, malformed) =
+                decode_counting(&mut self.decoder, src, offset, dst, last, policy)?;
+            self.malformed += malformed + self.emitted_malformed;
+            self.emitted_malformed = 0;
+            return Ok(None);
+        }
+        let verdict = self.detector.feed(src, last);
+        decode_counting(&mut self.decoder, prefix, offset, dst, false, policy)?;
+        match verdict {
+            Some(encoding) if encoding != self.tentative => {
+                self.decided = true;
+                self.decoder = encoding.new_decoder_without_bom_handling();
+                let replay_offset = self.position - self.replay.as_slice().len() as u64;
+                self.emitted_malformed = 0;
+                let result = decode_counting(
+                    &mut self.decoder,
+                    self.replay.as_slice(),
+                    replay_offset,
+                    dst,
+                    last,
+                    policy,
+                );
+                self.replay.clear();
+                let (_, malformed) = result?;
+                self.malformed += malformed;
+                if self.emitted_chars == 0 {
+                    return Ok(None);
+                }
+                Ok(Some(Revision {
+                    encoding,
+                    discard_chars: self.emitted_chars,
+                    discard_bytes: self.emitted_bytes,
+                }))
+            }
+            _ => {
+                let start = dst.len();
+                let (chars, malformed) = decode_counting(
+                    &mut self.decoder,
+                    rest,
+                    offset + safe as u64,
+                    dst,
+                    last,
+                    policy,
+                )?;
+                self.emitted_chars += chars;
+                self.emitted_bytes += dst.len() - start;
+                self.emitted_malformed += malformed;
+                if verdict.is_some() || last {
+                    self.decided = true;
+                    self.replay.clear();
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_revision() {
+        // "ab" and a kanji that is valid in both Shift_JIS and EUC-JP,
+        // followed by "あ" in EUC-JP.
+        let mut decoder = ProgressiveDecoder::new(true, SHIFT_JIS);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"ab\xE0\xE0", &mut text, false),
+            Ok(None)
+        );
+        assert_eq!(decoder.encoding(), SHIFT_JIS);
+        assert!(!decoder.is_decided());
+        let emitted = text.clone();
+        let mut appended = String::new();
+        let revision = decoder
+            .decode_to_string(b"\xA4\xA2", &mut appended, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(revision.encoding(), EUC_JP);
+        assert_eq!(revision.discard_chars(), 1);
+        let mut fixed = emitted[..emitted.len() - revision.discard_bytes()].to_string();
+        fixed.push_str(&appended);
+        let expected = EUC_JP.decode_without_bom_handling(b"ab\xE0\xE0\xA4\xA2").0;
+        assert_eq!(fixed, expected);
+        assert!(decoder.is_decided());
+    }
+
+    #[test]
+    fn test_no_revision_when_tentative_was_right() {
+        let mut decoder = ProgressiveDecoder::new(true, SHIFT_JIS);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"a\x82", &mut text, false),
+            Ok(None)
+        );
+        assert_eq!(decoder.decode_to_string(b"\xA0", &mut text, true), Ok(None));
+        assert_eq!(text, "aあ");
+    }
+
+    #[test]
+    fn test_no_revision_before_divergent_output() {
+        let mut decoder = ProgressiveDecoder::new(true, SHIFT_JIS);
+        let mut text = String::new();
+        assert_eq!(decoder.decode_to_string(b"ab", &mut text, false), Ok(None));
+        assert_eq!(
+            decoder.decode_to_string(b"\xA4\xA2", &mut text, true),
+            Ok(None)
+        );
+        assert_eq!(decoder.encoding(), EUC_JP);
+        assert_eq!(text, "abあ");
+    }
+
+    #[test]
+    fn test_caller_provided_buffer() {
+        // The kanji fits, but "あ" in EUC-JP doesn't, so there is no
+        // revision.
+        let mut storage = [0u8; 2];
+        let buffer = crate::SliceBuffer::new(&mut storage[..]);
+        let mut decoder = ProgressiveDecoder::with_buffer(true, SHIFT_JIS, buffer);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"ab\xE0\xE0", &mut text, false),
+            Ok(None)
+        );
+        assert!(!decoder.is_decided());
+        assert_eq!(
+            decoder.decode_to_string(b"\xA4\xA2", &mut text, false),
+            Ok(None)
+        );
+        assert!(decoder.is_decided());
+        assert_eq!(decoder.encoding(), SHIFT_JIS);
+        let expected = SHIFT_JIS
+            .decode_without_bom_handling(b"ab\xE0\xE0\xA4\xA2")
+            .0;
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn test_malformed_policy() {
+        // "あ" in Shift_JIS followed by a malformed sequence.
+        let mut decoder = ProgressiveDecoder::new(true, SHIFT_JIS);
+        decoder.set_malformed_policy(MalformedPolicy::Skip);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"a\x82\xA0\xFFb", &mut text, true),
+            Ok(None)
+        );
+        assert_eq!(text, "aあb");
+        assert_eq!(decoder.malformed(), 1);
+
+        let mut decoder = ProgressiveDecoder::new(true, SHIFT_JIS);
+        decoder.set_malformed_policy(MalformedPolicy::Error);
+        let mut text = String::new();
+        assert_eq!(
+            decoder.decode_to_string(b"a\x82\xA0", &mut text, false),
+            Ok(None)
+        );
+        let err = decoder
+            .decode_to_string(b"\x82", &mut text, true)
+            .unwrap_err();
+        assert_eq!(err.offset(), 3);
+        assert_eq!(text, "aあ");
+    }
+}
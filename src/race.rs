@@ -0,0 +1,100 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The race of two candidates that `Detector`, `TwoWayDetector` and
+//! `PairDetector` share: both candidates see every byte, the first one
+//! before the second, and runs of ASCII between characters are skipped.
+//! What a step means (which verdict a rejection decides and why) is up to
+//! the `Race`.
+
+#[cfg(not(feature = "reference-impl"))]
+use crate::find_non_ascii;
+use crate::raw::Step;
+
+/// A candidate state machine of a race.
+pub(crate) trait Racer {
+    /// Pushes one byte to the machine.
+    fn push(&mut self, byte: u8) -> Step;
+
+    /// Signals the end of the stream.
+    fn finish(&mut self) -> Step;
+
+    /// The number of bytes of an incomplete character that the machine is
+    /// holding.
+    #[cfg_attr(feature = "reference-impl", allow(dead_code))]
+    fn pending(&self) -> usize;
+
+    /// Resynchronizes using ASCII bytes that are skipped without pushing
+    /// them to the machine.
+    #[cfg_attr(feature = "reference-impl", allow(dead_code))]
+    fn resync(&mut self, skipped: &[u8]);
+}
+
+/// The candidates of a race and what their steps decide.
+pub(crate) trait Race {
+    type First: Racer;
+    type Second: Racer;
+    type Decision;
+
+    /// The two candidates.
+    fn candidates(&mut self) -> (&mut Self::First, &mut Self::Second);
+
+    /// Called with the step of the first candidate on the byte at `index`
+    /// of the buffer, or on the end of the stream if `None`. A decision
+    /// stops the race before the second candidate sees the byte.
+    fn first(&mut self, step: Step, index: Option<usize>) -> Option<Self::Decision>;
+
+    /// Called with the step of the second candidate after the first one
+    /// has taken the same byte with `first`.
+    fn second(&mut self, first: Step, step: Step, index: Option<usize>) -> Option<Self::Decision>;
+}
+
+/// Races the candidates over `buffer` from `start`, returning the first
+/// decision. Doesn't signal the end of the stream; see `finish()`.
+pub(crate) fn race<R: Race>(race: &mut R, buffer: &[u8], start: usize) -> Option<R::Decision> {
+    let mut i = start;
+    while i < buffer.len() {
+        #[cfg(not(feature = "reference-impl"))]
+        {
+            // Between characters, ASCII is neutral in both encodings, so
+            // skip runs of it instead of pushing each byte.
+            let (first, second) = race.candidates();
+            if first.pending() == 0 && second.pending() == 0 {
+                let ascii = find_non_ascii(&buffer[i..]);
+                if ascii != 0 {
+                    first.resync(&buffer[i..i + ascii]);
+                    second.resync(&buffer[i..i + ascii]);
+                    i += ascii;
+                    continue;
+                }
+            }
+        }
+        let byte = buffer[i];
+        let first = race.candidates().0.push(byte);
+        if let Some(decision) = race.first(first, Some(i)) {
+            return Some(decision);
+        }
+        let second = race.candidates().1.push(byte);
+        if let Some(decision) = race.second(first, second, Some(i)) {
+            return Some(decision);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Signals the end of the stream to the candidates, first to the first.
+pub(crate) fn finish<R: Race>(race: &mut R) -> Option<R::Decision> {
+    let first = race.candidates().0.finish();
+    if let Some(decision) = race.first(first, None) {
+        return Some(decision);
+    }
+    let second = race.candidates().1.finish();
+    race.second(first, second, None)
+}
@@ -0,0 +1,1642 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Low-level per-candidate state machines.
+//!
+//! These are the building blocks of `Detector`. Each machine represents the
+//! hypothesis that the input is in one particular encoding and is pushed
+//! one byte at a time. The machines don't buffer input, so they can be
+//! embedded in pipelines (e.g. packet scanners) whose buffering doesn't fit
+//! `Detector::feed()`.
+//!
+//! Combining the machines the way `Detector` does means: stop at the first
+//! machine that returns something other than `Step::Possible`, pushing each
+//! byte to the EUC-JP machine before the Shift_JIS machine. ISO-2022-JP
+//! being confirmed decides ISO-2022-JP. EUC-JP being rejected decides
+//! Shift_JIS and vice versa. EUC-JP being confirmed (only possible if JIS
+//! X 0212 has been made decisive) decides EUC-JP. The ISO-2022-JP machine
+//! being rejected merely removes it from consideration.
+//!
+//! The Shift_JIS and EUC-JP hypotheses come in two flavors that behave
+//! identically: the `*Machine` types are backed by `encoding_rs` decoders and
+//! the `*Validator` types are hand-written and don't need `encoding_rs`.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::race::Racer;
+use crate::state::StateReader;
+use crate::state::StateWriter;
+
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::Decoder;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::DecoderResult;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::Encoding;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::EUC_JP;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::SHIFT_JIS;
+
+use crate::tables::is_mapped;
+use crate::tables::JIS0208;
+use crate::tables::JIS0212;
+
+/// The maximum number of bytes that any machine (and, therefore, `Detector`)
+/// holds as an incomplete character or escape sequence, i.e. the maximum of
+/// `pending()`. These are the EUC-JP JIS X 0212 prefix (0x8F plus a lead
+/// byte) and the first two bytes of an ISO-2022-JP escape sequence. A
+/// protocol that has to replay the bytes whose meaning is still open after
+/// a call to `Detector::feed()` never needs to hold more than this many.
+pub const MAX_PENDING: usize = 2;
+
+/// The state of a candidate after a byte has been pushed to it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// The input so far is consistent with the candidate but doesn't
+    /// confirm it.
+    Possible,
+    /// The input so far confirms the candidate. Once confirmed, a machine
+    /// stays confirmed.
+    Confirmed,
+    /// The input so far rules out the candidate. Once rejected, a machine
+    /// stays rejected.
+    Rejected,
+}
+
+/// Returns `true` if `byte` can only occur as the last byte of a character
+/// in EUC-JP, i.e. if the next byte is necessarily at a character boundary.
+#[inline(always)]
+fn is_euc_jp_boundary(byte: u8) -> bool {
+    byte < 0x80
+}
+
+/// Returns `true` if `byte` can only occur as the last byte of a character
+/// in Shift_JIS, i.e. if the next byte is necessarily at a character
+/// boundary. (Shift_JIS trail bytes can be in the ASCII range, so only the
+/// ASCII bytes that are never trail bytes qualify.)
+#[inline(always)]
+fn is_shift_jis_boundary(byte: u8) -> bool {
+    byte < 0x40 || byte == 0x7F
+}
+
+/// Shared implementation of the two decoder-backed machines and of the
+/// candidates of `PairDetector`.
+#[cfg(feature = "encoding_rs")]
+pub(crate) struct DecoderMachine {
+    decoder: Decoder,
+    is_boundary: fn(u8) -> bool,
+    held: [u8; 2],
+    pending: u8,
+    decisive_lead: Option<u8>,
+    reject_katakana: bool,
+    defer_katakana: bool,
+    katakana_seen: bool,
+    katakana_rejected: bool,
+    synced: bool,
+    /// The non-ASCII UTF-16 code unit that the last byte completed or 0.
+    last_char: u16,
+    state: Step,
+}
+
+#[cfg(feature = "encoding_rs")]
+impl DecoderMachine {
+    fn new(encoding: &'static Encoding, is_boundary: fn(u8) -> bool, synced: bool) -> Self {
+        DecoderMachine {
+            decoder: encoding.new_decoder_without_bom_handling(),
+            is_boundary,
+            held: [0; 2],
+            pending: 0,
+            decisive_lead: None,
+            reject_katakana: true,
+            defer_katakana: false,
+            katakana_seen: false,
+            katakana_rejected: false,
+            synced,
+            last_char: 0,
+            state: Step::Possible,
+        }
+    }
+
+    /// A machine for any ASCII-compatible multi-byte `encoding` that only
+    /// rejects malformed input. Since trail bytes may be in the ASCII range,
+    /// the resynchronizing bytes are the same as for Shift_JIS.
+    pub(crate) fn for_encoding(encoding: &'static Encoding) -> Self {
+        let mut machine = DecoderMachine::new(encoding, is_shift_jis_boundary, true);
+        machine.reject_katakana = false;
+        machine
+    }
+
+    pub(crate) fn encoding(&self) -> &'static Encoding {
+        self.decoder.encoding()
+    }
+
+    /// Feed decoder with one byte (if `last` is `false`) or EOF (if `last`
+    /// is `true`). `byte` is ignored if `last` is `true`.
+    #[inline(always)]
+    fn decode(&mut self, byte: u8, last: bool) -> Step {
+        self.last_char = 0;
+        if self.state != Step::Possible {
+            return self.state;
+        }
+        // Room for a surrogate pair or for the two characters of some Big5
+        // sequences (for `PairDetector`).
+        let mut output = [0u16; 2];
+        let input = [byte];
+        let (result, _read, written) = self.decoder.decode_to_utf16_without_replacement(
+            if last { b"" } else { &input },
+            &mut output,
+            last,
+        );
+        match result {
+            DecoderResult::InputEmpty => {
+                if written != 0 {
+                    if output[0] >= 0x80 {
+                        self.last_char = output[0];
+                    }
+                    if let 0xFF61..=0xFF9F = output[0] {
+                        if !self.reject_katakana {
+                            // Counts like any other character.
+                        } else if self.defer_katakana {
+                            self.katakana_seen = true;
+                        } else {
+                            self.state = Step::Rejected;
+                            self.katakana_rejected = true;
+                        }
+                    } else if self.pending != 0 && Some(self.held[0]) == self.decisive_lead {
+                        self.state = Step::Confirmed;
+                    }
+                    self.pending = 0;
+                } else if !last {
+                    self.held[usize::from(self.pending)] = byte;
+                    self.pending += 1;
+                } else if self.katakana_seen {
+                    self.state = Step::Rejected;
+                    self.katakana_rejected = true;
+                }
+            }
+            DecoderResult::Malformed(_, _) => {
+                self.state = Step::Rejected;
+            }
+            DecoderResult::OutputFull => {
+                unreachable!();
+            }
+        }
+        self.state
+    }
+
+    #[inline(always)]
+    pub(crate) fn push(&mut self, byte: u8) -> Step {
+        if !self.synced {
+            self.synced = (self.is_boundary)(byte);
+            self.last_char = 0;
+            return self.state;
+        }
+        self.decode(byte, false)
+    }
+
+    fn resync(&mut self, skipped: &[u8]) {
+        if !self.synced {
+            self.synced = skipped.iter().any(|&b| (self.is_boundary)(b));
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> Step {
+        self.decode(0, true)
+    }
+
+    fn discard_pending(&mut self) {
+        if self.pending != 0 {
+            self.decoder = self.decoder.encoding().new_decoder_without_bom_handling();
+            self.pending = 0;
+        }
+    }
+
+    fn take_decoder(&mut self) -> Decoder {
+        let fresh = self.decoder.encoding().new_decoder_without_bom_handling();
+        core::mem::replace(&mut self.decoder, fresh)
+    }
+
+    fn write_state(&self, writer: &mut StateWriter) {
+        write_machine_state(
+            writer,
+            &self.held[..usize::from(self.pending)],
+            [self.katakana_seen, self.katakana_rejected, self.synced],
+            self.state,
+        );
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        let (held, pending, [katakana_seen, katakana_rejected, synced], state) =
+            read_machine_state(reader)?;
+        // Brings a fresh decoder to the same state.
+        let mut output = [0u16; 1];
+        for &byte in &held[..usize::from(pending)] {
+            let (result, _, written) =
+                self.decoder
+                    .decode_to_utf16_without_replacement(&[byte], &mut output, false);
+            if result != DecoderResult::InputEmpty || written != 0 {
+                return None;
+            }
+        }
+        self.held = held;
+        self.pending = pending;
+        self.katakana_seen = katakana_seen;
+        self.katakana_rejected = katakana_rejected;
+        self.synced = synced;
+        self.state = state;
+        Some(())
+    }
+
+    fn step(&self) -> Step {
+        self.state
+    }
+
+    fn last_char(&self) -> Option<char> {
+        match self.last_char {
+            0 => None,
+            unit => char::from_u32(u32::from(unit)),
+        }
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+impl Racer for DecoderMachine {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) -> Step {
+        DecoderMachine::push(self, byte)
+    }
+
+    fn finish(&mut self) -> Step {
+        DecoderMachine::finish(self)
+    }
+
+    fn pending(&self) -> usize {
+        usize::from(self.pending)
+    }
+
+    fn resync(&mut self, skipped: &[u8]) {
+        DecoderMachine::resync(self, skipped)
+    }
+}
+
+/// What a hand-written validator makes of one more byte.
+enum Decoded {
+    /// The byte continues an incomplete character.
+    Pending,
+    /// The byte completes a character other than half-width katakana.
+    Char,
+    /// The byte completes a half-width katakana character.
+    HalfWidthKatakana,
+    /// The byte makes the input malformed.
+    Malformed,
+}
+
+/// Classifies `byte` after the `held` bytes of an incomplete character
+/// according to the WHATWG Shift_JIS decoder.
+#[inline(always)]
+fn shift_jis_next(held: &[u8], byte: u8) -> Decoded {
+    match (held, byte) {
+        ([], 0x00..=0x80) => Decoded::Char,
+        ([], 0xA1..=0xDF) => Decoded::HalfWidthKatakana,
+        ([], 0x81..=0x9F) | ([], 0xE0..=0xFC) => Decoded::Pending,
+        (&[lead], 0x40..=0x7E) | (&[lead], 0x80..=0xFC) => {
+            let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+            let offset = if byte < 0x7F { 0x40 } else { 0x41 };
+            let pointer = usize::from(lead - lead_offset) * 188 + usize::from(byte - offset);
+            if (8836..=10715).contains(&pointer) || is_mapped(&JIS0208, pointer) {
+                Decoded::Char
+            } else {
+                Decoded::Malformed
+            }
+        }
+        _ => Decoded::Malformed,
+    }
+}
+
+/// Classifies `byte` after the `held` bytes of an incomplete character
+/// according to the WHATWG EUC-JP decoder.
+#[inline(always)]
+fn euc_jp_next(held: &[u8], byte: u8) -> Decoded {
+    match (held, byte) {
+        ([], 0x00..=0x7F) => Decoded::Char,
+        ([], 0x8E) | ([], 0x8F) | ([], 0xA1..=0xFE) => Decoded::Pending,
+        ([0x8E], 0xA1..=0xDF) => Decoded::HalfWidthKatakana,
+        ([0x8F], 0xA1..=0xFE) => Decoded::Pending,
+        (&[0x8F, lead], 0xA1..=0xFE) => mapped(&JIS0212, lead, byte),
+        (&[lead], 0xA1..=0xFE) if lead >= 0xA1 => mapped(&JIS0208, lead, byte),
+        _ => Decoded::Malformed,
+    }
+}
+
+/// Looks up an EUC-JP byte pair in `table`.
+#[inline(always)]
+fn mapped(table: &[u32], lead: u8, byte: u8) -> Decoded {
+    let pointer = usize::from(lead - 0xA1) * 94 + usize::from(byte - 0xA1);
+    if is_mapped(table, pointer) {
+        Decoded::Char
+    } else {
+        Decoded::Malformed
+    }
+}
+
+/// Shared implementation of the two hand-written machines. Mirrors
+/// `DecoderMachine` step by step, including the `pending` count.
+struct ValidatorMachine {
+    next: fn(&[u8], u8) -> Decoded,
+    is_boundary: fn(u8) -> bool,
+    held: [u8; 2],
+    pending: u8,
+    decisive_lead: Option<u8>,
+    reject_katakana: bool,
+    defer_katakana: bool,
+    katakana_seen: bool,
+    katakana_rejected: bool,
+    synced: bool,
+    state: Step,
+}
+
+impl ValidatorMachine {
+    fn new(next: fn(&[u8], u8) -> Decoded, is_boundary: fn(u8) -> bool, synced: bool) -> Self {
+        ValidatorMachine {
+            next,
+            is_boundary,
+            held: [0; 2],
+            pending: 0,
+            decisive_lead: None,
+            reject_katakana: true,
+            defer_katakana: false,
+            katakana_seen: false,
+            katakana_rejected: false,
+            synced,
+            state: Step::Possible,
+        }
+    }
+
+    #[inline(always)]
+    fn push(&mut self, byte: u8) -> Step {
+        if !self.synced {
+            self.synced = (self.is_boundary)(byte);
+            return self.state;
+        }
+        if self.state != Step::Possible {
+            return self.state;
+        }
+        match (self.next)(&self.held[..usize::from(self.pending)], byte) {
+            Decoded::Pending => {
+                self.held[usize::from(self.pending)] = byte;
+                self.pending += 1;
+            }
+            Decoded::Char => {
+                if self.pending != 0 && Some(self.held[0]) == self.decisive_lead {
+                    self.state = Step::Confirmed;
+                }
+                self.pending = 0;
+            }
+            Decoded::HalfWidthKatakana => {
+                if !self.reject_katakana {
+                    // Counts like any other character.
+                } else if self.defer_katakana {
+                    self.katakana_seen = true;
+                } else {
+                    self.state = Step::Rejected;
+                    self.katakana_rejected = true;
+                }
+                self.pending = 0;
+            }
+            Decoded::Malformed => {
+                self.state = Step::Rejected;
+            }
+        }
+        self.state
+    }
+
+    fn resync(&mut self, skipped: &[u8]) {
+        if !self.synced {
+            self.synced = skipped.iter().any(|&b| (self.is_boundary)(b));
+        }
+    }
+
+    fn finish(&mut self) -> Step {
+        if self.state == Step::Possible && (self.pending != 0 || self.katakana_seen) {
+            self.state = Step::Rejected;
+            self.katakana_rejected = self.pending == 0;
+        }
+        self.state
+    }
+
+    fn discard_pending(&mut self) {
+        self.pending = 0;
+    }
+
+    /// A fresh `encoding` decoder holding the same incomplete character.
+    #[cfg(feature = "encoding_rs")]
+    fn decoder(&self, encoding: &'static Encoding) -> Decoder {
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut output = [0u16; 1];
+        // Held bytes don't produce output.
+        let _ = decoder.decode_to_utf16_without_replacement(
+            &self.held[..usize::from(self.pending)],
+            &mut output,
+            false,
+        );
+        decoder
+    }
+
+    #[cfg_attr(feature = "encoding_rs", allow(dead_code))]
+    fn write_state(&self, writer: &mut StateWriter) {
+        write_machine_state(
+            writer,
+            &self.held[..usize::from(self.pending)],
+            [self.katakana_seen, self.katakana_rejected, self.synced],
+            self.state,
+        );
+    }
+
+    #[cfg_attr(feature = "encoding_rs", allow(dead_code))]
+    fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        let (held, pending, [katakana_seen, katakana_rejected, synced], state) =
+            read_machine_state(reader)?;
+        // Only held bytes that the validator would hold.
+        for (i, &byte) in held[..usize::from(pending)].iter().enumerate() {
+            if !matches!((self.next)(&held[..i], byte), Decoded::Pending) {
+                return None;
+            }
+        }
+        self.held = held;
+        self.pending = pending;
+        self.katakana_seen = katakana_seen;
+        self.katakana_rejected = katakana_rejected;
+        self.synced = synced;
+        self.state = state;
+        Some(())
+    }
+
+    fn step(&self) -> Step {
+        self.state
+    }
+}
+
+macro_rules! decoder_machine {
+    (
+        $name:ident,
+        $inner:ident,
+        $classifier:expr,
+        $is_boundary:ident,
+        $label:literal,
+        $boundary_doc:literal,
+        $backend_doc:literal
+    ) => {
+        #[doc = concat!("The state machine for the ", $label, " hypothesis.")]
+        ///
+        /// A character decoding as half-width katakana rejects the
+        /// candidate just like a malformed sequence does (unless deferred).
+        ///
+        #[doc = $backend_doc]
+        pub struct $name {
+            inner: $inner,
+        }
+
+        impl $name {
+            /// Instantiates the machine for input that starts at the start
+            /// of a stream.
+            pub fn new() -> Self {
+                $name {
+                    inner: $inner::new($classifier, $is_boundary, true),
+                }
+            }
+
+            /// Instantiates the machine for input that starts at an
+            /// arbitrary offset of a stream. The machine ignores input
+            /// until it has seen a byte after which the next byte is at a
+            /// character boundary.
+            #[doc = $boundary_doc]
+            pub fn new_mid_stream() -> Self {
+                $name {
+                    inner: $inner::new($classifier, $is_boundary, false),
+                }
+            }
+
+            /// Pushes one byte to the machine.
+            #[inline(always)]
+            pub fn push(&mut self, byte: u8) -> Step {
+                self.inner.push(byte)
+            }
+
+            /// Signals the end of the stream. An incomplete character at the
+            /// end of the stream rejects the candidate.
+            pub fn finish(&mut self) -> Step {
+                self.inner.finish()
+            }
+
+            /// The current state without pushing anything.
+            pub fn step(&self) -> Step {
+                self.inner.step()
+            }
+
+            /// Whether the candidate has been rejected because of half-width
+            /// katakana rather than because of a malformed sequence.
+            pub fn rejected_by_half_width_katakana(&self) -> bool {
+                self.inner.katakana_rejected
+            }
+
+            /// The number of bytes of an incomplete character that the
+            /// machine is holding. At most `MAX_PENDING`.
+            pub fn pending(&self) -> usize {
+                usize::from(self.inner.pending)
+            }
+
+            /// Resynchronizes using bytes that were skipped without pushing
+            /// them to the machine. Only has an effect on a machine that has
+            /// been instantiated with `new_mid_stream()` and that hasn't
+            /// resynchronized yet.
+            pub fn resync(&mut self, skipped: &[u8]) {
+                self.inner.resync(skipped);
+            }
+
+            /// Forgets an incomplete character without rejecting the
+            /// candidate, as if the stream had been at a character boundary.
+            pub fn discard_pending(&mut self) {
+                self.inner.discard_pending();
+            }
+
+            /// Sets whether half-width katakana rejects the candidate only
+            /// at `finish()` instead of right away, so that a malformed
+            /// sequence seen later can still reject the candidate first.
+            /// Defaults to `false`. Call before the first call to `push()`.
+            pub fn set_defer_half_width_katakana(&mut self, defer: bool) {
+                self.inner.defer_katakana = defer;
+            }
+
+            /// Sets whether half-width katakana rejects the candidate at all.
+            /// If `false`, half-width katakana counts like any other
+            /// character. Defaults to `true`. Call before the first call to
+            /// `push()`.
+            pub fn set_reject_half_width_katakana(&mut self, reject: bool) {
+                self.inner.reject_katakana = reject;
+            }
+
+            /// Writes what pushing bytes has changed for `Detector::to_state()`.
+            #[cfg_attr(feature = "encoding_rs", allow(dead_code))]
+            pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+                self.inner.write_state(writer);
+            }
+
+            /// Reads what `write_state()` wrote into a machine with the same
+            /// settings.
+            #[cfg_attr(feature = "encoding_rs", allow(dead_code))]
+            pub(crate) fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+                self.inner.read_state(reader)
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name::new()
+            }
+        }
+        impl Racer for $name {
+            #[inline(always)]
+            fn push(&mut self, byte: u8) -> Step {
+                $name::push(self, byte)
+            }
+
+            fn finish(&mut self) -> Step {
+                $name::finish(self)
+            }
+
+            fn pending(&self) -> usize {
+                $name::pending(self)
+            }
+
+            fn resync(&mut self, skipped: &[u8]) {
+                $name::resync(self, skipped)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "encoding_rs")]
+decoder_machine!(
+    ShiftJisMachine,
+    DecoderMachine,
+    SHIFT_JIS,
+    is_shift_jis_boundary,
+    "Shift_JIS",
+    "For Shift_JIS, the bytes 0x00 to 0x3F and 0x7F resynchronize.",
+    "Backed by an `encoding_rs` decoder."
+);
+#[cfg(feature = "encoding_rs")]
+decoder_machine!(
+    EucJpMachine,
+    DecoderMachine,
+    EUC_JP,
+    is_euc_jp_boundary,
+    "EUC-JP",
+    "For EUC-JP, any ASCII byte resynchronizes.",
+    "Backed by an `encoding_rs` decoder."
+);
+decoder_machine!(
+    ShiftJisValidator,
+    ValidatorMachine,
+    shift_jis_next,
+    is_shift_jis_boundary,
+    "Shift_JIS",
+    "For Shift_JIS, the bytes 0x00 to 0x3F and 0x7F resynchronize.",
+    "Hand-written and available without the `encoding_rs` feature. Behaves \
+     exactly like `ShiftJisMachine`."
+);
+decoder_machine!(
+    EucJpValidator,
+    ValidatorMachine,
+    euc_jp_next,
+    is_euc_jp_boundary,
+    "EUC-JP",
+    "For EUC-JP, any ASCII byte resynchronizes.",
+    "Hand-written and available without the `encoding_rs` feature. Behaves \
+     exactly like `EucJpMachine`."
+);
+
+#[cfg(feature = "encoding_rs")]
+impl EucJpMachine {
+    /// Sets whether a complete JIS X 0212 character (0x8F followed by two
+    /// bytes in the range 0xA1 to 0xFE that map to a character) confirms
+    /// EUC-JP. Defaults to `false`.
+    ///
+    /// Such a sequence usually decides EUC-JP anyway, because its last byte
+    /// decodes as half-width katakana under Shift_JIS unless it is 0xE0 or
+    /// higher. Making JIS X 0212 decisive also covers the remaining cases at
+    /// the risk of misdetecting Shift_JIS text that starts with a kanji whose
+    /// lead byte is 0x8F followed by a kanji whose lead byte is 0xE0 or
+    /// higher.
+    ///
+    /// The three bytes may arrive in separate calls to `push()`.
+    pub fn set_jis_x_0212_decisive(&mut self, decisive: bool) {
+        self.inner.decisive_lead = if decisive { Some(0x8F) } else { None };
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+impl ShiftJisMachine {
+    /// Takes the decoder that has decoded the input so far, e.g. to keep
+    /// decoding after Shift_JIS has been decided.
+    pub(crate) fn take_decoder(&mut self) -> Decoder {
+        self.inner.take_decoder()
+    }
+
+    /// The non-ASCII character that the last call to `push()` completed,
+    /// if any.
+    pub fn last_char(&self) -> Option<char> {
+        self.inner.last_char()
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+impl EucJpMachine {
+    /// Takes the decoder that has decoded the input so far, e.g. to keep
+    /// decoding after EUC-JP has been decided.
+    pub(crate) fn take_decoder(&mut self) -> Decoder {
+        self.inner.take_decoder()
+    }
+
+    /// The non-ASCII character that the last call to `push()` completed,
+    /// if any.
+    pub fn last_char(&self) -> Option<char> {
+        self.inner.last_char()
+    }
+}
+
+impl EucJpValidator {
+    /// Sets whether a complete JIS X 0212 character confirms EUC-JP. See
+    /// `EucJpMachine::set_jis_x_0212_decisive()`.
+    pub fn set_jis_x_0212_decisive(&mut self, decisive: bool) {
+        self.inner.decisive_lead = if decisive { Some(0x8F) } else { None };
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+impl EucJpValidator {
+    /// A decoder in the state that decoding the input so far would have
+    /// left it in, e.g. to keep decoding after EUC-JP has been decided.
+    pub(crate) fn decoder(&self) -> Decoder {
+        self.inner.decoder(EUC_JP)
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+impl ShiftJisValidator {
+    /// A decoder in the state that decoding the input so far would have
+    /// left it in, e.g. to keep decoding after Shift_JIS has been decided.
+    pub(crate) fn decoder(&self) -> Decoder {
+        self.inner.decoder(SHIFT_JIS)
+    }
+}
+
+/// A position within an ANSI control sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AnsiSequence {
+    /// After `ESC [`.
+    Csi,
+    /// After `ESC ]`.
+    Osc,
+    /// After ESC within an OSC sequence.
+    OscEscape,
+}
+
+impl AnsiSequence {
+    /// The sequence that `ESC` followed by `byte` starts, if any.
+    fn start(byte: u8) -> Option<AnsiSequence> {
+        match byte {
+            b'[' => Some(AnsiSequence::Csi),
+            b']' => Some(AnsiSequence::Osc),
+            _ => None,
+        }
+    }
+
+    /// Advances the sequence by `byte`, returning `None` when `byte` ends
+    /// it. A CSI sequence ends with a final byte in the range 0x40 to 0x7E
+    /// and an OSC sequence with BEL or `ESC \`.
+    fn next(self, byte: u8) -> Option<AnsiSequence> {
+        match (self, byte) {
+            (AnsiSequence::Csi, 0x40..=0x7E) => None,
+            (AnsiSequence::Csi, _) => Some(AnsiSequence::Csi),
+            (_, 0x07) => None,
+            (_, 0x1B) => Some(AnsiSequence::OscEscape),
+            (AnsiSequence::OscEscape, b'\\') => None,
+            _ => Some(AnsiSequence::Osc),
+        }
+    }
+}
+
+/// The maximum number of escape sequences that ISO-2022-JP detection
+/// considers. The machines reject the candidate at the next one, so that
+/// input crafted to keep the candidate alive with a flood of skipped ANSI
+/// sequences or redundant designations is cut off.
+pub const MAX_ESCAPE_SEQUENCES: usize = 64;
+
+/// The maximum length in bytes, after the introducing `ESC [` or `ESC ]`,
+/// of a skipped ANSI sequence. A longer sequence rejects the ISO-2022-JP
+/// candidate.
+pub const MAX_ANSI_SEQUENCE_LEN: usize = 256;
+
+/// Counts escape sequences against `MAX_ESCAPE_SEQUENCES` and the bytes of
+/// the current ANSI sequence against `MAX_ANSI_SEQUENCE_LEN`. The counts
+/// stop at one past the caps, so they fit in `u16`.
+#[derive(Debug, Copy, Clone, Default)]
+struct EscapeBudget {
+    sequences: u16,
+    sequence_len: u16,
+    exhausted: bool,
+}
+
+impl EscapeBudget {
+    /// Counts the start of an escape sequence. Returns `false` if that
+    /// exceeds the cap.
+    fn start(&mut self) -> bool {
+        self.sequences += 1;
+        self.sequence_len = 0;
+        self.exhausted |= usize::from(self.sequences) > MAX_ESCAPE_SEQUENCES;
+        !self.exhausted
+    }
+
+    /// Counts a byte of an ANSI sequence. Returns `false` if that exceeds
+    /// the cap.
+    fn extend(&mut self) -> bool {
+        self.sequence_len += 1;
+        self.exhausted |= usize::from(self.sequence_len) > MAX_ANSI_SEQUENCE_LEN;
+        !self.exhausted
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EscapeState {
+    Ascii,
+    Escape,
+    EscapeSecond(u8),
+    Ansi(AnsiSequence),
+    Confirmed(u8, u8),
+    Rejected,
+}
+
+/// The state machine for the ISO-2022-JP hypothesis.
+///
+/// Any non-ASCII byte rejects the candidate. The first escape sequence
+/// confirms the candidate if it is one of the ISO-2022-JP designation
+/// sequences and rejects the candidate otherwise.
+///
+/// Each byte takes constant work. Skipped ANSI sequences count against
+/// `MAX_ESCAPE_SEQUENCES` and `MAX_ANSI_SEQUENCE_LEN`.
+pub struct Iso2022JpMachine {
+    state: EscapeState,
+    skip_ansi: bool,
+    budget: EscapeBudget,
+}
+
+impl Iso2022JpMachine {
+    /// Instantiates the machine.
+    pub fn new() -> Self {
+        Iso2022JpMachine {
+            state: EscapeState::Ascii,
+            skip_ansi: false,
+            budget: EscapeBudget::default(),
+        }
+    }
+
+    /// Sets whether ANSI CSI (`ESC [`) and OSC (`ESC ]`) sequences, as
+    /// found in terminal captures, are skipped instead of counting as the
+    /// first escape sequence. Defaults to `false`. Call before the first
+    /// call to `push()`.
+    pub fn set_skip_ansi_sequences(&mut self, skip: bool) {
+        self.skip_ansi = skip;
+    }
+
+    /// Pushes one byte to the machine.
+    #[inline(always)]
+    pub fn push(&mut self, byte: u8) -> Step {
+        self.state = match self.state {
+            EscapeState::Confirmed(_, _) => return Step::Confirmed,
+            EscapeState::Rejected => return Step::Rejected,
+            _ if byte > 0x7F => EscapeState::Rejected,
+            EscapeState::Ascii => {
+                if byte == 0x1B {
+                    EscapeState::Escape
+                } else {
+                    EscapeState::Ascii
+                }
+            }
+            EscapeState::Escape => match AnsiSequence::start(byte) {
+                Some(sequence) if self.skip_ansi => {
+                    if self.budget.start() {
+                        EscapeState::Ansi(sequence)
+                    } else {
+                        EscapeState::Rejected
+                    }
+                }
+                _ => EscapeState::EscapeSecond(byte),
+            },
+            EscapeState::Ansi(sequence) => match sequence.next(byte) {
+                Some(sequence) if self.budget.extend() => EscapeState::Ansi(sequence),
+                Some(_) => EscapeState::Rejected,
+                None => EscapeState::Ascii,
+            },
+            EscapeState::EscapeSecond(second) => match (second, byte) {
+                (0x28, 0x42) | (0x28, 0x4A) | (0x28, 0x49) | (0x24, 0x40) | (0x24, 0x42) => {
+                    EscapeState::Confirmed(second, byte)
+                }
+                _ => EscapeState::Rejected,
+            },
+        };
+        self.step()
+    }
+
+    /// The current state without pushing anything.
+    pub fn step(&self) -> Step {
+        match self.state {
+            EscapeState::Confirmed(_, _) => Step::Confirmed,
+            EscapeState::Rejected => Step::Rejected,
+            _ => Step::Possible,
+        }
+    }
+
+    /// Signals the end of the stream.
+    pub fn finish(&mut self) -> Step {
+        self.step()
+    }
+
+    /// Whether the machine has seen an escape byte other than one starting
+    /// a skipped ANSI sequence. Until then, runs of ASCII other than 0x1B
+    /// can be skipped without pushing them.
+    pub fn escape_seen(&self) -> bool {
+        self.state != EscapeState::Ascii
+    }
+
+    /// The number of bytes of an incomplete escape sequence that the
+    /// machine is holding. At most `MAX_PENDING`.
+    pub fn pending(&self) -> usize {
+        match self.state {
+            EscapeState::Escape => 1,
+            EscapeState::EscapeSecond(_) => 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether the candidate was rejected for exceeding
+    /// `MAX_ESCAPE_SEQUENCES` or `MAX_ANSI_SEQUENCE_LEN`.
+    pub fn flooded(&self) -> bool {
+        self.budget.exhausted
+    }
+
+    /// Writes what pushing bytes has changed for `Detector::to_state()`.
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        self.state.write_state(writer);
+        self.budget.write_state(writer);
+    }
+
+    /// Reads what `write_state()` wrote into a machine with the same
+    /// settings.
+    pub(crate) fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        self.state = EscapeState::read_state(reader)?;
+        self.budget = EscapeBudget::read_state(reader)?;
+        Some(())
+    }
+
+    /// The escape sequence that confirmed the candidate, if confirmed.
+    pub(crate) fn confirming_sequence(&self) -> Option<[u8; 3]> {
+        match self.state {
+            EscapeState::Confirmed(second, third) => Some([0x1B, second, third]),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Iso2022JpMachine {
+    fn default() -> Self {
+        Iso2022JpMachine::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Iso2022JpMode {
+    Ascii,
+    Katakana,
+    Lead,
+    Trail(u8),
+    Escape,
+    EscapeSecond(u8),
+    Ansi(AnsiSequence),
+}
+
+/// Follows ISO-2022-JP input after the escape sequence that confirmed
+/// `Iso2022JpMachine` according to the WHATWG ISO-2022-JP decoder until a
+/// complete JIS X 0208 character confirms it for certain. A non-ASCII byte
+/// or malformed input rejects the candidate.
+///
+/// If `skip_ansi` is set, ANSI CSI and OSC sequences are skipped and the
+/// mode that was in effect before them resumes.
+///
+/// In strict mode (see `new_strict()`), a JIS X 0208 character doesn't
+/// confirm the candidate. Instead, the candidate is followed to the end of
+/// the stream, and a designation escape sequence right after another one,
+/// which the WHATWG decoder treats as an error, also rejects it.
+///
+/// Each byte takes constant work. All escape sequences, including the
+/// confirming one, count against `MAX_ESCAPE_SEQUENCES`, except that only
+/// skipped ANSI sequences count in strict mode, where designations can't
+/// follow each other without characters in between. Skipped ANSI sequences
+/// count against `MAX_ANSI_SEQUENCE_LEN`.
+pub(crate) struct Iso2022JpCertainty {
+    bytes: Vec<u8>,
+    mode: Iso2022JpMode,
+    resume: Iso2022JpMode,
+    skip_ansi: bool,
+    budget: EscapeBudget,
+    state: Step,
+    /// Whether `bytes` records the bytes.
+    record: bool,
+    strict: bool,
+    /// Whether no character has followed the last designation.
+    after_designation: bool,
+    /// Whether a JIS X 0208 character has been seen in strict mode.
+    jis_x_0208_seen: bool,
+}
+
+impl Iso2022JpCertainty {
+    /// Starts following after `sequence`, the confirming escape sequence.
+    /// If `record` is `false`, the bytes aren't kept, so `bytes()` is empty.
+    pub(crate) fn new(sequence: [u8; 3], skip_ansi: bool, record: bool) -> Self {
+        Iso2022JpCertainty::with_options(sequence, skip_ansi, record, false)
+    }
+
+    /// Starts following after `sequence` in strict mode. The bytes aren't
+    /// kept.
+    pub(crate) fn new_strict(sequence: [u8; 3], skip_ansi: bool) -> Self {
+        Iso2022JpCertainty::with_options(sequence, skip_ansi, false, true)
+    }
+
+    fn with_options(sequence: [u8; 3], skip_ansi: bool, record: bool, strict: bool) -> Self {
+        let mut certainty = Iso2022JpCertainty {
+            bytes: Vec::new(),
+            mode: Iso2022JpMode::Ascii,
+            resume: Iso2022JpMode::Ascii,
+            skip_ansi,
+            budget: EscapeBudget::default(),
+            state: Step::Possible,
+            record,
+            strict,
+            after_designation: false,
+            jis_x_0208_seen: false,
+        };
+        for &byte in sequence.iter() {
+            certainty.push(byte);
+        }
+        certainty
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) -> Step {
+        if self.state != Step::Possible {
+            return self.state;
+        }
+        if self.record {
+            self.bytes.push(byte);
+        }
+        let mode = match self.mode {
+            _ if byte > 0x7F => None,
+            Iso2022JpMode::Escape => match AnsiSequence::start(byte) {
+                // In strict mode, only ANSI sequences count.
+                Some(_) if self.skip_ansi && self.strict && !self.budget.start() => None,
+                Some(sequence) if self.skip_ansi => Some(Iso2022JpMode::Ansi(sequence)),
+                _ => Some(Iso2022JpMode::EscapeSecond(byte)),
+            },
+            Iso2022JpMode::Ansi(sequence) => match sequence.next(byte) {
+                Some(sequence) if self.budget.extend() => Some(Iso2022JpMode::Ansi(sequence)),
+                Some(_) => None,
+                None => Some(self.resume),
+            },
+            Iso2022JpMode::EscapeSecond(_) if self.strict && self.after_designation => None,
+            Iso2022JpMode::EscapeSecond(second) => {
+                self.after_designation = true;
+                match (second, byte) {
+                    (0x28, 0x42) | (0x28, 0x4A) => Some(Iso2022JpMode::Ascii),
+                    (0x28, 0x49) => Some(Iso2022JpMode::Katakana),
+                    (0x24, 0x40) | (0x24, 0x42) => Some(Iso2022JpMode::Lead),
+                    _ => None,
+                }
+            }
+            Iso2022JpMode::Trail(lead) => {
+                self.after_designation = false;
+                if let 0x21..=0x7E = byte {
+                    let pointer = usize::from(lead - 0x21) * 94 + usize::from(byte - 0x21);
+                    if is_mapped(&JIS0208, pointer) {
+                        if !self.strict {
+                            self.state = Step::Confirmed;
+                            return self.state;
+                        }
+                        self.jis_x_0208_seen = true;
+                        self.mode = Iso2022JpMode::Lead;
+                        return self.state;
+                    }
+                }
+                None
+            }
+            _ if byte == 0x1B => {
+                if self.strict || self.budget.start() {
+                    self.resume = self.mode;
+                    Some(Iso2022JpMode::Escape)
+                } else {
+                    None
+                }
+            }
+            Iso2022JpMode::Ascii => {
+                self.after_designation = false;
+                match byte {
+                    0x0E | 0x0F => None,
+                    _ => Some(Iso2022JpMode::Ascii),
+                }
+            }
+            Iso2022JpMode::Katakana => {
+                self.after_designation = false;
+                match byte {
+                    0x21..=0x5F => Some(Iso2022JpMode::Katakana),
+                    _ => None,
+                }
+            }
+            Iso2022JpMode::Lead => match byte {
+                0x21..=0x7E => Some(Iso2022JpMode::Trail(byte)),
+                _ => None,
+            },
+        };
+        match mode {
+            Some(mode) => self.mode = mode,
+            None => self.state = Step::Rejected,
+        }
+        self.state
+    }
+
+    /// Signals the end of the stream. An incomplete character or escape
+    /// sequence rejects the candidate.
+    pub(crate) fn finish(&mut self) -> Step {
+        if self.state == Step::Possible {
+            match self.mode {
+                Iso2022JpMode::Trail(_)
+                | Iso2022JpMode::Escape
+                | Iso2022JpMode::EscapeSecond(_)
+                | Iso2022JpMode::Ansi(_) => {
+                    self.state = Step::Rejected;
+                }
+                _ => {}
+            }
+        }
+        self.state
+    }
+
+    /// Whether the candidate was rejected for exceeding
+    /// `MAX_ESCAPE_SEQUENCES` or `MAX_ANSI_SEQUENCE_LEN`.
+    pub(crate) fn flooded(&self) -> bool {
+        self.budget.exhausted
+    }
+
+    /// Whether a JIS X 0208 character has been seen in strict mode.
+    pub(crate) fn jis_x_0208_seen(&self) -> bool {
+        self.jis_x_0208_seen
+    }
+
+    /// Writes the certainty for `Detector::to_state()`.
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.bytes);
+        self.mode.write_state(writer);
+        self.resume.write_state(writer);
+        writer.bool(self.skip_ansi);
+        self.budget.write_state(writer);
+        writer.variant(&STEPS, &self.state);
+        writer.bool(self.record);
+        writer.bool(self.strict);
+        writer.bool(self.after_designation);
+        writer.bool(self.jis_x_0208_seen);
+    }
+
+    /// Reads what `write_state()` wrote.
+    pub(crate) fn read_state(reader: &mut StateReader) -> Option<Self> {
+        Some(Iso2022JpCertainty {
+            bytes: reader.bytes()?.to_vec(),
+            mode: Iso2022JpMode::read_state(reader)?,
+            resume: Iso2022JpMode::read_state(reader)?,
+            skip_ansi: reader.bool()?,
+            budget: EscapeBudget::read_state(reader)?,
+            state: reader.variant(&STEPS)?,
+            record: reader.bool()?,
+            strict: reader.bool()?,
+            after_designation: reader.bool()?,
+            jis_x_0208_seen: reader.bool()?,
+        })
+    }
+
+    /// The bytes from the start of the confirming escape sequence on.
+    #[cfg(feature = "encoding_rs")]
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes[..]
+    }
+}
+
+const STEPS: [Step; 3] = [Step::Possible, Step::Confirmed, Step::Rejected];
+
+const ANSI_SEQUENCES: [AnsiSequence; 3] = [
+    AnsiSequence::Csi,
+    AnsiSequence::Osc,
+    AnsiSequence::OscEscape,
+];
+
+/// Writes the state shared by the Shift_JIS and EUC-JP machines: the bytes
+/// of an incomplete character, the katakana and sync flags and the step.
+fn write_machine_state(writer: &mut StateWriter, held: &[u8], flags: [bool; 3], state: Step) {
+    writer.bytes(held);
+    for &flag in flags.iter() {
+        writer.bool(flag);
+    }
+    writer.variant(&STEPS, &state);
+}
+
+/// Reads what `write_machine_state()` wrote, with the held bytes padded to
+/// `MAX_PENDING`.
+fn read_machine_state(reader: &mut StateReader) -> Option<([u8; 2], u8, [bool; 3], Step)> {
+    let bytes = reader.bytes()?;
+    if bytes.len() > MAX_PENDING {
+        return None;
+    }
+    let mut held = [0u8; 2];
+    held[..bytes.len()].copy_from_slice(bytes);
+    let flags = [reader.bool()?, reader.bool()?, reader.bool()?];
+    Some((held, bytes.len() as u8, flags, reader.variant(&STEPS)?))
+}
+
+impl EscapeBudget {
+    fn write_state(&self, writer: &mut StateWriter) {
+        writer.u64(u64::from(self.sequences));
+        writer.u64(u64::from(self.sequence_len));
+        writer.bool(self.exhausted);
+    }
+
+    fn read_state(reader: &mut StateReader) -> Option<Self> {
+        Some(EscapeBudget {
+            sequences: u16::try_from(reader.u64()?).ok()?,
+            sequence_len: u16::try_from(reader.u64()?).ok()?,
+            exhausted: reader.bool()?,
+        })
+    }
+}
+
+impl EscapeState {
+    fn write_state(&self, writer: &mut StateWriter) {
+        match *self {
+            EscapeState::Ascii => writer.u8(0),
+            EscapeState::Escape => writer.u8(1),
+            EscapeState::EscapeSecond(second) => {
+                writer.u8(2);
+                writer.u8(second);
+            }
+            EscapeState::Ansi(sequence) => {
+                writer.u8(3);
+                writer.variant(&ANSI_SEQUENCES, &sequence);
+            }
+            EscapeState::Confirmed(second, third) => {
+                writer.u8(4);
+                writer.u8(second);
+                writer.u8(third);
+            }
+            EscapeState::Rejected => writer.u8(5),
+        }
+    }
+
+    fn read_state(reader: &mut StateReader) -> Option<Self> {
+        Some(match reader.u8()? {
+            0 => EscapeState::Ascii,
+            1 => EscapeState::Escape,
+            2 => EscapeState::EscapeSecond(reader.u8()?),
+            3 => EscapeState::Ansi(reader.variant(&ANSI_SEQUENCES)?),
+            4 => EscapeState::Confirmed(reader.u8()?, reader.u8()?),
+            5 => EscapeState::Rejected,
+            _ => return None,
+        })
+    }
+}
+
+impl Iso2022JpMode {
+    fn write_state(&self, writer: &mut StateWriter) {
+        match *self {
+            Iso2022JpMode::Ascii => writer.u8(0),
+            Iso2022JpMode::Katakana => writer.u8(1),
+            Iso2022JpMode::Lead => writer.u8(2),
+            Iso2022JpMode::Trail(lead) => {
+                writer.u8(3);
+                writer.u8(lead);
+            }
+            Iso2022JpMode::Escape => writer.u8(4),
+            Iso2022JpMode::EscapeSecond(second) => {
+                writer.u8(5);
+                writer.u8(second);
+            }
+            Iso2022JpMode::Ansi(sequence) => {
+                writer.u8(6);
+                writer.variant(&ANSI_SEQUENCES, &sequence);
+            }
+        }
+    }
+
+    fn read_state(reader: &mut StateReader) -> Option<Self> {
+        Some(match reader.u8()? {
+            0 => Iso2022JpMode::Ascii,
+            1 => Iso2022JpMode::Katakana,
+            2 => Iso2022JpMode::Lead,
+            3 => Iso2022JpMode::Trail(reader.u8()?),
+            4 => Iso2022JpMode::Escape,
+            5 => Iso2022JpMode::EscapeSecond(reader.u8()?),
+            6 => Iso2022JpMode::Ansi(reader.variant(&ANSI_SEQUENCES)?),
+            _ => return None,
+        })
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_shift_jis_machine() {
+        let mut machine = ShiftJisMachine::new();
+        assert_eq!(machine.push(0x82), Step::Possible);
+        assert_eq!(machine.pending(), 1);
+        assert_eq!(machine.push(0xA0), Step::Possible);
+        assert_eq!(machine.pending(), 0);
+        assert_eq!(machine.push(0xB1), Step::Rejected);
+        assert_eq!(machine.push(b'a'), Step::Rejected);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_euc_jp_machine_truncated() {
+        let mut machine = EucJpMachine::new();
+        assert_eq!(machine.push(0xA4), Step::Possible);
+        assert_eq!(machine.finish(), Step::Rejected);
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_euc_jp_machine_jis_x_0212() {
+        // A kanji from JIS X 0212 whose last byte is not half-width katakana
+        // in Shift_JIS.
+        let mut machine = EucJpMachine::new();
+        machine.set_jis_x_0212_decisive(true);
+        assert_eq!(machine.push(0x8F), Step::Possible);
+        assert_eq!(machine.pending(), 1);
+        assert_eq!(machine.push(0xB0), Step::Possible);
+        assert_eq!(machine.pending(), 2);
+        assert_eq!(machine.push(0xE0), Step::Confirmed);
+        assert_eq!(machine.pending(), 0);
+        assert_eq!(machine.push(0x80), Step::Confirmed);
+        let mut machine = EucJpMachine::new();
+        machine.set_jis_x_0212_decisive(true);
+        assert_eq!(machine.push(0xA4), Step::Possible);
+        assert_eq!(machine.push(0xA2), Step::Possible);
+    }
+
+    #[test]
+    fn test_validators() {
+        let mut validator = ShiftJisValidator::new();
+        assert_eq!(validator.push(0x82), Step::Possible);
+        assert_eq!(validator.pending(), 1);
+        assert_eq!(validator.push(0xA0), Step::Possible);
+        assert_eq!(validator.push(0xB1), Step::Rejected);
+        let mut validator = EucJpValidator::new();
+        validator.set_jis_x_0212_decisive(true);
+        assert_eq!(validator.push(0x8F), Step::Possible);
+        assert_eq!(validator.push(0xB0), Step::Possible);
+        assert_eq!(validator.pending(), 2);
+        assert_eq!(validator.push(0xE0), Step::Confirmed);
+        let mut validator = EucJpValidator::new();
+        assert_eq!(validator.push(0xA4), Step::Possible);
+        assert_eq!(validator.finish(), Step::Rejected);
+    }
+
+    #[test]
+    fn test_defer_half_width_katakana() {
+        let mut validator = ShiftJisValidator::new();
+        validator.set_defer_half_width_katakana(true);
+        assert_eq!(validator.push(0xB1), Step::Possible);
+        assert_eq!(validator.finish(), Step::Rejected);
+        assert!(validator.rejected_by_half_width_katakana());
+        #[cfg(feature = "encoding_rs")]
+        {
+            let mut machine = ShiftJisMachine::new();
+            machine.set_defer_half_width_katakana(true);
+            assert_eq!(machine.push(0xB1), Step::Possible);
+            assert_eq!(machine.push(0x82), Step::Possible);
+            assert_eq!(machine.push(0xA0), Step::Possible);
+            assert_eq!(machine.finish(), Step::Rejected);
+            let mut machine = EucJpMachine::new();
+            machine.set_defer_half_width_katakana(true);
+            assert_eq!(machine.push(0x8E), Step::Possible);
+            assert_eq!(machine.push(0xB1), Step::Possible);
+            assert_eq!(machine.push(0x82), Step::Rejected);
+            assert!(!machine.rejected_by_half_width_katakana());
+        }
+    }
+
+    #[test]
+    fn test_reject_half_width_katakana() {
+        let mut validator = ShiftJisValidator::new();
+        validator.set_reject_half_width_katakana(false);
+        assert_eq!(validator.push(0xB1), Step::Possible);
+        assert_eq!(validator.finish(), Step::Possible);
+        #[cfg(feature = "encoding_rs")]
+        {
+            let mut machine = EucJpMachine::new();
+            machine.set_reject_half_width_katakana(false);
+            assert_eq!(machine.push(0x8E), Step::Possible);
+            assert_eq!(machine.push(0xB1), Step::Possible);
+            assert_eq!(machine.pending(), 0);
+            assert_eq!(machine.finish(), Step::Possible);
+        }
+    }
+
+    /// Examples of decoder behavior from the WHATWG Encoding Standard that
+    /// the strict mode relies on: (bytes, Shift_JIS result, EUC-JP result)
+    /// after EOF.
+    const WHATWG_EXAMPLES: [(&[u8], Step, Step); 12] = [
+        // U+0080 in Shift_JIS; not a valid byte in EUC-JP.
+        (b"\x80", Step::Possible, Step::Rejected),
+        (b"\xA0", Step::Rejected, Step::Rejected),
+        // Half-width katakana in Shift_JIS (and a truncated character in
+        // EUC-JP), then half-width katakana in EUC-JP that is a kanji in
+        // Shift_JIS.
+        (b"\xB1", Step::Rejected, Step::Rejected),
+        (b"\x8E\xB1", Step::Possible, Step::Rejected),
+        // Shift_JIS user-defined area mapped to the Private Use Area.
+        (b"\xF0\x40", Step::Possible, Step::Rejected),
+        // "あ" in Shift_JIS and in EUC-JP.
+        (b"\x82\xA0", Step::Possible, Step::Rejected),
+        (b"\xA4\xA2", Step::Rejected, Step::Possible),
+        // A lead byte followed by an ASCII byte that isn't a trail byte.
+        (b"\x82 ", Step::Rejected, Step::Rejected),
+        // Unmapped pointers.
+        (b"\x85\x40", Step::Rejected, Step::Rejected),
+        (b"\xA9\xA1", Step::Rejected, Step::Rejected),
+        // JIS X 0212 in EUC-JP.
+        (b"\x8F\xB0\xA1", Step::Rejected, Step::Possible),
+        // Truncated at EOF.
+        (b"\xE0", Step::Rejected, Step::Rejected),
+    ];
+
+    #[test]
+    fn test_whatwg_examples() {
+        for &(bytes, shift_jis, euc_jp) in WHATWG_EXAMPLES.iter() {
+            let mut validator = ShiftJisValidator::new();
+            for &byte in bytes {
+                validator.push(byte);
+            }
+            assert_eq!(validator.finish(), shift_jis, "{:X?}", bytes);
+            let mut validator = EucJpValidator::new();
+            for &byte in bytes {
+                validator.push(byte);
+            }
+            assert_eq!(validator.finish(), euc_jp, "{:X?}", bytes);
+        }
+    }
+
+    /// Pushes `bytes` and then EOF to both machines, checking that they
+    /// agree at every step.
+    #[cfg(feature = "encoding_rs")]
+    fn check_agreement(bytes: &[u8], decisive: bool) {
+        let mut shift_jis = (ShiftJisMachine::new(), ShiftJisValidator::new());
+        let mut euc_jp = (EucJpMachine::new(), EucJpValidator::new());
+        euc_jp.0.set_jis_x_0212_decisive(decisive);
+        euc_jp.1.set_jis_x_0212_decisive(decisive);
+        for &byte in bytes {
+            assert_eq!(
+                shift_jis.0.push(byte),
+                shift_jis.1.push(byte),
+                "{:X?}",
+                bytes
+            );
+            assert_eq!(shift_jis.0.pending(), shift_jis.1.pending(), "{:X?}", bytes);
+            assert_eq!(euc_jp.0.push(byte), euc_jp.1.push(byte), "{:X?}", bytes);
+            assert_eq!(euc_jp.0.pending(), euc_jp.1.pending(), "{:X?}", bytes);
+        }
+        assert_eq!(shift_jis.0.finish(), shift_jis.1.finish(), "{:X?}", bytes);
+        assert_eq!(euc_jp.0.finish(), euc_jp.1.finish(), "{:X?}", bytes);
+        assert_eq!(
+            shift_jis.0.rejected_by_half_width_katakana(),
+            shift_jis.1.rejected_by_half_width_katakana(),
+            "{:X?}",
+            bytes
+        );
+        assert_eq!(
+            euc_jp.0.rejected_by_half_width_katakana(),
+            euc_jp.1.rejected_by_half_width_katakana(),
+            "{:X?}",
+            bytes
+        );
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_validators_match_decoders() {
+        // No character is longer than two bytes except for EUC-JP JIS X
+        // 0212, so this covers every state.
+        for first in 0..=0xFFu8 {
+            for second in 0..=0xFFu8 {
+                check_agreement(&[first, second], false);
+                check_agreement(&[0x8F, first, second], true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_pending() {
+        // The validators match the decoder-backed machines (see above), and
+        // no state is reached only after more than three bytes.
+        for prefix in [&[][..], &[0x8F][..], &[0x1B][..]].iter() {
+            for first in 0..=0xFFu8 {
+                for second in 0..=0xFFu8 {
+                    let mut shift_jis = ShiftJisValidator::new();
+                    let mut euc_jp = EucJpValidator::new();
+                    let mut iso_2022_jp = Iso2022JpMachine::new();
+                    for &byte in prefix.iter().chain([first, second].iter()) {
+                        shift_jis.push(byte);
+                        euc_jp.push(byte);
+                        iso_2022_jp.push(byte);
+                        assert!(shift_jis.pending() <= MAX_PENDING);
+                        assert!(euc_jp.pending() <= MAX_PENDING);
+                        assert!(iso_2022_jp.pending() <= MAX_PENDING);
+                    }
+                }
+            }
+        }
+        let mut euc_jp = EucJpValidator::new();
+        euc_jp.push(0x8F);
+        euc_jp.push(0xA1);
+        assert_eq!(euc_jp.pending(), MAX_PENDING);
+    }
+
+    #[test]
+    fn test_iso_2022_jp_certainty() {
+        // "あ" after ESC $ B.
+        let mut certainty = Iso2022JpCertainty::new([0x1B, 0x24, 0x42], false, false);
+        assert_eq!(certainty.push(0x24), Step::Possible);
+        assert_eq!(certainty.push(0x22), Step::Confirmed);
+        // Only ASCII after ESC ( B, then binary data.
+        let mut certainty = Iso2022JpCertainty::new([0x1B, 0x28, 0x42], false, false);
+        assert_eq!(certainty.push(b'a'), Step::Possible);
+        assert_eq!(certainty.finish(), Step::Possible);
+        assert_eq!(certainty.push(0xFF), Step::Rejected);
+        // A line break where a lead byte is expected.
+        let mut certainty = Iso2022JpCertainty::new([0x1B, 0x24, 0x40], false, false);
+        assert_eq!(certainty.push(b'\n'), Step::Rejected);
+        // A skipped SGR sequence before "あ".
+        let mut certainty = Iso2022JpCertainty::new([0x1B, 0x24, 0x42], true, false);
+        for &byte in b"\x1B[0m\x24".iter() {
+            assert_eq!(certainty.push(byte), Step::Possible);
+        }
+        assert_eq!(certainty.push(0x22), Step::Confirmed);
+    }
+
+    #[test]
+    fn test_iso_2022_jp_strict() {
+        // "ああ" after ESC $ B and then ASCII: the characters don't confirm.
+        let mut certainty = Iso2022JpCertainty::new_strict([0x1B, 0x24, 0x42], false);
+        for &byte in b"\x24\x22\x24\x22\x1B(Ba".iter() {
+            assert_eq!(certainty.push(byte), Step::Possible);
+        }
+        assert_eq!(certainty.finish(), Step::Possible);
+        assert!(certainty.jis_x_0208_seen());
+        // A designation right after another one.
+        let mut certainty = Iso2022JpCertainty::new_strict([0x1B, 0x28, 0x42], false);
+        assert_eq!(certainty.push(0x1B), Step::Possible);
+        assert_eq!(certainty.push(0x24), Step::Possible);
+        assert_eq!(certainty.push(0x42), Step::Rejected);
+        // Shift Out.
+        let mut certainty = Iso2022JpCertainty::new_strict([0x1B, 0x28, 0x42], false);
+        assert_eq!(certainty.push(b'a'), Step::Possible);
+        assert_eq!(certainty.push(0x0E), Step::Rejected);
+        // Designations with characters in between don't flood.
+        let mut certainty = Iso2022JpCertainty::new_strict([0x1B, 0x28, 0x42], false);
+        for _ in 0..MAX_ESCAPE_SEQUENCES {
+            for &byte in b"a\x1B(B".iter() {
+                assert_eq!(certainty.push(byte), Step::Possible);
+            }
+        }
+        assert!(!certainty.jis_x_0208_seen());
+    }
+
+    #[test]
+    fn test_iso_2022_jp_machine() {
+        let mut machine = Iso2022JpMachine::new();
+        assert_eq!(machine.push(b'a'), Step::Possible);
+        assert!(!machine.escape_seen());
+        assert_eq!(machine.push(0x1B), Step::Possible);
+        assert_eq!(machine.push(0x24), Step::Possible);
+        assert_eq!(machine.pending(), 2);
+        assert_eq!(machine.push(0x42), Step::Confirmed);
+        assert_eq!(machine.push(0xFF), Step::Confirmed);
+
+        let mut machine = Iso2022JpMachine::new();
+        assert_eq!(machine.push(0x1B), Step::Possible);
+        assert_eq!(machine.push(0x5B), Step::Possible);
+        assert_eq!(machine.push(0x6D), Step::Rejected);
+
+        // "\x1B[31m" and "\x1B]0;title\x07" skipped before "\x1B(B".
+        let mut machine = Iso2022JpMachine::new();
+        machine.set_skip_ansi_sequences(true);
+        for &byte in b"\x1B[31m\x1B]0;title\x07".iter() {
+            assert_eq!(machine.push(byte), Step::Possible);
+        }
+        assert!(!machine.escape_seen());
+        for &byte in b"\x1B]0;\x1B\\\x1B(".iter() {
+            assert_eq!(machine.push(byte), Step::Possible);
+        }
+        assert_eq!(machine.push(0x42), Step::Confirmed);
+    }
+
+    #[test]
+    fn test_escape_flood() {
+        let mut machine = Iso2022JpMachine::new();
+        machine.set_skip_ansi_sequences(true);
+        for _ in 0..MAX_ESCAPE_SEQUENCES {
+            for &byte in b"\x1B[m".iter() {
+                assert_eq!(machine.push(byte), Step::Possible);
+            }
+        }
+        assert_eq!(machine.push(0x1B), Step::Possible);
+        assert_eq!(machine.push(b'['), Step::Rejected);
+        assert!(machine.flooded());
+
+        let mut machine = Iso2022JpMachine::new();
+        machine.set_skip_ansi_sequences(true);
+        machine.push(0x1B);
+        machine.push(b']');
+        for _ in 0..MAX_ANSI_SEQUENCE_LEN {
+            assert_eq!(machine.push(b'a'), Step::Possible);
+        }
+        assert_eq!(machine.push(b'a'), Step::Rejected);
+        assert!(machine.flooded());
+
+        // Redundant designations without any characters.
+        let mut certainty = Iso2022JpCertainty::new([0x1B, 0x28, 0x42], false, false);
+        for _ in 1..MAX_ESCAPE_SEQUENCES {
+            for &byte in b"\x1B(B".iter() {
+                assert_eq!(certainty.push(byte), Step::Possible);
+            }
+        }
+        assert_eq!(certainty.push(0x1B), Step::Rejected);
+        assert!(certainty.flooded());
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+/// How the input is divided into records.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecordStructure {
+    /// Records of the given number of bytes (not zero) without delimiters.
+    FixedLength(usize),
+    /// Records terminated by the given delimiter byte, e.g. b'\n'.
+    Delimited(u8),
+}
+
+/// A detector for input that consists of records, such as fixed-width
+/// mainframe exports.
+///
+/// Each record boundary is treated as a character boundary: the detector
+/// never pairs the last byte of a record with the first byte of the next
+/// one, and a character cut off at the end of a record (as happens when a
+/// field is truncated to a fixed width) is discarded instead of counting as
+/// a malformed sequence. Delimiter bytes are not fed to the detector.
+pub struct RecordDetector {
+    detector: Detector,
+    structure: RecordStructure,
+    position: usize,
+}
+
+impl RecordDetector {
+    /// Instantiates the detector. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    ///
+    /// # Panics
+    ///
+    /// If `structure` is `RecordStructure::FixedLength(0)`.
+    pub fn new(allow_2022: bool, structure: RecordStructure) -> Self {
+        assert_ne!(
+            structure,
+            RecordStructure::FixedLength(0),
+            "Records cannot be empty."
+        );
+        RecordDetector {
+            detector: Detector::new(allow_2022),
+            structure,
+            position: 0,
+        }
+    }
+
+    /// Feeds bytes to the detector. Records may be split across calls. The
+    /// return value and the rules about calling again are the same as for
+    /// `Detector::feed()`.
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        let mut rest = buffer;
+        while !rest.is_empty() {
+            let (record, after) = match self.structure {
+                RecordStructure::FixedLength(length) => {
+                    let take = core::cmp::min(length - self.position, rest.len());
+                    self.position += take;
+                    let (record, after) = rest.split_at(take);
+                    if self.position == length {
+                        self.position = 0;
+                        (record, Some(after))
+                    } else {
+                        (record, None)
+                    }
+                }
+                RecordStructure::Delimited(delimiter) => match memchr::memchr(delimiter, rest) {
+                    Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+                    None => (rest, None),
+                },
+            };
+            if let Some(encoding) = self.detector.feed(record, false) {
+                return Some(encoding);
+            }
+            match after {
+                Some(after) => {
+                    self.detector.record_boundary();
+                    rest = after;
+                }
+                None => break,
+            }
+        }
+        if last {
+            self.detector.record_boundary();
+            return self.detector.feed(b"", true);
+        }
+        None
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_fixed_length_truncation() {
+        // Two 3-byte records, the first one ending with a lead byte whose
+        // trail byte was cut off and the second one truncated likewise.
+        let input = b"ab\xE0 c\xE0";
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(&input[..], true), Some(SHIFT_JIS));
+        for split in 0..=input.len() {
+            let mut detector = RecordDetector::new(true, RecordStructure::FixedLength(3));
+            assert_eq!(detector.feed(&input[..split], false), None);
+            assert_eq!(detector.feed(&input[split..], true), None);
+        }
+    }
+
+    #[test]
+    fn test_delimited() {
+        // A Shift_JIS lead byte right before the delimiter and a byte that
+        // would be an invalid trail byte after it.
+        let mut detector = RecordDetector::new(true, RecordStructure::Delimited(b'\n'));
+        assert_eq!(
+            detector.feed(b"\x82\n\x20\x82\xA0\n", true),
+            Some(SHIFT_JIS)
+        );
+        let mut detector = RecordDetector::new(true, RecordStructure::Delimited(b'\n'));
+        assert_eq!(detector.feed(b"\xE0\n\x7F", true), None);
+        let mut detector = Detector::new(true);
+        assert_eq!(detector.feed(b"\xE0\n\x7F", true), Some(SHIFT_JIS));
+    }
+}
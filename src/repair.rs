@@ -0,0 +1,266 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::SHIFT_JIS;
+use encoding_rs::WINDOWS_1252;
+
+use crate::Detector;
+
+/// A wrong decoding that may have produced mojibake.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WrongDecoding {
+    /// Bytes were mapped one-to-one to U+0000 to U+00FF.
+    Latin1,
+    /// Bytes were decoded using an `encoding_rs` encoding.
+    Encoding(&'static Encoding),
+}
+
+impl WrongDecoding {
+    /// The name of the wrong decoding.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WrongDecoding::Latin1 => "ISO-8859-1",
+            WrongDecoding::Encoding(encoding) => encoding.name(),
+        }
+    }
+
+    /// Recovers the original bytes or returns `None` if `text` cannot have
+    /// been produced by this decoding.
+    fn recover(&self, text: &str) -> Option<Vec<u8>> {
+        match *self {
+            WrongDecoding::Latin1 => text
+                .chars()
+                .map(|c| if c <= '\u{FF}' { Some(c as u8) } else { None })
+                .collect(),
+            WrongDecoding::Encoding(encoding) => {
+                let (bytes, _, unmappable) = encoding.encode(text);
+                if unmappable {
+                    None
+                } else {
+                    Some(bytes.into_owned())
+                }
+            }
+        }
+    }
+}
+
+/// The wrong decodings tried by `suggest_repairs()`, in order.
+const WRONG_DECODINGS: [WrongDecoding; 4] = [
+    WrongDecoding::Latin1,
+    WrongDecoding::Encoding(WINDOWS_1252),
+    WrongDecoding::Encoding(SHIFT_JIS),
+    WrongDecoding::Encoding(EUC_JP),
+];
+
+/// A candidate correction of mojibake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    text: String,
+    wrong_decoding: WrongDecoding,
+    encoding: &'static Encoding,
+    score: i64,
+}
+
+impl Repair {
+    /// The corrected text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The hypothesized wrong decoding that produced the mojibake.
+    pub fn wrong_decoding(&self) -> WrongDecoding {
+        self.wrong_decoding
+    }
+
+    /// The encoding the original bytes were detected as.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// How plausible the corrected text is as Japanese. Higher is better.
+    /// Only meaningful for comparing candidates for the same input.
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} read as {}: {}",
+            self.encoding.name(),
+            self.wrong_decoding.name(),
+            self.text
+        )
+    }
+}
+
+/// The per-character weights that `suggest_repairs()` scores candidates
+/// with. Characters not covered by any field score zero.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RepairWeights {
+    /// Hiragana and full-width katakana (U+3040 to U+30FF).
+    pub kana: i64,
+    /// CJK Unified Ideographs (U+4E00 to U+9FFF).
+    pub kanji: i64,
+    /// CJK punctuation (U+3000 to U+303F) and full-width forms (U+FF01 to
+    /// U+FF5E).
+    pub full_width: i64,
+    /// Half-width katakana (U+FF61 to U+FF9F).
+    pub half_width_katakana: i64,
+    /// C1 controls and U+FFFD REPLACEMENT CHARACTER.
+    pub control_or_replacement: i64,
+    /// Latin-1 symbols and letters (U+00A0 to U+00FF).
+    pub latin1: i64,
+}
+
+impl RepairWeights {
+    /// The weights used by `suggest_repairs()`.
+    pub const DEFAULT: RepairWeights = RepairWeights {
+        kana: 2,
+        kanji: 2,
+        full_width: 2,
+        half_width_katakana: -1,
+        control_or_replacement: -4,
+        latin1: -1,
+    };
+
+    /// Scores `text` by how much it looks like Japanese: kana, kanji and
+    /// full-width punctuation count for it, while half-width katakana, C1
+    /// controls, replacement characters and Latin-1 symbols typical of
+    /// mojibake count against it.
+    fn plausibility(&self, text: &str) -> i64 {
+        text.chars().map(|c| self.weight(c)).sum()
+    }
+
+    /// The weight of one character.
+    pub(crate) fn weight(&self, c: char) -> i64 {
+        match c {
+            '\u{3040}'..='\u{30FF}' => self.kana,
+            '\u{4E00}'..='\u{9FFF}' => self.kanji,
+            '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF5E}' => self.full_width,
+            '\u{FF61}'..='\u{FF9F}' => self.half_width_katakana,
+            '\u{80}'..='\u{9F}' | '\u{FFFD}' => self.control_or_replacement,
+            '\u{A0}'..='\u{FF}' => self.latin1,
+            _ => 0,
+        }
+    }
+}
+
+impl Default for RepairWeights {
+    fn default() -> Self {
+        RepairWeights::DEFAULT
+    }
+}
+
+/// Given `text` that may have been decoded using the wrong encoding,
+/// suggests corrections ranked by plausibility, best first.
+///
+/// For each wrong decoding (ISO-8859-1, windows-1252, Shift_JIS and EUC-JP)
+/// that could have produced `text`, the original bytes are recovered,
+/// the detector is run on them and they are decoded as the detected
+/// encoding (Shift_JIS if undecided). Candidates that don't decode without
+/// errors, that are identical to `text` or that are no more plausible than
+/// `text` are left out, so an empty result means that no repair is
+/// suggested.
+pub fn suggest_repairs(text: &str) -> Vec<Repair> {
+    suggest_repairs_with_weights(text, &RepairWeights::DEFAULT)
+}
+
+/// The same as `suggest_repairs()` but scoring with caller-chosen weights,
+/// e.g. for experimenting with the ranking.
+pub fn suggest_repairs_with_weights(text: &str, weights: &RepairWeights) -> Vec<Repair> {
+    let baseline = weights.plausibility(text);
+    let mut repairs: Vec<Repair> = Vec::new();
+    for &wrong_decoding in WRONG_DECODINGS.iter() {
+        let bytes = match wrong_decoding.recover(text) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let encoding = Detector::new(true).feed(&bytes, true).unwrap_or(SHIFT_JIS);
+        let repaired = match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+            Some(repaired) => repaired,
+            None => continue,
+        };
+        if repaired == text || repairs.iter().any(|r| r.text == repaired) {
+            continue;
+        }
+        let score = weights.plausibility(&repaired);
+        if score <= baseline {
+            continue;
+        }
+        repairs.push(Repair {
+            text: repaired.into_owned(),
+            wrong_decoding,
+            encoding,
+            score,
+        });
+    }
+    repairs.sort_by_key(|repair| core::cmp::Reverse(repair.score));
+    repairs
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_jis_read_as_windows_1252() {
+        let (bytes, _, _) = SHIFT_JIS.encode("日本語のテキスト");
+        let (mojibake, _) = WINDOWS_1252.decode_without_bom_handling(&bytes);
+        let repairs = suggest_repairs(&mojibake);
+        let best = &repairs[0];
+        assert_eq!(best.text(), "日本語のテキスト");
+        assert_eq!(best.encoding(), SHIFT_JIS);
+        assert_eq!(best.wrong_decoding(), WrongDecoding::Encoding(WINDOWS_1252));
+        assert_eq!(
+            best.to_string(),
+            "Shift_JIS read as windows-1252: 日本語のテキスト"
+        );
+    }
+
+    #[test]
+    fn test_euc_jp_read_as_latin1() {
+        let (bytes, _, _) = EUC_JP.encode("ひらがな");
+        let mojibake: String = bytes.iter().map(|&b| char::from(b)).collect();
+        let best = &suggest_repairs(&mojibake)[0];
+        assert_eq!(best.text(), "ひらがな");
+        assert_eq!(best.wrong_decoding(), WrongDecoding::Latin1);
+    }
+
+    #[test]
+    fn test_weights() {
+        let (bytes, _, _) = EUC_JP.encode("ひらがな");
+        let mojibake: String = bytes.iter().map(|&b| char::from(b)).collect();
+        assert_eq!(suggest_repairs(&mojibake)[0].score(), 8);
+        let weights = RepairWeights {
+            kana: 0,
+            latin1: 0,
+            control_or_replacement: 0,
+            ..RepairWeights::DEFAULT
+        };
+        assert!(suggest_repairs_with_weights(&mojibake, &weights).is_empty());
+    }
+
+    #[test]
+    fn test_no_repair_for_correct_text() {
+        assert!(suggest_repairs("日本語").is_empty());
+        assert!(suggest_repairs("plain").is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::EncoderResult;
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::ISO_2022_JP;
+use encoding_rs::SHIFT_JIS;
+
+/// Returns the byte offset of the first character of `text` that doesn't
+/// survive a round trip through `encoding` or `None` if all characters do.
+///
+/// A round trip is required (as opposed to merely being encodable), because
+/// the encoders map some characters to bytes that decode to a different
+/// character. (E.g. U+00A5 encodes as 0x5C in Shift_JIS and half-width
+/// katakana encode as full-width katakana in ISO-2022-JP.)
+fn first_unmappable(encoding: &'static Encoding, text: &str) -> Option<usize> {
+    let mut bytes = [0u8; 16];
+    for (offset, c) in text.char_indices() {
+        if c < '\u{80}' && encoding != ISO_2022_JP {
+            continue;
+        }
+        let character = &text[offset..offset + c.len_utf8()];
+        let mut encoder = encoding.new_encoder();
+        let (result, _read, written) =
+            encoder.encode_from_utf8_without_replacement(character, &mut bytes, true);
+        if result != EncoderResult::InputEmpty {
+            return Some(offset);
+        }
+        match encoding.decode_without_bom_handling_and_without_replacement(&bytes[..written]) {
+            Some(ref decoded) if decoded == character => {}
+            _ => {
+                return Some(offset);
+            }
+        }
+    }
+    None
+}
+
+/// Which of the Japanese legacy encodings can represent a given text
+/// losslessly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Representability {
+    shift_jis: Option<usize>,
+    euc_jp: Option<usize>,
+    iso_2022_jp: Option<usize>,
+}
+
+impl Representability {
+    /// Examines `text` under Shift_JIS, EUC-JP and ISO-2022-JP.
+    pub fn new(text: &str) -> Self {
+        Representability {
+            shift_jis: first_unmappable(SHIFT_JIS, text),
+            euc_jp: first_unmappable(EUC_JP, text),
+            iso_2022_jp: first_unmappable(ISO_2022_JP, text),
+        }
+    }
+
+    /// The byte offset (in the UTF-8 text) of the first character that
+    /// `encoding` cannot represent losslessly, `None` if `encoding`
+    /// represents all of the text or `Some(0)` if `encoding` is not one of
+    /// the three Japanese legacy encodings.
+    pub fn first_unmappable(&self, encoding: &'static Encoding) -> Option<usize> {
+        if encoding == SHIFT_JIS {
+            self.shift_jis
+        } else if encoding == EUC_JP {
+            self.euc_jp
+        } else if encoding == ISO_2022_JP {
+            self.iso_2022_jp
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Whether `encoding` represents all of the text losslessly.
+    pub fn can_represent(&self, encoding: &'static Encoding) -> bool {
+        self.first_unmappable(encoding).is_none()
+    }
+
+    /// The encoding that represents all of the text losslessly, preferring
+    /// Shift_JIS, then EUC-JP and then ISO-2022-JP, or `None` if none of them
+    /// can.
+    pub fn safest(&self) -> Option<&'static Encoding> {
+        [SHIFT_JIS, EUC_JP, ISO_2022_JP]
+            .iter()
+            .cloned()
+            .find(|&encoding| self.can_represent(encoding))
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_representable() {
+        let r = Representability::new("abc あいう 漢字");
+        assert!(r.can_represent(SHIFT_JIS));
+        assert!(r.can_represent(EUC_JP));
+        assert!(r.can_represent(ISO_2022_JP));
+        assert_eq!(r.safest(), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn test_lossy_mappings() {
+        // Half-width katakana don't round-trip through ISO-2022-JP.
+        let r = Representability::new("aｱ");
+        assert_eq!(r.first_unmappable(ISO_2022_JP), Some(1));
+        assert!(r.can_represent(SHIFT_JIS));
+        // The yen sign doesn't round-trip through Shift_JIS and an emoji
+        // isn't representable at all.
+        let r = Representability::new("¥😀");
+        assert_eq!(r.first_unmappable(SHIFT_JIS), Some(0));
+        assert_eq!(r.first_unmappable(EUC_JP), Some(0));
+        assert_eq!(r.safest(), None);
+        // ISO-2022-JP cannot represent the escape character.
+        let r = Representability::new("a\u{1B}");
+        assert_eq!(r.first_unmappable(ISO_2022_JP), Some(1));
+        assert_eq!(r.safest(), Some(SHIFT_JIS));
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Certainty;
+use crate::Decision;
+use crate::Detector;
+use crate::Reason;
+use crate::Verdict;
+
+/// What a `CharRule` makes of a character.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RuleAction {
+    /// The character counts as usual.
+    Accept,
+    /// The character counts against the candidate's score by this much
+    /// (see `Detector::set_scoring()`).
+    Penalize(u32),
+    /// The character rules out the candidate like a malformed sequence
+    /// does, which decides the other candidate.
+    Disqualify,
+}
+
+/// A caller-provided rule over the non-ASCII characters that the Shift_JIS
+/// (`Verdict::ShiftJis`) and EUC-JP (`Verdict::EucJp`) candidates decode,
+/// e.g. to disqualify an encoding under which a file would contain
+/// characters that never appear in files of its kind. See
+/// `Detector::set_char_rule()`.
+pub type CharRule = fn(Verdict, char) -> RuleAction;
+
+impl Detector {
+    /// Sets a rule that each non-ASCII character gets run past, once as
+    /// decoded by the Shift_JIS candidate and once as decoded by the EUC-JP
+    /// candidate. Defaults to `None`. Call before the first call to
+    /// `feed()`.
+    ///
+    /// A disqualified character decides the other encoding with
+    /// `Reason::ShiftJisRule` or `Reason::EucJpRule`. Penalties only count
+    /// with `set_scoring()` enabled. Characters in ASCII runs and after an
+    /// ISO-2022-JP escape sequence aren't run past the rule, and the rule
+    /// isn't part of `to_state()`: set it again after `from_state()`.
+    ///
+    /// For example, a rule that disqualifies half-width katakana under
+    /// Shift_JIS only, with `set_reject_half_width_katakana(false)`, suits
+    /// EDI files that are known not to use it.
+    pub fn set_char_rule(&mut self, rule: Option<CharRule>) {
+        self.char_rule = rule;
+    }
+
+    /// Runs the character that the last byte completed for `candidate`, the
+    /// byte at `index` of the buffer, past the rule.
+    #[inline(always)]
+    pub(crate) fn apply_char_rule(
+        &mut self,
+        candidate: Verdict,
+        byte: u8,
+        index: usize,
+    ) -> Option<Decision> {
+        let rule = self.char_rule?;
+        let c = match candidate {
+            Verdict::ShiftJis => self.shift_jis.last_char(),
+            _ => self.euc_jp.last_char(),
+        }?;
+        match rule(candidate, c) {
+            RuleAction::Accept => None,
+            #[cfg_attr(not(feature = "scoring"), allow(unused_variables))]
+            RuleAction::Penalize(penalty) => {
+                #[cfg(feature = "scoring")]
+                if let Some(scorer) = self.scorer.as_mut() {
+                    scorer.penalize(candidate, penalty);
+                }
+                None
+            }
+            RuleAction::Disqualify => Some(match candidate {
+                Verdict::ShiftJis => Decision {
+                    verdict: Verdict::EucJp,
+                    certainty: Certainty::Certain,
+                    reason: Reason::ShiftJisRule,
+                    byte: Some(byte),
+                    examined: index + 1,
+                    consumed: Some(index + 1),
+                },
+                _ => Decision {
+                    verdict: Verdict::ShiftJis,
+                    certainty: Certainty::Certain,
+                    reason: Reason::EucJpRule,
+                    byte: Some(byte),
+                    examined: index + 1,
+                    consumed: Some(index),
+                },
+            }),
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_shift_jis_katakana(candidate: Verdict, c: char) -> RuleAction {
+        match (candidate, c) {
+            (Verdict::ShiftJis, '\u{FF61}'..='\u{FF9F}') => RuleAction::Disqualify,
+            _ => RuleAction::Accept,
+        }
+    }
+
+    #[cfg(feature = "scoring")]
+    fn penalize_euc_jp_kanji(candidate: Verdict, c: char) -> RuleAction {
+        match (candidate, c) {
+            (Verdict::EucJp, '\u{4E00}'..='\u{9FFF}') => RuleAction::Penalize(100),
+            _ => RuleAction::Accept,
+        }
+    }
+
+    #[test]
+    fn test_disqualify() {
+        // "あ" in EUC-JP is two half-width katakana characters in
+        // Shift_JIS.
+        let mut detector = Detector::new(true);
+        detector.set_reject_half_width_katakana(false);
+        assert_eq!(detector.feed_verdict(b"ab\xA4\xA2", true), None);
+        let mut detector = Detector::new(true);
+        detector.set_reject_half_width_katakana(false);
+        detector.set_char_rule(Some(no_shift_jis_katakana));
+        assert_eq!(
+            detector.feed_verdict(b"ab\xA4\xA2", true),
+            Some(Verdict::EucJp)
+        );
+        let outcome = detector.outcome();
+        assert_eq!(outcome.reason(), Some(Reason::ShiftJisRule));
+        assert_eq!(outcome.examined_len(), 3);
+        #[cfg(feature = "diagnostics")]
+        assert!(detector
+            .policy()
+            .rules()
+            .iter()
+            .any(|rule| rule.id() == "char-rule" && rule.enabled()));
+    }
+
+    #[cfg(feature = "scoring")]
+    #[test]
+    fn test_penalize() {
+        // Valid in both; EUC-JP scores higher without the penalty.
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        assert_eq!(
+            detector.feed_verdict(b"\xF0\xA1", true),
+            Some(Verdict::EucJp)
+        );
+        let mut detector = Detector::new(true);
+        detector.set_scoring(true);
+        detector.set_char_rule(Some(penalize_euc_jp_kanji));
+        assert_eq!(
+            detector.feed_verdict(b"\xF0\xA1", true),
+            Some(Verdict::ShiftJis)
+        );
+    }
+}
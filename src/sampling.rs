@@ -0,0 +1,205 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+
+use crate::Detector;
+use crate::Verdict;
+
+/// How `detect_sampled()` picks the windows it examines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SamplingConfig {
+    /// The seed of the pseudo-random window offsets. The same seed picks the
+    /// same offsets for the same buffer length in every version of the
+    /// crate and on every platform.
+    pub seed: u64,
+    /// The number of windows.
+    pub windows: usize,
+    /// The length of each window in bytes.
+    pub window_len: usize,
+}
+
+impl SamplingConfig {
+    /// The configuration used by `detect_sampled()` with a seed of zero:
+    /// eight windows of 4 KB.
+    pub const DEFAULT: SamplingConfig = SamplingConfig {
+        seed: 0,
+        windows: 8,
+        window_len: 4096,
+    };
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig::DEFAULT
+    }
+}
+
+/// The result of `detect_sampled()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sampled {
+    verdict: Option<Verdict>,
+    seed: u64,
+    window_len: usize,
+    offsets: Vec<u64>,
+    deciding_window: Option<usize>,
+}
+
+impl Sampled {
+    /// The verdict of the first window that decided or `None` if none did.
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.verdict
+    }
+
+    /// The seed that the window offsets were picked with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The length of the windows, except that a window doesn't extend past
+    /// the end of the buffer or into the next window.
+    pub fn window_len(&self) -> usize {
+        self.window_len
+    }
+
+    /// The offsets of the windows in the buffer in increasing order.
+    pub fn offsets(&self) -> &[u64] {
+        &self.offsets
+    }
+
+    /// The index into `offsets()` of the window that decided, if any.
+    /// Windows after it weren't examined.
+    pub fn deciding_window(&self) -> Option<usize> {
+        self.deciding_window
+    }
+}
+
+/// The SplitMix64 generator, which the offsets are specified in terms of.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1330_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Detects the encoding of a complete buffer, e.g. a memory-mapped huge
+/// file, from pseudo-random windows of it instead of from all of it.
+/// `allow_2022` has the same meaning as for `Detector::new()`.
+///
+/// The buffer is split into `config.windows` equal strata (or fewer if
+/// fewer windows cover it) and a window of `config.window_len` bytes is
+/// placed at a pseudo-random offset within each stratum, so the windows are
+/// spread over the whole buffer without overlapping. If the strata are no
+/// longer than the windows, the windows cover the whole buffer. The windows
+/// are examined in order, each with a fresh detector (see
+/// `Detector::new_mid_stream()` for all but a window at the start of the
+/// buffer), until one decides.
+///
+/// The offsets depend only on `config` and the length of the buffer, so
+/// a verdict can be reproduced and audited from `Sampled::seed()` and
+/// `Sampled::offsets()`.
+pub fn detect_sampled(buffer: &[u8], allow_2022: bool, config: &SamplingConfig) -> Sampled {
+    let offsets = sample_offsets(buffer.len(), config);
+    let mut verdict = None;
+    let mut deciding_window = None;
+    for (index, &offset) in offsets.iter().enumerate() {
+        let start = offset as usize;
+        let end = match offsets.get(index + 1) {
+            Some(&next) => (start + config.window_len).min(next as usize),
+            None => (start + config.window_len).min(buffer.len()),
+        };
+        let mut detector = if start == 0 {
+            Detector::new(allow_2022)
+        } else {
+            Detector::new_mid_stream(allow_2022)
+        };
+        verdict = detector.feed_verdict(&buffer[start..end], end == buffer.len());
+        if verdict.is_some() {
+            deciding_window = Some(index);
+            break;
+        }
+    }
+    Sampled {
+        verdict,
+        seed: config.seed,
+        window_len: config.window_len,
+        offsets,
+        deciding_window,
+    }
+}
+
+/// The window offsets of `detect_sampled()` for a buffer of length `len`.
+fn sample_offsets(len: usize, config: &SamplingConfig) -> Vec<u64> {
+    // No more windows than it takes to cover the buffer.
+    let windows = config
+        .windows
+        .min(len.div_ceil(config.window_len.max(1)))
+        .max(1);
+    let stratum = len / windows;
+    let mut rng = SplitMix64(config.seed);
+    let mut offsets = Vec::with_capacity(windows);
+    for index in 0..windows {
+        let first = index * stratum;
+        let slack = stratum.saturating_sub(config.window_len);
+        let offset = if slack == 0 {
+            first
+        } else {
+            first + (rng.next() % (slack as u64 + 1)) as usize
+        };
+        offsets.push(offset as u64);
+    }
+    offsets
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_sampled() {
+        let config = SamplingConfig {
+            seed: 42,
+            windows: 4,
+            window_len: 16,
+        };
+        // "あ " in EUC-JP, repeated.
+        let buffer = b"\xA4\xA2 ".repeat(1366);
+        let sampled = detect_sampled(&buffer, true, &config);
+        assert_eq!(sampled.seed(), 42);
+        assert_eq!(sampled.offsets().len(), 4);
+        assert!(sampled
+            .offsets()
+            .iter()
+            .enumerate()
+            .all(|(index, &offset)| offset / 1024 == index as u64 && offset % 1024 <= 1008));
+        assert_eq!(sampled, detect_sampled(&buffer, true, &config));
+        let other = SamplingConfig { seed: 7, ..config };
+        assert_ne!(
+            sampled.offsets(),
+            detect_sampled(&buffer, true, &other).offsets()
+        );
+        assert_eq!(sampled.verdict(), Some(Verdict::EucJp));
+        assert_eq!(sampled.deciding_window(), Some(0));
+        // Small buffers are covered by the windows.
+        let sampled = detect_sampled(b"abc\xA4\xA2", true, &SamplingConfig::DEFAULT);
+        assert_eq!(sampled.offsets(), &[0]);
+        assert_eq!(sampled.verdict(), Some(Verdict::EucJp));
+        assert_eq!(sampled.deciding_window(), Some(0));
+        let sampled = detect_sampled(b"", true, &SamplingConfig::DEFAULT);
+        assert_eq!(sampled.verdict(), None);
+    }
+}
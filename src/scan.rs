@@ -0,0 +1,458 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Detection over directory trees, with a journal for resuming.
+//!
+//! A `Scanner` walks the paths it is given depth-first in name order and
+//! yields a `FileReport` per file as soon as the file has been detected.
+//! Symlinks to directories found during the walk are skipped, so that a
+//! link cycle can't make the walk go on forever; the paths given to the
+//! scanner are walked even if they are symlinks.
+//!
+//! With a `Journal`, each detected file is appended to the journal file
+//! with its size, modification time and result, so that an interrupted scan
+//! can be resumed: files whose size and modification time still match the
+//! journal are reported from it (`FileReport::cached()`) without being read
+//! again.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::ChunkSizes;
+use crate::DetectionOutcome;
+use crate::Detector;
+
+/// The result for one file, as detected or as cached in the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    path: PathBuf,
+    encoding: Option<String>,
+    reason: Option<String>,
+    examined: u64,
+    error: Option<String>,
+    cached: bool,
+}
+
+impl FileReport {
+    fn from_outcome(path: PathBuf, outcome: &DetectionOutcome) -> Self {
+        FileReport {
+            path,
+            encoding: outcome.verdict().map(|verdict| verdict.name().to_string()),
+            reason: outcome.reason().map(|reason| reason.rule_id().to_string()),
+            examined: outcome.examined_len(),
+            error: None,
+            cached: false,
+        }
+    }
+
+    fn from_error(path: PathBuf, error: &str) -> Self {
+        FileReport {
+            path,
+            encoding: None,
+            reason: None,
+            examined: 0,
+            error: Some(error.to_string()),
+            cached: false,
+        }
+    }
+
+    /// The path of the file as reached from the paths given to the
+    /// `Scanner`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the detected encoding (see `Verdict::name()`), or `None`
+    /// if undecided or on error.
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    /// The id of the rule that decided (see `Reason::rule_id()`).
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// The number of bytes examined (see `DetectionOutcome::examined_len()`).
+    pub fn examined_len(&self) -> u64 {
+        self.examined
+    }
+
+    /// Why the file or directory couldn't be read, if it couldn't.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Whether the result came from the journal instead of reading the file.
+    pub fn cached(&self) -> bool {
+        self.cached
+    }
+}
+
+/// What identifies an unchanged file: its size and its modification time
+/// in nanoseconds since the Unix epoch (if the platform has one).
+type Key = (u64, Option<u128>);
+
+fn key_of(path: &Path) -> Option<Key> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos());
+    Some((metadata.len(), mtime))
+}
+
+/// Formats a journal line: size, mtime, encoding, reason, examined, error
+/// and path separated by tabs, with `-` for none.
+fn journal_line(key: Key, report: &FileReport) -> String {
+    let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        key.0,
+        key.1
+            .map_or_else(|| "-".to_string(), |mtime| mtime.to_string()),
+        field(&report.encoding),
+        field(&report.reason),
+        report.examined,
+        field(&report.error),
+        report.path.display()
+    )
+}
+
+/// Parses a journal line written by `journal_line()`.
+fn parse_journal_line(line: &str) -> Option<(Key, FileReport)> {
+    let mut fields = line.splitn(7, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let mtime = match fields.next()? {
+        "-" => None,
+        mtime => Some(mtime.parse().ok()?),
+    };
+    let mut field = || match fields.next() {
+        Some("-") => Some(None),
+        Some(value) => Some(Some(value.to_string())),
+        None => None,
+    };
+    let encoding = field()?;
+    let reason = field()?;
+    let examined = field()?.and_then(|examined| examined.parse().ok())?;
+    let error = field()?;
+    let path = fields.next()?;
+    let report = FileReport {
+        path: PathBuf::from(path),
+        encoding,
+        reason,
+        examined,
+        error,
+        cached: true,
+    };
+    Some(((size, mtime), report))
+}
+
+/// The results of an earlier scan, and the file that the results of this
+/// one are appended to.
+pub struct Journal {
+    done: HashMap<PathBuf, (Key, FileReport)>,
+    file: File,
+}
+
+impl Journal {
+    /// Loads the journal at `path`, if there is one, and opens it for
+    /// appending, creating it if needed. Later lines for the same path
+    /// supersede earlier ones, and a last line cut short by an
+    /// interruption is ignored.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut done = HashMap::new();
+        let mut cut_short = false;
+        match File::open(path) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                let mut line = String::new();
+                while reader.read_line(&mut line)? != 0 {
+                    // A line without its line break was cut short.
+                    match line.strip_suffix('\n') {
+                        Some(complete) => {
+                            if let Some((key, report)) = parse_journal_line(complete) {
+                                done.insert(report.path.clone(), (key, report));
+                            }
+                        }
+                        None => cut_short = true,
+                    }
+                    line.clear();
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if cut_short {
+            // Ends the cut-short line so that it doesn't swallow the next.
+            file.write_all(b"\n")?;
+        }
+        Ok(Journal { done, file })
+    }
+
+    /// The journaled result for `path` if its size and modification time
+    /// are still `key`.
+    fn lookup(&self, path: &Path, key: Key) -> Option<&FileReport> {
+        match self.done.get(path) {
+            Some((cached_key, report)) if *cached_key == key => Some(report),
+            _ => None,
+        }
+    }
+
+    /// Appends `report` and flushes, so that it survives an interruption.
+    /// Paths with line breaks aren't journaled.
+    fn record(&mut self, key: Key, report: &FileReport) -> io::Result<()> {
+        let line = journal_line(key, report);
+        if line.contains(['\n', '\r']) {
+            return Ok(());
+        }
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()
+    }
+}
+
+/// An iterator that detects the files under a set of paths.
+///
+/// Yields an error only if appending to the `Journal` fails; files and
+/// directories that can't be read are reported with `FileReport::error()`.
+pub struct Scanner {
+    pending: Vec<PathBuf>,
+    journal: Option<Journal>,
+    allow_2022: bool,
+    chunk_sizes: ChunkSizes,
+}
+
+impl Scanner {
+    /// Creates a scanner over `paths`, whose detectors are created with
+    /// `Detector::new(allow_2022)`.
+    pub fn new(paths: Vec<PathBuf>, allow_2022: bool) -> Self {
+        let mut pending = paths;
+        pending.reverse();
+        Scanner {
+            pending,
+            journal: None,
+            allow_2022,
+            chunk_sizes: ChunkSizes::DEFAULT,
+        }
+    }
+
+    /// Sets the journal to resume from and append to. Defaults to `None`.
+    /// Call before the first call to `next()`.
+    pub fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+    }
+
+    /// Sets how much to read from each file at a time. Defaults to
+    /// `ChunkSizes::DEFAULT`. Call before the first call to `next()`.
+    pub fn set_chunk_sizes(&mut self, chunk_sizes: ChunkSizes) {
+        self.chunk_sizes = chunk_sizes;
+    }
+
+    /// Runs a detector on `path` until it finishes.
+    fn detect(&self, path: PathBuf) -> FileReport {
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return FileReport::from_error(path, "could not open file"),
+        };
+        let mut detector = Detector::new(self.allow_2022);
+        let mut buffer = vec![0u8; self.chunk_sizes.first()];
+        loop {
+            let num_read = match file.read(&mut buffer[..]) {
+                Ok(num_read) => num_read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return FileReport::from_error(path, "could not read file"),
+            };
+            let outcome = detector.feed_with_outcome(&buffer[..num_read], num_read == 0);
+            if outcome.finished() {
+                return FileReport::from_outcome(path, &outcome);
+            }
+            let next = self.chunk_sizes.next(buffer.len(), false);
+            buffer.resize(next, 0);
+        }
+    }
+}
+
+/// Whether `path` is a symlink to a directory.
+fn is_symlinked_dir(path: &Path) -> bool {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type().is_symlink() && path.is_dir(),
+        Err(_) => false,
+    }
+}
+
+impl Iterator for Scanner {
+    type Item = io::Result<FileReport>;
+
+    fn next(&mut self) -> Option<io::Result<FileReport>> {
+        loop {
+            let path = self.pending.pop()?;
+            if path.is_dir() {
+                let mut children: Vec<PathBuf> = match std::fs::read_dir(&path) {
+                    Ok(entries) => entries
+                        .filter_map(|entry| entry.ok().map(|e| e.path()))
+                        .filter(|child| !is_symlinked_dir(child))
+                        .collect(),
+                    Err(_) => {
+                        return Some(Ok(FileReport::from_error(path, "could not read directory")))
+                    }
+                };
+                // Depth-first in name order, so that the order is stable
+                // across runs.
+                children.sort();
+                children.reverse();
+                self.pending.extend(children);
+                continue;
+            }
+            let key = key_of(&path);
+            if let (Some(journal), Some(key)) = (self.journal.as_ref(), key) {
+                if let Some(report) = journal.lookup(&path, key) {
+                    return Some(Ok(report.clone()));
+                }
+            }
+            let report = self.detect(path);
+            if let (Some(journal), Some(key)) = (self.journal.as_mut(), key) {
+                if let Err(e) = journal.record(key, &report) {
+                    return Some(Err(e));
+                }
+            }
+            return Some(Ok(report));
+        }
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("shift_or_euc-scan-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scan(paths: Vec<PathBuf>, journal: Option<&Path>) -> Vec<FileReport> {
+        let mut scanner = Scanner::new(paths, true);
+        scanner.set_journal(journal.map(|path| Journal::open(path).unwrap()));
+        scanner.map(Result::unwrap).collect()
+    }
+
+    fn names(reports: &[FileReport], root: &Path) -> Vec<String> {
+        reports
+            .iter()
+            .map(|report| {
+                report
+                    .path()
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    /// A tree of files with `a` and `c` deciding and `b/d` undecided.
+    fn make_tree(name: &str) -> PathBuf {
+        let dir = temp_dir(name);
+        std::fs::write(dir.join("a"), b"\x82\xA0").unwrap();
+        std::fs::create_dir(dir.join("b")).unwrap();
+        std::fs::write(dir.join("b").join("d"), b"ascii").unwrap();
+        std::fs::write(dir.join("c"), b"\xA4\xA2").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = make_tree("walk");
+        let reports = scan(vec![dir.clone()], None);
+        assert_eq!(names(&reports, &dir), ["a", "b/d", "c"]);
+        assert_eq!(reports[0].encoding(), Some("Shift_JIS"));
+        assert_eq!(reports[1].encoding(), None);
+        assert_eq!(reports[1].error(), None);
+        assert_eq!(reports[2].encoding(), Some("EUC-JP"));
+        assert!(reports.iter().all(|report| !report.cached()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_dir_skipped() {
+        let dir = make_tree("symlink");
+        // A cycle back to the root.
+        std::os::unix::fs::symlink(&dir, dir.join("b").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a"), dir.join("e")).unwrap();
+        let reports = scan(vec![dir.clone()], None);
+        assert_eq!(names(&reports, &dir), ["a", "b/d", "c", "e"]);
+        assert_eq!(reports[3].encoding(), Some("Shift_JIS"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resume_after_partial_journal() {
+        let dir = make_tree("resume");
+        let journal = temp_dir("resume-journal").join("journal");
+        let full = scan(vec![dir.clone()], Some(&journal));
+        let written = std::fs::read_to_string(&journal).unwrap();
+        assert_eq!(written.lines().count(), 3);
+        // Interrupted while writing the second line.
+        let first = written.lines().next().unwrap();
+        let second = written.lines().nth(1).unwrap();
+        std::fs::write(
+            &journal,
+            format!("{}\n{}", first, &second[..second.len() / 2]),
+        )
+        .unwrap();
+        let resumed = scan(vec![dir.clone()], Some(&journal));
+        assert_eq!(names(&resumed, &dir), ["a", "b/d", "c"]);
+        assert!(resumed[0].cached());
+        assert!(!resumed[1].cached());
+        assert!(!resumed[2].cached());
+        for (resumed, full) in resumed.iter().zip(full.iter()) {
+            assert_eq!(resumed.encoding(), full.encoding());
+            assert_eq!(resumed.reason(), full.reason());
+            assert_eq!(resumed.examined_len(), full.examined_len());
+        }
+        // The resumed scan completed the journal.
+        let again = scan(vec![dir.clone()], Some(&journal));
+        assert!(again.iter().all(FileReport::cached));
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(journal.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_changed_file_detected_again() {
+        let dir = make_tree("changed");
+        let journal = temp_dir("changed-journal").join("journal");
+        scan(vec![dir.clone()], Some(&journal));
+        std::fs::write(dir.join("a"), b"\xA4\xA2\xA4\xA2").unwrap();
+        let resumed = scan(vec![dir.clone()], Some(&journal));
+        assert!(!resumed[0].cached());
+        assert_eq!(resumed[0].encoding(), Some("EUC-JP"));
+        assert!(resumed[1].cached());
+        assert!(resumed[2].cached());
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(journal.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,568 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+
+use crate::state::StateReader;
+use crate::state::StateWriter;
+use crate::Certainty;
+use crate::Detector;
+use crate::Reason;
+use crate::Verdict;
+
+/// Thresholds on the margin between the Shift_JIS and EUC-JP scores of
+/// `Detector::set_scoring()`, e.g. as fitted by `calibrate_scoring()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ScoringThresholds {
+    /// Below this margin, the detector remains undecided. Treated as 1 if
+    /// zero, since a tie never decides.
+    pub min_margin: u64,
+    /// From this margin on, a scored verdict has `Certainty::Likely`
+    /// instead of `Certainty::Scored`.
+    pub likely_margin: u64,
+}
+
+impl ScoringThresholds {
+    /// The thresholds used unless set otherwise: any margin decides, and
+    /// since nothing has been calibrated, no verdict is likely.
+    pub const DEFAULT: ScoringThresholds = ScoringThresholds {
+        min_margin: 1,
+        likely_margin: u64::MAX,
+    };
+}
+
+impl Default for ScoringThresholds {
+    fn default() -> Self {
+        ScoringThresholds::DEFAULT
+    }
+}
+
+/// The class of a character, which determines its score.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Class {
+    Ascii,
+    /// Hiragana and katakana.
+    Kana,
+    Punctuation,
+    /// Alphanumerics, Greek, Cyrillic, box drawing and other symbols.
+    Symbol,
+    Level1Kanji,
+    Level2Kanji,
+    HalfWidthKatakana,
+    UserDefined,
+    /// Vendor extensions and, in EUC-JP, JIS X 0212.
+    Other,
+}
+
+impl Class {
+    fn weight(self) -> i64 {
+        match self {
+            Class::Kana | Class::Level1Kanji => 3,
+            Class::Punctuation => 2,
+            Class::Symbol | Class::Level2Kanji => 1,
+            Class::Ascii | Class::Other => 0,
+            // Rare outside text art.
+            Class::HalfWidthKatakana => -1,
+            // Rare outside in-house documents.
+            Class::UserDefined => -3,
+        }
+    }
+}
+
+/// The number of non-ASCII characters in each class when decoding the
+/// input as one encoding, as reported by `class_breakdown()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct CharacterClasses {
+    kana: u64,
+    punctuation: u64,
+    symbols: u64,
+    level_1_kanji: u64,
+    level_2_kanji: u64,
+    half_width_katakana: u64,
+    user_defined: u64,
+    other: u64,
+}
+
+impl CharacterClasses {
+    /// The counters in declaration order.
+    fn counts(&self) -> [u64; 8] {
+        [
+            self.kana,
+            self.punctuation,
+            self.symbols,
+            self.level_1_kanji,
+            self.level_2_kanji,
+            self.half_width_katakana,
+            self.user_defined,
+            self.other,
+        ]
+    }
+
+    fn count(&mut self, class: Class) {
+        let count = match class {
+            Class::Ascii => return,
+            Class::Kana => &mut self.kana,
+            Class::Punctuation => &mut self.punctuation,
+            Class::Symbol => &mut self.symbols,
+            Class::Level1Kanji => &mut self.level_1_kanji,
+            Class::Level2Kanji => &mut self.level_2_kanji,
+            Class::HalfWidthKatakana => &mut self.half_width_katakana,
+            Class::UserDefined => &mut self.user_defined,
+            Class::Other => &mut self.other,
+        };
+        *count += 1;
+    }
+
+    /// Hiragana and katakana (JIS X 0208 rows 4 and 5).
+    pub fn kana(&self) -> u64 {
+        self.kana
+    }
+
+    /// Punctuation (JIS X 0208 row 1).
+    pub fn punctuation(&self) -> u64 {
+        self.punctuation
+    }
+
+    /// Alphanumerics, Greek, Cyrillic, box drawing and other symbols (JIS X
+    /// 0208 rows 2, 3 and 6 to 8).
+    pub fn symbols(&self) -> u64 {
+        self.symbols
+    }
+
+    /// Common kanji (JIS X 0208 Level 1, rows 16 to 47).
+    pub fn level_1_kanji(&self) -> u64 {
+        self.level_1_kanji
+    }
+
+    /// Rare kanji (JIS X 0208 Level 2, rows 48 to 84).
+    pub fn level_2_kanji(&self) -> u64 {
+        self.level_2_kanji
+    }
+
+    /// Half-width katakana.
+    pub fn half_width_katakana(&self) -> u64 {
+        self.half_width_katakana
+    }
+
+    /// Characters in the Shift_JIS user-defined area (lead bytes 0xF0 to
+    /// 0xF9). Always zero for EUC-JP.
+    pub fn user_defined(&self) -> u64 {
+        self.user_defined
+    }
+
+    /// Vendor extensions and, for EUC-JP, JIS X 0212 characters.
+    pub fn other(&self) -> u64 {
+        self.other
+    }
+}
+
+/// What the non-ASCII characters of an input look like as Shift_JIS and as
+/// EUC-JP, e.g. for telling a person which encoding undecided input is
+/// likelier to be in. See `class_breakdown()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ClassBreakdown {
+    shift_jis: CharacterClasses,
+    euc_jp: CharacterClasses,
+}
+
+impl ClassBreakdown {
+    /// The classes when decoding as Shift_JIS.
+    pub fn shift_jis(&self) -> &CharacterClasses {
+        &self.shift_jis
+    }
+
+    /// The classes when decoding as EUC-JP.
+    pub fn euc_jp(&self) -> &CharacterClasses {
+        &self.euc_jp
+    }
+}
+
+/// The running score of one candidate encoding.
+struct Candidate {
+    /// The number of bytes in a character given its first byte.
+    len: fn(u8) -> usize,
+    /// The class of a complete character.
+    class: fn(&[u8]) -> Class,
+    held: [u8; 3],
+    held_len: usize,
+    score: i64,
+    classes: CharacterClasses,
+}
+
+impl Candidate {
+    fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.held[..self.held_len]);
+        writer.i64(self.score);
+        for &count in self.classes.counts().iter() {
+            writer.u64(count);
+        }
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        let held = reader.bytes()?;
+        if held.len() >= self.held.len() {
+            return None;
+        }
+        self.held[..held.len()].copy_from_slice(held);
+        self.held_len = held.len();
+        self.score = reader.i64()?;
+        self.classes = CharacterClasses {
+            kana: reader.u64()?,
+            punctuation: reader.u64()?,
+            symbols: reader.u64()?,
+            level_1_kanji: reader.u64()?,
+            level_2_kanji: reader.u64()?,
+            half_width_katakana: reader.u64()?,
+            user_defined: reader.u64()?,
+            other: reader.u64()?,
+        };
+        Some(())
+    }
+
+    fn new(len: fn(u8) -> usize, class: fn(&[u8]) -> Class) -> Self {
+        Candidate {
+            len,
+            class,
+            held: [0; 3],
+            held_len: 0,
+            score: 0,
+            classes: CharacterClasses::default(),
+        }
+    }
+
+    fn feed(&mut self, buffer: &[u8]) {
+        for &byte in buffer {
+            self.held[self.held_len] = byte;
+            self.held_len += 1;
+            if self.held_len == (self.len)(self.held[0]) {
+                let class = (self.class)(&self.held[..self.held_len]);
+                self.score += class.weight();
+                self.classes.count(class);
+                self.held_len = 0;
+            }
+        }
+    }
+}
+
+/// Scores how plausible the input is as Shift_JIS and as EUC-JP by the
+/// class of each character, for deciding input that is valid in both.
+///
+/// The classes are JIS X 0208 rows: kana, punctuation and the common
+/// (Level 1) kanji count most, the rest of JIS X 0208 counts a little,
+/// vendor extensions don't count and half-width katakana and user-defined
+/// characters count against the encoding. Every byte is scored, since
+/// input that gets this far has no malformed sequences to resynchronize
+/// after.
+pub(crate) struct Scorer {
+    shift_jis: Candidate,
+    euc_jp: Candidate,
+}
+
+impl Scorer {
+    pub(crate) fn new() -> Self {
+        Scorer {
+            shift_jis: Candidate::new(shift_jis_len, shift_jis_class),
+            euc_jp: Candidate::new(euc_jp_len, euc_jp_class),
+        }
+    }
+
+    /// Writes the scores and held bytes for `Detector::to_state()`.
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        self.shift_jis.write_state(writer);
+        self.euc_jp.write_state(writer);
+    }
+
+    /// Reads what `write_state()` wrote.
+    pub(crate) fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        self.shift_jis.read_state(reader)?;
+        self.euc_jp.read_state(reader)
+    }
+
+    /// Counts `penalty` against the score of `candidate`.
+    #[cfg(feature = "encoding_rs")]
+    pub(crate) fn penalize(&mut self, candidate: Verdict, penalty: u32) {
+        match candidate {
+            Verdict::ShiftJis => self.shift_jis.score -= i64::from(penalty),
+            _ => self.euc_jp.score -= i64::from(penalty),
+        }
+    }
+
+    pub(crate) fn feed(&mut self, buffer: &[u8]) {
+        self.shift_jis.feed(buffer);
+        self.euc_jp.feed(buffer);
+    }
+
+    /// The scores of Shift_JIS and EUC-JP so far.
+    pub(crate) fn scores(&self) -> (i64, i64) {
+        (self.shift_jis.score, self.euc_jp.score)
+    }
+
+    /// The character classes counted so far.
+    pub(crate) fn class_breakdown(&self) -> ClassBreakdown {
+        ClassBreakdown {
+            shift_jis: self.shift_jis.classes,
+            euc_jp: self.euc_jp.classes,
+        }
+    }
+
+    /// The encoding with the higher score and the certainty of that verdict
+    /// or `None` if the margin is below the minimum.
+    pub(crate) fn verdict(&self, thresholds: &ScoringThresholds) -> Option<(Verdict, Certainty)> {
+        let margin = self.shift_jis.score.abs_diff(self.euc_jp.score);
+        if margin < thresholds.min_margin.max(1) {
+            return None;
+        }
+        let verdict = if self.shift_jis.score > self.euc_jp.score {
+            Verdict::ShiftJis
+        } else {
+            Verdict::EucJp
+        };
+        let certainty = if margin >= thresholds.likely_margin {
+            Certainty::Likely
+        } else {
+            Certainty::Scored
+        };
+        Some((verdict, certainty))
+    }
+}
+
+/// The class of a JIS X 0208 character given its row.
+fn row_class(row: u8) -> Class {
+    match row {
+        1 => Class::Punctuation,
+        4 | 5 => Class::Kana,
+        2..=8 => Class::Symbol,
+        16..=47 => Class::Level1Kanji,
+        48..=84 => Class::Level2Kanji,
+        // NEC special characters and NEC-selected IBM extensions.
+        _ => Class::Other,
+    }
+}
+
+fn shift_jis_len(lead: u8) -> usize {
+    match lead {
+        0x81..=0x9F | 0xE0..=0xFC => 2,
+        _ => 1,
+    }
+}
+
+fn shift_jis_class(character: &[u8]) -> Class {
+    match *character {
+        [0x00..=0x7F] => Class::Ascii,
+        [0xA1..=0xDF] => Class::HalfWidthKatakana,
+        [0xF0..=0xF9, _] => Class::UserDefined,
+        [lead @ 0x81..=0xEF, trail] => {
+            let offset = if lead < 0xA0 {
+                lead - 0x81
+            } else {
+                lead - 0xC1
+            };
+            row_class(offset * 2 + 1 + u8::from(trail >= 0x9F))
+        }
+        // IBM extensions.
+        _ => Class::Other,
+    }
+}
+
+fn euc_jp_len(lead: u8) -> usize {
+    match lead {
+        0x8F => 3,
+        0x8E | 0xA1..=0xFE => 2,
+        _ => 1,
+    }
+}
+
+fn euc_jp_class(character: &[u8]) -> Class {
+    match *character {
+        [0x00..=0x7F] => Class::Ascii,
+        [0x8E, _] => Class::HalfWidthKatakana,
+        [lead @ 0xA1..=0xFE, _] => row_class(lead - 0xA0),
+        // JIS X 0212.
+        _ => Class::Other,
+    }
+}
+
+/// Counts the classes of the characters of `bytes`, taken as a complete
+/// stream, when decoding it as Shift_JIS and as EUC-JP.
+///
+/// This is meant for input that a `Detector` leaves undecided, which is
+/// valid in both encodings. For other input, the counts after the first
+/// malformed sequence are not meaningful. `Detector::class_breakdown()`
+/// reports the same for a stream.
+pub fn class_breakdown(bytes: &[u8]) -> ClassBreakdown {
+    let mut scorer = Scorer::new();
+    scorer.feed(bytes);
+    scorer.class_breakdown()
+}
+
+/// The result of `calibrate_scoring()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Calibration {
+    thresholds: ScoringThresholds,
+    scored: usize,
+    likely: usize,
+    likely_correct: usize,
+}
+
+impl Calibration {
+    /// The fitted thresholds, to pass to `Detector::set_scoring_thresholds()`.
+    pub fn thresholds(&self) -> ScoringThresholds {
+        self.thresholds
+    }
+
+    /// The number of samples that the scores decided.
+    pub fn scored(&self) -> usize {
+        self.scored
+    }
+
+    /// The number of samples that the fitted thresholds make likely.
+    pub fn likely(&self) -> usize {
+        self.likely
+    }
+
+    /// The number of likely samples whose verdict matched the label.
+    pub fn likely_correct(&self) -> usize {
+        self.likely_correct
+    }
+}
+
+/// Fits `ScoringThresholds` to a corpus of samples labeled with their
+/// actual encoding, so that `Certainty::Likely` means that at least
+/// `min_accuracy_percent` percent of the scored verdicts with that
+/// certainty were right on the corpus.
+///
+/// Each sample is run through a detector with scoring enabled as a
+/// complete stream. Samples that another rule decides or that remain
+/// undecided don't count. The fitted `likely_margin` is the smallest margin
+/// that reaches the accuracy, so that as many verdicts as possible are
+/// likely, or `u64::MAX` if no margin does. `min_margin` is left at 1.
+///
+/// Accuracy on the corpus only carries over to inputs that resemble it, so
+/// the corpus should be drawn from the inputs that the thresholds will be
+/// used on.
+pub fn calibrate_scoring<'a, I>(
+    samples: I,
+    allow_2022: bool,
+    min_accuracy_percent: u64,
+) -> Calibration
+where
+    I: IntoIterator<Item = (&'a [u8], Verdict)>,
+{
+    let mut margins: Vec<(u64, bool)> = Vec::new();
+    for (bytes, label) in samples {
+        let mut detector = Detector::new(allow_2022);
+        detector.set_scoring(true);
+        let verdict = detector.feed_verdict(bytes, true);
+        let outcome = detector.outcome();
+        if outcome.reason() != Some(Reason::Scored) {
+            continue;
+        }
+        if let Some((shift_jis, euc_jp)) = outcome.scores() {
+            margins.push((shift_jis.abs_diff(euc_jp), verdict == Some(label)));
+        }
+    }
+    // Going from the largest margin down, the verdicts at or above each
+    // margin are the ones that a threshold at that margin makes likely.
+    margins.sort_unstable_by_key(|&(margin, _)| core::cmp::Reverse(margin));
+    let mut thresholds = ScoringThresholds::DEFAULT;
+    let (mut likely, mut likely_correct) = (0, 0);
+    let (mut total, mut correct) = (0, 0);
+    let mut i = 0;
+    while i < margins.len() {
+        let margin = margins[i].0;
+        while i < margins.len() && margins[i].0 == margin {
+            total += 1;
+            correct += usize::from(margins[i].1);
+            i += 1;
+        }
+        if (correct as u64) * 100 >= (total as u64) * min_accuracy_percent {
+            thresholds.likely_margin = margin;
+            likely = total;
+            likely_correct = correct;
+        }
+    }
+    Calibration {
+        thresholds,
+        scored: margins.len(),
+        likely,
+        likely_correct,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scores() {
+        // "あ" in both encodings.
+        assert_eq!(shift_jis_class(b"\x82\xA0").weight(), 3);
+        assert_eq!(euc_jp_class(b"\xA4\xA2").weight(), 3);
+        // A Level 2 kanji in EUC-JP is a user-defined character in
+        // Shift_JIS.
+        let mut scorer = Scorer::new();
+        scorer.feed(b"a\xF0");
+        scorer.feed(b"\xA1");
+        assert_eq!(scorer.scores(), (Class::UserDefined.weight(), 1));
+        let thresholds = ScoringThresholds::DEFAULT;
+        assert_eq!(
+            scorer.verdict(&thresholds),
+            Some((Verdict::EucJp, Certainty::Scored))
+        );
+        let thresholds = ScoringThresholds {
+            min_margin: 1,
+            likely_margin: 4,
+        };
+        assert_eq!(
+            scorer.verdict(&thresholds),
+            Some((Verdict::EucJp, Certainty::Likely))
+        );
+        // Level 2 kanji in both.
+        let mut scorer = Scorer::new();
+        scorer.feed(b"\xE0\xA1");
+        assert_eq!(scorer.verdict(&thresholds), None);
+    }
+
+    #[test]
+    fn test_class_breakdown() {
+        // "あい" in EUC-JP is four half-width katakana characters in
+        // Shift_JIS, then a Level 2 kanji in both.
+        let breakdown = class_breakdown(b"a\xA4\xA2\xA4\xA4\xE0\xA1");
+        assert_eq!(breakdown.euc_jp().kana(), 2);
+        assert_eq!(breakdown.euc_jp().level_2_kanji(), 1);
+        assert_eq!(breakdown.shift_jis().half_width_katakana(), 4);
+        assert_eq!(breakdown.shift_jis().level_2_kanji(), 1);
+        assert_eq!(breakdown.shift_jis().kana(), 0);
+    }
+
+    #[test]
+    fn test_calibrate_scoring() {
+        // Margins of 4 (right), 8 (right) and 4 (wrong) and a sample that a
+        // malformed sequence decides.
+        let samples: [(&[u8], Verdict); 4] = [
+            (b"\xF0\xA1", Verdict::EucJp),
+            (b"\xF0\xA1\xF0\xA1", Verdict::EucJp),
+            (b"\xF1\xA1", Verdict::ShiftJis),
+            (b"\x82\xA0", Verdict::ShiftJis),
+        ];
+        let calibration = calibrate_scoring(samples.iter().copied(), true, 100);
+        assert_eq!(calibration.scored(), 3);
+        assert_eq!(calibration.thresholds().likely_margin, 8);
+        assert_eq!(calibration.likely(), 1);
+        let calibration = calibrate_scoring(samples.iter().copied(), true, 60);
+        assert_eq!(calibration.thresholds().likely_margin, 4);
+        assert_eq!(calibration.likely(), 3);
+        assert_eq!(calibration.likely_correct(), 2);
+        let calibration = calibrate_scoring(samples[2..].iter().copied(), true, 60);
+        assert_eq!(calibration.thresholds(), ScoringThresholds::DEFAULT);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use encoding_rs::Encoding;
+
+use crate::Certainty;
+use crate::Detector;
+
+/// The default for the maximum number of out-of-order bytes that a
+/// `SegmentDetector` holds before giving up on the missing data.
+const DEFAULT_MAX_BUFFERED: usize = 64 * 1024;
+
+/// A guess made by a `SegmentDetector`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SegmentVerdict {
+    encoding: &'static Encoding,
+    certainty: Certainty,
+    after_gap: bool,
+}
+
+impl SegmentVerdict {
+    /// The guessed encoding.
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// How strong the evidence is: the certainty of the `Detector` for
+    /// gapless data and `Certainty::Tentative` after a gap (see
+    /// `after_gap()`).
+    pub fn certainty(&self) -> Certainty {
+        self.certainty
+    }
+
+    /// `true` if the deciding evidence was seen after a gap in the stream.
+    ///
+    /// Such a guess is weaker than one made from gapless data: the detector
+    /// resynchronizes after each gap, but it cannot know what state the lost
+    /// data would have left the stream in (e.g. an ISO-2022-JP designation).
+    pub fn after_gap(&self) -> bool {
+        self.after_gap
+    }
+}
+
+/// A detector for payloads of a reassembled byte stream, such as TCP
+/// segments, that may arrive out of order, overlap each other or be missing.
+///
+/// Each payload is identified by the stream offset of its first byte. (With
+/// TCP, this is the sequence number relative to the initial sequence number,
+/// unwrapped by the caller into `u64`.) Payloads are reordered and
+/// retransmitted bytes are dropped, keeping the first copy received. Data
+/// that ends up contiguous is fed to a `Detector`.
+///
+/// When data is missing (`skip_gap()`, `finish()` or too many out-of-order
+/// bytes), the character that straddled the gap is discarded: the detector
+/// is restarted in mid-stream mode, so it only collects evidence again after
+/// a byte that has to be followed by a character boundary. A verdict based on
+/// evidence from after a gap is marked as such and down-weighted to
+/// `Certainty::Tentative`.
+pub struct SegmentDetector {
+    detector: Detector,
+    next_offset: u64,
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    max_buffered: usize,
+    after_gap: bool,
+    verdict: Option<SegmentVerdict>,
+    finished: bool,
+}
+
+impl SegmentDetector {
+    /// Instantiates the detector for a stream whose first byte has the
+    /// offset `initial_offset`. `allow_2022` has the same meaning as for
+    /// `Detector::new()`. At most 64 KiB of out-of-order data is held.
+    pub fn new(allow_2022: bool, initial_offset: u64) -> Self {
+        SegmentDetector::with_max_buffered(allow_2022, initial_offset, DEFAULT_MAX_BUFFERED)
+    }
+
+    /// Like `new()` but with a caller-chosen cap on the number of
+    /// out-of-order bytes held. When the cap is exceeded, the data before the
+    /// first held payload is considered lost.
+    pub fn with_max_buffered(allow_2022: bool, initial_offset: u64, max_buffered: usize) -> Self {
+        SegmentDetector {
+            detector: Detector::new(allow_2022),
+            next_offset: initial_offset,
+            out_of_order: BTreeMap::new(),
+            buffered: 0,
+            max_buffered,
+            after_gap: false,
+            verdict: None,
+            finished: false,
+        }
+    }
+
+    /// Adds a payload whose first byte is at stream offset `offset`.
+    ///
+    /// Returns the verdict once one has been reached. Returns `None` while
+    /// undecided. After a verdict has been returned, further payloads are
+    /// ignored and the same verdict is returned.
+    pub fn push(&mut self, offset: u64, payload: &[u8]) -> Option<SegmentVerdict> {
+        if self.verdict.is_some() || self.finished {
+            return self.verdict;
+        }
+        let end = offset + payload.len() as u64;
+        if end <= self.next_offset {
+            // A retransmission of data already seen.
+            return None;
+        }
+        if offset > self.next_offset {
+            self.hold(offset, payload);
+            if self.buffered > self.max_buffered {
+                return self.skip_gap();
+            }
+            return None;
+        }
+        let skip = (self.next_offset - offset) as usize;
+        if self.feed_contiguous(&payload[skip..]) {
+            return self.verdict;
+        }
+        self.drain()
+    }
+
+    /// Declares the data between the already-processed part of the stream and
+    /// the first out-of-order payload lost and continues from that payload.
+    pub fn skip_gap(&mut self) -> Option<SegmentVerdict> {
+        if self.verdict.is_some() || self.finished {
+            return self.verdict;
+        }
+        let first = match self.out_of_order.keys().next() {
+            Some(&first) => first,
+            None => return None,
+        };
+        self.restart_after_gap();
+        self.next_offset = first;
+        self.drain()
+    }
+
+    /// Signals the end of the stream, considering any still-missing data
+    /// lost. Returns the verdict or `None` if the detector remained
+    /// undecided, in which case falling back to Shift_JIS is the best guess
+    /// for Web purposes.
+    pub fn finish(&mut self) -> Option<SegmentVerdict> {
+        while self.verdict.is_none() && !self.out_of_order.is_empty() {
+            self.skip_gap();
+        }
+        if self.verdict.is_none() && !self.finished {
+            self.finished = true;
+            if let Some(encoding) = self.detector.feed(b"", true) {
+                self.decide(encoding);
+            }
+        }
+        self.verdict
+    }
+
+    /// The verdict if one has been reached.
+    pub fn verdict(&self) -> Option<SegmentVerdict> {
+        self.verdict
+    }
+
+    /// The offset of the next byte the detector expects.
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    fn hold(&mut self, offset: u64, payload: &[u8]) {
+        let entry = self.out_of_order.entry(offset).or_default();
+        if payload.len() > entry.len() {
+            self.buffered += payload.len() - entry.len();
+            entry.clear();
+            entry.extend_from_slice(payload);
+        }
+    }
+
+    fn drain(&mut self) -> Option<SegmentVerdict> {
+        while let Some((&offset, _)) = self.out_of_order.iter().next() {
+            if offset > self.next_offset {
+                break;
+            }
+            let payload = self.out_of_order.remove(&offset).unwrap();
+            self.buffered -= payload.len();
+            let end = offset + payload.len() as u64;
+            if end <= self.next_offset {
+                continue;
+            }
+            let skip = (self.next_offset - offset) as usize;
+            if self.feed_contiguous(&payload[skip..]) {
+                break;
+            }
+        }
+        self.verdict
+    }
+
+    /// Returns `true` if a verdict was reached.
+    fn feed_contiguous(&mut self, data: &[u8]) -> bool {
+        self.next_offset += data.len() as u64;
+        if let Some(encoding) = self.detector.feed(data, false) {
+            self.decide(encoding);
+            return true;
+        }
+        false
+    }
+
+    fn decide(&mut self, encoding: &'static Encoding) {
+        let certainty = if self.after_gap {
+            Certainty::Tentative
+        } else {
+            self.detector.certainty().unwrap_or(Certainty::Certain)
+        };
+        self.verdict = Some(SegmentVerdict {
+            encoding,
+            certainty,
+            after_gap: self.after_gap,
+        });
+        self.out_of_order.clear();
+        self.buffered = 0;
+    }
+
+    fn restart_after_gap(&mut self) {
+        self.after_gap = true;
+        self.detector = Detector::new_mid_stream(self.detector.iso_2022_jp_possible());
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_reordered_and_overlapping() {
+        // ASCII and "あ" in EUC-JP split into reordered, overlapping
+        // segments.
+        let mut detector = SegmentDetector::new(true, 1000);
+        assert_eq!(detector.push(1003, b"\xA4\xA2"), None);
+        assert_eq!(detector.push(1000, b"ab"), None);
+        assert_eq!(detector.push(1000, b"a"), None);
+        assert_eq!(detector.next_offset(), 1002);
+        let verdict = detector.push(1001, b"bc").unwrap();
+        assert_eq!(verdict.encoding(), EUC_JP);
+        assert_eq!(verdict.certainty(), Certainty::Certain);
+        assert!(!verdict.after_gap());
+    }
+
+    #[test]
+    fn test_gap_discards_straddling_character() {
+        // A lead byte before the gap and the tail of a character after it.
+        // Joined together, they'd be a valid EUC-JP character but an
+        // invalid Shift_JIS sequence. Then "あ" in Shift_JIS.
+        let mut detector = SegmentDetector::new(true, 0);
+        assert_eq!(detector.push(0, b"a\xE0"), None);
+        assert_eq!(detector.push(10, b"\xFD \x82\xA0"), None);
+        let verdict = detector.finish().unwrap();
+        assert_eq!(verdict.encoding(), SHIFT_JIS);
+        assert_eq!(verdict.certainty(), Certainty::Tentative);
+        assert!(verdict.after_gap());
+    }
+
+    #[test]
+    fn test_buffer_cap_forces_gap() {
+        let mut detector = SegmentDetector::with_max_buffered(false, 0, 4);
+        assert_eq!(detector.push(10, b"ab"), None);
+        assert_eq!(detector.push(12, b"cd"), None);
+        assert_eq!(detector.push(14, b" \xA4\xA2").unwrap().encoding(), EUC_JP);
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A framework-agnostic detection endpoint.
+//!
+//! `Handler` maps a request body to a JSON response. It doesn't know
+//! anything about HTTP servers, so it can be mounted in any of them by
+//! passing the body bytes in and copying `status()`, `content_type()` and
+//! `body()` to the framework's response type.
+//!
+//! # Stability
+//!
+//! The status codes and the JSON shapes documented on `Handler::handle()`
+//! are part of the crate's semver contract: fields may be added in minor
+//! releases but are never removed, renamed or retyped.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::detect_with_deadline;
+use crate::policy::push_json_string;
+
+/// The response produced by `Handler::handle()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    /// The HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The value for the Content-Type header.
+    pub fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    /// The JSON response body.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+/// A detection request handler with a fixed configuration.
+#[derive(Debug, Clone)]
+pub struct Handler {
+    allow_2022: bool,
+    max_body_len: usize,
+    timeout: Duration,
+}
+
+impl Handler {
+    /// Instantiates a handler that allows ISO-2022-JP, accepts bodies of up
+    /// to 1 MiB and spends at most about 100 ms per request.
+    pub fn new() -> Self {
+        Handler {
+            allow_2022: true,
+            max_body_len: 1024 * 1024,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets whether ISO-2022-JP is a possible guess.
+    pub fn set_allow_2022(&mut self, allow_2022: bool) {
+        self.allow_2022 = allow_2022;
+    }
+
+    /// Sets the maximum accepted body length in bytes.
+    pub fn set_max_body_len(&mut self, max_body_len: usize) {
+        self.max_body_len = max_body_len;
+    }
+
+    /// Sets how long detection may take per request. See
+    /// `detect_with_deadline()` for the granularity.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Detects the encoding of `body`.
+    ///
+    /// A body longer than the maximum gets status 413 and
+    /// `{"error":"body too large","max_body_len":N}`. Otherwise, the status
+    /// is 200 and the body is
+    /// `{"encoding":E,"fallback":"Shift_JIS","examined":N,"complete":B}`
+    /// where `E` is `"Shift_JIS"`, `"EUC-JP"`, `"ISO-2022-JP"` or `null`
+    /// if undecided, `N` is the number of bytes examined and `B` is
+    /// `false` if the timeout cut detection short, in which case `E` is the
+    /// best guess from the bytes examined (see `DeadlineResult::encoding()`).
+    pub fn handle(&self, body: &[u8]) -> Response {
+        if body.len() > self.max_body_len {
+            return Response {
+                status: 413,
+                body: format!(
+                    "{{\"error\":\"body too large\",\"max_body_len\":{}}}",
+                    self.max_body_len
+                ),
+            };
+        }
+        let result = detect_with_deadline(body, self.allow_2022, Instant::now() + self.timeout);
+        let mut json = String::from("{\"encoding\":");
+        match result.encoding() {
+            Some(encoding) => push_json_string(&mut json, encoding.name()),
+            None => json.push_str("null"),
+        }
+        json.push_str(&format!(
+            ",\"fallback\":\"Shift_JIS\",\"examined\":{},\"complete\":{}}}",
+            result.examined(),
+            result.complete()
+        ));
+        Response {
+            status: 200,
+            body: json,
+        }
+    }
+}
+
+impl Default for Handler {
+    fn default() -> Self {
+        Handler::new()
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let mut handler = Handler::new();
+        let response = handler.handle(b"a\xA4\xA2");
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.body(),
+            "{\"encoding\":\"EUC-JP\",\"fallback\":\"Shift_JIS\",\"examined\":3,\"complete\":true}"
+        );
+        assert_eq!(
+            handler.handle(b"abc").body(),
+            "{\"encoding\":null,\"fallback\":\"Shift_JIS\",\"examined\":3,\"complete\":true}"
+        );
+        handler.set_max_body_len(2);
+        let response = handler.handle(b"abc");
+        assert_eq!(response.status(), 413);
+        assert_eq!(
+            response.body(),
+            "{\"error\":\"body too large\",\"max_body_len\":2}"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+
+use crate::Detector;
+use crate::Verdict;
+
+/// The default for the number of bytes after which an undecided window of
+/// a `SessionDetector` is restarted.
+const DEFAULT_WINDOW_LEN: usize = 4096;
+
+/// A change of the encoding of a session reported by `SessionDetector`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Switch {
+    from: Option<Verdict>,
+    to: Verdict,
+    offset: u64,
+}
+
+impl Switch {
+    /// The previous encoding of the session or `None` for the first
+    /// verdict.
+    pub fn from(&self) -> Option<Verdict> {
+        self.from
+    }
+
+    /// The new encoding of the session.
+    pub fn to(&self) -> Verdict {
+        self.to
+    }
+
+    /// The stream offset just after the byte that decided the new encoding.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// A detector for long-lived sessions, such as telnet connections to BBS
+/// hosts, whose peer may switch between encodings over time.
+///
+/// The session is examined in windows. Each window runs a fresh `Detector`
+/// with ISO-2022-JP allowed. When it decides, the verdict becomes the
+/// encoding of the session (reporting a `Switch` if it differs from the
+/// previous one) and the next window starts right after the deciding byte.
+/// A window that stays undecided for the window length is restarted, so
+/// that evidence from long ago (such as non-ASCII that ruled out
+/// ISO-2022-JP) does not prevent later switches from being noticed. Windows
+/// other than the first start in mid-stream mode (see
+/// `Detector::new_mid_stream()`), since they may start in the middle of a
+/// character.
+pub struct SessionDetector {
+    window: Detector,
+    window_start: u64,
+    window_len: usize,
+    position: u64,
+    current: Option<Verdict>,
+}
+
+impl SessionDetector {
+    /// Instantiates the detector with the default window length of 4096
+    /// bytes.
+    pub fn new() -> Self {
+        SessionDetector::with_window_len(DEFAULT_WINDOW_LEN)
+    }
+
+    /// Instantiates the detector restarting undecided windows after
+    /// `window_len` bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `window_len` is zero.
+    pub fn with_window_len(window_len: usize) -> Self {
+        assert!(window_len > 0, "The window length must not be zero.");
+        SessionDetector {
+            window: Detector::new(true),
+            window_start: 0,
+            window_len,
+            position: 0,
+            current: None,
+        }
+    }
+
+    /// The current encoding of the session or `None` if no window has
+    /// decided yet.
+    pub fn current(&self) -> Option<Verdict> {
+        self.current
+    }
+
+    /// Examines the next bytes of the session and returns the switches they
+    /// caused, in stream order.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Switch> {
+        let mut switches = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let room = self.window_len - (self.position - self.window_start) as usize;
+            let chunk = &rest[..rest.len().min(room)];
+            if let Some(verdict) = self.window.feed_verdict(chunk, false) {
+                let end = self.window_start + self.window.examined_len();
+                rest = &rest[(end - self.position) as usize..];
+                self.position = end;
+                switches.extend(self.decided(verdict));
+                self.restart();
+                continue;
+            }
+            rest = &rest[chunk.len()..];
+            self.position += chunk.len() as u64;
+            if self.position - self.window_start == self.window_len as u64 {
+                self.restart();
+            }
+        }
+        switches
+    }
+
+    /// Signals the end of the session, which lets the last window decide
+    /// based on an incomplete character at the end.
+    pub fn finish(mut self) -> Option<Switch> {
+        let verdict = self.window.feed_verdict(b"", true)?;
+        self.decided(verdict)
+    }
+
+    /// Makes `verdict` the current encoding, returning a switch if it
+    /// changed.
+    fn decided(&mut self, verdict: Verdict) -> Option<Switch> {
+        if self.current == Some(verdict) {
+            return None;
+        }
+        let switch = Switch {
+            from: self.current,
+            to: verdict,
+            offset: self.position,
+        };
+        self.current = Some(verdict);
+        Some(switch)
+    }
+
+    /// Starts a new window at the current position.
+    fn restart(&mut self) {
+        self.window = Detector::new_mid_stream(true);
+        self.window_start = self.position;
+    }
+}
+
+impl Default for SessionDetector {
+    fn default() -> Self {
+        SessionDetector::new()
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switches() {
+        let mut session = SessionDetector::with_window_len(4);
+        // "あ" in Shift_JIS, then in ISO-2022-JP after the window restarts.
+        let switches = session.push(b"\r\n\x82\xA0 \x82\xA0");
+        assert_eq!(
+            switches,
+            vec![Switch {
+                from: None,
+                to: Verdict::ShiftJis,
+                offset: 3,
+            }]
+        );
+        assert!(session.push(b"\r\n").is_empty());
+        let switches = session.push(b"\r\n\x1B$B\x24\x22\x1B(B\r\n");
+        assert_eq!(switches.len(), 1);
+        assert_eq!(switches[0].from(), Some(Verdict::ShiftJis));
+        assert_eq!(switches[0].to(), Verdict::Iso2022Jp);
+        assert_eq!(switches[0].offset(), 14);
+        assert_eq!(session.current(), Some(Verdict::Iso2022Jp));
+        // Back to Shift_JIS.
+        let switches = session.push(b"\x82\xA0");
+        assert_eq!(switches[0].to(), Verdict::ShiftJis);
+        assert_eq!(session.finish(), None);
+    }
+
+    #[test]
+    fn test_undecided_window_restarts() {
+        // A kanji that is valid in both Shift_JIS and EUC-JP rules out
+        // ISO-2022-JP until the window restarts.
+        let mut session = SessionDetector::with_window_len(4);
+        assert!(session.push(b"\xE0\xE0\r\n").is_empty());
+        let switches = session.push(b"\x1B$B");
+        assert_eq!(switches[0].to(), Verdict::Iso2022Jp);
+        assert_eq!(switches[0].offset(), 7);
+    }
+}
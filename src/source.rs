@@ -0,0 +1,180 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding_rs::Encoding;
+
+use crate::Detector;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SourceState {
+    Code,
+    /// After a `/` in code.
+    Slash,
+    /// In a `//` or `#` comment.
+    LineComment,
+    /// In a `/* */` comment.
+    BlockComment,
+    /// After a `*` in a `/* */` comment.
+    BlockCommentStar,
+    /// In a literal delimited by the given quote.
+    Quoted(u8),
+    /// After a backslash in a literal delimited by the given quote.
+    QuotedEscape(u8),
+}
+
+impl SourceState {
+    /// Whether bytes in this state are evidence.
+    fn is_evidence(self) -> bool {
+        self != SourceState::Code && self != SourceState::Slash
+    }
+}
+
+/// A detector for the source code of common programming languages in which
+/// only comments and string literals count as evidence, since the rest is
+/// ASCII noise and comments are where legacy codebases have their
+/// Shift_JIS.
+///
+/// The lexing is heuristic and does not depend on the language: `//` and
+/// `#` start comments that end at the line break, `/* */` delimits
+/// comments, and `"`, `'` and `` ` `` delimit literals that end at the
+/// matching quote or at the line break, with backslash escapes. The bytes
+/// ending a comment or literal are evidence, too, so that a character cut
+/// off by them counts as malformed, as it would for a compiler.
+///
+/// Like a compiler that doesn't know the encoding, the lexer takes a
+/// Shift_JIS trail byte 0x5C for a backslash, so a literal ending with such
+/// a character may be taken to continue to the line break.
+pub struct SourceDetector {
+    detector: Detector,
+    state: SourceState,
+}
+
+impl SourceDetector {
+    /// Instantiates the detector. `allow_2022` has the same meaning as for
+    /// `Detector::new()`.
+    pub fn new(allow_2022: bool) -> Self {
+        SourceDetector {
+            detector: Detector::new(allow_2022),
+            state: SourceState::Code,
+        }
+    }
+
+    /// Feeds bytes to the detector. Comments and literals may be split
+    /// across calls. The return value and the rules about calling again are
+    /// the same as for `Detector::feed()`.
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        let mut evidence_start = None;
+        for (i, &byte) in buffer.iter().enumerate() {
+            match (self.state.is_evidence(), evidence_start) {
+                (true, None) => evidence_start = Some(i),
+                (false, Some(start)) => {
+                    evidence_start = None;
+                    if let Some(encoding) = self.detector.feed(&buffer[start..i], false) {
+                        return Some(encoding);
+                    }
+                }
+                _ => {}
+            }
+            self.state = match self.state {
+                SourceState::Code => code(byte),
+                SourceState::Slash => match byte {
+                    b'/' => SourceState::LineComment,
+                    b'*' => SourceState::BlockComment,
+                    _ => code(byte),
+                },
+                SourceState::LineComment => match byte {
+                    b'\n' => SourceState::Code,
+                    _ => SourceState::LineComment,
+                },
+                SourceState::BlockComment => match byte {
+                    b'*' => SourceState::BlockCommentStar,
+                    _ => SourceState::BlockComment,
+                },
+                SourceState::BlockCommentStar => match byte {
+                    b'/' => SourceState::Code,
+                    b'*' => SourceState::BlockCommentStar,
+                    _ => SourceState::BlockComment,
+                },
+                SourceState::Quoted(quote) => match byte {
+                    b'\\' => SourceState::QuotedEscape(quote),
+                    b'\n' => SourceState::Code,
+                    _ if byte == quote => SourceState::Code,
+                    _ => SourceState::Quoted(quote),
+                },
+                SourceState::QuotedEscape(quote) => SourceState::Quoted(quote),
+            };
+        }
+        if let Some(start) = evidence_start {
+            if let Some(encoding) = self.detector.feed(&buffer[start..], false) {
+                return Some(encoding);
+            }
+        }
+        if last {
+            return self.detector.feed(b"", true);
+        }
+        None
+    }
+}
+
+/// The state after `byte` in code.
+fn code(byte: u8) -> SourceState {
+    match byte {
+        b'/' => SourceState::Slash,
+        b'#' => SourceState::LineComment,
+        b'"' | b'\'' | b'`' => SourceState::Quoted(byte),
+        _ => SourceState::Code,
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::EUC_JP;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_comments_and_literals_only() {
+        // Code that looks like "あ" in EUC-JP and a block comment, a string
+        // literal with an escaped quote and a line comment with "あ" in
+        // Shift_JIS.
+        let inputs: [&[u8]; 3] = [
+            b"x = \xA4\xA2 / 2; /* * \x82\xA0 */\n",
+            b"t = \xA4\xA2; s = \"\\\"\x82\xA0\";\n",
+            b"\xA4\xA2 = 1 # \x82\xA0\n",
+        ];
+        for input in inputs.iter() {
+            assert_eq!(Detector::new(true).feed(input, true), Some(EUC_JP));
+            for split in 0..=input.len() {
+                let mut detector = SourceDetector::new(true);
+                let verdict = match detector.feed(&input[..split], false) {
+                    Some(encoding) => Some(encoding),
+                    None => detector.feed(&input[split..], true),
+                };
+                assert_eq!(verdict, Some(SHIFT_JIS));
+            }
+        }
+    }
+
+    #[test]
+    fn test_delimiter_after_lead_byte() {
+        // A lead byte cut off by the closing quote is malformed instead of
+        // forming a kanji with the content of the next literal.
+        let mut detector = SourceDetector::new(true);
+        assert_eq!(
+            detector.feed(b"s = '\xE0' + '\xA1';\n", true),
+            Some(SHIFT_JIS)
+        );
+        // Code is not evidence.
+        let mut detector = SourceDetector::new(true);
+        assert_eq!(detector.feed(b"s = \xE0;\n", true), None);
+    }
+}
@@ -0,0 +1,489 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::raw::Iso2022JpCertainty;
+use crate::utf8::Utf8Candidate;
+#[cfg(feature = "encoding_rs")]
+use crate::Backend;
+use crate::Certainty;
+use crate::Conformance;
+use crate::Detector;
+use crate::DetectorConfig;
+use crate::EdgeByteTreatment;
+use crate::Reason;
+#[cfg(feature = "scoring")]
+use crate::ScoringThresholds;
+use crate::Suspicious;
+use crate::Verdict;
+
+/// The first bytes of a serialized `DetectorState`: a tag and the version
+/// of the format.
+const MAGIC: [u8; 4] = *b"SoE\x01";
+
+/// A snapshot of everything a `Detector` knows about the stream so far,
+/// from `Detector::to_state()`, for resuming detection in another process
+/// with `Detector::from_state()`, e.g. when a huge upload is processed in
+/// resumable chunks across worker restarts.
+///
+/// The state includes the options, the bytes of a character that the last
+/// buffer ended in the middle of and all the counters, so the resumed
+/// detector decides exactly as the original one would have on the rest of
+/// the stream. It is a few hundred bytes at most.
+///
+/// The state is stored as bytes in a format of this crate that is
+/// versioned: a state from another version of the format is rejected
+/// rather than misread.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DetectorState {
+    bytes: Vec<u8>,
+}
+
+impl DetectorState {
+    /// The serialized state, e.g. for storing with a checkpoint.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Wraps bytes that `as_bytes()` returned. They are checked by
+    /// `Detector::from_state()`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        DetectorState {
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+/// Appends the fields of a state in order.
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[cfg(feature = "scoring")]
+    pub(crate) fn i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    /// Writes `value` as its index in `all`.
+    pub(crate) fn variant<T: PartialEq>(&mut self, all: &[T], value: &T) {
+        let index = all.iter().position(|candidate| candidate == value);
+        self.u8(index.unwrap() as u8);
+    }
+
+    /// Writes whether `value` is `Some` and then its content with `write`.
+    pub(crate) fn option<T, F>(&mut self, value: Option<T>, write: F)
+    where
+        F: FnOnce(&mut StateWriter, T),
+    {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+}
+
+/// Reads the fields of a state in the order `StateWriter` wrote them.
+/// Every method returns `None` if the bytes end or don't make sense.
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        let (&first, rest) = self.bytes.split_first()?;
+        self.bytes = rest;
+        Some(first)
+    }
+
+    pub(crate) fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn array(&mut self) -> Option<[u8; 8]> {
+        if self.bytes.len() < 8 {
+            return None;
+        }
+        let (first, rest) = self.bytes.split_at(8);
+        self.bytes = rest;
+        let mut array = [0u8; 8];
+        array.copy_from_slice(first);
+        Some(array)
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    #[cfg(feature = "scoring")]
+    pub(crate) fn i64(&mut self) -> Option<i64> {
+        self.array().map(i64::from_le_bytes)
+    }
+
+    pub(crate) fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.usize()?;
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (first, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(first)
+    }
+
+    pub(crate) fn variant<T: Copy>(&mut self, all: &[T]) -> Option<T> {
+        all.get(usize::from(self.u8()?)).copied()
+    }
+
+    pub(crate) fn option<T, F>(&mut self, read: F) -> Option<Option<T>>
+    where
+        F: FnOnce(&mut StateReader<'a>) -> Option<T>,
+    {
+        if self.bool()? {
+            read(self).map(Some)
+        } else {
+            Some(None)
+        }
+    }
+}
+
+pub(crate) const VERDICTS: [Verdict; 4] = [
+    Verdict::ShiftJis,
+    Verdict::EucJp,
+    Verdict::Iso2022Jp,
+    Verdict::Utf8,
+];
+
+pub(crate) const CERTAINTIES: [Certainty; 5] = [
+    Certainty::Tentative,
+    Certainty::Certain,
+    Certainty::Scored,
+    Certainty::Likely,
+    Certainty::Guessed,
+];
+
+pub(crate) const REASONS: [Reason; 18] = [
+    Reason::Iso2022JpEscape,
+    Reason::Iso2022JpCharacter,
+    Reason::Iso2022JpEndOfStream,
+    Reason::EucJpMalformed,
+    Reason::EucJpHalfWidthKatakana,
+    Reason::EucJpRule,
+    Reason::EucJpTruncated,
+    Reason::JisX0212,
+    Reason::ShiftJisMalformed,
+    Reason::ShiftJisHalfWidthKatakana,
+    Reason::ShiftJisRule,
+    Reason::ShiftJisTruncated,
+    Reason::Scored,
+    Reason::Utf8,
+    Reason::Blank,
+    Reason::Iso2022JpStructure,
+    Reason::UndecidedDefault,
+    Reason::ByteBudget,
+];
+
+const SUSPICIONS: [Suspicious; 2] = [Suspicious::EscapeFlood, Suspicious::Ambiguity];
+
+const CONFORMANCES: [Conformance; 2] = [Conformance::StrictWhatwg, Conformance::Pragmatic];
+
+const TREATMENTS: [EdgeByteTreatment; 3] = [
+    EdgeByteTreatment::Evidence,
+    EdgeByteTreatment::Ignore,
+    EdgeByteTreatment::Error,
+];
+
+#[cfg(feature = "encoding_rs")]
+const BACKENDS: [Backend; 2] = [Backend::Decoder, Backend::Validator];
+
+fn write_config(writer: &mut StateWriter, config: &DetectorConfig) {
+    writer.bool(config.allow_2022);
+    writer.bool(config.allow_utf8);
+    writer.bool(config.mid_stream);
+    writer.variant(&CONFORMANCES, &config.conformance);
+    writer.bool(config.require_certain_iso_2022_jp);
+    writer.bool(config.strict_iso_2022_jp);
+    writer.bool(config.skip_ansi_sequences);
+    writer.bool(config.jis_x_0212_decisive);
+    writer.bool(config.text_art);
+    writer.bool(config.reject_half_width_katakana);
+    writer.variant(&TREATMENTS, &config.del_treatment);
+    writer.variant(&TREATMENTS, &config.c1_treatment);
+    writer.bool(config.hashing);
+    #[cfg(feature = "scoring")]
+    {
+        writer.bool(config.scoring);
+        let thresholds = &config.scoring_thresholds;
+        writer.u64(thresholds.min_margin);
+        writer.u64(thresholds.likely_margin);
+    }
+    writer.variant(&VERDICTS, &config.fallback);
+    writer.option(config.blank_verdict.as_ref(), |writer, verdict| {
+        writer.variant(&VERDICTS, verdict)
+    });
+    writer.option(config.undecided_default.as_ref(), |writer, verdict| {
+        writer.variant(&VERDICTS, verdict)
+    });
+    writer.option(config.max_examined_len, StateWriter::u64);
+    writer.option(config.max_bytes, StateWriter::u64);
+    #[cfg(feature = "encoding_rs")]
+    writer.variant(&BACKENDS, &config.backend);
+}
+
+fn read_config(reader: &mut StateReader) -> Option<DetectorConfig> {
+    let mut config = DetectorConfig::new(reader.bool()?);
+    config.allow_utf8 = reader.bool()?;
+    config.mid_stream = reader.bool()?;
+    config.conformance = reader.variant(&CONFORMANCES)?;
+    config.require_certain_iso_2022_jp = reader.bool()?;
+    config.strict_iso_2022_jp = reader.bool()?;
+    config.skip_ansi_sequences = reader.bool()?;
+    config.jis_x_0212_decisive = reader.bool()?;
+    config.text_art = reader.bool()?;
+    config.reject_half_width_katakana = reader.bool()?;
+    config.del_treatment = reader.variant(&TREATMENTS)?;
+    config.c1_treatment = reader.variant(&TREATMENTS)?;
+    config.hashing = reader.bool()?;
+    #[cfg(feature = "scoring")]
+    {
+        config.scoring = reader.bool()?;
+        config.scoring_thresholds = ScoringThresholds {
+            min_margin: reader.u64()?,
+            likely_margin: reader.u64()?,
+        };
+    }
+    config.fallback = reader.variant(&VERDICTS)?;
+    config.blank_verdict = reader.option(|reader| reader.variant(&VERDICTS))?;
+    config.undecided_default = reader.option(|reader| reader.variant(&VERDICTS))?;
+    config.max_examined_len = reader.option(StateReader::u64)?;
+    config.max_bytes = reader.option(StateReader::u64)?;
+    #[cfg(feature = "encoding_rs")]
+    {
+        config.backend = reader.variant(&BACKENDS)?;
+    }
+    Some(config)
+}
+
+impl Detector {
+    /// Snapshots the state of the detector between calls to `feed()`, e.g.
+    /// to checkpoint the detection of a stream that is processed in
+    /// resumable chunks. Returns `None` in validation mode (see
+    /// `set_validation_mode()`), whose decoder state can't be captured. The
+    /// rule of `set_char_rule()` isn't captured either.
+    pub fn to_state(&self) -> Option<DetectorState> {
+        #[cfg(feature = "encoding_rs")]
+        if self.validation.is_some() {
+            return None;
+        }
+        let mut writer = StateWriter {
+            bytes: MAGIC.to_vec(),
+        };
+        write_config(&mut writer, &self.config());
+        self.write_state(&mut writer);
+        Some(DetectorState {
+            bytes: writer.bytes,
+        })
+    }
+
+    /// Instantiates a detector that continues where the detector that
+    /// produced `state` with `to_state()` left off, possibly in another
+    /// process. Returns `None` if `state` is corrupt or from another
+    /// version of the format.
+    pub fn from_state(state: &DetectorState) -> Option<Detector> {
+        let bytes = state.bytes.strip_prefix(&MAGIC[..])?;
+        let mut reader = StateReader { bytes };
+        let mut detector = Detector::with_config(&read_config(&mut reader)?);
+        detector.read_state(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return None;
+        }
+        Some(detector)
+    }
+
+    /// Writes the fields that feeding the detector changes.
+    fn write_state(&self, writer: &mut StateWriter) {
+        self.shift_jis.write_state(writer);
+        self.euc_jp.write_state(writer);
+        self.iso_2022_jp.write_state(writer);
+        writer.bool(self.iso_2022_jp_disqualified);
+        writer.option(self.iso_2022_jp_certainty.as_ref(), |writer, certainty| {
+            certainty.write_state(writer)
+        });
+        writer.option(self.verdict.as_ref(), |writer, verdict| {
+            writer.variant(&VERDICTS, verdict)
+        });
+        writer.option(self.certainty.as_ref(), |writer, certainty| {
+            writer.variant(&CERTAINTIES, certainty)
+        });
+        writer.option(self.reason.as_ref(), |writer, reason| {
+            writer.variant(&REASONS, reason)
+        });
+        writer.option(self.decided_at, |writer, (offset, byte)| {
+            writer.u64(offset);
+            writer.option(byte, StateWriter::u8);
+        });
+        writer.option(self.iso_2022_jp_ruled_out, |writer, (offset, byte)| {
+            writer.u64(offset);
+            writer.u8(byte);
+        });
+        writer.u64(self.position);
+        writer.option(self.suspicious.as_ref(), |writer, suspicious| {
+            writer.variant(&SUSPICIONS, suspicious)
+        });
+        writer.u64(self.ambiguous_bytes);
+        writer.option(self.held_c1, StateWriter::u8);
+        writer.option(self.edge_byte_error, StateWriter::u64);
+        writer.bool(self.finished);
+        writer.bool(self.blank);
+        writer.u64(self.examined_len);
+        writer.bool(self.budget_exhausted);
+        if let Some(hash) = self.examined_hash {
+            writer.u64(hash);
+        }
+        #[cfg(feature = "scoring")]
+        if let Some(scorer) = &self.scorer {
+            scorer.write_state(writer);
+        }
+        writer.option(self.utf8.as_ref(), |writer, utf8| utf8.write_state(writer));
+    }
+
+    /// Reads what `write_state()` wrote into a detector instantiated with
+    /// the same options.
+    fn read_state(&mut self, reader: &mut StateReader) -> Option<()> {
+        self.shift_jis.read_state(reader)?;
+        self.euc_jp.read_state(reader)?;
+        self.iso_2022_jp.read_state(reader)?;
+        self.iso_2022_jp_disqualified = reader.bool()?;
+        self.iso_2022_jp_certainty = reader.option(Iso2022JpCertainty::read_state)?;
+        self.verdict = reader.option(|reader| reader.variant(&VERDICTS))?;
+        self.certainty = reader.option(|reader| reader.variant(&CERTAINTIES))?;
+        self.reason = reader.option(|reader| reader.variant(&REASONS))?;
+        self.decided_at =
+            reader.option(|reader| Some((reader.u64()?, reader.option(StateReader::u8)?)))?;
+        self.iso_2022_jp_ruled_out = reader.option(|reader| Some((reader.u64()?, reader.u8()?)))?;
+        self.position = reader.u64()?;
+        self.suspicious = reader.option(|reader| reader.variant(&SUSPICIONS))?;
+        self.ambiguous_bytes = reader.u64()?;
+        self.held_c1 = reader.option(StateReader::u8)?;
+        self.edge_byte_error = reader.option(StateReader::u64)?;
+        self.finished = reader.bool()?;
+        self.blank = reader.bool()?;
+        self.examined_len = reader.u64()?;
+        self.budget_exhausted = reader.bool()?;
+        if self.examined_hash.is_some() {
+            self.examined_hash = Some(reader.u64()?);
+        }
+        #[cfg(feature = "scoring")]
+        if let Some(scorer) = &mut self.scorer {
+            scorer.read_state(reader)?;
+        }
+        self.utf8 = reader.option(Utf8Candidate::read_state)?;
+        Some(())
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DetectorBuilder;
+
+    /// Feeds `input` in two parts, checkpointing in between, and checks
+    /// that the resumed detector agrees with an uninterrupted one.
+    fn check_resume(builder: &DetectorBuilder, input: &[u8], split: usize) {
+        let mut uninterrupted = builder.build();
+        let expected = uninterrupted.feed_verdict(input, true);
+        let mut first = builder.build();
+        assert_eq!(first.feed_verdict(&input[..split], false), None);
+        let state = first.to_state().unwrap();
+        let state = DetectorState::from_bytes(state.as_bytes());
+        let mut resumed = Detector::from_state(&state).unwrap();
+        assert_eq!(resumed.feed_verdict(&input[split..], true), expected);
+        assert_eq!(resumed.outcome(), uninterrupted.outcome());
+    }
+
+    #[test]
+    fn test_resume() {
+        let builder = DetectorBuilder::new();
+        // A kanji valid in both, split after the lead byte that both hold,
+        // and then "あ" in EUC-JP or in Shift_JIS.
+        check_resume(&builder, b"ab\xE0\xA1\xA4\xA2", 3);
+        check_resume(&builder, b"ab\xE0\xA1\x82\xA0", 3);
+        // A JIS X 0212 character split after its first two bytes.
+        check_resume(
+            &builder.clone().jis_x_0212_decisive(true),
+            b"\xE0\xA1\x8F\xA2\xAF",
+            4,
+        );
+        // Within an escape sequence that then awaits a JIS X 0208
+        // character.
+        let builder = builder.require_certain_iso_2022_jp(true).hashing(true);
+        check_resume(&builder, b"a\x1B$B\x24\x22", 2);
+        check_resume(&builder, b"a\x1B$B\x24\x22", 5);
+        // A Shift_JIS decision held back while the input is valid UTF-8.
+        check_resume(
+            &DetectorBuilder::new().allow_utf8(true),
+            b"\xE6\x97\xA5x\x82\xA0",
+            3,
+        );
+        // Scores of kanji that both decode.
+        #[cfg(feature = "scoring")]
+        check_resume(
+            &DetectorBuilder::new().scoring(true),
+            b"\xE0\xA1\xE0\xA1",
+            3,
+        );
+    }
+
+    #[test]
+    fn test_rejected_state() {
+        let mut detector = Detector::new(true);
+        detector.feed_verdict(b"ab", false);
+        let mut bytes = detector.to_state().unwrap().as_bytes().to_vec();
+        assert!(Detector::from_state(&DetectorState::from_bytes(&bytes)).is_some());
+        bytes[3] = 0;
+        assert!(Detector::from_state(&DetectorState::from_bytes(&bytes)).is_none());
+        bytes.truncate(10);
+        assert!(Detector::from_state(&DetectorState::from_bytes(&bytes)).is_none());
+        #[cfg(feature = "encoding_rs")]
+        {
+            let mut detector = Detector::new(true);
+            detector.set_validation_mode(true);
+            assert!(detector.to_state().is_none());
+        }
+    }
+}
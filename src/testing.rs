@@ -0,0 +1,497 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for testing this crate and crates that wrap it. Requires the
+//! `test-support` feature.
+//!
+//! The crate's own accumulated regression inputs live in the
+//! `fuzz/regressions` directory of the source distribution. Downstream forks
+//! and bindings can replay that directory (or their own) with
+//! `replay_corpus()`.
+//!
+//! `conformance_matrix()` checks the detector on every two-byte sequence
+//! against expectations derived from `encoding_rs` alone, so bindings can
+//! run the same matrix against their own build.
+//!
+//! `assert_chunking_stable()` checks that a detector configuration reaches
+//! the same verdict at the same offset however an input is split into
+//! calls to `feed()`, for wrappers that buffer or re-chunk input.
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+
+use encoding_rs::DecoderResult;
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::SHIFT_JIS;
+
+use crate::raw::EucJpMachine;
+use crate::raw::Iso2022JpMachine;
+use crate::raw::ShiftJisMachine;
+use crate::raw::Step;
+use crate::sampling::SplitMix64;
+use crate::Detector;
+use crate::Verdict;
+
+/// The number of chunkings that `check_chunking_stability()` tries.
+pub const CHUNKINGS: usize = 256;
+
+/// How an input failed a replay check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckFailure {
+    /// The detector panicked.
+    Panicked,
+    /// Feeding the input in one call and feeding it one byte at a time gave
+    /// different verdicts.
+    Mismatch {
+        /// Whether ISO-2022-JP was allowed.
+        allow_2022: bool,
+        /// The verdict from feeding the input in one call.
+        one_shot: Option<&'static Encoding>,
+        /// The verdict from feeding the input one byte at a time.
+        streaming: Option<&'static Encoding>,
+    },
+    /// `Detector` and `reference_verdict()` gave different verdicts.
+    ReferenceMismatch {
+        /// Whether ISO-2022-JP was allowed.
+        allow_2022: bool,
+        /// The verdict from `Detector`.
+        detector: Option<Verdict>,
+        /// The verdict from `reference_verdict()`.
+        reference: Option<Verdict>,
+    },
+}
+
+/// An input in a corpus that failed the replay check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayFailure {
+    /// The path of the input file.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub failure: CheckFailure,
+}
+
+fn one_shot(input: &[u8], allow_2022: bool) -> Option<&'static Encoding> {
+    Detector::new(allow_2022).feed(input, true)
+}
+
+fn streaming(input: &[u8], allow_2022: bool) -> Option<&'static Encoding> {
+    let mut detector = Detector::new(allow_2022);
+    for byte in input.chunks(1) {
+        if let Some(encoding) = detector.feed(byte, false) {
+            return Some(encoding);
+        }
+    }
+    detector.feed(b"", true)
+}
+
+/// Detects `input` the way a default `Detector` does, but by pushing every
+/// byte to the machines of the `raw` module in the documented order without
+/// any of the shortcuts `Detector` takes. This is the baseline that the
+/// optimized `Detector` is compared against.
+pub fn reference_verdict(input: &[u8], allow_2022: bool) -> Option<Verdict> {
+    let mut shift_jis = ShiftJisMachine::new();
+    let mut euc_jp = EucJpMachine::new();
+    let mut iso_2022_jp = Iso2022JpMachine::new();
+    let mut iso_2022_jp_possible = allow_2022;
+    for &byte in input {
+        if iso_2022_jp_possible {
+            match iso_2022_jp.push(byte) {
+                Step::Confirmed => return Some(Verdict::Iso2022Jp),
+                Step::Rejected => iso_2022_jp_possible = false,
+                Step::Possible => {}
+            }
+        }
+        match euc_jp.push(byte) {
+            Step::Rejected => return Some(Verdict::ShiftJis),
+            Step::Confirmed => return Some(Verdict::EucJp),
+            Step::Possible => {}
+        }
+        if shift_jis.push(byte) == Step::Rejected {
+            return Some(Verdict::EucJp);
+        }
+    }
+    if euc_jp.finish() == Step::Rejected {
+        return Some(Verdict::ShiftJis);
+    }
+    if shift_jis.finish() == Step::Rejected {
+        return Some(Verdict::EucJp);
+    }
+    None
+}
+
+/// The index of the byte of `input` at which decoding as `encoding` first
+/// yields a malformed sequence or half-width katakana, or `input.len()` for
+/// a malformed sequence at the end.
+fn first_evidence(encoding: &'static Encoding, input: &[u8]) -> Option<usize> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(16);
+    for (i, byte) in input.iter().enumerate() {
+        let mut src = std::slice::from_ref(byte);
+        loop {
+            text.clear();
+            let (result, read) =
+                decoder.decode_to_string_without_replacement(src, &mut text, false);
+            if text.chars().any(|c| ('\u{FF61}'..='\u{FF9F}').contains(&c)) {
+                return Some(i);
+            }
+            match result {
+                DecoderResult::Malformed(_, _) => return Some(i),
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => src = &src[read..],
+            }
+        }
+    }
+    text.clear();
+    match decoder.decode_to_string_without_replacement(b"", &mut text, true) {
+        (DecoderResult::Malformed(_, _), _) => Some(input.len()),
+        _ => None,
+    }
+}
+
+/// The verdict that the detector is expected to reach for `first` and
+/// `second` between two spaces, derived from the `encoding_rs` decoders
+/// instead of this crate's machines: the first malformed sequence or
+/// half-width katakana when decoding as one encoding decides the other one,
+/// with EUC-JP going first if both happen at the same byte. The spaces make
+/// sure that the bytes don't pair with their surroundings. ISO-2022-JP can't
+/// be confirmed by two bytes followed by a space, so the expectation is the
+/// same whether it is allowed or not.
+pub fn expected_two_byte_verdict(first: u8, second: u8) -> Option<Verdict> {
+    let input = [b' ', first, second, b' '];
+    match (
+        first_evidence(EUC_JP, &input),
+        first_evidence(SHIFT_JIS, &input),
+    ) {
+        (Some(euc_jp), Some(shift_jis)) if shift_jis < euc_jp => Some(Verdict::EucJp),
+        (Some(_), _) => Some(Verdict::ShiftJis),
+        (None, Some(_)) => Some(Verdict::EucJp),
+        (None, None) => None,
+    }
+}
+
+/// The verdicts on all 65,536 two-byte sequences between two spaces, as
+/// expected by `expected_two_byte_verdict()` and as reached by `Detector`.
+pub struct ConformanceMatrix {
+    expected: Vec<Option<Verdict>>,
+    actual: Vec<Option<Verdict>>,
+}
+
+impl ConformanceMatrix {
+    /// The expected verdict for `first` followed by `second`.
+    pub fn expected(&self, first: u8, second: u8) -> Option<Verdict> {
+        self.expected[usize::from(first) << 8 | usize::from(second)]
+    }
+
+    /// The verdict that the detector reached for `first` followed by
+    /// `second`.
+    pub fn actual(&self, first: u8, second: u8) -> Option<Verdict> {
+        self.actual[usize::from(first) << 8 | usize::from(second)]
+    }
+
+    /// The sequences whose verdict differs from the expected one.
+    pub fn mismatches(&self) -> Vec<[u8; 2]> {
+        (0..self.expected.len())
+            .filter(|&i| self.expected[i] != self.actual[i])
+            .map(|i| [(i >> 8) as u8, i as u8])
+            .collect()
+    }
+
+    /// Writes the matrix as CSV with the columns `first`, `second` (as two
+    /// upper-case hex digits), `expected` and `actual` (as encoding names or
+    /// `undecided`), one row per sequence in byte order after a header row.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let name = |verdict: Option<Verdict>| verdict.map_or("undecided", Verdict::name);
+        writeln!(out, "first,second,expected,actual")?;
+        for i in 0..self.expected.len() {
+            writeln!(
+                out,
+                "{:02X},{:02X},{},{}",
+                i >> 8,
+                i & 0xFF,
+                name(self.expected[i]),
+                name(self.actual[i])
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a `Detector` (with ISO-2022-JP allowed or not as given by
+/// `allow_2022`) on every two-byte sequence between two spaces and pairs
+/// the verdicts with `expected_two_byte_verdict()`.
+pub fn conformance_matrix(allow_2022: bool) -> ConformanceMatrix {
+    let mut expected = Vec::with_capacity(0x10000);
+    let mut actual = Vec::with_capacity(0x10000);
+    for i in 0..0x10000usize {
+        let (first, second) = ((i >> 8) as u8, i as u8);
+        expected.push(expected_two_byte_verdict(first, second));
+        actual.push(Detector::new(allow_2022).feed_verdict(&[b' ', first, second, b' '], true));
+    }
+    ConformanceMatrix { expected, actual }
+}
+
+/// Checks that the detector doesn't panic on `input`, that it reaches the
+/// same verdict whether `input` is fed in one call or one byte at a time
+/// and that the verdict matches `reference_verdict()`, both with and without
+/// ISO-2022-JP allowed.
+pub fn check_input(input: &[u8]) -> Result<(), CheckFailure> {
+    for &allow_2022 in [true, false].iter() {
+        let verdicts = panic::catch_unwind(|| {
+            (
+                one_shot(input, allow_2022),
+                streaming(input, allow_2022),
+                reference_verdict(input, allow_2022),
+            )
+        });
+        match verdicts {
+            Ok((one_shot, streaming, reference)) => {
+                if one_shot != streaming {
+                    return Err(CheckFailure::Mismatch {
+                        allow_2022,
+                        one_shot,
+                        streaming,
+                    });
+                }
+                let detector = Detector::new(allow_2022).feed_verdict(input, true);
+                if detector != reference {
+                    return Err(CheckFailure::ReferenceMismatch {
+                        allow_2022,
+                        detector,
+                        reference,
+                    });
+                }
+            }
+            Err(_) => {
+                return Err(CheckFailure::Panicked);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A chunking of an input under which the detector reached a different
+/// verdict or decided at a different offset than when fed the input in one
+/// call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkingMismatch {
+    /// The lengths of the calls to `feed()`, which add up to the length of
+    /// the input. A final call with `true` as `last` and an empty buffer
+    /// isn't listed.
+    pub chunks: Vec<usize>,
+    /// The verdict and `Detector::decided_at()` from feeding the input in
+    /// one call.
+    pub expected: (Option<Verdict>, Option<u64>),
+    /// The verdict and `Detector::decided_at()` from feeding `chunks`.
+    pub actual: (Option<Verdict>, Option<u64>),
+}
+
+/// Feeds `input` to `detector` in calls of the lengths `chunks`, passing
+/// `last` with the final chunk if `last_with_data` is `true` and in an extra
+/// empty call otherwise, up to the verdict.
+fn feed_chunks(
+    mut detector: Detector,
+    input: &[u8],
+    chunks: &[usize],
+    last_with_data: bool,
+) -> (Option<Verdict>, Option<u64>) {
+    let mut start = 0;
+    for (i, &len) in chunks.iter().enumerate() {
+        let last = last_with_data && i == chunks.len() - 1;
+        let verdict = detector.feed_verdict(&input[start..start + len], last);
+        start += len;
+        if verdict.is_some() || last {
+            return (verdict, detector.decided_at());
+        }
+    }
+    (detector.feed_verdict(b"", true), detector.decided_at())
+}
+
+/// Feeds `input` as the complete stream to detectors from `new_detector`
+/// (which must return detectors that haven't been fed yet) in one call and
+/// then under `CHUNKINGS` pseudo-random chunkings picked by `seed`, from
+/// one byte at a time to a few large calls with some empty ones, and
+/// returns the first chunking whose verdict or `Detector::decided_at()`
+/// differs.
+///
+/// For detectors with `Detector::set_max_bytes()`, `last` always comes with
+/// the final chunk: a stream that ends exactly at the budget is decided by
+/// its end when the call that reaches the budget says so and forced to the
+/// budget's guess otherwise, which is not a chunking issue.
+pub fn check_chunking_stability<F>(
+    input: &[u8],
+    new_detector: F,
+    seed: u64,
+) -> Result<(), ChunkingMismatch>
+where
+    F: Fn() -> Detector,
+{
+    let expected = feed_chunks(new_detector(), input, &[input.len()], true);
+    let budget = new_detector().config().max_bytes.is_some();
+    let mut random = SplitMix64(seed);
+    for round in 0..CHUNKINGS {
+        // Cycles the maximum chunk length through 1, 2, 4 and so on.
+        let max_len = (1usize << (round % 16)).min(input.len().max(1));
+        let mut chunks = Vec::new();
+        let mut remaining = input.len();
+        while remaining != 0 {
+            let len = (random.next() % (max_len as u64 + 1)) as usize;
+            let len = len.min(remaining);
+            chunks.push(len);
+            remaining -= len;
+        }
+        let last_with_data = (random.next() & 1 == 0 || budget) && !chunks.is_empty();
+        let actual = feed_chunks(new_detector(), input, &chunks, last_with_data);
+        if actual != expected {
+            return Err(ChunkingMismatch {
+                chunks,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Panics with the offending chunking unless `check_chunking_stability()`
+/// with seed zero passes, for use in the tests of downstream wrappers.
+pub fn assert_chunking_stable<F>(input: &[u8], new_detector: F)
+where
+    F: Fn() -> Detector,
+{
+    if let Err(mismatch) = check_chunking_stability(input, new_detector, 0) {
+        panic!(
+            "Chunking changed the outcome for {:X?}: {:?}",
+            input, mismatch
+        );
+    }
+}
+
+/// Runs `check_input()` on every file in the directory `dir` (not
+/// recursing into subdirectories) and returns the failures.
+pub fn replay_corpus<P: AsRef<Path>>(dir: P) -> io::Result<Vec<ReplayFailure>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    let mut failures = Vec::new();
+    for path in paths {
+        let input = fs::read(&path)?;
+        if let Err(failure) = check_input(&input) {
+            failures.push(ReplayFailure { path, failure });
+        }
+    }
+    Ok(failures)
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_regressions() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/regressions");
+        assert_eq!(replay_corpus(dir).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_conformance_matrix() {
+        for &allow_2022 in [true, false].iter() {
+            let matrix = conformance_matrix(allow_2022);
+            assert_eq!(matrix.mismatches(), Vec::<[u8; 2]>::new());
+            // "あ" in Shift_JIS and in EUC-JP and a kanji valid in both.
+            assert_eq!(matrix.actual(0x82, 0xA0), Some(Verdict::ShiftJis));
+            assert_eq!(matrix.actual(0xA4, 0xA2), Some(Verdict::EucJp));
+            assert_eq!(matrix.actual(0xE0, 0xE0), None);
+        }
+        let mut csv = Vec::new();
+        conformance_matrix(true).write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 0x10001);
+        assert!(csv.contains("\n82,A0,Shift_JIS,Shift_JIS\n"));
+    }
+
+    #[test]
+    fn test_chunking_stability() {
+        let inputs: [&[u8]; 4] = [
+            b"abc \xA4\xA2 def",
+            b"\x1B$B$\"\x1B(B",
+            b"\xF0\xA1\xF0\xA1 \x82\xA0",
+            b"",
+        ];
+        for &input in inputs.iter() {
+            assert_chunking_stable(input, || Detector::new(true));
+            assert_chunking_stable(input, || {
+                crate::DetectorBuilder::new()
+                    .allow_utf8(true)
+                    .max_bytes(Some(5))
+                    .build()
+            });
+        }
+        // A skipped ANSI sequence ends the escape sequence in progress, so
+        // where it ends relative to the chunks mustn't matter.
+        assert_chunking_stable(b"\x1B[mB\x85abc", || {
+            crate::DetectorBuilder::new()
+                .allow_2022(true)
+                .skip_ansi_sequences(true)
+                .mid_stream(true)
+                .build()
+        });
+        // The stream ends exactly at the budget.
+        assert_chunking_stable(b"$Babc \xE0", || {
+            crate::DetectorBuilder::new()
+                .allow_utf8(true)
+                .max_bytes(Some(7))
+                .build()
+        });
+    }
+
+    #[test]
+    fn test_check_input() {
+        assert_eq!(check_input(b"abc\x1B(B"), Ok(()));
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        // Pseudo-random mixes of ASCII runs, escapes and bytes that are
+        // interesting to the candidates.
+        let alphabet: [&[u8]; 10] = [
+            b"abc ",
+            b"\n",
+            b"\x1B",
+            b"$B",
+            b"\x82\xA0",
+            b"\xA4\xA2",
+            b"\xE0",
+            b"\x8E",
+            b"\x8F\xB0",
+            b"\xB1",
+        ];
+        let mut state = 1u32;
+        for _ in 0..2000 {
+            let mut input = Vec::new();
+            for _ in 0..8 {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                input.extend_from_slice(alphabet[(state >> 16) as usize % alphabet.len()]);
+            }
+            assert_eq!(check_input(&input), Ok(()), "{:X?}", input);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::raw::Step;
+use crate::Detector;
+
+/// A run of bytes that the candidates evaluated the same way, as recorded
+/// by the `debug-trace` feature (see `Detector::trace()`).
+///
+/// A candidate's step is `None` if the candidate wasn't evaluated on the
+/// run, e.g. because ISO-2022-JP had been ruled out or because the EUC-JP
+/// candidate decided before the Shift_JIS candidate saw the byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceRun {
+    start: u64,
+    len: u64,
+    iso_2022_jp: Option<Step>,
+    euc_jp: Option<Step>,
+    shift_jis: Option<Step>,
+}
+
+impl TraceRun {
+    /// The stream offset of the first byte of the run.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The number of bytes in the run. Zero for the evaluation at the end of
+    /// the stream, which `start()` is the offset of.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the run is the evaluation at the end of the stream.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The step of the ISO-2022-JP candidate on each byte of the run.
+    pub fn iso_2022_jp(&self) -> Option<Step> {
+        self.iso_2022_jp
+    }
+
+    /// The step of the EUC-JP candidate on each byte of the run.
+    pub fn euc_jp(&self) -> Option<Step> {
+        self.euc_jp
+    }
+
+    /// The step of the Shift_JIS candidate on each byte of the run.
+    pub fn shift_jis(&self) -> Option<Step> {
+        self.shift_jis
+    }
+}
+
+impl Detector {
+    /// The candidate steps of the bytes examined so far, as runs of bytes
+    /// with the same steps in stream order. Requires the `debug-trace`
+    /// feature.
+    ///
+    /// Bytes that no candidate was evaluated on, such as the ASCII skipped
+    /// between characters (see the `reference-impl` feature), leave gaps
+    /// between the runs. The trace grows with the non-ASCII input and
+    /// isn't part of `to_state()`. `reset()` clears it.
+    pub fn trace(&self) -> &[TraceRun] {
+        &self.trace
+    }
+
+    /// Records the steps of the byte at `index` of the buffer that
+    /// `detect()` runs on.
+    pub(crate) fn trace_byte(
+        &mut self,
+        index: usize,
+        iso_2022_jp: Option<Step>,
+        euc_jp: Option<Step>,
+        shift_jis: Option<Step>,
+    ) {
+        let start = self.position + index as u64;
+        if let Some(run) = self.trace.last_mut() {
+            if run.len == 1
+                && run.start == start
+                && iso_2022_jp.is_none()
+                && run.euc_jp.is_none()
+                && run.shift_jis.is_none()
+            {
+                // The byte that ruled out ISO-2022-JP, now seen by the
+                // others.
+                run.euc_jp = euc_jp;
+                run.shift_jis = shift_jis;
+                return;
+            }
+            if run.len != 0
+                && run.start + run.len == start
+                && run.iso_2022_jp == iso_2022_jp
+                && run.euc_jp == euc_jp
+                && run.shift_jis == shift_jis
+            {
+                run.len += 1;
+                return;
+            }
+        }
+        self.trace.push(TraceRun {
+            start,
+            len: 1,
+            iso_2022_jp,
+            euc_jp,
+            shift_jis,
+        });
+    }
+
+    /// Records the steps of the end of the stream after the buffer of
+    /// length `len` that `detect()` runs on.
+    pub(crate) fn trace_finish(
+        &mut self,
+        len: usize,
+        iso_2022_jp: Option<Step>,
+        euc_jp: Option<Step>,
+        shift_jis: Option<Step>,
+    ) {
+        let start = self.position + len as u64;
+        if let Some(run) = self.trace.last_mut() {
+            if run.len == 0 && run.start == start && iso_2022_jp.is_none() {
+                // The end of the stream that ruled out ISO-2022-JP.
+                run.euc_jp = euc_jp;
+                run.shift_jis = shift_jis;
+                return;
+            }
+        }
+        self.trace.push(TraceRun {
+            start,
+            len: 0,
+            iso_2022_jp,
+            euc_jp,
+            shift_jis,
+        });
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Verdict;
+
+    #[test]
+    fn test_trace() {
+        let mut detector = Detector::new(true);
+        assert_eq!(
+            detector.feed_verdict(b"ab\xF0\xA1\xF0", true),
+            Some(Verdict::ShiftJis)
+        );
+        let trace = detector.trace();
+        // "ab" is skipped, 0xF0 rules out ISO-2022-JP and the rest is
+        // possible in both.
+        assert_eq!(trace.len(), 3);
+        assert_eq!((trace[0].start(), trace[0].len()), (2, 1));
+        assert_eq!(trace[0].iso_2022_jp(), Some(Step::Rejected));
+        assert_eq!(trace[0].euc_jp(), Some(Step::Possible));
+        assert_eq!((trace[1].start(), trace[1].len()), (3, 2));
+        assert_eq!(trace[1].iso_2022_jp(), None);
+        assert_eq!(trace[1].shift_jis(), Some(Step::Possible));
+        // The EUC-JP lead byte at the end is cut off.
+        assert!(trace[2].is_empty());
+        assert_eq!(trace[2].start(), 5);
+        assert_eq!(trace[2].euc_jp(), Some(Step::Rejected));
+        assert_eq!(trace[2].shift_jis(), None);
+        detector.reset(true);
+        assert!(detector.trace().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::utf8::Utf8Validator;
+
+/// Wholesale mangling of a file in transport, found by `check_transport()`,
+/// which makes the file unsuitable for detecting among the Japanese legacy
+/// encodings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Mangling {
+    /// The byte distribution looks like EBCDIC text: 0x40 (the EBCDIC
+    /// space) is frequent, ASCII spaces and letters are rare and most bytes
+    /// are in the upper half.
+    Ebcdic,
+    /// UTF-16LE without a BOM: zero bytes (the upper halves of ASCII
+    /// characters) occur at odd offsets only.
+    Utf16Le,
+    /// UTF-16BE without a BOM: zero bytes occur at even offsets only.
+    Utf16Be,
+    /// UTF-8 that was decoded as windows-1252 (or ISO-8859-1) and encoded as
+    /// UTF-8 again.
+    DoubleUtf8,
+}
+
+/// Below this many bytes, a sample is too small for `check_transport()` to
+/// tell anything.
+const MIN_SAMPLE_LEN: usize = 16;
+
+/// The characters of windows-1252 for the bytes 0x80 to 0x9F, with the
+/// bytes that windows-1252 leaves unassigned decoding to C1 controls as in
+/// the WHATWG Encoding Standard.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// Checks `sample`, typically the first few kilobytes of a file, for
+/// wholesale mangling in transport before any Japanese-specific logic
+/// runs, so that a conversion tool can refuse a file that no legacy
+/// decoding would turn into text. Returns `None` if the sample looks like
+/// it may be in a Japanese legacy encoding (including if it is too short
+/// to tell).
+///
+/// Like `prefilter()`, this is a statistical guess, checked in the order of
+/// the `Mangling` variants after UTF-16 (whose zero bytes are the
+/// strongest sign).
+pub fn check_transport(sample: &[u8]) -> Option<Mangling> {
+    if sample.len() < MIN_SAMPLE_LEN {
+        return None;
+    }
+    if let Some(utf_16) = check_utf_16(sample) {
+        return Some(utf_16);
+    }
+    if looks_like_ebcdic(sample) {
+        return Some(Mangling::Ebcdic);
+    }
+    if is_double_utf_8(sample) {
+        return Some(Mangling::DoubleUtf8);
+    }
+    None
+}
+
+/// Zero bytes at one parity in at least two and a 32nd of the code units
+/// (e.g. one line break per line of Japanese text) and hardly any at the
+/// other.
+fn check_utf_16(sample: &[u8]) -> Option<Mangling> {
+    let units = (sample.len() / 2) as u64;
+    let (mut even, mut odd) = (0u64, 0u64);
+    for pair in sample.chunks_exact(2) {
+        even += u64::from(pair[0] == 0);
+        odd += u64::from(pair[1] == 0);
+    }
+    let frequent = |zeros: u64| zeros >= 2 && zeros * 32 >= units;
+    if frequent(odd) && even * 8 <= odd {
+        Some(Mangling::Utf16Le)
+    } else if frequent(even) && odd * 8 <= even {
+        Some(Mangling::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// EBCDIC spaces (0x40) are at least 8% of the sample and outnumber both
+/// ASCII spaces and the bytes in the ASCII letter and punctuation range
+/// (where EBCDIC has only punctuation but Shift_JIS has trail bytes), and
+/// at least half of the sample is in the upper half (where EBCDIC has its
+/// letters).
+fn looks_like_ebcdic(sample: &[u8]) -> bool {
+    let len = sample.len() as u64;
+    let (mut ebcdic_spaces, mut ascii_spaces, mut ascii_graphic, mut high) =
+        (0u64, 0u64, 0u64, 0u64);
+    for &byte in sample {
+        match byte {
+            0x40 => ebcdic_spaces += 1,
+            0x20 => ascii_spaces += 1,
+            0x41..=0x7E => ascii_graphic += 1,
+            0x80..=0xFF => high += 1,
+            _ => {}
+        }
+    }
+    ebcdic_spaces * 100 >= len * 8
+        && ebcdic_spaces > 4 * ascii_spaces
+        && ebcdic_spaces > 2 * ascii_graphic
+        && high * 2 >= len
+}
+
+/// The sample is UTF-8 (except for a character cut off at its end), and
+/// the bytes it decodes to under windows-1252 or ISO-8859-1 are non-ASCII
+/// UTF-8 again.
+fn is_double_utf_8(sample: &[u8]) -> bool {
+    let text = match core::str::from_utf8(sample) {
+        Ok(text) => text,
+        Err(err) if err.error_len().is_none() => {
+            // Cut off at the end of the sample.
+            match core::str::from_utf8(&sample[..err.valid_up_to()]) {
+                Ok(text) => text,
+                Err(_) => return false,
+            }
+        }
+        Err(_) => return false,
+    };
+    let mut validator = Utf8Validator::default();
+    let mut non_ascii = false;
+    for c in text.chars() {
+        let byte = if c <= '\u{FF}' {
+            c as u8
+        } else {
+            match WINDOWS_1252_HIGH.iter().position(|&high| high == c) {
+                Some(index) => 0x80 + index as u8,
+                None => return false,
+            }
+        };
+        non_ascii |= byte >= 0x80;
+        if !validator.push(byte) {
+            return false;
+        }
+    }
+    non_ascii
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_transport() {
+        let utf_16le: Vec<u8> = "<html lang=ja>"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(check_transport(&utf_16le), Some(Mangling::Utf16Le));
+        let utf_16be: Vec<u8> = "<html>あい</html>"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_be_bytes().to_vec())
+            .collect();
+        assert_eq!(check_transport(&utf_16be), Some(Mangling::Utf16Be));
+        // "This is a test of EBCDIC." in IBM037.
+        let ebcdic = b"\xE3\x88\x89\xA2\x40\x89\xA2\x40\x81\x40\xA3\x85\xA2\xA3\x40\x96\x86\x40\xC5\xC2\xC3\xC4\xC9\xC3\x4B";
+        assert_eq!(check_transport(ebcdic), Some(Mangling::Ebcdic));
+        // "日本語のテキスト" in UTF-8, decoded as windows-1252 and encoded as
+        // UTF-8.
+        let double: String = "日本語のテキスト"
+            .bytes()
+            .map(|byte| match byte {
+                0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+                byte => char::from(byte),
+            })
+            .collect();
+        assert_eq!(
+            check_transport(double.as_bytes()),
+            Some(Mangling::DoubleUtf8)
+        );
+        assert_eq!(check_transport("日本語のテキスト".as_bytes()), None);
+        // "　あいうえおかきくけこ" in Shift_JIS and EUC-JP.
+        let shift_jis = b"\x81\x40\x82\xA0\x82\xA2\x82\xA4\x82\xA6\x82\xA8\x82\xA9\x82\xAB\x82\xAD\x82\xAF\x82\xB1";
+        assert_eq!(check_transport(shift_jis), None);
+        let euc_jp = b"\xA1\xA1\xA4\xA2\xA4\xA4\xA4\xA6\xA4\xA8\xA4\xAA\xA4\xAB\xA4\xAD\xA4\xAF\xA4\xB1\xA4\xB3";
+        assert_eq!(check_transport(euc_jp), None);
+        assert_eq!(check_transport(b"\0a"), None);
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::Encoding;
+
+use crate::find_non_ascii;
+use crate::race;
+use crate::race::Race;
+use crate::raw::Step;
+use crate::EucJpCandidate;
+use crate::ShiftJisCandidate;
+use crate::Verdict;
+
+/// A detector that only distinguishes between Shift_JIS and EUC-JP.
+///
+/// Decides the same as `Detector::new(false)` but without any ISO-2022-JP
+/// state. Obtained from `Detector::shift_jis_vs_euc_jp()`.
+pub struct TwoWayDetector {
+    shift_jis: ShiftJisCandidate,
+    euc_jp: EucJpCandidate,
+    non_ascii_seen: bool,
+    finished: bool,
+}
+
+impl TwoWayDetector {
+    /// Instantiates the detector.
+    pub fn new() -> Self {
+        TwoWayDetector {
+            shift_jis: ShiftJisCandidate::new(),
+            euc_jp: EucJpCandidate::new(),
+            non_ascii_seen: false,
+            finished: false,
+        }
+    }
+
+    /// Sets whether a complete JIS X 0212 character decides EUC-JP. See
+    /// `Detector::set_jis_x_0212_decisive()`. Call before the first call to
+    /// `feed()`.
+    pub fn set_jis_x_0212_decisive(&mut self, decisive: bool) {
+        self.euc_jp.set_jis_x_0212_decisive(decisive);
+    }
+
+    /// Inspects the next `buffer`. Has the same contract as
+    /// `Detector::feed()`.
+    #[cfg(feature = "encoding_rs")]
+    pub fn feed(&mut self, buffer: &[u8], last: bool) -> Option<&'static Encoding> {
+        self.feed_verdict(buffer, last).map(Verdict::encoding)
+    }
+
+    /// The same as `feed()` but returns a `Verdict`, which is never
+    /// `Verdict::Iso2022Jp`.
+    pub fn feed_verdict(&mut self, buffer: &[u8], last: bool) -> Option<Verdict> {
+        assert!(
+            !self.finished,
+            "Tried to used a detector that has finished."
+        );
+        let verdict = self.detect(buffer, last);
+        self.finished = last || verdict.is_some();
+        verdict
+    }
+
+    fn detect(&mut self, buffer: &[u8], last: bool) -> Option<Verdict> {
+        let mut i = 0;
+        if !self.non_ascii_seen {
+            i = find_non_ascii(buffer);
+            self.non_ascii_seen = i < buffer.len();
+        }
+        if let Some(verdict) = race::race(self, buffer, i) {
+            return Some(verdict);
+        }
+        if last {
+            return race::finish(self);
+        }
+        None
+    }
+}
+
+impl Race for TwoWayDetector {
+    type First = EucJpCandidate;
+    type Second = ShiftJisCandidate;
+    type Decision = Verdict;
+
+    fn candidates(&mut self) -> (&mut EucJpCandidate, &mut ShiftJisCandidate) {
+        (&mut self.euc_jp, &mut self.shift_jis)
+    }
+
+    fn first(&mut self, euc_jp: Step, index: Option<usize>) -> Option<Verdict> {
+        match (euc_jp, index) {
+            (Step::Rejected, _) => Some(Verdict::ShiftJis),
+            (Step::Confirmed, Some(_)) => Some(Verdict::EucJp),
+            _ => None,
+        }
+    }
+
+    fn second(&mut self, _: Step, shift_jis: Step, _: Option<usize>) -> Option<Verdict> {
+        match shift_jis {
+            Step::Rejected => Some(Verdict::EucJp),
+            _ => None,
+        }
+    }
+}
+
+impl Default for TwoWayDetector {
+    fn default() -> Self {
+        TwoWayDetector::new()
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use crate::Detector;
+
+    #[test]
+    fn test_matches_detector() {
+        let inputs: [&[u8]; 6] = [
+            b"abc",
+            b"a\x82\xA0",
+            b"a\xA4\xA2",
+            b"\x1B$B\xA4\xA2",
+            b"\xE0\xE0\xE0",
+            b"\xE0",
+        ];
+        for input in inputs.iter() {
+            for split in 0..=input.len() {
+                let (head, tail) = input.split_at(split);
+                let mut two_way = Detector::shift_jis_vs_euc_jp();
+                let mut detector = Detector::new(false);
+                let expected = detector
+                    .feed_verdict(head, false)
+                    .or_else(|| detector.feed_verdict(tail, true));
+                let verdict = two_way
+                    .feed_verdict(head, false)
+                    .or_else(|| two_way.feed_verdict(tail, true));
+                assert_eq!(verdict, expected, "{:X?}", input);
+            }
+        }
+    }
+}
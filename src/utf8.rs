@@ -0,0 +1,371 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::hash::fnv1a_update;
+use crate::state::StateReader;
+use crate::state::StateWriter;
+use crate::state::CERTAINTIES;
+use crate::state::REASONS;
+use crate::state::VERDICTS;
+use crate::Certainty;
+use crate::Decision;
+use crate::Detector;
+use crate::Reason;
+use crate::Verdict;
+
+/// Validates UTF-8 a byte at a time (except that the input may end in the
+/// middle of a character).
+#[derive(Default)]
+pub(crate) struct Utf8Validator {
+    /// The number of continuation bytes still expected.
+    remaining: u8,
+    /// The bounds of the next continuation byte.
+    lower: u8,
+    upper: u8,
+}
+
+impl Utf8Validator {
+    pub(crate) fn push(&mut self, byte: u8) -> bool {
+        if self.remaining != 0 {
+            if byte < self.lower || byte > self.upper {
+                return false;
+            }
+            self.remaining -= 1;
+            self.lower = 0x80;
+            self.upper = 0xBF;
+            return true;
+        }
+        let (remaining, lower, upper) = match byte {
+            0x00..=0x7F => return true,
+            0xC2..=0xDF => (1, 0x80, 0xBF),
+            0xE0 => (2, 0xA0, 0xBF),
+            0xE1..=0xEC | 0xEE..=0xEF => (2, 0x80, 0xBF),
+            0xED => (2, 0x80, 0x9F),
+            0xF0 => (3, 0x90, 0xBF),
+            0xF1..=0xF3 => (3, 0x80, 0xBF),
+            0xF4 => (3, 0x80, 0x8F),
+            _ => return false,
+        };
+        self.remaining = remaining;
+        self.lower = lower;
+        self.upper = upper;
+        true
+    }
+}
+
+/// A legacy decision that waits for UTF-8 to be ruled out, with what
+/// `Detector::feed_verdict()` would have recorded for it.
+struct Held {
+    verdict: Verdict,
+    certainty: Certainty,
+    reason: Reason,
+    decided_at: (u64, Option<u8>),
+    examined_len: u64,
+    examined_hash: Option<u64>,
+}
+
+/// The state of the UTF-8 candidate of `Detector::set_allow_utf8()`.
+#[derive(Default)]
+pub(crate) struct Utf8Candidate {
+    validator: Utf8Validator,
+    non_ascii: bool,
+    held: Option<Held>,
+}
+
+impl Utf8Candidate {
+    /// The verdict of the decision of the other rules being held back, if
+    /// any.
+    pub(crate) fn held_verdict(&self) -> Option<Verdict> {
+        self.held.as_ref().map(|held| held.verdict)
+    }
+
+    /// Whether the input checked so far has non-ASCII bytes, all of them
+    /// well-formed UTF-8.
+    #[cfg(feature = "encoding_rs")]
+    pub(crate) fn non_ascii(&self) -> bool {
+        self.non_ascii
+    }
+
+    /// Pushes `buffer`. Returns `false` if it rules out UTF-8, including by
+    /// a character cut off by the end of the stream if `last`.
+    fn check(&mut self, buffer: &[u8], last: bool) -> bool {
+        #[cfg(feature = "simdutf8")]
+        let buffer = self.skip_valid(buffer);
+        for &byte in buffer {
+            if byte >= 0x80 {
+                self.non_ascii = true;
+            }
+            if !self.validator.push(byte) {
+                return false;
+            }
+        }
+        !last || self.validator.remaining == 0
+    }
+
+    /// Finishes a character split across buffers and then validates as
+    /// much of the rest of `buffer` as is well-formed with SIMD. Returns the
+    /// bytes that are left for `check()`: a malformed or cut-off character
+    /// and what follows it.
+    #[cfg(feature = "simdutf8")]
+    fn skip_valid<'a>(&mut self, mut buffer: &'a [u8]) -> &'a [u8] {
+        // Continuation bytes are non-ASCII, so `non_ascii` is already set.
+        while self.validator.remaining != 0 {
+            match buffer.split_first() {
+                Some((&byte, rest)) if self.validator.push(byte) => buffer = rest,
+                _ => return buffer,
+            }
+        }
+        let valid = match simdutf8::compat::from_utf8(buffer) {
+            Ok(_) => buffer.len(),
+            Err(err) => err.valid_up_to(),
+        };
+        if !self.non_ascii {
+            self.non_ascii = !buffer[..valid].is_ascii();
+        }
+        &buffer[valid..]
+    }
+
+    /// Writes the candidate for `Detector::to_state()`.
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.validator.remaining);
+        writer.u8(self.validator.lower);
+        writer.u8(self.validator.upper);
+        writer.bool(self.non_ascii);
+        writer.option(self.held.as_ref(), |writer, held| {
+            writer.variant(&VERDICTS, &held.verdict);
+            writer.variant(&CERTAINTIES, &held.certainty);
+            writer.variant(&REASONS, &held.reason);
+            writer.u64(held.decided_at.0);
+            writer.option(held.decided_at.1, StateWriter::u8);
+            writer.u64(held.examined_len);
+            writer.option(held.examined_hash, StateWriter::u64);
+        });
+    }
+
+    /// Reads what `write_state()` wrote.
+    pub(crate) fn read_state(reader: &mut StateReader) -> Option<Self> {
+        let validator = Utf8Validator {
+            remaining: reader.u8()?,
+            lower: reader.u8()?,
+            upper: reader.u8()?,
+        };
+        if validator.remaining > 3 {
+            return None;
+        }
+        Some(Utf8Candidate {
+            validator,
+            non_ascii: reader.bool()?,
+            held: reader.option(|reader| {
+                Some(Held {
+                    verdict: reader.variant(&VERDICTS)?,
+                    certainty: reader.variant(&CERTAINTIES)?,
+                    reason: reader.variant(&REASONS)?,
+                    decided_at: (reader.u64()?, reader.option(StateReader::u8)?),
+                    examined_len: reader.u64()?,
+                    examined_hash: reader.option(StateReader::u64)?,
+                })
+            })?,
+        })
+    }
+}
+
+impl Detector {
+    /// Sets whether UTF-8 is a possible guess. Defaults to `false`. Call
+    /// before the first call to `feed()`. Has no effect in validation mode
+    /// (see `set_validation_mode()`).
+    ///
+    /// Many files assumed to be in a Japanese legacy encoding are actually
+    /// UTF-8 without a BOM, which the other rules misdetect as Shift_JIS.
+    /// With this option, `Verdict::Utf8` (`UTF_8`) is decided with
+    /// `Reason::Utf8` if the examined input has non-ASCII bytes and all of
+    /// them are well-formed UTF-8 at the end of the stream (or when the
+    /// budget of `set_max_examined_len()` runs out). Otherwise, the other
+    /// rules decide as usual.
+    ///
+    /// While the non-ASCII input is well-formed UTF-8, a decision of the
+    /// other rules is held back until UTF-8 is ruled out and then reported
+    /// with its original position. Legacy-encoded text rules out UTF-8
+    /// within its first few non-ASCII bytes, but UTF-8 input is only decided
+    /// at the end of the stream. An ISO-2022-JP escape sequence before any
+    /// non-ASCII byte still decides ISO-2022-JP right away.
+    pub fn set_allow_utf8(&mut self, allow: bool) {
+        self.allow_utf8 = allow;
+        self.utf8 = if allow {
+            Some(Utf8Candidate::default())
+        } else {
+            None
+        };
+    }
+
+    /// Feeds `examinable` to the UTF-8 candidate alone if it is holding a
+    /// decision of the other rules. Returns `None` if it isn't and
+    /// otherwise what `feed_verdict()` returns.
+    pub(crate) fn feed_held_utf8(
+        &mut self,
+        examinable: &[u8],
+        last: bool,
+    ) -> Option<Option<Verdict>> {
+        let utf8 = self.utf8.as_mut()?;
+        utf8.held.as_ref()?;
+        if !utf8.check(examinable, last && !self.budget_exhausted) {
+            let held = self.utf8.take()?.held?;
+            self.verdict = Some(held.verdict);
+            self.certainty = Some(held.certainty);
+            self.reason = Some(held.reason);
+            self.decided_at = Some(held.decided_at);
+            self.examined_len = held.examined_len;
+            self.examined_hash = held.examined_hash;
+            self.finished = true;
+            return Some(self.verdict);
+        }
+        self.examined_len += examinable.len() as u64;
+        if let Some(hash) = self.examined_hash.as_mut() {
+            *hash = fnv1a_update(*hash, examinable);
+        }
+        if last || self.budget_exhausted {
+            self.utf8 = None;
+            self.verdict = Some(Verdict::Utf8);
+            self.certainty = Some(Certainty::Certain);
+            self.reason = Some(Reason::Utf8);
+            self.decided_at = Some((self.examined_len, None));
+            self.finished = true;
+        }
+        Some(self.verdict)
+    }
+
+    /// Feeds `examinable` to the UTF-8 candidate after the other rules have
+    /// made `decision` of it and returns the decision to report instead.
+    pub(crate) fn decide_utf8(
+        &mut self,
+        examinable: &[u8],
+        last: bool,
+        decision: Option<Decision>,
+    ) -> Option<Decision> {
+        #[cfg(feature = "encoding_rs")]
+        {
+            if self.validation.is_some() {
+                return decision;
+            }
+        }
+        let utf8 = match self.utf8.as_mut() {
+            Some(utf8) => utf8,
+            None => return decision,
+        };
+        let non_ascii_before = utf8.non_ascii;
+        if !utf8.check(examinable, last && !self.budget_exhausted) {
+            self.utf8 = None;
+            return decision;
+        }
+        if let Some(decision) = decision {
+            if !non_ascii_before && examinable[..decision.examined].is_ascii() {
+                // ISO-2022-JP before any non-ASCII byte.
+                self.utf8 = None;
+                return Some(decision);
+            }
+        }
+        if last || self.budget_exhausted {
+            if !utf8.non_ascii {
+                return decision;
+            }
+            self.utf8 = None;
+            return Some(Decision {
+                verdict: Verdict::Utf8,
+                certainty: Certainty::Certain,
+                reason: Reason::Utf8,
+                byte: None,
+                examined: examinable.len(),
+                consumed: None,
+            });
+        }
+        if let Some(decision) = decision {
+            let examined_len = self.examined_len + decision.examined as u64;
+            utf8.held = Some(Held {
+                verdict: decision.verdict,
+                certainty: decision.certainty,
+                reason: decision.reason,
+                decided_at: match decision.byte {
+                    Some(byte) => (examined_len - 1, Some(byte)),
+                    None => (examined_len, None),
+                },
+                examined_len,
+                examined_hash: self
+                    .examined_hash
+                    .map(|hash| fnv1a_update(hash, &examinable[..decision.examined])),
+            });
+        }
+        None
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DetectorBuilder;
+
+    #[test]
+    fn test_utf8() {
+        let builder = DetectorBuilder::new().allow_utf8(true);
+        // "日本語" in UTF-8.
+        let utf8 = "日本語".as_bytes();
+        assert_eq!(
+            Detector::new(true).feed_verdict(utf8, true),
+            Some(Verdict::ShiftJis)
+        );
+        let mut detector = builder.build();
+        assert_eq!(detector.feed_verdict(utf8, false), None);
+        assert_eq!(detector.feed_verdict(b"", true), Some(Verdict::Utf8));
+        let outcome = detector.outcome();
+        assert_eq!(outcome.reason(), Some(Reason::Utf8));
+        assert_eq!(outcome.examined_len(), 9);
+        // Characters split across calls still validate.
+        let mut detector = builder.build();
+        for &byte in utf8 {
+            assert_eq!(detector.feed_verdict(&[byte], false), None);
+        }
+        assert_eq!(detector.feed_verdict(b"", true), Some(Verdict::Utf8));
+        // A character cut off at the end isn't UTF-8.
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(&utf8[..8], true),
+            Some(Verdict::ShiftJis)
+        );
+        // "あ" in EUC-JP rules out UTF-8 right away.
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(b"ab\xA4\xA2", false),
+            Some(Verdict::EucJp)
+        );
+        assert_eq!(detector.outcome().examined_len(), 3);
+        // Shift_JIS decides at "日" but is held back until the byte after
+        // it rules out UTF-8.
+        let mut detector = builder.clone().hashing(true).build();
+        assert_eq!(detector.feed_verdict(b"\xE6\x97\xA5", false), None);
+        assert_eq!(
+            detector.feed_verdict(b"x\x82\xA0", false),
+            Some(Verdict::ShiftJis)
+        );
+        let outcome = detector.outcome();
+        assert_eq!(outcome.examined_len(), 2);
+        assert_eq!(
+            detector.examined_hash(),
+            Some(crate::hash_examined(b"\xE6\x97"))
+        );
+        // ASCII stays undecided, and ISO-2022-JP is decided as usual.
+        let mut detector = builder.build();
+        assert_eq!(detector.feed_verdict(b"abc", true), None);
+        let mut detector = builder.build();
+        assert_eq!(
+            detector.feed_verdict(b"\x1B$B", false),
+            Some(Verdict::Iso2022Jp)
+        );
+    }
+}
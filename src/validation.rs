@@ -0,0 +1,206 @@
+// Copyright 2018 Mozilla Foundation. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::vec::Vec;
+
+use encoding_rs::Decoder;
+use encoding_rs::DecoderResult;
+use encoding_rs::Encoding;
+use encoding_rs::EUC_JP;
+use encoding_rs::ISO_2022_JP;
+use encoding_rs::SHIFT_JIS;
+
+/// The maximum number of malformed sequence offsets recorded per encoding.
+pub const MAX_RECORDED_OFFSETS: usize = 8;
+
+/// The malformed sequences that decoding a buffer as a particular encoding
+/// produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedSequences {
+    count: usize,
+    first_offsets: Vec<usize>,
+}
+
+impl MalformedSequences {
+    fn new(encoding: &'static Encoding, buffer: &[u8]) -> Self {
+        let mut malformed = MalformedSequences::empty();
+        malformed.decode(
+            &mut encoding.new_decoder_without_bom_handling(),
+            buffer,
+            0,
+            true,
+        );
+        malformed
+    }
+
+    fn empty() -> Self {
+        MalformedSequences {
+            count: 0,
+            first_offsets: Vec::new(),
+        }
+    }
+
+    /// Decodes `buffer`, which starts at stream offset `offset`, recording
+    /// the malformed sequences.
+    fn decode(&mut self, decoder: &mut Decoder, buffer: &[u8], offset: usize, last: bool) {
+        let mut output = [0u16; 1024];
+        let mut total_read = 0;
+        loop {
+            let (result, read, _written) = decoder.decode_to_utf16_without_replacement(
+                &buffer[total_read..],
+                &mut output,
+                last,
+            );
+            total_read += read;
+            match result {
+                DecoderResult::InputEmpty => {
+                    return;
+                }
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(bad, after) => {
+                    self.count += 1;
+                    if self.first_offsets.len() < MAX_RECORDED_OFFSETS {
+                        self.first_offsets
+                            .push(offset + total_read - usize::from(after) - usize::from(bad));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The number of malformed sequences.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The byte offsets of the starts of the first (up to
+    /// `MAX_RECORDED_OFFSETS`) malformed sequences.
+    pub fn first_offsets(&self) -> &[usize] {
+        &self.first_offsets[..]
+    }
+}
+
+/// Keeps decoding with the encoding that a `Detector` in validation mode
+/// decided.
+pub(crate) struct Continuation {
+    decoder: Option<Decoder>,
+    malformed: MalformedSequences,
+}
+
+impl Continuation {
+    /// `decoder` has decoded the stream so far without errors. `None` means
+    /// that the stream has ended.
+    pub(crate) fn new(decoder: Option<Decoder>) -> Self {
+        Continuation {
+            decoder,
+            malformed: MalformedSequences::empty(),
+        }
+    }
+
+    /// Decodes `buffer`, which starts at stream offset `offset`.
+    pub(crate) fn feed(&mut self, buffer: &[u8], offset: usize, last: bool) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            self.malformed.decode(decoder, buffer, offset, last);
+        }
+        if last {
+            self.decoder = None;
+        }
+    }
+
+    pub(crate) fn malformed(&self) -> &MalformedSequences {
+        &self.malformed
+    }
+}
+
+/// Per-encoding counts of malformed sequences over a complete buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    shift_jis: MalformedSequences,
+    euc_jp: MalformedSequences,
+    iso_2022_jp: MalformedSequences,
+}
+
+impl ValidationReport {
+    /// The malformed sequences when decoding as Shift_JIS.
+    pub fn shift_jis(&self) -> &MalformedSequences {
+        &self.shift_jis
+    }
+
+    /// The malformed sequences when decoding as EUC-JP.
+    pub fn euc_jp(&self) -> &MalformedSequences {
+        &self.euc_jp
+    }
+
+    /// The malformed sequences when decoding as ISO-2022-JP.
+    pub fn iso_2022_jp(&self) -> &MalformedSequences {
+        &self.iso_2022_jp
+    }
+
+    /// The malformed sequences for `encoding` or `None` if `encoding` is
+    /// not one of the three Japanese legacy encodings.
+    pub fn for_encoding(&self, encoding: &'static Encoding) -> Option<&MalformedSequences> {
+        if encoding == SHIFT_JIS {
+            Some(&self.shift_jis)
+        } else if encoding == EUC_JP {
+            Some(&self.euc_jp)
+        } else if encoding == ISO_2022_JP {
+            Some(&self.iso_2022_jp)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decodes the complete `buffer` as each of Shift_JIS, EUC-JP and
+/// ISO-2022-JP and reports how many malformed sequences each produced.
+///
+/// Unlike `Detector`, this doesn't stop at the first piece of evidence, so
+/// it is useful for quantifying how well mixed or corrupted input fits each
+/// encoding. Half-width katakana do not count as malformed.
+pub fn validate(buffer: &[u8]) -> ValidationReport {
+    ValidationReport {
+        shift_jis: MalformedSequences::new(SHIFT_JIS, buffer),
+        euc_jp: MalformedSequences::new(EUC_JP, buffer),
+        iso_2022_jp: MalformedSequences::new(ISO_2022_JP, buffer),
+    }
+}
+
+// Any copyright to the test code below this comment is dedicated to the
+// Public Domain. http://creativecommons.org/publicdomain/zero/1.0/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_counts() {
+        // "あ" in Shift_JIS (two invalid bytes in EUC-JP), then a byte that
+        // is invalid in EUC-JP twice.
+        let report = validate(b"a\x82\xA0b\x80c\x80");
+        assert_eq!(report.shift_jis().count(), 0);
+        assert_eq!(report.euc_jp().count(), 4);
+        assert_eq!(report.euc_jp().first_offsets(), &[1, 2, 4, 6]);
+        assert_eq!(report.iso_2022_jp().count(), 4);
+    }
+
+    #[test]
+    fn test_validate_offsets_capped() {
+        let report = validate(&[0xFFu8; 20][..]);
+        assert_eq!(report.shift_jis().count(), 20);
+        assert_eq!(
+            report
+                .for_encoding(SHIFT_JIS)
+                .unwrap()
+                .first_offsets()
+                .len(),
+            MAX_RECORDED_OFFSETS
+        );
+        assert!(report.for_encoding(encoding_rs::UTF_8).is_none());
+    }
+}